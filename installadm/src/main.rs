@@ -1,23 +1,38 @@
 use crate::machined::claim_request::ClaimSecret;
+use crate::machined::install_progress::Message;
 use crate::machined::machine_service_client::MachineServiceClient;
-use crate::machined::{ClaimRequest, InstallConfig, SystemInfoRequest};
-use crate::state::{read_state_file, save_state, Server};
+use crate::machined::{
+    ActivateBootEnvironmentRequest, AttachInstallSessionRequest, BootEnvironment,
+    CancelInstallSessionRequest, ClaimRequest, ExportPoolsRequest, FirmwareMode,
+    GetBlobCacheRequest, GetInstallReportRequest, GetLogRequest, InstallConfig, InstallPhase,
+    InstallProgress, InstallReport, InstallSessionState, ListBootEnvironmentsRequest,
+    ListInstallSessionsRequest, PowerOffRequest, PrepareDisksRequest, PruneBlobCacheRequest,
+    RebootRequest, RefreshTokenRequest, SystemInfoRequest, SystemInfoSection, TokenScope,
+    ValidateConfigRequest, ValidateConfigResponse,
+};
+use crate::state::{read_state_file, save_state, Server, ServerTls, State};
 use clap::{Parser, Subcommand};
+use indicatif::{ProgressBar, ProgressStyle};
 use miette::Diagnostic;
+use prost::Message as _;
 use std::fs::read_to_string;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
 use thiserror::Error;
 use tonic::codec::CompressionEncoding;
 use tonic::codegen::http;
 use tonic::codegen::tokio_stream::StreamExt;
+use tonic::metadata::errors::InvalidMetadataValue;
+use tonic::metadata::MetadataValue;
 use tonic::transport::Channel;
-use tonic::Status;
+use tonic::{Code, Status, Streaming};
 use url::Url;
 
 mod config;
 mod machined;
 mod state;
+mod tls;
 mod usb;
 
 #[derive(Debug, Error, Diagnostic)]
@@ -40,6 +55,10 @@ pub enum Error {
     UrlParse(#[from] url::ParseError),
     #[error("No such server please claim it first")]
     NoSuchServer,
+    #[error("unknown system information section {0}")]
+    InvalidSystemInfoSection(String),
+    #[error("{0} is not a valid size")]
+    InvalidSize(String),
     #[error("No parent dir")]
     NoParentDir,
     #[error("Please provide a servername none can be inferred")]
@@ -60,10 +79,25 @@ pub enum Error {
     OciError(String),
     #[error(transparent)]
     AnyhowError(#[from] anyhow::Error),
+    #[error("claim token can not be sent: {0}")]
+    InvalidClaimToken(#[from] InvalidMetadataValue),
+    #[error("machine configuration is not valid for this machine")]
+    InvalidMachineConfig,
+    #[error("unknown token scope {0}, expected info, install or reboot")]
+    InvalidTokenScope(String),
+    #[error("the server certificate has the fingerprint {0}, compare it with the fingerprint machined shows on the console and pass it with --fingerprint")]
+    UntrustedCertificate(String),
+    #[error("the server certificate has the fingerprint {actual} not {expected}")]
+    FingerprintMismatch { expected: String, actual: String },
+    #[error("TLS error: {0}")]
+    TlsError(String),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Renew claim tokens that expire in less than this many seconds
+const TOKEN_REFRESH_SECS: i64 = 15 * 60;
+
 #[derive(Debug, Parser)]
 struct Args {
     #[command(subcommand)]
@@ -76,11 +110,151 @@ enum Commands {
         url: String,
         secret: Option<String>,
         name: Option<String>,
+        /// Operator recorded in the claim token, the current user by default
+        #[arg(long)]
+        operator: Option<String>,
+        /// Scopes to request (info, install, reboot), all of them by default
+        #[arg(long, value_delimiter = ',')]
+        scope: Vec<String>,
+        /// SHA-256 fingerprint of the certificate of an https server, as shown on its console
+        #[arg(long)]
+        fingerprint: Option<String>,
+        /// Verify the certificate of an https server against this CA instead of a fingerprint
+        #[arg(long, conflicts_with = "fingerprint")]
+        ca_cert: Option<PathBuf>,
+        /// Client certificate for servers that require one
+        #[arg(long, requires = "client_key")]
+        client_cert: Option<PathBuf>,
+        #[arg(long, requires = "client_cert")]
+        client_key: Option<PathBuf>,
+    },
+    /// Renew the claim token of a server before it expires
+    ///
+    /// Tokens are renewed automatically when less than 15 minutes are left. A machine has to
+    /// be claimed again 24 hours after it was claimed.
+    RefreshToken {
+        /// Name of the server to connect to
+        name: String,
     },
     Install {
         name: String,
         #[arg(short, long)]
         config: PathBuf,
+        /// Name of the install session, generated by machined if not given
+        #[arg(short, long)]
+        session_name: Option<String>,
+    },
+    /// Check a machine configuration against the hardware of a server without installing
+    Validate {
+        /// Name of the server to connect to
+        name: String,
+        #[arg(short, long)]
+        config: PathBuf,
+    },
+    /// List the install sessions of a machined server
+    Sessions {
+        /// Name of the server to connect to
+        name: String,
+    },
+    /// Reattach to an install session
+    ///
+    /// Replays all progress messages of the session and follows it until the install finishes.
+    Attach {
+        /// Name of the server to connect to
+        name: String,
+        /// Id or name of the install session
+        session: String,
+    },
+    /// Cancel a running install session, it rolls back what it completed
+    CancelSession {
+        /// Name of the server to connect to
+        name: String,
+        /// Id or name of the install session
+        session: String,
+    },
+    /// Show the blobs machined keeps in its image blob cache
+    BlobCache {
+        /// Name of the server to connect to
+        name: String,
+        /// Remove the least recently used blobs until at most this much is left, e.g. `2G`,
+        /// `0` empties the cache
+        #[arg(long)]
+        prune: Option<String>,
+    },
+    /// Reboot a machine, e.g. into the boot environment an install created
+    Reboot {
+        /// Name of the server to connect to
+        name: String,
+        /// Activate this boot environment before rebooting
+        #[arg(short, long)]
+        boot_environment: Option<String>,
+        /// Reboot even while an install session is running
+        #[arg(long)]
+        force: bool,
+    },
+    /// Power off a machine
+    PowerOff {
+        /// Name of the server to connect to
+        name: String,
+        /// Power off even while an install session is running
+        #[arg(long)]
+        force: bool,
+    },
+    /// List the boot environments of a machine
+    BootEnvironments {
+        /// Name of the server to connect to
+        name: String,
+        /// Make the next boot use this boot environment
+        #[arg(long)]
+        activate: Option<String>,
+    },
+    /// Unmount /a and export the pools an install created, e.g. before moving the disks
+    ExportPools {
+        /// Name of the server to connect to
+        name: String,
+        /// Export even while an install session is running
+        #[arg(long)]
+        force: bool,
+    },
+    /// Show what a successful install put on a machine
+    ///
+    /// machined also writes the report into the installed system, as
+    /// /var/log/machined/install-report.json.
+    InstallReport {
+        /// Name of the server to connect to
+        name: String,
+        /// Id or name of the install session, the last successful install by default
+        session: Option<String>,
+    },
+    /// Show the log of machined
+    Log {
+        /// Name of the server to connect to
+        name: String,
+        /// Number of lines from the end of the log, 0 for all lines machined keeps
+        #[arg(short = 'n', long, default_value = "100")]
+        lines: u32,
+    },
+    /// Clear old ZFS labels and partition tables from disks reused from other systems
+    ///
+    /// Without --confirm nothing is written: the disks are looked up on the machine and a
+    /// confirmation token for exactly these disks on this machine is printed. Run the
+    /// command again with it to wipe them.
+    PrepareDisks {
+        /// Name of the server to connect to
+        name: String,
+        /// Disks to wipe as system-info lists them
+        #[arg(required = true)]
+        disks: Vec<String>,
+        /// Also erase all data with the secure erase of the disks, can take hours. Only
+        /// supported on Linux
+        #[arg(long)]
+        secure_erase: bool,
+        /// Confirmation token printed by the previous run
+        #[arg(long)]
+        confirm: Option<String>,
+        /// Also wipe disks of imported pools and the disks the installer runs from
+        #[arg(long)]
+        force: bool,
     },
     /// Retrieve system information from a machined server
     ///
@@ -89,6 +263,10 @@ enum Commands {
     SystemInfo {
         /// Name of the server to connect to
         name: String,
+        /// Only retrieve these sections (disks, network, smbios, partitions, pci, cpu,
+        /// memory, firmware), all of them by default
+        #[arg(short, long, value_delimiter = ',')]
+        section: Vec<String>,
     },
     /// Create a bootable USB stick with EFI boot files
     ///
@@ -155,7 +333,17 @@ async fn main() -> Result<()> {
     let mut state = read_state_file()?;
 
     match args.command {
-        Commands::Claim { secret, url, name } => {
+        Commands::Claim {
+            secret,
+            url,
+            name,
+            operator,
+            scope,
+            fingerprint,
+            ca_cert,
+            client_cert,
+            client_key,
+        } => {
             let url_url: Url = url.parse()?;
             if secret.is_none() {
                 return Err(Error::CurrentlyPasswordClaimRequired);
@@ -169,80 +357,406 @@ async fn main() -> Result<()> {
                 return Err(Error::ServerNameCannotBeInferred);
             };
 
+            let operator = operator
+                .or_else(|| std::env::var("USER").ok())
+                .unwrap_or_default();
+            let scopes = scope
+                .iter()
+                .map(|name| parse_token_scope(name).map(|scope| scope as i32))
+                .collect::<Result<Vec<_>>>()?;
             let claim_request = tonic::Request::new(ClaimRequest {
                 claim_secret: secret.map(|s| ClaimSecret::ClaimPassword(s)),
+                operator,
+                scopes,
             });
-            let mut client = connect(url.as_str()).await?;
+            let tls = if url_url.scheme() == "https" {
+                Some(match ca_cert {
+                    Some(ca_cert) => tls::trust_ca(&url_url, &ca_cert, client_cert, client_key)?,
+                    None => {
+                        tls::pin_certificate(
+                            &url_url,
+                            fingerprint.as_deref(),
+                            client_cert,
+                            client_key,
+                        )
+                        .await?
+                    }
+                })
+            } else {
+                None
+            };
+            let mut client = connect(url.as_str(), tls.as_ref()).await?;
 
             let response = client.claim(claim_request).await?;
             let claim_response = response.into_inner();
+            let scopes = claim_response
+                .scopes()
+                .map(format_token_scope)
+                .collect::<Vec<_>>();
+            println!(
+                "Claimed {} as {} with scopes {}",
+                server_name,
+                claim_response.operator,
+                scopes.join(", ")
+            );
             let srv = Server {
                 name: server_name.clone(),
                 uri: url,
                 claim_token: claim_response.claim_token,
+                expires_at: claim_response.expires_at,
+                tls,
             };
             state.add_server(srv);
             save_state(state)?;
         }
-        Commands::Install { config, name } => {
-            let server = state.get_server(&name).ok_or(Error::NoSuchServer)?;
+        Commands::RefreshToken { name } => {
+            let server = state.get_server(&name).ok_or(Error::NoSuchServer)?.clone();
+            let server = refresh_token(&mut state, server).await?;
+            println!(
+                "Claim token of {} is valid for another {} minutes",
+                server.name,
+                (server.expires_at - unix_now()) / 60
+            );
+        }
+        Commands::Install {
+            config,
+            name,
+            session_name,
+        } => {
+            let server = &claimed_server(&mut state, &name).await?;
+            let machineconfig = read_to_string(&config)?;
+            let install_request = authorized_request(
+                server,
+                InstallConfig {
+                    machineconfig,
+                    session_name: session_name.unwrap_or_default(),
+                },
+            )?;
+            let mut client = connect(server.uri.as_str(), server.tls.as_ref()).await?;
+            let response = match client.install(install_request).await {
+                Ok(response) => response,
+                Err(status) if status.code() == Code::FailedPrecondition => {
+                    match ValidateConfigResponse::decode(status.details()) {
+                        Ok(report) if !report.issues.is_empty() => {
+                            print_validation_report(&report);
+                            return Err(Error::InvalidMachineConfig);
+                        }
+                        _ => return Err(status.into()),
+                    }
+                }
+                Err(status) => return Err(status.into()),
+            };
+            print_progress(response.into_inner()).await?;
+        }
+        Commands::Validate { name, config } => {
+            let server = &claimed_server(&mut state, &name).await?;
             let machineconfig = read_to_string(&config)?;
-            let install_request = tonic::Request::new(InstallConfig { machineconfig });
-            let mut client = connect(server.uri.as_str()).await?;
-            let response = client.install(install_request).await?;
-            let mut stream = response.into_inner();
-            while let Some(stream_resp) = stream.next().await {
-                let progress = stream_resp?;
-                println!("{}: {:?}", progress.level, progress.message);
+            let request = authorized_request(server, ValidateConfigRequest { machineconfig })?;
+            let mut client = connect(server.uri.as_str(), server.tls.as_ref()).await?;
+            let report = client.validate_config(request).await?.into_inner();
+            print_validation_report(&report);
+            if !report.valid {
+                return Err(Error::InvalidMachineConfig);
+            }
+        }
+        Commands::Sessions { name } => {
+            let server = &claimed_server(&mut state, &name).await?;
+            let request = authorized_request(server, ListInstallSessionsRequest {})?;
+            let mut client = connect(server.uri.as_str(), server.tls.as_ref()).await?;
+            let sessions = client.list_install_sessions(request).await?.into_inner();
+
+            println!(
+                "{:<38} {:<24} {:<12} {:<8} {:<20}",
+                "ID", "Name", "State", "Events", "Started"
+            );
+            println!("{:-<104}", "");
+            for session in sessions.sessions {
+                println!(
+                    "{:<38} {:<24} {:<12} {:<8} {:<20}",
+                    session.id,
+                    session.name,
+                    session.state().as_str_name(),
+                    session.events,
+                    session.started_at
+                );
+            }
+        }
+        Commands::Attach { name, session } => {
+            let server = &claimed_server(&mut state, &name).await?;
+            let request = authorized_request(server, AttachInstallSessionRequest { session })?;
+            let mut client = connect(server.uri.as_str(), server.tls.as_ref()).await?;
+            let response = client.attach_install_session(request).await?;
+            print_progress(response.into_inner()).await?;
+        }
+        Commands::CancelSession { name, session } => {
+            let server = &claimed_server(&mut state, &name).await?;
+            let request = authorized_request(server, CancelInstallSessionRequest { session })?;
+            let mut client = connect(server.uri.as_str(), server.tls.as_ref()).await?;
+            let response = client.cancel_install_session(request).await?.into_inner();
+            if let Some(session) = response.session {
+                if session.state() == InstallSessionState::Running {
+                    println!("Cancellation of install session {} requested", session.name);
+                } else {
+                    println!(
+                        "Install session {} is {}",
+                        session.name,
+                        session.state().as_str_name()
+                    );
+                }
+            }
+        }
+        Commands::BlobCache { name, prune } => {
+            let server = &claimed_server(&mut state, &name).await?;
+            let mut client = connect(server.uri.as_str(), server.tls.as_ref()).await?;
+            let cache = if let Some(keep) = prune {
+                let keep_bytes = parse_size(&keep).ok_or(Error::InvalidSize(keep))?;
+                let request = authorized_request(server, PruneBlobCacheRequest { keep_bytes })?;
+                let pruned = client.prune_blob_cache(request).await?.into_inner();
+                println!(
+                    "removed {} blobs, freed {}",
+                    pruned.removed,
+                    format_size(pruned.freed_bytes)
+                );
+                pruned.cache.unwrap_or_default()
+            } else {
+                let request = authorized_request(server, GetBlobCacheRequest {})?;
+                client.get_blob_cache(request).await?.into_inner()
+            };
+
+            println!(
+                "{}: {} of {}",
+                cache.path,
+                format_size(cache.size_bytes),
+                format_size(cache.max_size_bytes)
+            );
+            println!("{:<80} {:>10} {:<20}", "Digest", "Size", "Last used");
+            println!("{:-<112}", "");
+            for blob in cache.blobs {
+                println!(
+                    "{:<80} {:>10} {:<20}",
+                    blob.digest,
+                    format_size(blob.size),
+                    blob.last_used
+                );
+            }
+        }
+        Commands::Reboot {
+            name,
+            boot_environment,
+            force,
+        } => {
+            let server = &claimed_server(&mut state, &name).await?;
+            let request = authorized_request(
+                server,
+                RebootRequest {
+                    force,
+                    boot_environment: boot_environment.unwrap_or_default(),
+                },
+            )?;
+            let mut client = connect(server.uri.as_str(), server.tls.as_ref()).await?;
+            let response = client.reboot(request).await?.into_inner();
+            println!("{} reboots in {} seconds", name, response.delay_seconds);
+        }
+        Commands::PowerOff { name, force } => {
+            let server = &claimed_server(&mut state, &name).await?;
+            let request = authorized_request(server, PowerOffRequest { force })?;
+            let mut client = connect(server.uri.as_str(), server.tls.as_ref()).await?;
+            let response = client.power_off(request).await?.into_inner();
+            println!("{} powers off in {} seconds", name, response.delay_seconds);
+        }
+        Commands::BootEnvironments { name, activate } => {
+            let server = &claimed_server(&mut state, &name).await?;
+            let mut client = connect(server.uri.as_str(), server.tls.as_ref()).await?;
+            if let Some(be_name) = activate {
+                let request =
+                    authorized_request(server, ActivateBootEnvironmentRequest { name: be_name })?;
+                let be = client.activate_boot_environment(request).await?.into_inner();
+                println!("{} boots {} next", name, be.name);
+            }
+            let request = authorized_request(server, ListBootEnvironmentsRequest {})?;
+            let response = client.list_boot_environments(request).await?.into_inner();
+            print_boot_environments(&response.boot_environments);
+        }
+        Commands::ExportPools { name, force } => {
+            let server = &claimed_server(&mut state, &name).await?;
+            let request = authorized_request(server, ExportPoolsRequest { force })?;
+            let mut client = connect(server.uri.as_str(), server.tls.as_ref()).await?;
+            let response = client.export_pools(request).await?.into_inner();
+            if response.pools.is_empty() {
+                println!("no pools to export");
+            }
+            for pool in response.pools {
+                println!("exported {}", pool);
+            }
+        }
+        Commands::InstallReport { name, session } => {
+            let server = &claimed_server(&mut state, &name).await?;
+            let request = authorized_request(
+                server,
+                GetInstallReportRequest {
+                    session: session.unwrap_or_default(),
+                },
+            )?;
+            let mut client = connect(server.uri.as_str(), server.tls.as_ref()).await?;
+            let report = client.get_install_report(request).await?.into_inner();
+            print_install_report(&report);
+        }
+        Commands::Log { name, lines } => {
+            let server = &claimed_server(&mut state, &name).await?;
+            let request = authorized_request(server, GetLogRequest { lines })?;
+            let mut client = connect(server.uri.as_str(), server.tls.as_ref()).await?;
+            for line in client.get_log(request).await?.into_inner().lines {
+                println!("{}", line);
+            }
+        }
+        Commands::PrepareDisks {
+            name,
+            disks,
+            secure_erase,
+            confirm,
+            force,
+        } => {
+            let server = &claimed_server(&mut state, &name).await?;
+            let request = authorized_request(
+                server,
+                PrepareDisksRequest {
+                    disks: disks.clone(),
+                    secure_erase,
+                    confirmation: confirm.unwrap_or_default(),
+                    force,
+                },
+            )?;
+            let mut client = connect(server.uri.as_str(), server.tls.as_ref()).await?;
+            let response = client.prepare_disks(request).await?.into_inner();
+            for disk in &response.disks {
+                println!(
+                    "{:<10} {:<15} {:<20} {:<15} {:<15}",
+                    disk.device,
+                    disk.vendor,
+                    disk.product,
+                    format_size(disk.size_bytes),
+                    disk.serial
+                );
+            }
+            if response.wiped {
+                println!("Wiped {} disks on {}", response.disks.len(), name);
+            } else {
+                println!(
+                    "\nThis wipes all pools and partitions on these disks. To go ahead run:\n  \
+                     installadm prepare-disks {} {}{}{} --confirm {}",
+                    name,
+                    disks.join(" "),
+                    if secure_erase { " --secure-erase" } else { "" },
+                    if force { " --force" } else { "" },
+                    response.confirmation
+                );
             }
         }
-        Commands::SystemInfo { name } => {
-            let server = state.get_server(&name).ok_or(Error::NoSuchServer)?;
-            let mut client = connect(server.uri.as_str()).await?;
+        Commands::SystemInfo { name, section } => {
+            let server = &claimed_server(&mut state, &name).await?;
+            let mut client = connect(server.uri.as_str(), server.tls.as_ref()).await?;
+            let sections = section
+                .iter()
+                .map(|name| parse_system_info_section(name))
+                .collect::<Result<Vec<_>>>()?;
+            let wants = |section: SystemInfoSection| sections.is_empty() || sections.contains(&section);
             
             println!("Retrieving system information from server: {}", name);
-            let request = tonic::Request::new(SystemInfoRequest {});
+            let request = authorized_request(
+                server,
+                SystemInfoRequest {
+                    sections: sections.iter().map(|section| *section as i32).collect(),
+                },
+            )?;
             let response = client.get_system_info(request).await?;
             let system_info = response.into_inner();
+
+            if let Some(firmware) = &system_info.firmware {
+                println!("\nFirmware: {}{}",
+                         match firmware.mode() {
+                             FirmwareMode::Uefi => "UEFI",
+                             FirmwareMode::Bios => "BIOS",
+                             FirmwareMode::Unspecified => "unknown",
+                         },
+                         if firmware.secure_boot { " (secure boot enabled)" } else { "" });
+            }
+            if let Some(cpu) = &system_info.cpu {
+                println!("CPU: {} ({} sockets, {} cores, {} threads)",
+                         cpu.model, cpu.sockets, cpu.cores, cpu.threads);
+            }
+            if wants(SystemInfoSection::Memory) {
+                println!("Memory: {}", format_size(system_info.memory_bytes));
+            }
+
+            if wants(SystemInfoSection::Pci) {
+                println!("\nPCI Devices:");
+                println!("{:<14} {:<6} {:<6} {:<10} {:<8} {:<15}",
+                         "Address", "Vendor", "Device", "Subsystem", "Class", "Driver");
+                println!("{:-<70}", "");
+                for device in &system_info.pci_devices {
+                    println!("{:<14} {:04x}   {:04x}   {:04x}:{:04x}  {:06x}   {:<15}",
+                             device.address,
+                             device.vendor_id,
+                             device.device_id,
+                             device.subsystem_vendor_id,
+                             device.subsystem_device_id,
+                             device.class_code,
+                             if device.driver.is_empty() { "-" } else { &device.driver });
+                }
+            }
+
+            if wants(SystemInfoSection::Disks) {
+                // Display disk information
+                println!("\nDisk Information:");
+                println!("{:<10} {:<15} {:<20} {:<15} {:<10} {:<10} {:<15} {:<5} {:<5} {:<10}", 
+                         "Device", "Vendor", "Product", "Size", "Removable", "SSD", "Serial", "FLT", "LOC", "Location");
+                println!("{:-<120}", "");
             
-            // Display disk information
-            println!("\nDisk Information:");
-            println!("{:<10} {:<15} {:<20} {:<15} {:<10} {:<10} {:<15} {:<5} {:<5} {:<10}", 
-                     "Device", "Vendor", "Product", "Size", "Removable", "SSD", "Serial", "FLT", "LOC", "Location");
-            println!("{:-<120}", "");
-            
-            for disk in system_info.disks {
-                // Convert size to human-readable format
-                let size = format_size(disk.size_bytes);
+                for disk in system_info.disks {
+                    // Convert size to human-readable format
+                    let size = format_size(disk.size_bytes);
                 
-                println!("{:<10} {:<15} {:<20} {:<15} {:<10} {:<10} {:<15} {:<5} {:<5} {:<10}", 
-                         disk.device, 
-                         disk.vendor, 
-                         disk.product, 
-                         size,
-                         if disk.removable { "Yes" } else { "No" },
-                         if disk.solid_state { "Yes" } else { "No" },
-                         disk.serial,
-                         disk.fault_status,
-                         disk.location_code,
-                         disk.chassis_bay);
+                    println!("{:<10} {:<15} {:<20} {:<15} {:<10} {:<10} {:<15} {:<5} {:<5} {:<10}", 
+                             disk.device, 
+                             disk.vendor, 
+                             disk.product, 
+                             size,
+                             if disk.removable { "Yes" } else { "No" },
+                             if disk.solid_state { "Yes" } else { "No" },
+                             disk.serial,
+                             disk.fault_status,
+                             disk.location_code,
+                             disk.chassis_bay);
+                }
             }
-            
-            // Display network interface information
-            println!("\nNetwork Interface Information:");
-            println!("{:<10} {:<10} {:<10} {:<10} {:<15} {:<10} {:<20}", 
-                     "Name", "Class", "Media", "State", "Speed", "MTU", "MAC Address");
-            println!("{:-<100}", "");
-            
-            for interface in system_info.network_interfaces {
+
+            if !system_info.partitions.is_empty() {
+                println!("\nPartitions:");
+                for partition in &system_info.partitions {
+                    println!("{:<14} {:<10} {}",
+                             partition.device,
+                             partition.parent_device,
+                             format_size(partition.size_bytes));
+                }
+            }
+
+            if wants(SystemInfoSection::Network) {
+                // Display network interface information
+                println!("\nNetwork Interface Information:");
                 println!("{:<10} {:<10} {:<10} {:<10} {:<15} {:<10} {:<20}", 
-                         interface.name, 
-                         interface.class, 
-                         interface.media, 
-                         interface.state, 
-                         interface.speed,
-                         interface.mtu,
-                         interface.mac_address);
+                         "Name", "Class", "Media", "State", "Speed", "MTU", "MAC Address");
+                println!("{:-<100}", "");
+            
+                for interface in system_info.network_interfaces {
+                    println!("{:<10} {:<10} {:<10} {:<10} {:<15} {:<10} {:<20}", 
+                             interface.name, 
+                             interface.class, 
+                             interface.media, 
+                             interface.state, 
+                             interface.speed,
+                             interface.mtu,
+                             interface.mac_address);
+                }
             }
             
             // Display SMBIOS information if available
@@ -339,7 +853,37 @@ async fn main() -> Result<()> {
                         println!("  Threads Enabled: {}", processor.threads_enabled);
                     }
                 }
-                
+
+                // Display Cache information
+                if !smbios.caches.is_empty() {
+                    println!("\nCache Information:");
+                    for cache in smbios.caches.iter() {
+                        println!("Cache {}", cache.handle);
+                        println!("  Socket Designation: {}", cache.socket_designation);
+                        println!("  Level: L{}", cache.level);
+                        println!("  Enabled: {}", if cache.enabled { "Yes" } else { "No" });
+                        println!("  Maximum Size: {} bytes", cache.max_size);
+                        println!("  Installed Size: {} bytes", cache.installed_size);
+                        println!("  Cache Type: {}", cache.cache_type);
+                        println!("  Associativity: {}", cache.associativity);
+                        println!("  Error Correction: {}", cache.error_correction);
+                    }
+                }
+
+                // Display System Slot information
+                if !smbios.slots.is_empty() {
+                    println!("\nSystem Slot Information:");
+                    for (i, slot) in smbios.slots.iter().enumerate() {
+                        println!("Slot #{}", i);
+                        println!("  Designation: {}", slot.designation);
+                        println!("  Slot Type: 0x{:x}", slot.slot_type);
+                        println!("  Data Bus Width: {}", slot.data_bus_width);
+                        println!("  Current Usage: {}", if slot.current_usage == 4 { "In use" } else { "Available" });
+                        println!("  Slot ID: {}", slot.slot_id);
+                        println!("  Bus Address: {}", slot.bus_address);
+                    }
+                }
+
                 // Display Memory Array information
                 if !smbios.memory_arrays.is_empty() {
                     println!("\nMemory Array Information:");
@@ -439,10 +983,12 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-async fn connect(url: &str) -> Result<MachineServiceClient<Channel>> {
-    let channel = Channel::builder(http::Uri::from_str(url)?)
-        .connect()
-        .await?;
+async fn connect(url: &str, tls: Option<&ServerTls>) -> Result<MachineServiceClient<Channel>> {
+    let mut endpoint = Channel::builder(http::Uri::from_str(url)?);
+    if let Some(tls) = tls {
+        endpoint = endpoint.tls_config(tls::client_tls_config(tls)?)?;
+    }
+    let channel = endpoint.connect().await?;
 
     let client = MachineServiceClient::new(channel)
         .send_compressed(CompressionEncoding::Zstd)
@@ -450,7 +996,259 @@ async fn connect(url: &str) -> Result<MachineServiceClient<Channel>> {
     Ok(client)
 }
 
+/// Look up a claimed server, renewing its claim token when it is about to expire
+async fn claimed_server(state: &mut State, name: &str) -> Result<Server> {
+    let server = state.get_server(name).ok_or(Error::NoSuchServer)?.clone();
+    // Tokens of older installadm versions have no known expiry
+    let remaining = server.expires_at - unix_now();
+    if server.expires_at != 0 && remaining > 0 && remaining < TOKEN_REFRESH_SECS {
+        refresh_token(state, server).await
+    } else {
+        Ok(server)
+    }
+}
+
+/// Renew the claim token of a server and save it
+async fn refresh_token(state: &mut State, server: Server) -> Result<Server> {
+    let mut client = connect(server.uri.as_str(), server.tls.as_ref()).await?;
+    let request = authorized_request(&server, RefreshTokenRequest {})?;
+    let response = client.refresh_token(request).await?.into_inner();
+    let server = Server {
+        claim_token: response.claim_token,
+        expires_at: response.expires_at,
+        ..server
+    };
+    state.add_server(server.clone());
+    save_state(state.clone())?;
+    Ok(server)
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+fn parse_token_scope(name: &str) -> Result<TokenScope> {
+    match name {
+        "info" => Ok(TokenScope::Info),
+        "install" => Ok(TokenScope::Install),
+        "reboot" => Ok(TokenScope::Reboot),
+        _ => Err(Error::InvalidTokenScope(name.to_string())),
+    }
+}
+
+fn format_token_scope(scope: TokenScope) -> &'static str {
+    match scope {
+        TokenScope::Info => "info",
+        TokenScope::Install => "install",
+        TokenScope::Reboot => "reboot",
+        TokenScope::Unspecified => "unknown",
+    }
+}
+
+/// Build a request carrying the claim token of the server
+fn authorized_request<T>(server: &Server, message: T) -> Result<tonic::Request<T>> {
+    let mut request = tonic::Request::new(message);
+    request.metadata_mut().insert(
+        "authorization",
+        MetadataValue::from_str(&server.claim_token)?,
+    );
+    Ok(request)
+}
+
+/// Print boot environments like `beadm list`, N marks the running and R the next boot
+fn print_boot_environments(boot_environments: &[BootEnvironment]) {
+    println!(
+        "{:<32} {:<6} {:<16} {:>10} {:<20}",
+        "BE", "Active", "Mountpoint", "Space", "Created"
+    );
+    println!("{:-<88}", "");
+    for be in boot_environments {
+        let active = match (be.active, be.active_on_reboot) {
+            (true, true) => "NR",
+            (true, false) => "N",
+            (false, true) => "R",
+            (false, false) => "-",
+        };
+        println!(
+            "{:<32} {:<6} {:<16} {:>10} {:<20}",
+            be.name,
+            active,
+            if be.mountpoint.is_empty() {
+                "-"
+            } else {
+                &be.mountpoint
+            },
+            format_size(be.space_bytes),
+            be.created
+        );
+    }
+}
+
+/// Print an install report, with the hardware summed up and the machine configuration last
+fn print_install_report(report: &InstallReport) {
+    println!("Session:          {} ({})", report.session_name, report.session_id);
+    println!("Operator:         {}", report.operator);
+    println!("machined:         {}", report.machined_version);
+    println!("Started:          {}", report.started_at);
+    println!("Finished:         {}", report.finished_at);
+    println!("Image:            {}", report.image);
+    println!("Manifest:         {}", report.manifest_digest);
+    for (idx, layer) in report.layer_digests.iter().enumerate() {
+        println!("{:<18}{}", if idx == 0 { "Layers:" } else { "" }, layer);
+    }
+    println!("Boot environment: {}", report.boot_environment);
+
+    if let Some(hardware) = &report.hardware {
+        if let Some(system) = hardware.smbios.as_ref().and_then(|smbios| smbios.system.as_ref()) {
+            println!(
+                "System:           {} {} (serial {}, UUID {})",
+                system.manufacturer, system.product, system.serial_number, system.uuid
+            );
+        }
+        if let Some(cpu) = &hardware.cpu {
+            println!(
+                "CPU:              {} ({} sockets, {} cores, {} threads)",
+                cpu.model, cpu.sockets, cpu.cores, cpu.threads
+            );
+        }
+        println!("Memory:           {}", format_size(hardware.memory_bytes));
+        for (idx, disk) in hardware.disks.iter().enumerate() {
+            println!(
+                "{:<18}{} {} {} {} (serial {})",
+                if idx == 0 { "Disks:" } else { "" },
+                disk.device,
+                disk.vendor,
+                disk.product,
+                format_size(disk.size_bytes),
+                disk.serial
+            );
+        }
+        for (idx, link) in hardware.network_interfaces.iter().enumerate() {
+            println!(
+                "{:<18}{} {}",
+                if idx == 0 { "Links:" } else { "" },
+                link.name,
+                link.mac_address
+            );
+        }
+    }
+
+    println!("\nMachine configuration:\n{}", report.machineconfig);
+}
+
+/// Print the issues found while validating a machine configuration
+fn print_validation_report(report: &ValidateConfigResponse) {
+    if !report.hardware_checked {
+        println!("hardware could not be discovered, only the configuration structure was checked");
+    }
+    if report.valid {
+        println!("machine configuration is valid");
+    }
+    for issue in &report.issues {
+        println!("{}: {}", issue.subject, issue.message);
+    }
+}
+
+/// Print install progress until the install session finishes
+///
+/// Byte counters of downloads and layer extraction are rendered as a progress bar.
+async fn print_progress(mut stream: Streaming<InstallProgress>) -> Result<()> {
+    let mut session_id = None;
+    let mut bar: Option<ProgressBar> = None;
+    while let Some(stream_resp) = stream.next().await {
+        let progress = stream_resp?;
+        if session_id.is_none() && !progress.session_id.is_empty() {
+            println!("install session: {}", progress.session_id);
+            session_id = Some(progress.session_id.clone());
+        }
+
+        let phase = progress.phase();
+        if progress.bytes_total > 0 {
+            let bar = bar.get_or_insert_with(|| {
+                let bar = ProgressBar::new(progress.bytes_total);
+                bar.set_style(
+                    ProgressStyle::default_bar()
+                        .template("{msg} [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
+                        .unwrap()
+                        .progress_chars("#>-"),
+                );
+                bar
+            });
+            bar.set_length(progress.bytes_total);
+            bar.set_position(progress.bytes_done);
+            bar.set_message(format!(
+                "{} {}/{}",
+                phase.as_str_name(),
+                progress.step,
+                progress.total_steps
+            ));
+            if progress.bytes_done >= progress.bytes_total {
+                bar.finish();
+                bar.reset();
+            }
+            continue;
+        }
+
+        let text = match &progress.message {
+            Some(Message::Info(text)) | Some(Message::Error(text)) => text.as_str(),
+            None => "",
+        };
+        let line = if phase == InstallPhase::Unspecified {
+            format!("{}: {}", progress.level().as_str_name(), text)
+        } else {
+            format!(
+                "{}: [{} {}/{}] {}",
+                progress.level().as_str_name(),
+                phase.as_str_name(),
+                progress.step,
+                progress.total_steps,
+                text
+            )
+        };
+        // A hidden bar, e.g. when the output is not a terminal, drops the lines it prints
+        match &bar {
+            Some(bar) if !bar.is_hidden() => bar.println(line),
+            _ => println!("{}", line),
+        }
+    }
+    if let Some(bar) = bar {
+        bar.finish_and_clear();
+    }
+    Ok(())
+}
+
 /// Format a size in bytes to a human-readable string
+/// Look up a section of the system information by its short name, e.g. `pci`
+fn parse_system_info_section(name: &str) -> Result<SystemInfoSection> {
+    SystemInfoSection::from_str_name(&format!("SYSTEM_INFO_SECTION_{}", name.trim().to_uppercase()))
+        .filter(|section| *section != SystemInfoSection::Unspecified)
+        .ok_or_else(|| Error::InvalidSystemInfoSection(name.to_string()))
+}
+
+/// Parse a size like `512M` or `1.5G` into bytes, units are powers of 1024
+fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim().to_uppercase();
+    let size = size
+        .strip_suffix("IB")
+        .or_else(|| size.strip_suffix('B'))
+        .unwrap_or(&size);
+    let (number, shift) = match size.chars().last()? {
+        'K' => (&size[..size.len() - 1], 10),
+        'M' => (&size[..size.len() - 1], 20),
+        'G' => (&size[..size.len() - 1], 30),
+        'T' => (&size[..size.len() - 1], 40),
+        _ => (size, 0),
+    };
+    let number = number.trim().parse::<f64>().ok()?;
+    if number < 0.0 {
+        return None;
+    }
+    Some((number * (1u64 << shift) as f64) as u64)
+}
+
 fn format_size(size_bytes: u64) -> String {
     const KB: u64 = 1024;
     const MB: u64 = KB * 1024;
@@ -2,6 +2,7 @@ use crate::{Error, Result};
 use platform_dirs::AppDirs;
 use serde::{Deserialize, Serialize};
 use std::fs::create_dir_all;
+use std::path::PathBuf;
 
 const SATE_FILE_NAME: &str = "state.json";
 const APP_NAME: &str = "installadm";
@@ -12,7 +13,9 @@ pub struct State {
 }
 
 impl State {
+    /// Add a server, replacing a server of the same name
     pub fn add_server(&mut self, server: Server) {
+        self.servers.retain(|existing| existing.name != server.name);
         self.servers.push(server);
     }
 
@@ -30,6 +33,23 @@ pub struct Server {
     pub name: String,
     pub uri: String,
     pub claim_token: String,
+    /// Unix timestamp the claim token expires at, 0 if unknown
+    #[serde(default)]
+    pub expires_at: i64,
+    /// TLS settings of servers claimed over https
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<ServerTls>,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct ServerTls {
+    /// PEM certificate the server is verified against, its own certificate when it was pinned
+    /// by fingerprint
+    pub certificate: String,
+    /// Name the server certificate has to be issued for
+    pub domain_name: String,
+    pub client_cert: Option<PathBuf>,
+    pub client_key: Option<PathBuf>,
 }
 
 pub fn read_state_file() -> Result<State> {
@@ -0,0 +1,197 @@
+use crate::state::ServerTls;
+use crate::{Error, Result};
+use base64::Engine;
+use sha2::{Digest, Sha256};
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::client::danger::{
+    HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+};
+use tokio_rustls::rustls::crypto::{self, CryptoProvider};
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use tokio_rustls::rustls::{ClientConfig, DigitallySignedStruct, SignatureScheme};
+use tokio_rustls::TlsConnector;
+use tonic::transport::{Certificate, ClientTlsConfig, Identity};
+use url::Url;
+
+/// Name machined issues its self-signed certificate for
+const MACHINED_TLS_NAME: &str = "machined";
+
+/// Fetch the certificate of a server and pin it if it matches the fingerprint the operator
+/// read from the console of the machine.
+///
+/// Without a fingerprint the certificate is not trusted, the error shows its fingerprint
+/// so the operator can compare it.
+pub async fn pin_certificate(
+    url: &Url,
+    fingerprint: Option<&str>,
+    client_cert: Option<PathBuf>,
+    client_key: Option<PathBuf>,
+) -> Result<ServerTls> {
+    let cert = fetch_certificate(url).await?;
+    let actual = format_fingerprint(&cert);
+    match fingerprint {
+        Some(expected) if normalize_fingerprint(expected) == normalize_fingerprint(&actual) => {
+            Ok(ServerTls {
+                certificate: pem_encode(&cert),
+                domain_name: MACHINED_TLS_NAME.to_string(),
+                client_cert,
+                client_key,
+            })
+        }
+        Some(expected) => Err(Error::FingerprintMismatch {
+            expected: expected.to_string(),
+            actual,
+        }),
+        None => Err(Error::UntrustedCertificate(actual)),
+    }
+}
+
+/// Trust servers with certificates of a CA, e.g. when machined serves a certificate of
+/// the organisation instead of a self-signed one
+pub fn trust_ca(
+    url: &Url,
+    ca_cert: &Path,
+    client_cert: Option<PathBuf>,
+    client_key: Option<PathBuf>,
+) -> Result<ServerTls> {
+    Ok(ServerTls {
+        certificate: read_to_string(ca_cert)?,
+        domain_name: url
+            .host_str()
+            .ok_or(Error::ServerNameCannotBeInferred)?
+            .to_string(),
+        client_cert,
+        client_key,
+    })
+}
+
+pub fn client_tls_config(tls: &ServerTls) -> Result<ClientTlsConfig> {
+    let config = ClientTlsConfig::new()
+        .ca_certificate(Certificate::from_pem(&tls.certificate))
+        .domain_name(&tls.domain_name);
+    match (&tls.client_cert, &tls.client_key) {
+        (Some(cert), Some(key)) => Ok(config.identity(Identity::from_pem(
+            read_to_string(cert)?,
+            read_to_string(key)?,
+        ))),
+        (None, None) => Ok(config),
+        _ => Err(Error::TlsError(
+            "--client-cert and --client-key must be given together".to_string(),
+        )),
+    }
+}
+
+async fn fetch_certificate(url: &Url) -> Result<CertificateDer<'static>> {
+    let host = url.host_str().ok_or(Error::ServerNameCannotBeInferred)?;
+    let port = url.port().unwrap_or(50051);
+    let verifier = Arc::new(CaptureCertificate::new());
+    let config = ClientConfig::builder_with_provider(Arc::new(crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| Error::TlsError(e.to_string()))?
+        .dangerous()
+        .with_custom_certificate_verifier(verifier.clone())
+        .with_no_client_auth();
+    let stream = TcpStream::connect((host, port)).await?;
+    // Servers requiring client certificates abort the handshake, the certificate has been
+    // seen by then
+    let _ = TlsConnector::from(Arc::new(config))
+        .connect(ServerName::try_from(MACHINED_TLS_NAME).unwrap(), stream)
+        .await;
+    let cert = verifier.seen.lock().unwrap().take();
+    cert.ok_or_else(|| Error::TlsError(format!("{} did not present a certificate", url)))
+}
+
+/// SHA-256 fingerprint in the format machined logs it
+fn format_fingerprint(cert: &CertificateDer) -> String {
+    Sha256::digest(cert.as_ref())
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+fn normalize_fingerprint(fingerprint: &str) -> String {
+    fingerprint
+        .chars()
+        .filter(|c| c.is_ascii_hexdigit())
+        .collect::<String>()
+        .to_ascii_uppercase()
+}
+
+fn pem_encode(cert: &CertificateDer) -> String {
+    let encoded = base64::prelude::BASE64_STANDARD.encode(cert.as_ref());
+    let mut pem = String::from("-----BEGIN CERTIFICATE-----\n");
+    for line in encoded.as_bytes().chunks(64) {
+        pem.push_str(std::str::from_utf8(line).unwrap_or_default());
+        pem.push('\n');
+    }
+    pem.push_str("-----END CERTIFICATE-----\n");
+    pem
+}
+
+/// Accepts any certificate and keeps it to compare its fingerprint afterwards
+#[derive(Debug)]
+struct CaptureCertificate {
+    provider: CryptoProvider,
+    seen: Mutex<Option<CertificateDer<'static>>>,
+}
+
+impl CaptureCertificate {
+    fn new() -> Self {
+        CaptureCertificate {
+            provider: crypto::ring::default_provider(),
+            seen: Mutex::new(None),
+        }
+    }
+}
+
+impl ServerCertVerifier for CaptureCertificate {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> std::result::Result<ServerCertVerified, tokio_rustls::rustls::Error> {
+        *self.seen.lock().unwrap() = Some(end_entity.clone().into_owned());
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
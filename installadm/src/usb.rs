@@ -2,7 +2,7 @@ use anyhow::Result;
 use futures_util::StreamExt;
 use indicatif::{ProgressBar, ProgressStyle};
 use libarchive::archive::{ReadCompression, ReadFormat};
-use ociclient::{Client as OciClient, ImageReference, ManifestVariant};
+use ociclient::{Client as OciClient, Descriptor, ImageReference, ManifestVariant, OciDigest};
 use reqwest::Client;
 use std::fs::{self, File};
 use std::io::{Seek, SeekFrom, Write};
@@ -14,6 +14,7 @@ use tempfile::tempdir;
 use url::Url;
 use which::which;
 use serde_json::json;
+use sha2::{Digest, Sha256};
 
 use crate::config::InstallAdmConfig;
 use crate::Error;
@@ -704,19 +705,37 @@ async fn download_and_extract_assets(url: &str, mount_point: &Path) -> Result<()
     Ok(())
 }
 
-/// Download an OCI image to the USB stick
+/// Mount point of the USB stick on the installer
+const USB_MOUNT_POINT: &str = "/usb";
+
+/// Parse an image reference as given on the command line, with or without `oci://`
+fn parse_image_reference(image_ref: &str) -> Result<ImageReference, Error> {
+    ImageReference::from_str(image_ref.strip_prefix("oci://").unwrap_or(image_ref))
+        .map_err(|e| Error::OciError(format!("Invalid image reference: {}", e)))
+}
+
+/// Directory of the OCI image layout an image is downloaded to, relative to the USB stick
+fn image_layout_dir(image_reference: &ImageReference) -> PathBuf {
+    Path::new("images").join(image_reference.name.as_str())
+}
+
+/// Download an OCI image to the USB stick as an OCI image layout
+///
+/// The layout is written to `images/<name>` and the image is tagged with the tag it was
+/// downloaded with, machined installs it without network with
+/// `oci-layout:/usb/images/<name>:<tag>`.
 async fn download_oci_image(image_ref: &str, mount_point: &Path) -> Result<(), Error> {
     println!("Downloading OCI image: {}...", image_ref);
 
     // Parse the image reference
-    let image_reference = ImageReference::from_str(image_ref)
-        .map_err(|e| Error::OciError(format!("Invalid image reference: {}", e)))?;
+    let image_reference = parse_image_reference(image_ref)?;
 
     // Create OCI client
     let registry_url = format!(
         "https://{}",
         image_reference
             .hostname
+            .clone()
             .unwrap_or(String::from("localhost"))
     );
     let client = OciClient::new(registry_url, None);
@@ -730,51 +749,84 @@ async fn download_oci_image(image_ref: &str, mount_point: &Path) -> Result<(), E
         .map_err(|e| Error::OciError(format!("Failed to query manifest: {}", e)))?
         .ok_or_else(|| Error::OciError("Manifest not found".to_string()))?;
 
+    let ManifestVariant::Manifest(image_manifest) = manifest else {
+        return Err(Error::OciError(
+            "Only image manifests are supported".to_string(),
+        ));
+    };
+
     // Create directory for the image
-    let image_dir = mount_point
-        .join("images")
-        .join(image_reference.name.as_str());
-    fs::create_dir_all(&image_dir).map_err(|e| Error::IoError(e))?;
+    let image_dir = mount_point.join(image_layout_dir(&image_reference));
+    let blob_dir = image_dir.join("blobs");
+    fs::create_dir_all(&blob_dir).map_err(Error::IoError)?;
+    let blob_path = |digest: &OciDigest| {
+        blob_dir.join(digest.algorithm()).join(digest.hex())
+    };
+
+    println!("Downloading image layers...");
+
+    // Download config
+    let config_path = blob_path(&image_manifest.config.digest);
+    fs::create_dir_all(config_path.parent().unwrap()).map_err(Error::IoError)?;
+    session
+        .download_blob(&image_manifest.config.digest, &config_path, true)
+        .await
+        .map_err(|e| Error::OciError(format!("Failed to download config: {}", e)))?;
 
     // Download layers
-    match manifest {
-        ManifestVariant::Manifest(image_manifest) => {
-            println!("Downloading image layers...");
-
-            // Download config
-            let config_path = image_dir.join("config.json");
-            session
-                .download_blob(&image_manifest.config.digest, &config_path, true)
-                .await
-                .map_err(|e| Error::OciError(format!("Failed to download config: {}", e)))?;
-
-            // Download layers
-            for (i, layer) in image_manifest.layers.iter().enumerate() {
-                println!(
-                    "Downloading layer {}/{}...",
-                    i + 1,
-                    image_manifest.layers.len()
-                );
-                let layer_path = image_dir.join(format!("layer_{}.tar", i));
-                session
-                    .download_blob(&layer.digest, &layer_path, true)
-                    .await
-                    .map_err(|e| Error::OciError(format!("Failed to download layer: {}", e)))?;
-            }
-
-            // Save manifest
-            let manifest_path = image_dir.join("manifest.json");
-            let manifest_json =
-                serde_json::to_string_pretty(&image_manifest).map_err(|e| Error::JSONError(e))?;
-            fs::write(manifest_path, manifest_json).map_err(|e| Error::IoError(e))?;
-        }
-        _ => {
-            return Err(Error::OciError(
-                "Only image manifests are supported".to_string(),
-            ));
-        }
+    for (i, layer) in image_manifest.layers.iter().enumerate() {
+        println!(
+            "Downloading layer {}/{}...",
+            i + 1,
+            image_manifest.layers.len()
+        );
+        let layer_path = blob_path(&layer.digest);
+        fs::create_dir_all(layer_path.parent().unwrap()).map_err(Error::IoError)?;
+        session
+            .download_blob(&layer.digest, &layer_path, true)
+            .await
+            .map_err(|e| Error::OciError(format!("Failed to download layer: {}", e)))?;
     }
 
+    // Save the manifest in the field names of the image spec and index it by its digest
+    let descriptor = |descriptor: &Descriptor| {
+        json!({
+            "mediaType": descriptor.media_type,
+            "digest": descriptor.digest.to_string(),
+            "size": descriptor.size,
+        })
+    };
+    let manifest_json = serde_json::to_vec_pretty(&json!({
+        "schemaVersion": 2,
+        "mediaType": image_manifest.media_type,
+        "config": descriptor(&image_manifest.config),
+        "layers": image_manifest.layers.iter().map(descriptor).collect::<Vec<_>>(),
+    }))
+    .map_err(Error::JSONError)?;
+    let manifest_hex = format!("{:x}", Sha256::digest(&manifest_json));
+    fs::create_dir_all(blob_dir.join("sha256")).map_err(Error::IoError)?;
+    fs::write(blob_dir.join("sha256").join(&manifest_hex), &manifest_json)
+        .map_err(Error::IoError)?;
+
+    let index = json!({
+        "schemaVersion": 2,
+        "manifests": [{
+            "mediaType": image_manifest.media_type,
+            "digest": format!("sha256:{}", manifest_hex),
+            "size": manifest_json.len(),
+            "annotations": {
+                "org.opencontainers.image.ref.name": image_reference.tag,
+            },
+        }],
+    });
+    let index_json = serde_json::to_string_pretty(&index).map_err(Error::JSONError)?;
+    fs::write(image_dir.join("index.json"), index_json).map_err(Error::IoError)?;
+    fs::write(
+        image_dir.join("oci-layout"),
+        r#"{"imageLayoutVersion":"1.0.0"}"#,
+    )
+    .map_err(Error::IoError)?;
+
     println!("OCI image downloaded successfully");
     Ok(())
 }
@@ -850,19 +902,19 @@ pub async fn testrun_installer(
         let config_content = fs::read_to_string(config_path)
             .map_err(|e| Error::IoError(e))?;
 
-        // Determine the file extension based on content or use a default
-        let extension = if config_content.trim().starts_with('{') {
-            // Looks like JSON
-            ".json"
-        } else if config_content.contains('=') && !config_content.contains(':') {
-            // Likely TOML
-            ".toml"
-        } else if config_content.contains(':') && !config_content.contains('{') {
-            // Likely YAML
-            ".yaml"
-        } else {
-            // Default to JSON if can't determine
-            ".json"
+        // Keep the extension of the file, machined picks the parser by it
+        let extension = match Path::new(config_path).extension().and_then(|ext| ext.to_str()) {
+            Some(ext @ ("kdl" | "json" | "toml" | "yaml" | "yml")) => format!(".{}", ext),
+            _ => if config_content.trim().starts_with('{') {
+                // Looks like JSON
+                ".json".to_string()
+            } else if config_content.trim_start().starts_with("---") {
+                // YAML document
+                ".yaml".to_string()
+            } else {
+                // machined detects the format of the content itself
+                ".kdl".to_string()
+            },
         };
 
         let usb_config_path = mount_point.join(format!("machined{}", extension));
@@ -945,24 +997,41 @@ pub async fn testrun_installer(
 
 /// Generate a default configuration file for the installer
 fn generate_default_config(oci_image: Option<&str>) -> Result<String, Error> {
-    // Default OCI image if none provided
-    let image = oci_image.unwrap_or("oci://aopc.cloud/openindiana/hipster:latest");
+    // An image downloaded to the USB stick is installed from there, otherwise the default
+    // OCI image is pulled from the registry
+    let image = match oci_image {
+        Some(image_ref) => {
+            let image_reference = parse_image_reference(image_ref)?;
+            format!(
+                "oci-layout:{}:{}",
+                Path::new(USB_MOUNT_POINT)
+                    .join(image_layout_dir(&image_reference))
+                    .display(),
+                image_reference.tag
+            )
+        }
+        None => String::from("oci://aopc.cloud/openindiana/hipster:latest"),
+    };
 
     // Create a default configuration
     let config = json!({
-        "pool": {
-            "name": "rpool",
-            "vdev": {
-                "kind": "Mirror",
-                "disks": ["c0t0d0", "c0t1d0"]  // Default disks, will be detected by machined
-            },
-            "options": [
-                {
-                    "name": "compression",
-                    "value": "zstd"
-                }
-            ]
-        },
+        "pools": [
+            {
+                "name": "rpool",
+                "vdevs": [
+                    {
+                        "kind": "mirror",
+                        "disks": ["c0t0d0", "c0t1d0"]  // Default disks, will be detected by machined
+                    }
+                ],
+                "filesystem_options": [
+                    {
+                        "name": "compression",
+                        "value": "zstd"
+                    }
+                ]
+            }
+        ],
         "image": image,
         "boot_environment_name": "illumos",
         "sysconfig": {
@@ -974,7 +1043,7 @@ fn generate_default_config(oci_image: Option<&str>) -> Result<String, Error> {
                     "addresses": [
                         {
                             "name": "v4",
-                            "kind": "Dhcp4"
+                            "kind": "dhcp4"
                         }
                     ]
                 }
@@ -1,7 +1,7 @@
 import { ChangeDetectionStrategy, Component, computed, inject, signal } from '@angular/core';
 import { CommonModule } from '@angular/common';
 import { ActivatedRoute, Router } from '@angular/router';
-import { MachinesService, Machine } from '../services/machines.service';
+import { MachinesService, Machine, BootEnvironment } from '../services/machines.service';
 
 @Component({
   selector: 'app-machine-details',
@@ -32,6 +32,61 @@ export class MachineDetailsComponent {
     }
   }
 
+  readonly force = signal(false);
+  readonly bootEnvironments = signal<BootEnvironment[]>([]);
+  readonly output = signal<string>('');
+
+  reboot() {
+    if (!confirm('Reboot this machine?')) return;
+    this.svc.reboot(this.id(), this.force()).subscribe({
+      next: (r) => this.output.set(`Rebooting in ${r.delay_seconds}s`),
+      error: (e) => this.output.set(this.errorText(e)),
+    });
+  }
+
+  powerOff() {
+    if (!confirm('Power off this machine?')) return;
+    this.svc.powerOff(this.id(), this.force()).subscribe({
+      next: (r) => this.output.set(`Powering off in ${r.delay_seconds}s`),
+      error: (e) => this.output.set(this.errorText(e)),
+    });
+  }
+
+  loadBootEnvironments() {
+    this.svc.listBootEnvironments(this.id()).subscribe({
+      next: (list) => this.bootEnvironments.set(list),
+      error: (e) => this.output.set(this.errorText(e)),
+    });
+  }
+
+  activate(name: string) {
+    this.svc.activateBootEnvironment(this.id(), name).subscribe({
+      next: (be) => {
+        this.output.set(`${be.name} is active on reboot`);
+        this.loadBootEnvironments();
+      },
+      error: (e) => this.output.set(this.errorText(e)),
+    });
+  }
+
+  exportPools() {
+    this.svc.exportPools(this.id(), this.force()).subscribe({
+      next: (r) => this.output.set(r.pools.length ? `Exported ${r.pools.join(', ')}` : 'No pools to export'),
+      error: (e) => this.output.set(this.errorText(e)),
+    });
+  }
+
+  showLog() {
+    this.svc.getLog(this.id()).subscribe({
+      next: (r) => this.output.set(r.lines.join('\n')),
+      error: (e) => this.output.set(this.errorText(e)),
+    });
+  }
+
+  private errorText(e: any): string {
+    return e?.error?.error ?? 'Request failed';
+  }
+
   back() {
     this.router.navigate(['/machines']);
   }
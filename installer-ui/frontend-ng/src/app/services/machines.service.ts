@@ -31,6 +31,15 @@ export interface Machine {
 
 interface ClaimRequest { url: string; claimPassword: string }
 
+export interface BootEnvironment {
+  name: string;
+  active: boolean;
+  active_on_reboot: boolean;
+  mountpoint?: string;
+  space_bytes: number;
+  created: number;
+}
+
 @Injectable({ providedIn: 'root' })
 export class MachinesService {
   private readonly http = inject(HttpClient);
@@ -142,6 +151,35 @@ export class MachinesService {
       );
   }
 
+  // power and post-install control
+  reboot(id: string, force = false, bootEnvironment?: string) {
+    return this.http.post<{ delay_seconds: number }>(`${this.machineUrl(id)}/reboot`, { force, boot_environment: bootEnvironment });
+  }
+
+  powerOff(id: string, force = false) {
+    return this.http.post<{ delay_seconds: number }>(`${this.machineUrl(id)}/poweroff`, { force });
+  }
+
+  listBootEnvironments(id: string) {
+    return this.http.get<BootEnvironment[]>(`${this.machineUrl(id)}/boot-environments`);
+  }
+
+  activateBootEnvironment(id: string, name: string) {
+    return this.http.post<BootEnvironment>(`${this.machineUrl(id)}/boot-environments/${encodeURIComponent(name)}/activate`, {});
+  }
+
+  exportPools(id: string, force = false) {
+    return this.http.post<{ pools: string[] }>(`${this.machineUrl(id)}/export-pools`, { force });
+  }
+
+  getLog(id: string, lines = 200) {
+    return this.http.get<{ lines: string[] }>(`${this.machineUrl(id)}/log`, { params: { lines } });
+  }
+
+  private machineUrl(id: string) {
+    return `${this.apiBase}/machines/${encodeURIComponent(id)}`;
+  }
+
   // selection helpers
   toggleSelected(id: string) {
     const set = new Set(this.selectedIds());
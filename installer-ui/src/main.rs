@@ -11,6 +11,7 @@ use axum::{
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tokio::sync::RwLock;
+use tonic::transport::Channel;
 use tower_http::services::ServeDir;
 use tracing::info;
 use tokio::net::TcpListener;
@@ -21,8 +22,14 @@ pub mod machined_grpc {
     tonic::include_proto!("machined");
 }
 
+mod tls;
+
 use machined_grpc::machine_service_client::MachineServiceClient;
-use machined_grpc::{claim_request, ClaimRequest, SystemInfoRequest};
+use machined_grpc::{
+    claim_request, ActivateBootEnvironmentRequest, ClaimRequest, ExportPoolsRequest, GetLogRequest,
+    ListBootEnvironmentsRequest, PowerOffRequest, RebootRequest, SystemInfoRequest,
+    SystemInfoSection,
+};
 
 #[derive(Serialize, Clone, ToSchema)]
 struct DiskDto {
@@ -57,6 +64,8 @@ struct MachineEntry {
     endpoint: String,
     name: Option<String>,
     token: Option<String>,
+    /// PEM certificate pinned for `https://` endpoints
+    certificate: Option<String>,
 }
 
 #[derive(Clone)]
@@ -75,6 +84,12 @@ enum ApiError {
     NotFound(String),
     #[error("bad request: {0}")]
     BadRequest(String),
+    #[error("the machine certificate has the fingerprint {0}, compare it with the fingerprint machined shows on the console and pass it as fingerprint")]
+    UntrustedCertificate(String),
+    #[error("the machine certificate has the fingerprint {actual} not {expected}")]
+    FingerprintMismatch { expected: String, actual: String },
+    #[error("TLS error: {0}")]
+    Tls(String),
     #[error("internal error: {0}")]
     Other(String),
 }
@@ -85,7 +100,10 @@ impl IntoResponse for ApiError {
             ApiError::Grpc(ref s) => {
                 // Map tonic status to HTTP status roughly
                 match s.code() {
-                    tonic::Code::PermissionDenied => StatusCode::UNAUTHORIZED,
+                    tonic::Code::PermissionDenied | tonic::Code::Unauthenticated => {
+                        StatusCode::UNAUTHORIZED
+                    }
+                    tonic::Code::FailedPrecondition => StatusCode::CONFLICT,
                     tonic::Code::InvalidArgument => StatusCode::BAD_REQUEST,
                     tonic::Code::NotFound => StatusCode::NOT_FOUND,
                     _ => StatusCode::BAD_GATEWAY,
@@ -93,7 +111,10 @@ impl IntoResponse for ApiError {
             }
             ApiError::Transport(_) => StatusCode::BAD_GATEWAY,
             ApiError::NotFound(_) => StatusCode::NOT_FOUND,
-            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::BadRequest(_)
+            | ApiError::UntrustedCertificate(_)
+            | ApiError::FingerprintMismatch { .. } => StatusCode::BAD_REQUEST,
+            ApiError::Tls(_) => StatusCode::BAD_GATEWAY,
             ApiError::Other(_) => StatusCode::INTERNAL_SERVER_ERROR,
         };
         let body = serde_json::json!({ "error": self.to_string() });
@@ -123,12 +144,17 @@ struct MachineDto {
     endpoint: String,
     name: Option<String>,
     has_token: bool,
+    /// Connections use TLS with the pinned machine certificate
+    tls: bool,
 }
 
 #[derive(Deserialize, ToSchema)]
 struct AddMachineBody {
     endpoint: String,
     name: Option<String>,
+    /// SHA-256 fingerprint of the TLS certificate machined shows on its console, required
+    /// for `https://` endpoints
+    fingerprint: Option<String>,
 }
 
 #[derive(Serialize, ToSchema)]
@@ -150,6 +176,7 @@ async fn list_machines(State(state): State<AppState>) -> Result<impl IntoRespons
             endpoint: m.endpoint.clone(),
             name: m.name.clone(),
             has_token: m.token.is_some(),
+            tls: m.certificate.is_some(),
         })
         .collect();
     Ok(Json(list))
@@ -171,6 +198,11 @@ async fn add_machine(
     if body.endpoint.trim().is_empty() {
         return Err(ApiError::BadRequest("endpoint required".into()));
     }
+    let certificate = if body.endpoint.starts_with("https://") {
+        Some(tls::pin_certificate(&body.endpoint, body.fingerprint.as_deref()).await?)
+    } else {
+        None
+    };
     // Simple ID: use endpoint as ID if unique, otherwise suffix with number
     let mut id = body
         .name
@@ -190,6 +222,7 @@ async fn add_machine(
             endpoint: body.endpoint,
             name: body.name,
             token: None,
+            certificate,
         },
     );
     Ok(Json(AddMachineResp { id }))
@@ -199,11 +232,15 @@ async fn add_machine(
 struct ClaimBody {
     claim_password: Option<String>,
     claim_payload: Option<String>,
+    /// Operator recorded in the claim token, `installer-ui` if omitted
+    operator: Option<String>,
 }
 
 #[derive(Serialize, ToSchema)]
 struct ClaimResp {
     claim_token: String,
+    /// Unix timestamp the token expires at
+    expires_at: i64,
 }
 
 #[utoipa::path(
@@ -220,29 +257,36 @@ async fn claim_machine(
     Path(id): Path<String>,
     Json(body): Json<ClaimBody>,
 ) -> Result<impl IntoResponse, ApiError> {
-    let (endpoint, req) = {
+    let (machine, req) = {
         let map = state.machines.read().await;
         let m = map
             .get(&id)
             .ok_or_else(|| ApiError::NotFound(format!("machine '{}' not found", id)))?;
+        let operator = body
+            .operator
+            .clone()
+            .unwrap_or_else(|| "installer-ui".to_string());
         let req = if let Some(pw) = body.claim_password.clone() {
             ClaimRequest {
                 claim_secret: Some(claim_request::ClaimSecret::ClaimPassword(pw)),
+                operator,
+                ..Default::default()
             }
         } else if let Some(payload) = body.claim_payload.clone() {
             ClaimRequest {
                 claim_secret: Some(claim_request::ClaimSecret::ClaimPayload(payload)),
+                operator,
+                ..Default::default()
             }
         } else {
             return Err(ApiError::BadRequest(
                 "claim_password or claim_payload required".into(),
             ));
         };
-        (m.endpoint.clone(), req)
+        (m.clone(), req)
     };
 
-    let mut client = MachineServiceClient::connect(endpoint).await?;
-    client = client.accept_compressed(tonic::codec::CompressionEncoding::Zstd);
+    let mut client = connect(&machine).await?;
     let resp = client.claim(req).await?.into_inner();
 
     // Store token
@@ -255,6 +299,7 @@ async fn claim_machine(
 
     Ok(Json(ClaimResp {
         claim_token: resp.claim_token,
+        expires_at: resp.expires_at,
     }))
 }
 
@@ -289,18 +334,9 @@ async fn system_info_machine(
     use axum::http::header;
     use prost::Message;
 
-    let (endpoint, stored_token) = {
-        let map = state.machines.read().await;
-        let m = map
-            .get(&id)
-            .ok_or_else(|| ApiError::NotFound(format!("machine '{}' not found", id)))?;
-        (m.endpoint.clone(), m.token.clone())
-    };
-
-    let mut client = MachineServiceClient::connect(endpoint).await?;
-    client = client.accept_compressed(tonic::codec::CompressionEncoding::Zstd);
+    let (mut client, stored_token) = machine_client(&state, &id).await?;
 
-    let mut req = tonic::Request::new(SystemInfoRequest {});
+    let mut req = tonic::Request::new(SystemInfoRequest::default());
     if let Some(token) = q.token.or(stored_token) {
         let mv = tonic::metadata::MetadataValue::from_str(&token)
             .map_err(|e| ApiError::Other(e.to_string()))?;
@@ -365,18 +401,19 @@ async fn storage_machine(
     Path(id): Path<String>,
     Query(q): Query<StorageQuery>,
 ) -> Result<impl IntoResponse, ApiError> {
-    let (endpoint, stored_token) = {
-        let map = state.machines.read().await;
-        let m = map
-            .get(&id)
-            .ok_or_else(|| ApiError::NotFound(format!("machine '{}' not found", id)))?;
-        (m.endpoint.clone(), m.token.clone())
-    };
+    let (mut client, stored_token) = machine_client(&state, &id).await?;
 
-    let mut client = MachineServiceClient::connect(endpoint).await?;
-    client = client.accept_compressed(tonic::codec::CompressionEncoding::Zstd);
+    let include_parts = match q.include_partitions.as_deref() {
+        Some("1") | Some("true") | Some("yes") | Some("on") => true,
+        _ => false,
+    };
 
-    let mut req = tonic::Request::new(SystemInfoRequest {});
+    // Only collect what the storage view shows
+    let mut sections = vec![SystemInfoSection::Disks as i32];
+    if include_parts {
+        sections.push(SystemInfoSection::Partitions as i32);
+    }
+    let mut req = tonic::Request::new(SystemInfoRequest { sections });
     if let Some(token) = q.token.or(stored_token) {
         let mv = tonic::metadata::MetadataValue::from_str(&token)
             .map_err(|e| ApiError::Other(e.to_string()))?;
@@ -402,11 +439,6 @@ async fn storage_machine(
         })
         .collect();
 
-    let include_parts = match q.include_partitions.as_deref() {
-        Some("1") | Some("true") | Some("yes") | Some("on") => true,
-        _ => false,
-    };
-
     let partitions: Vec<PartitionDto> = if include_parts {
         resp.partitions
             .into_iter()
@@ -423,6 +455,277 @@ async fn storage_machine(
     Ok(Json(StorageDto { disks, partitions }))
 }
 
+/// Connect to a machine, returning the client and the token stored when it was claimed
+async fn machine_client(
+    state: &AppState,
+    id: &str,
+) -> Result<(MachineServiceClient<Channel>, Option<String>), ApiError> {
+    let machine = {
+        let map = state.machines.read().await;
+        map.get(id)
+            .cloned()
+            .ok_or_else(|| ApiError::NotFound(format!("machine '{}' not found", id)))?
+    };
+    let client = connect(&machine).await?;
+    Ok((client, machine.token))
+}
+
+/// Connect to a machine, over TLS for `https://` endpoints
+async fn connect(machine: &MachineEntry) -> Result<MachineServiceClient<Channel>, ApiError> {
+    let channel = tls::connect(&machine.endpoint, machine.certificate.as_deref()).await?;
+    Ok(MachineServiceClient::new(channel)
+        .accept_compressed(tonic::codec::CompressionEncoding::Zstd))
+}
+
+/// Build a gRPC request carrying the claim token if there is one
+fn authorized<T>(message: T, token: Option<String>) -> Result<tonic::Request<T>, ApiError> {
+    let mut req = tonic::Request::new(message);
+    if let Some(token) = token {
+        let mv = tonic::metadata::MetadataValue::from_str(&token)
+            .map_err(|e| ApiError::Other(e.to_string()))?;
+        req.metadata_mut().insert("authorization", mv);
+    }
+    Ok(req)
+}
+
+#[derive(Deserialize, Default, IntoParams, ToSchema)]
+struct TokenQuery {
+    /// Optional JWT claim token; if omitted, uses stored token if available
+    token: Option<String>,
+}
+
+#[derive(Deserialize, Default, ToSchema)]
+struct PowerBody {
+    /// Also while an install session is running
+    #[serde(default)]
+    force: bool,
+    /// Boot environment to activate before rebooting, ignored when powering off
+    boot_environment: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct PowerResp {
+    /// The machine goes down after this many seconds
+    delay_seconds: u32,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/machines/{id}/reboot",
+    params(
+        ("id" = String, Path, description = "Machine ID"),
+        TokenQuery
+    ),
+    request_body = PowerBody,
+    responses(
+        (status = 200, description = "Reboot scheduled", body = PowerResp),
+        (status = 409, description = "An install session is running")
+    )
+)]
+async fn reboot_machine(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(q): Query<TokenQuery>,
+    Json(body): Json<PowerBody>,
+) -> Result<impl IntoResponse, ApiError> {
+    let (mut client, stored_token) = machine_client(&state, &id).await?;
+    let req = authorized(
+        RebootRequest {
+            force: body.force,
+            boot_environment: body.boot_environment.unwrap_or_default(),
+        },
+        q.token.or(stored_token),
+    )?;
+    let resp = client.reboot(req).await?.into_inner();
+    Ok(Json(PowerResp {
+        delay_seconds: resp.delay_seconds,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/machines/{id}/poweroff",
+    params(
+        ("id" = String, Path, description = "Machine ID"),
+        TokenQuery
+    ),
+    request_body = PowerBody,
+    responses(
+        (status = 200, description = "Power off scheduled", body = PowerResp),
+        (status = 409, description = "An install session is running")
+    )
+)]
+async fn poweroff_machine(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(q): Query<TokenQuery>,
+    Json(body): Json<PowerBody>,
+) -> Result<impl IntoResponse, ApiError> {
+    let (mut client, stored_token) = machine_client(&state, &id).await?;
+    let req = authorized(
+        PowerOffRequest { force: body.force },
+        q.token.or(stored_token),
+    )?;
+    let resp = client.power_off(req).await?.into_inner();
+    Ok(Json(PowerResp {
+        delay_seconds: resp.delay_seconds,
+    }))
+}
+
+#[derive(Serialize, ToSchema)]
+struct BootEnvironmentDto {
+    name: String,
+    /// The running system booted from it
+    active: bool,
+    /// The next boot uses it
+    active_on_reboot: bool,
+    mountpoint: Option<String>,
+    space_bytes: u64,
+    /// Unix timestamp of its creation
+    created: i64,
+}
+
+impl From<machined_grpc::BootEnvironment> for BootEnvironmentDto {
+    fn from(be: machined_grpc::BootEnvironment) -> Self {
+        BootEnvironmentDto {
+            name: be.name,
+            active: be.active,
+            active_on_reboot: be.active_on_reboot,
+            mountpoint: if be.mountpoint.is_empty() {
+                None
+            } else {
+                Some(be.mountpoint)
+            },
+            space_bytes: be.space_bytes,
+            created: be.created,
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/machines/{id}/boot-environments",
+    params(
+        ("id" = String, Path, description = "Machine ID"),
+        TokenQuery
+    ),
+    responses((status = 200, description = "Boot environments", body = [BootEnvironmentDto]))
+)]
+async fn boot_environments_machine(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(q): Query<TokenQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let (mut client, stored_token) = machine_client(&state, &id).await?;
+    let req = authorized(ListBootEnvironmentsRequest {}, q.token.or(stored_token))?;
+    let resp = client.list_boot_environments(req).await?.into_inner();
+    let list: Vec<BootEnvironmentDto> = resp
+        .boot_environments
+        .into_iter()
+        .map(BootEnvironmentDto::from)
+        .collect();
+    Ok(Json(list))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/machines/{id}/boot-environments/{name}/activate",
+    params(
+        ("id" = String, Path, description = "Machine ID"),
+        ("name" = String, Path, description = "Boot environment name"),
+        TokenQuery
+    ),
+    responses(
+        (status = 200, description = "Activated boot environment", body = BootEnvironmentDto),
+        (status = 404, description = "No such boot environment")
+    )
+)]
+async fn activate_boot_environment_machine(
+    State(state): State<AppState>,
+    Path((id, name)): Path<(String, String)>,
+    Query(q): Query<TokenQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let (mut client, stored_token) = machine_client(&state, &id).await?;
+    let req = authorized(
+        ActivateBootEnvironmentRequest { name },
+        q.token.or(stored_token),
+    )?;
+    let be = client.activate_boot_environment(req).await?.into_inner();
+    Ok(Json(BootEnvironmentDto::from(be)))
+}
+
+#[derive(Serialize, ToSchema)]
+struct ExportPoolsResp {
+    /// Pools that were exported
+    pools: Vec<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/machines/{id}/export-pools",
+    params(
+        ("id" = String, Path, description = "Machine ID"),
+        TokenQuery
+    ),
+    request_body = PowerBody,
+    responses(
+        (status = 200, description = "Exported pools", body = ExportPoolsResp),
+        (status = 409, description = "An install session is running")
+    )
+)]
+async fn export_pools_machine(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(q): Query<TokenQuery>,
+    Json(body): Json<PowerBody>,
+) -> Result<impl IntoResponse, ApiError> {
+    let (mut client, stored_token) = machine_client(&state, &id).await?;
+    let req = authorized(
+        ExportPoolsRequest { force: body.force },
+        q.token.or(stored_token),
+    )?;
+    let resp = client.export_pools(req).await?.into_inner();
+    Ok(Json(ExportPoolsResp { pools: resp.pools }))
+}
+
+#[derive(Deserialize, Default, IntoParams, ToSchema)]
+struct LogQuery {
+    /// Optional JWT claim token; if omitted, uses stored token if available
+    token: Option<String>,
+    /// Number of lines from the end of the log, all lines machined keeps if omitted
+    lines: Option<u32>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct LogResp {
+    lines: Vec<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/machines/{id}/log",
+    params(
+        ("id" = String, Path, description = "Machine ID"),
+        LogQuery
+    ),
+    responses((status = 200, description = "machined log lines, oldest first", body = LogResp))
+)]
+async fn log_machine(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(q): Query<LogQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let (mut client, stored_token) = machine_client(&state, &id).await?;
+    let req = authorized(
+        GetLogRequest {
+            lines: q.lines.unwrap_or_default(),
+        },
+        q.token.or(stored_token),
+    )?;
+    let resp = client.get_log(req).await?.into_inner();
+    Ok(Json(LogResp { lines: resp.lines }))
+}
+
 #[derive(Deserialize, ToSchema)]
 struct GenerateConfigBody {
     filename: Option<String>,
@@ -484,26 +787,33 @@ async fn generate_pool_config(
     kdl.push_str(&format!("pool \"{}\" {{\n", name));
 
     for v in pool.vdevs {
-        let mut vtype = v.vtype.to_lowercase();
-        if vtype == "stripe" {
-            if v.devices.len() <= 1 {
-                warnings.push("type 'stripe' with <=1 device mapped to 'mirror'".to_string());
-                vtype = "mirror".to_string();
-            } else {
-                warnings.push("type 'stripe' mapped to 'raidz' for compatibility".to_string());
-                vtype = "raidz".to_string();
+        let vtype = v.vtype.to_lowercase();
+        let disks = v
+            .devices
+            .iter()
+            .map(|d| format!(" \"{}\"", escape_kdl(d)))
+            .collect::<String>();
+        match vtype.as_str() {
+            "stripe" | "mirror" | "raidz" | "raidz1" | "raidz2" | "raidz3" => {
+                kdl.push_str(&format!("  vdev \"{}\" {{\n", vtype));
+                if !disks.is_empty() {
+                    kdl.push_str(&format!("    disks{}\n", disks));
+                }
+                kdl.push_str("  }\n");
             }
-        }
-        kdl.push_str(&format!("  vdev \"{}\" {{\n", escape_kdl(&vtype)));
-        if !v.devices.is_empty() {
-            kdl.push_str("    disks");
-            for d in v.devices.into_iter() {
-                let d_esc = escape_kdl(&d);
-                kdl.push_str(&format!(" \"{}\"", d_esc));
+            // Auxiliary vdev classes, mirrored when given more than one device
+            "log" | "special" | "dedup" => {
+                let layout = if v.devices.len() > 1 { "mirror" } else { "stripe" };
+                kdl.push_str(&format!("  {} {{\n", vtype));
+                kdl.push_str(&format!("    vdev \"{}\" {{\n", layout));
+                kdl.push_str(&format!("      disks{}\n", disks));
+                kdl.push_str("    }\n  }\n");
             }
-            kdl.push('\n');
+            "cache" | "spare" => {
+                kdl.push_str(&format!("  {}{}\n", vtype, disks));
+            }
+            _ => warnings.push(format!("unknown vdev type '{}' skipped", v.vtype)),
         }
-        kdl.push_str("  }\n");
     }
 
     kdl.push_str("}\n");
@@ -522,7 +832,7 @@ async fn generate_config_route(
 ) -> Result<impl IntoResponse, ApiError> {
     let path = body
         .filename
-        .unwrap_or_else(|| "stdin.kdl".to_string());
+        .unwrap_or_else(|| "stdin".to_string());
     let mc = machineconfig::parse_config(&path, &body.content)
         .map_err(|e| ApiError::Other(e.to_string()))?;
 
@@ -553,6 +863,12 @@ async fn index_handler() -> impl IntoResponse {
         claim_machine,
         system_info_machine,
         storage_machine,
+        reboot_machine,
+        poweroff_machine,
+        boot_environments_machine,
+        activate_boot_environment_machine,
+        export_pools_machine,
+        log_machine,
         generate_config_route,
         generate_pool_config
     ),
@@ -569,6 +885,13 @@ async fn index_handler() -> impl IntoResponse {
             StorageDto,
             SystemInfoQuery,
             StorageQuery,
+            TokenQuery,
+            PowerBody,
+            PowerResp,
+            BootEnvironmentDto,
+            ExportPoolsResp,
+            LogQuery,
+            LogResp,
             GenerateConfigBody,
             GenerateConfigResp,
             VdevInput,
@@ -617,6 +940,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .route("/api/machines/{id}/claim", post(claim_machine))
         .route("/api/machines/{id}/system-info", get(system_info_machine))
         .route("/api/machines/{id}/storage", get(storage_machine))
+        .route("/api/machines/{id}/reboot", post(reboot_machine))
+        .route("/api/machines/{id}/poweroff", post(poweroff_machine))
+        .route("/api/machines/{id}/boot-environments", get(boot_environments_machine))
+        .route(
+            "/api/machines/{id}/boot-environments/{name}/activate",
+            post(activate_boot_environment_machine),
+        )
+        .route("/api/machines/{id}/export-pools", post(export_pools_machine))
+        .route("/api/machines/{id}/log", get(log_machine))
         // Backward-compatible single-machine endpoints
         .route("/api/claim", post(claim_route))
         .route("/api/system-info", get(system_info_route))
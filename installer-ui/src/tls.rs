@@ -0,0 +1,177 @@
+use std::sync::{Arc, Mutex};
+
+use base64::Engine;
+use sha2::{Digest, Sha256};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::client::danger::{
+    HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+};
+use tokio_rustls::rustls::crypto::{self, CryptoProvider};
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use tokio_rustls::rustls::{ClientConfig, DigitallySignedStruct, SignatureScheme};
+use tokio_rustls::TlsConnector;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint, Uri};
+
+use crate::ApiError;
+
+/// Name machined issues its self-signed certificate for
+const MACHINED_TLS_NAME: &str = "machined";
+
+/// Fetch the certificate of a machine and return it as PEM if it matches the fingerprint
+/// the operator read from the console of the machine.
+///
+/// Without a fingerprint the certificate is not trusted, the error shows its fingerprint
+/// so the operator can compare it.
+pub async fn pin_certificate(
+    endpoint: &str,
+    fingerprint: Option<&str>,
+) -> Result<String, ApiError> {
+    let cert = fetch_certificate(endpoint).await?;
+    let actual = format_fingerprint(&cert);
+    match fingerprint {
+        Some(expected) if normalize_fingerprint(expected) == normalize_fingerprint(&actual) => {
+            Ok(pem_encode(&cert))
+        }
+        Some(expected) => Err(ApiError::FingerprintMismatch {
+            expected: expected.to_string(),
+            actual,
+        }),
+        None => Err(ApiError::UntrustedCertificate(actual)),
+    }
+}
+
+/// Connect to a machine, trusting only its pinned certificate when there is one and over
+/// plaintext otherwise
+pub async fn connect(endpoint: &str, certificate: Option<&str>) -> Result<Channel, ApiError> {
+    let mut channel = Endpoint::from_shared(endpoint.to_string()).map_err(|e| {
+        ApiError::BadRequest(format!("{} is not a valid endpoint: {}", endpoint, e))
+    })?;
+    if let Some(certificate) = certificate {
+        channel = channel.tls_config(
+            ClientTlsConfig::new()
+                .ca_certificate(Certificate::from_pem(certificate))
+                .domain_name(MACHINED_TLS_NAME),
+        )?;
+    }
+    Ok(channel.connect().await?)
+}
+
+async fn fetch_certificate(endpoint: &str) -> Result<CertificateDer<'static>, ApiError> {
+    let uri: Uri = endpoint.parse().map_err(|e| {
+        ApiError::BadRequest(format!("{} is not a valid endpoint: {}", endpoint, e))
+    })?;
+    let host = uri
+        .host()
+        .map(|host| host.trim_start_matches('[').trim_end_matches(']'))
+        .ok_or_else(|| ApiError::BadRequest(format!("{} has no host", endpoint)))?;
+    let port = uri.port_u16().unwrap_or(50051);
+    let verifier = Arc::new(CaptureCertificate::new());
+    let config = ClientConfig::builder_with_provider(Arc::new(crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| ApiError::Tls(e.to_string()))?
+        .dangerous()
+        .with_custom_certificate_verifier(verifier.clone())
+        .with_no_client_auth();
+    let stream = TcpStream::connect((host, port))
+        .await
+        .map_err(|e| ApiError::Tls(format!("cannot connect to {}: {}", endpoint, e)))?;
+    // Servers requiring client certificates abort the handshake, the certificate has been
+    // seen by then
+    let _ = TlsConnector::from(Arc::new(config))
+        .connect(ServerName::try_from(MACHINED_TLS_NAME).unwrap(), stream)
+        .await;
+    let cert = verifier.seen.lock().unwrap().take();
+    cert.ok_or_else(|| ApiError::Tls(format!("{} did not present a certificate", endpoint)))
+}
+
+/// SHA-256 fingerprint in the format machined shows it
+fn format_fingerprint(cert: &CertificateDer) -> String {
+    Sha256::digest(cert.as_ref())
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+fn normalize_fingerprint(fingerprint: &str) -> String {
+    fingerprint
+        .chars()
+        .filter(|c| c.is_ascii_hexdigit())
+        .collect::<String>()
+        .to_ascii_uppercase()
+}
+
+fn pem_encode(cert: &CertificateDer) -> String {
+    let encoded = base64::prelude::BASE64_STANDARD.encode(cert.as_ref());
+    let mut pem = String::from("-----BEGIN CERTIFICATE-----\n");
+    for line in encoded.as_bytes().chunks(64) {
+        pem.push_str(std::str::from_utf8(line).unwrap_or_default());
+        pem.push('\n');
+    }
+    pem.push_str("-----END CERTIFICATE-----\n");
+    pem
+}
+
+/// Accepts any certificate and keeps it to compare its fingerprint afterwards
+#[derive(Debug)]
+struct CaptureCertificate {
+    provider: CryptoProvider,
+    seen: Mutex<Option<CertificateDer<'static>>>,
+}
+
+impl CaptureCertificate {
+    fn new() -> Self {
+        CaptureCertificate {
+            provider: crypto::ring::default_provider(),
+            seen: Mutex::new(None),
+        }
+    }
+}
+
+impl ServerCertVerifier for CaptureCertificate {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, tokio_rustls::rustls::Error> {
+        *self.seen.lock().unwrap() = Some(end_entity.clone().into_owned());
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
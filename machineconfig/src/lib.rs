@@ -1,8 +1,15 @@
 #[cfg(test)]
 mod tests {
 
+    use crate::{parse_config, ConfigFormat, ImageSource, MachineConfig, VolumeUsage};
     use miette::IntoDiagnostic;
     use std::fs;
+    use std::path::PathBuf;
+
+    fn sample() -> MachineConfig {
+        let sample_string = fs::read_to_string("sample.kdl").unwrap();
+        parse_config("sample.kdl", &sample_string).unwrap()
+    }
 
     #[test]
     fn it_works() -> miette::Result<()> {
@@ -12,28 +19,365 @@ mod tests {
         assert_eq!(cfg.sysconfig.nameservers.len(), 2);
         assert_eq!(cfg.sysconfig.nameservers[0], String::from("9.9.9.9"));
         assert_eq!(cfg.pools[0].name, "rpool");
-        assert_eq!(cfg.pools[0].options[0].name, "compression");
-        assert_eq!(cfg.pools[0].options[0].value, "zstd");
+        assert_eq!(cfg.pools[0].filesystem_options[0].name, "compression");
+        assert_eq!(cfg.pools[0].filesystem_options[0].value, "zstd");
+        assert_eq!(cfg.pools[0].volumes[0].usage, Some(VolumeUsage::Swap));
         Ok(())
     }
+
+    #[test]
+    fn builds_zpool_topology() {
+        let cfg = parse_config(
+            "topology.kdl",
+            r#"
+            pool "tank" {
+                vdev "raidz2" {
+                    disks "c1t0d0" "c1t1d0" "c1t2d0" "c1t3d0"
+                }
+                log {
+                    vdev "mirror" {
+                        disks "c2t0d0" "c2t1d0"
+                    }
+                }
+                special {
+                    vdev "mirror" {
+                        disks "c3t0d0" "c3t1d0"
+                    }
+                }
+                dedup {
+                    vdev "stripe" {
+                        disks "c4t0d0"
+                    }
+                }
+                cache "c5t0d0"
+                spare "c6t0d0" "c6t1d0"
+                options {
+                    ashift "12"
+                }
+                dataset "export" mountpoint="/export" {
+                    properties {
+                        atime "off"
+                    }
+                    dataset "home"
+                }
+            }
+            image "oci://aopc.cloud/openindiana/hipster:2024.12"
+            sysconfig {
+                hostname "storage01"
+            }
+            wipe-disks secure-erase=true
+            "#,
+        )
+        .unwrap();
+        assert!(cfg.wipe_disks.as_ref().unwrap().secure_erase);
+        let pool = &cfg.pools[0];
+        assert_eq!(
+            pool.vdev_args().join(" "),
+            "raidz2 c1t0d0 c1t1d0 c1t2d0 c1t3d0 log mirror c2t0d0 c2t1d0 \
+             special mirror c3t0d0 c3t1d0 dedup c4t0d0 cache c5t0d0 spare c6t0d0 c6t1d0"
+        );
+        assert_eq!(pool.disks().count(), 12);
+        let tree = pool
+            .dataset_tree()
+            .into_iter()
+            .map(|(name, dataset)| (name, dataset.create_properties()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tree,
+            vec![
+                (
+                    String::from("tank/export"),
+                    vec![
+                        (String::from("mountpoint"), String::from("/export")),
+                        (String::from("atime"), String::from("off")),
+                    ]
+                ),
+                (String::from("tank/export/home"), vec![]),
+            ]
+        );
+        let kdl = cfg.to_kdl();
+        assert!(kdl.contains("wipe-disks secure-erase=true\n"), "{}", kdl);
+        assert_eq!(parse_config("topology.kdl", &kdl).unwrap(), cfg);
+
+        let mut plain = cfg.clone();
+        plain.wipe_disks.as_mut().unwrap().secure_erase = false;
+        let kdl = plain.to_kdl();
+        assert!(
+            kdl.contains("wipe-disks\n") && !kdl.contains("secure-erase"),
+            "{}",
+            kdl
+        );
+        let reparsed = parse_config("topology.kdl", &kdl).unwrap();
+        assert!(!reparsed.wipe_disks.as_ref().unwrap().secure_erase);
+        assert_eq!(reparsed, plain);
+    }
+
+    #[test]
+    fn samples_in_every_format_are_equivalent() {
+        let expected = sample();
+        for path in ["sample.json", "sample.yaml", "sample.toml"] {
+            let cfg = crate::load_config(path).unwrap();
+            assert_eq!(cfg, expected, "{} differs from sample.kdl", path);
+        }
+    }
+
+    #[test]
+    fn round_trips_through_every_format() {
+        let expected = sample();
+        for format in [
+            ConfigFormat::Kdl,
+            ConfigFormat::Json,
+            ConfigFormat::Yaml,
+            ConfigFormat::Toml,
+        ] {
+            let content = expected.to_string_as(format).unwrap();
+            assert_eq!(ConfigFormat::detect(&content), format, "{}", content);
+            let cfg = parse_config("machined", &content).unwrap();
+            assert_eq!(cfg, expected, "{:?} round trip differs", format);
+        }
+    }
+
+    #[test]
+    fn generates_boot_environment_names() {
+        let now = chrono::DateTime::from_timestamp(1735732800, 0).unwrap();
+        let mut mc = MachineConfig::default();
+        mc.sysconfig.hostname = String::from("node01");
+        assert_eq!(mc.root_pool(), "rpool");
+        assert_eq!(
+            mc.boot_environment("openindiana-{date}:{time}", now)
+                .unwrap(),
+            "openindiana-2025-01-01:12:00"
+        );
+        mc.boot_environment_template = Some(String::from("mydistro-{hostname}-{timestamp}"));
+        assert_eq!(
+            mc.boot_environment("openindiana-{date}:{time}", now)
+                .unwrap(),
+            "mydistro-node01-1735732800"
+        );
+        mc.boot_environment_template = Some(String::from("mydistro-{release}"));
+        assert!(mc.boot_environment("", now).is_err());
+        mc.boot_environment_template = Some(String::from("my distro"));
+        assert!(mc.boot_environment("", now).is_err());
+        mc.boot_environment_name = Some(String::from("initial"));
+        assert_eq!(mc.boot_environment("", now).unwrap(), "initial");
+    }
+
+    #[test]
+    fn parses_image_sources() {
+        assert_eq!(
+            ImageSource::parse("oci://aopc.cloud/openindiana/hipster:2024.12").unwrap(),
+            ImageSource::Registry(String::from("aopc.cloud/openindiana/hipster:2024.12"))
+        );
+        assert_eq!(
+            ImageSource::parse("openindiana/hipster").unwrap(),
+            ImageSource::Registry(String::from("openindiana/hipster"))
+        );
+        assert_eq!(
+            ImageSource::parse("oci-layout:/usb/images/hipster:2024.12").unwrap(),
+            ImageSource::Layout {
+                path: PathBuf::from("/usb/images/hipster"),
+                reference: Some(String::from("2024.12")),
+            }
+        );
+        assert_eq!(
+            ImageSource::parse("file:///usb/images/hipster.v2").unwrap(),
+            ImageSource::Layout {
+                path: PathBuf::from("/usb/images/hipster.v2"),
+                reference: None,
+            }
+        );
+        assert_eq!(
+            ImageSource::parse("file:///usb/images/hipster@sha256:abcd").unwrap(),
+            ImageSource::Layout {
+                path: PathBuf::from("/usb/images/hipster"),
+                reference: Some(String::from("sha256:abcd")),
+            }
+        );
+        assert!(ImageSource::parse("oci-layout:images/hipster").is_err());
+        assert!(ImageSource::parse("oci-layout:/usb/images/hipster:").is_err());
+        assert!(ImageSource::parse("oci://").is_err());
+    }
+
+    #[test]
+    fn reads_postinstall_payloads() {
+        let postinstall = sample().postinstall;
+        assert_eq!(postinstall.files[0].mode().unwrap(), 0o644);
+        assert_eq!(
+            postinstall.files[0].payload().read().unwrap(),
+            b"Installed by machined\n"
+        );
+        assert_eq!(postinstall.smf_manifests[0].file_name(), "site-setup.xml");
+        assert_eq!(postinstall.scripts[0].interpreter(), "/bin/sh");
+
+        let mut file = postinstall.files[1].clone();
+        file.content = Some(String::from("ssh-ed25519 AAAA"));
+        assert!(!file.payload().is_valid());
+        file.mode = Some(String::from("rw-r--r--"));
+        assert!(file.mode().is_err());
+    }
+
+    #[test]
+    fn redacts_inline_content() {
+        let mc = sample();
+        let redacted = mc.redacted();
+        assert_eq!(
+            redacted.postinstall.files[0].content.as_deref(),
+            Some("<redacted, 22 bytes>")
+        );
+        assert_eq!(
+            redacted.postinstall.scripts[0].content.as_deref(),
+            Some("<redacted, 28 bytes>")
+        );
+        assert_eq!(redacted.postinstall.files[1], mc.postinstall.files[1]);
+        assert_eq!(redacted.pools, mc.pools);
+    }
+
+    #[test]
+    fn detects_formats() {
+        assert_eq!(
+            ConfigFormat::from_path("/usb/machined.yml"),
+            Some(ConfigFormat::Yaml)
+        );
+        assert_eq!(
+            ConfigFormat::from_path("machined.KDL"),
+            Some(ConfigFormat::Kdl)
+        );
+        assert_eq!(ConfigFormat::from_path("machined"), None);
+        assert_eq!(
+            ConfigFormat::detect("  {\"image\": \"x\"}"),
+            ConfigFormat::Json
+        );
+        assert_eq!(ConfigFormat::detect("---\nimage: x\n"), ConfigFormat::Yaml);
+        assert_eq!(
+            ConfigFormat::detect("# comment\nimage: x\n"),
+            ConfigFormat::Yaml
+        );
+        assert_eq!(ConfigFormat::detect("image = \"x\"\n"), ConfigFormat::Toml);
+        assert_eq!(
+            ConfigFormat::detect("[[pools]]\nname = \"rpool\"\n"),
+            ConfigFormat::Toml
+        );
+        assert_eq!(
+            ConfigFormat::detect("// comment\nimage \"x\"\n"),
+            ConfigFormat::Kdl
+        );
+    }
 }
 
+use chrono::{DateTime, Utc};
 use miette::Diagnostic;
-use std::fmt::Display;
-use sysconfig::SysConfig;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Write};
+use std::fs;
+use std::path::{Path, PathBuf};
+pub use sysconfig::config::{AddressKind, AddressObject, Interface, SysConfig};
 use thiserror::Error;
 
 #[derive(Error, Debug, Diagnostic)]
 pub enum Error {
     #[error(transparent)]
     Knus(#[from] knus::Error),
+    #[error("invalid JSON machine configuration: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("invalid YAML machine configuration: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("invalid TOML machine configuration: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("machine configuration can not be written as TOML: {0}")]
+    TomlSerialize(#[from] toml::ser::Error),
+    #[error("failed to read machine configuration {0}: {1}")]
+    Read(String, std::io::Error),
+    #[error("boot environment template {0} has an unclosed placeholder")]
+    InvalidBeTemplate(String),
+    #[error("boot environment template placeholder {{{0}}} is unknown")]
+    UnknownBePlaceholder(String),
+    #[error("boot environment name \"{0}\" is not valid")]
+    InvalidBeName(String),
+    #[error("image {0} is not a valid image reference")]
+    InvalidImage(String),
+    #[error("either source or content must be given")]
+    InvalidPayload,
+    #[error("file mode {0} is not an octal mode")]
+    InvalidFileMode(String),
+}
+
+/// The formats a machine configuration can be written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Kdl,
+    Json,
+    Yaml,
+    Toml,
 }
 
+impl ConfigFormat {
+    /// The format of a configuration file going by its extension
+    pub fn from_path(path: impl AsRef<Path>) -> Option<ConfigFormat> {
+        let ext = path.as_ref().extension()?.to_str()?.to_lowercase();
+        match ext.as_str() {
+            "kdl" => Some(ConfigFormat::Kdl),
+            "json" => Some(ConfigFormat::Json),
+            "yaml" | "yml" => Some(ConfigFormat::Yaml),
+            "toml" => Some(ConfigFormat::Toml),
+            _ => None,
+        }
+    }
+
+    /// Guess the format of a configuration from its first line that is not a comment.
+    ///
+    /// JSON starts with a brace, TOML with a table header or `key = value` and YAML with
+    /// a document marker or `key:`. Everything else is taken to be KDL.
+    pub fn detect(content: &str) -> ConfigFormat {
+        let first = content
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with("//"))
+            .unwrap_or_default();
+        let key_len = first
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+            .unwrap_or(first.len());
+        let after_key = first[key_len..].trim_start();
+        if first.starts_with('{') {
+            ConfigFormat::Json
+        } else if first.starts_with("---") || (key_len > 0 && first[key_len..].starts_with(':')) {
+            ConfigFormat::Yaml
+        } else if first.starts_with('[') || (key_len > 0 && after_key.starts_with('=')) {
+            ConfigFormat::Toml
+        } else {
+            ConfigFormat::Kdl
+        }
+    }
+}
+
+/// Parse a machine configuration, in the format its path names or else the detected one
 pub fn parse_config(path: &str, content: &str) -> Result<MachineConfig, Error> {
-    Ok(knus::parse(path, &content)?)
+    let format = ConfigFormat::from_path(path).unwrap_or_else(|| ConfigFormat::detect(content));
+    parse_config_as(format, path, content)
+}
+
+pub fn parse_config_as(
+    format: ConfigFormat,
+    path: &str,
+    content: &str,
+) -> Result<MachineConfig, Error> {
+    Ok(match format {
+        ConfigFormat::Kdl => knus::parse(path, content)?,
+        ConfigFormat::Json => serde_json::from_str(content)?,
+        ConfigFormat::Yaml => serde_yaml::from_str(content)?,
+        ConfigFormat::Toml => toml::from_str(content)?,
+    })
 }
 
-#[derive(Debug, knus::Decode, Default)]
+/// Read and parse a machine configuration file in any of the supported formats
+pub fn load_config(path: impl AsRef<Path>) -> Result<MachineConfig, Error> {
+    let path = path.as_ref();
+    let content =
+        fs::read_to_string(path).map_err(|e| Error::Read(path.display().to_string(), e))?;
+    parse_config(&path.to_string_lossy(), &content)
+}
+
+#[derive(Debug, Clone, knus::Decode, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct MachineConfig {
     #[knus(children(name = "pool"))]
     pub pools: Vec<Pool>,
@@ -41,26 +385,474 @@ pub struct MachineConfig {
     #[knus(child, unwrap(argument))]
     pub image: String,
 
+    /// Pool the boot environment is created in, [`DEFAULT_ROOT_POOL`] if not given
+    #[knus(child, unwrap(argument))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub root_pool: Option<String>,
+
     #[knus(child, unwrap(argument))]
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub boot_environment_name: Option<String>,
 
+    /// Template the boot environment name is generated from when no name is given
+    #[knus(child, unwrap(argument))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub boot_environment_template: Option<String>,
+
+    /// Properties of the boot environment dataset
+    #[knus(child, default, unwrap(children))]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub boot_environment_properties: Vec<PoolOption>,
+
     #[knus(child)]
     pub sysconfig: SysConfig,
+
+    /// Customisations of the installed system applied once the image is unpacked
+    #[knus(child, default)]
+    #[serde(skip_serializing_if = "PostInstall::is_empty")]
+    pub postinstall: PostInstall,
+
+    /// Leave pools and datasets of a failed installation in place for debugging
+    #[knus(child)]
+    pub skip_rollback: bool,
+
+    /// Clear old ZFS labels and partition tables from the disks of the pools before
+    /// creating them, for disks reused from other systems
+    #[knus(child)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wipe_disks: Option<WipeDisks>,
 }
 
-#[derive(Debug, knus::Decode, Default)]
+impl MachineConfig {
+    /// Name of the pool holding the boot environments
+    pub fn root_pool(&self) -> &str {
+        self.root_pool.as_deref().unwrap_or(DEFAULT_ROOT_POOL)
+    }
+
+    /// Name of the boot environment to install, the configured name or one generated from the
+    /// configured template or else `default_template`
+    pub fn boot_environment(
+        &self,
+        default_template: &str,
+        now: DateTime<Utc>,
+    ) -> Result<String, Error> {
+        if let Some(name) = &self.boot_environment_name {
+            return Ok(name.clone());
+        }
+        let template = self
+            .boot_environment_template
+            .as_deref()
+            .unwrap_or(default_template);
+        expand_be_template(template, &self.sysconfig.hostname, now)
+    }
+
+    /// Where the configured image is installed from
+    pub fn image_source(&self) -> Result<ImageSource, Error> {
+        ImageSource::parse(&self.image)
+    }
+
+    /// A copy of the configuration to keep in logs and install reports: the inline content of
+    /// post-install files, SMF manifests and scripts may hold keys or passwords and is replaced
+    /// by its size
+    pub fn redacted(&self) -> MachineConfig {
+        let redact = |content: &mut Option<String>| {
+            if let Some(content) = content {
+                *content = format!("<redacted, {} bytes>", content.len());
+            }
+        };
+        let mut mc = self.clone();
+        for file in &mut mc.postinstall.files {
+            redact(&mut file.content);
+        }
+        for manifest in &mut mc.postinstall.smf_manifests {
+            redact(&mut manifest.content);
+        }
+        for script in &mut mc.postinstall.scripts {
+            redact(&mut script.content);
+        }
+        mc
+    }
+
+    /// Write the configuration in the given format
+    pub fn to_string_as(&self, format: ConfigFormat) -> Result<String, Error> {
+        Ok(match format {
+            ConfigFormat::Kdl => self.to_kdl(),
+            ConfigFormat::Json => serde_json::to_string_pretty(self)?,
+            ConfigFormat::Yaml => serde_yaml::to_string(self)?,
+            ConfigFormat::Toml => toml::to_string(self)?,
+        })
+    }
+
+    /// Write the configuration as KDL in the layout of `sample.kdl`
+    pub fn to_kdl(&self) -> String {
+        let mut kdl = String::new();
+        for pool in &self.pools {
+            pool.write_kdl(&mut kdl);
+            kdl.push('\n');
+        }
+
+        let _ = writeln!(kdl, "image {}", kdl_string(&self.image));
+        if let Some(root_pool) = &self.root_pool {
+            let _ = writeln!(kdl, "root-pool {}", kdl_string(root_pool));
+        }
+        if let Some(be_name) = &self.boot_environment_name {
+            let _ = writeln!(kdl, "boot-environment-name {}", kdl_string(be_name));
+        }
+        if let Some(template) = &self.boot_environment_template {
+            let _ = writeln!(kdl, "boot-environment-template {}", kdl_string(template));
+        }
+        write_kdl_properties(
+            &mut kdl,
+            "",
+            "boot-environment-properties",
+            &self.boot_environment_properties,
+        );
+        if self.skip_rollback {
+            let _ = writeln!(kdl, "skip-rollback");
+        }
+        if let Some(wipe_disks) = &self.wipe_disks {
+            if wipe_disks.secure_erase {
+                let _ = writeln!(kdl, "wipe-disks secure-erase=true");
+            } else {
+                let _ = writeln!(kdl, "wipe-disks");
+            }
+        }
+
+        let sc = &self.sysconfig;
+        let _ = writeln!(kdl, "\nsysconfig {{");
+        let _ = writeln!(kdl, "    hostname {}", kdl_string(&sc.hostname));
+        for ns in &sc.nameservers {
+            let _ = writeln!(kdl, "    nameserver {}", kdl_string(ns));
+        }
+        for iface in &sc.interfaces {
+            let mut line = String::from("    interface");
+            if let Some(name) = &iface.name {
+                let _ = write!(line, " {}", kdl_string(name));
+            }
+            if let Some(selector) = &iface.selector {
+                let _ = write!(line, " selector={}", kdl_string(selector));
+            }
+            let _ = writeln!(kdl, "{} {{", line);
+            for addr in &iface.addresses {
+                let _ = write!(
+                    kdl,
+                    "        address name={} kind={}",
+                    kdl_string(&addr.name),
+                    kdl_string(&addr.kind.to_string().to_lowercase())
+                );
+                if let Some(address) = &addr.address {
+                    let _ = write!(kdl, " {}", kdl_string(address));
+                }
+                kdl.push('\n');
+            }
+            let _ = writeln!(kdl, "    }}");
+        }
+        let _ = writeln!(kdl, "}}");
+
+        if !self.postinstall.is_empty() {
+            kdl.push('\n');
+            self.postinstall.write_kdl(&mut kdl);
+        }
+        kdl
+    }
+}
+
+/// Pool the boot environments are created in unless the configuration names another
+pub const DEFAULT_ROOT_POOL: &str = "rpool";
+
+/// Generate a boot environment name from a template.
+///
+/// `{date}` is replaced by the date as `YYYY-MM-DD`, `{time}` by the time as `HH:MM`,
+/// `{timestamp}` by the seconds since the epoch and `{hostname}` by the configured hostname.
+pub fn expand_be_template(
+    template: &str,
+    hostname: &str,
+    now: DateTime<Utc>,
+) -> Result<String, Error> {
+    let mut name = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        name.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| Error::InvalidBeTemplate(template.to_string()))?;
+        match &rest[start + 1..start + end] {
+            "date" => name.push_str(&now.format("%Y-%m-%d").to_string()),
+            "time" => name.push_str(&now.format("%H:%M").to_string()),
+            "timestamp" => name.push_str(&now.timestamp().to_string()),
+            "hostname" => name.push_str(hostname),
+            placeholder => return Err(Error::UnknownBePlaceholder(placeholder.to_string())),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    name.push_str(rest);
+    if name.is_empty() || name.contains(|c: char| c == '/' || c == '@' || c.is_whitespace()) {
+        return Err(Error::InvalidBeName(name));
+    }
+    Ok(name)
+}
+
+/// Where the image to install comes from
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImageSource {
+    /// An image in an OCI registry as `[registry/]repository[:tag]`
+    Registry(String),
+    /// An OCI image layout directory, e.g. on the USB stick of an air-gapped installer.
+    ///
+    /// `reference` is the tag or digest of the image in the layout to install. Without one the
+    /// layout must hold a single image.
+    Layout {
+        path: PathBuf,
+        reference: Option<String>,
+    },
+}
+
+impl ImageSource {
+    /// Parse an image reference.
+    ///
+    /// `oci://<registry>/<repository>:<tag>` and references without a scheme name an image in a
+    /// registry. `oci-layout:<path>` and `file://<path>` name an image layout directory, a tag
+    /// can follow the path as `:<tag>` and a digest as `@<digest>`.
+    pub fn parse(image: &str) -> Result<ImageSource, Error> {
+        let invalid = || Error::InvalidImage(image.to_string());
+        let layout = image
+            .strip_prefix("oci-layout:")
+            .or_else(|| image.strip_prefix("file://"));
+        let Some(layout) = layout else {
+            let reference = image.strip_prefix("oci://").unwrap_or(image);
+            if reference.is_empty() {
+                return Err(invalid());
+            }
+            return Ok(ImageSource::Registry(reference.to_string()));
+        };
+
+        // Tags and digests can only follow the last component of the path
+        let name_start = layout.rfind('/').map(|idx| idx + 1).unwrap_or(0);
+        let name = &layout[name_start..];
+        let (path, reference) = match name.find('@').or_else(|| name.rfind(':')) {
+            Some(idx) => (
+                &layout[..name_start + idx],
+                Some(&layout[name_start + idx + 1..]),
+            ),
+            None => (layout, None),
+        };
+        if !path.starts_with('/') || reference.is_some_and(|reference| reference.is_empty()) {
+            return Err(invalid());
+        }
+        Ok(ImageSource::Layout {
+            path: PathBuf::from(path),
+            reference: reference.map(String::from),
+        })
+    }
+}
+
+/// Quote a string value for KDL
+fn kdl_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// A ZFS pool with its vdevs grouped by allocation class, its properties and datasets
+#[derive(Debug, Clone, knus::Decode, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Pool {
     #[knus(argument)]
     pub name: String,
 
+    /// Data vdevs
     #[knus(children(name = "vdev"))]
     pub vdevs: Vec<VDev>,
 
-    #[knus(child, unwrap(children))]
+    /// Separate intent log vdevs
+    #[knus(child, default, unwrap(children(name = "vdev")))]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub log: Vec<VDev>,
+
+    /// Vdevs of the special allocation class for metadata and small blocks
+    #[knus(child, default, unwrap(children(name = "vdev")))]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub special: Vec<VDev>,
+
+    /// Vdevs holding the deduplication tables
+    #[knus(child, default, unwrap(children(name = "vdev")))]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub dedup: Vec<VDev>,
+
+    /// L2ARC cache devices
+    #[knus(child, default, unwrap(arguments))]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub cache: Vec<String>,
+
+    /// Hot spares
+    #[knus(child, default, unwrap(arguments))]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub spare: Vec<String>,
+
+    /// Pool properties, `zpool create -o`
+    #[knus(child, default, unwrap(children))]
     pub options: Vec<PoolOption>,
+
+    /// Properties of the root dataset inherited by all datasets, `zpool create -O`
+    #[knus(child, default, unwrap(children))]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub filesystem_options: Vec<PoolOption>,
+
+    /// Datasets created below the root dataset of the pool
+    #[knus(children(name = "dataset"))]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub datasets: Vec<Dataset>,
+
+    /// Volumes created below the root dataset of the pool
+    #[knus(children(name = "volume"))]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub volumes: Vec<Volume>,
+}
+
+impl Pool {
+    /// The vdev groups of the pool with the `zpool` keyword introducing them
+    pub fn vdev_groups(&self) -> [(Option<&'static str>, &Vec<VDev>); 4] {
+        [
+            (None, &self.vdevs),
+            (Some("log"), &self.log),
+            (Some("special"), &self.special),
+            (Some("dedup"), &self.dedup),
+        ]
+    }
+
+    /// All lists of disks in the pool: those of every vdev, the cache and the spare devices
+    pub fn disk_lists(&self) -> impl Iterator<Item = &Vec<String>> {
+        self.vdevs
+            .iter()
+            .chain(self.log.iter())
+            .chain(self.special.iter())
+            .chain(self.dedup.iter())
+            .map(|vdev| &vdev.disks)
+            .chain([&self.cache, &self.spare])
+    }
+
+    pub fn disk_lists_mut(&mut self) -> impl Iterator<Item = &mut Vec<String>> {
+        self.vdevs
+            .iter_mut()
+            .chain(self.log.iter_mut())
+            .chain(self.special.iter_mut())
+            .chain(self.dedup.iter_mut())
+            .map(|vdev| &mut vdev.disks)
+            .chain([&mut self.cache, &mut self.spare])
+    }
+
+    /// All disks of the pool
+    pub fn disks(&self) -> impl Iterator<Item = &String> {
+        self.disk_lists().flatten()
+    }
+
+    /// The vdev specification of `zpool create` following the pool name
+    pub fn vdev_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        for (keyword, vdevs) in self.vdev_groups() {
+            if vdevs.is_empty() {
+                continue;
+            }
+            args.extend(keyword.map(String::from));
+            for vdev in vdevs {
+                args.extend(vdev.kind.zpool_keyword().map(String::from));
+                args.extend(vdev.disks.iter().cloned());
+            }
+        }
+        for (keyword, disks) in [("cache", &self.cache), ("spare", &self.spare)] {
+            if !disks.is_empty() {
+                args.push(keyword.to_string());
+                args.extend(disks.iter().cloned());
+            }
+        }
+        args
+    }
+
+    /// The datasets of the pool with their full names, parents before their children
+    pub fn dataset_tree(&self) -> Vec<(String, &Dataset)> {
+        let mut tree = Vec::new();
+        for dataset in &self.datasets {
+            dataset.collect(&self.name, &mut tree);
+        }
+        tree
+    }
+
+    fn write_kdl(&self, kdl: &mut String) {
+        let _ = writeln!(kdl, "pool {} {{", kdl_string(&self.name));
+        for vdev in &self.vdevs {
+            vdev.write_kdl(kdl, "    ");
+        }
+        for (name, vdevs) in [
+            ("log", &self.log),
+            ("special", &self.special),
+            ("dedup", &self.dedup),
+        ] {
+            if !vdevs.is_empty() {
+                let _ = writeln!(kdl, "    {} {{", name);
+                for vdev in vdevs {
+                    vdev.write_kdl(kdl, "        ");
+                }
+                let _ = writeln!(kdl, "    }}");
+            }
+        }
+        for (name, disks) in [("cache", &self.cache), ("spare", &self.spare)] {
+            if !disks.is_empty() {
+                let _ = writeln!(kdl, "    {} {}", name, kdl_strings(disks));
+            }
+        }
+        write_kdl_properties(kdl, "    ", "options", &self.options);
+        write_kdl_properties(kdl, "    ", "filesystem-options", &self.filesystem_options);
+        for dataset in &self.datasets {
+            dataset.write_kdl(kdl, "    ");
+        }
+        for volume in &self.volumes {
+            volume.write_kdl(kdl, "    ");
+        }
+        let _ = writeln!(kdl, "}}");
+    }
+}
+
+/// Write a block of properties as child nodes named after the properties
+fn write_kdl_properties(kdl: &mut String, indent: &str, name: &str, properties: &[PoolOption]) {
+    if properties.is_empty() {
+        return;
+    }
+    let _ = writeln!(kdl, "{}{} {{", indent, name);
+    for opt in properties {
+        let _ = writeln!(kdl, "{}    {} {}", indent, opt.name, kdl_string(&opt.value));
+    }
+    let _ = writeln!(kdl, "{}}}", indent);
+}
+
+fn kdl_strings(values: &[String]) -> String {
+    values
+        .iter()
+        .map(|value| kdl_string(value))
+        .collect::<Vec<_>>()
+        .join(" ")
 }
 
-#[derive(Debug, knus::Decode, Default)]
+/// How the disks of the pools are wiped before the pools are created
+#[derive(Debug, Clone, knus::Decode, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WipeDisks {
+    /// Also erase all data with the secure erase of the disks, which can take hours
+    #[knus(property, default)]
+    pub secure_erase: bool,
+}
+
+/// A property of a pool or dataset
+#[derive(Debug, Clone, knus::Decode, Default, PartialEq, Serialize, Deserialize)]
 pub struct PoolOption {
     #[knus(node_name)]
     pub name: String,
@@ -74,7 +866,8 @@ impl Display for PoolOption {
     }
 }
 
-#[derive(Debug, knus::Decode, Default)]
+#[derive(Debug, Clone, knus::Decode, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct VDev {
     #[knus(argument)]
     pub kind: VDevType,
@@ -83,17 +876,429 @@ pub struct VDev {
     pub disks: Vec<String>,
 }
 
-#[derive(knus::DecodeScalar, Debug, Default, strum::Display)]
+impl VDev {
+    fn write_kdl(&self, kdl: &mut String, indent: &str) {
+        let _ = writeln!(kdl, "{}vdev {} {{", indent, kdl_string(self.kind.name()));
+        let _ = writeln!(kdl, "{}    disks {}", indent, kdl_strings(&self.disks));
+        let _ = writeln!(kdl, "{}}}", indent);
+    }
+}
+
+/// Layout of the disks in a vdev
+#[derive(
+    knus::DecodeScalar, Debug, Clone, Default, PartialEq, strum::Display, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
 pub enum VDevType {
+    /// Disks used side by side without redundancy
+    Stripe,
     #[default]
     Mirror,
-    RaidZ,
-    RaidZ1,
-    RaidZ2,
-    RaidZ3,
-    Spare,
-    Log,
-    Debup,
-    Special,
-    Cache,
+    Raidz,
+    Raidz1,
+    Raidz2,
+    Raidz3,
 }
+
+impl VDevType {
+    /// Name of the vdev type in the configuration
+    pub fn name(&self) -> &'static str {
+        match self {
+            VDevType::Stripe => "stripe",
+            VDevType::Mirror => "mirror",
+            VDevType::Raidz => "raidz",
+            VDevType::Raidz1 => "raidz1",
+            VDevType::Raidz2 => "raidz2",
+            VDevType::Raidz3 => "raidz3",
+        }
+    }
+
+    /// Keyword of the vdev type in `zpool create`, striped disks are listed without one
+    pub fn zpool_keyword(&self) -> Option<&'static str> {
+        match self {
+            VDevType::Stripe => None,
+            kind => Some(kind.name()),
+        }
+    }
+
+    /// Number of disks the vdev type needs at least
+    pub fn minimum_disks(&self) -> usize {
+        match self {
+            VDevType::Stripe => 1,
+            VDevType::Mirror => 2,
+            VDevType::Raidz | VDevType::Raidz1 => 3,
+            VDevType::Raidz2 => 4,
+            VDevType::Raidz3 => 5,
+        }
+    }
+
+    pub fn is_raidz(&self) -> bool {
+        matches!(
+            self,
+            VDevType::Raidz | VDevType::Raidz1 | VDevType::Raidz2 | VDevType::Raidz3
+        )
+    }
+}
+
+/// A filesystem dataset, named relative to its parent
+#[derive(Debug, Clone, knus::Decode, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Dataset {
+    #[knus(argument)]
+    pub name: String,
+
+    #[knus(property)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mountpoint: Option<String>,
+
+    #[knus(child, default, unwrap(children))]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub properties: Vec<PoolOption>,
+
+    #[knus(children(name = "dataset"))]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub datasets: Vec<Dataset>,
+}
+
+impl Dataset {
+    /// Properties to create the dataset with, the mountpoint first
+    pub fn create_properties(&self) -> Vec<(String, String)> {
+        self.mountpoint
+            .iter()
+            .map(|mountpoint| (String::from("mountpoint"), mountpoint.clone()))
+            .chain(
+                self.properties
+                    .iter()
+                    .map(|opt| (opt.name.clone(), opt.value.clone())),
+            )
+            .collect()
+    }
+
+    fn collect<'a>(&'a self, parent: &str, tree: &mut Vec<(String, &'a Dataset)>) {
+        let name = format!("{}/{}", parent, self.name);
+        tree.push((name.clone(), self));
+        for child in &self.datasets {
+            child.collect(&name, tree);
+        }
+    }
+
+    fn write_kdl(&self, kdl: &mut String, indent: &str) {
+        let _ = write!(kdl, "{}dataset {}", indent, kdl_string(&self.name));
+        if let Some(mountpoint) = &self.mountpoint {
+            let _ = write!(kdl, " mountpoint={}", kdl_string(mountpoint));
+        }
+        if self.properties.is_empty() && self.datasets.is_empty() {
+            kdl.push('\n');
+            return;
+        }
+        kdl.push_str(" {\n");
+        let child_indent = format!("{}    ", indent);
+        write_kdl_properties(kdl, &child_indent, "properties", &self.properties);
+        for child in &self.datasets {
+            child.write_kdl(kdl, &child_indent);
+        }
+        let _ = writeln!(kdl, "{}}}", indent);
+    }
+}
+
+/// A ZFS volume, e.g. for swap or crash dumps
+#[derive(Debug, Clone, knus::Decode, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Volume {
+    #[knus(argument)]
+    pub name: String,
+
+    /// Size of the volume as `zfs create -V` takes it, e.g. `4G`
+    #[knus(property)]
+    pub size: String,
+
+    /// What the installed system uses the volume for
+    #[knus(property)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<VolumeUsage>,
+
+    #[knus(child, default, unwrap(children))]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub properties: Vec<PoolOption>,
+}
+
+impl Volume {
+    fn write_kdl(&self, kdl: &mut String, indent: &str) {
+        let _ = write!(
+            kdl,
+            "{}volume {} size={}",
+            indent,
+            kdl_string(&self.name),
+            kdl_string(&self.size)
+        );
+        if let Some(usage) = &self.usage {
+            let _ = write!(kdl, " usage={}", kdl_string(usage.name()));
+        }
+        if self.properties.is_empty() {
+            kdl.push('\n');
+            return;
+        }
+        kdl.push_str(" {\n");
+        write_kdl_properties(
+            kdl,
+            &format!("{}    ", indent),
+            "properties",
+            &self.properties,
+        );
+        let _ = writeln!(kdl, "{}}}", indent);
+    }
+}
+
+#[derive(
+    knus::DecodeScalar, Debug, Clone, Copy, PartialEq, strum::Display, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum VolumeUsage {
+    Swap,
+    Dump,
+}
+
+impl VolumeUsage {
+    pub fn name(&self) -> &'static str {
+        match self {
+            VolumeUsage::Swap => "swap",
+            VolumeUsage::Dump => "dump",
+        }
+    }
+}
+
+/// Customisations of the installed system, applied in the order of the fields after the image
+/// is unpacked and the system configuration is written
+#[derive(Debug, Clone, knus::Decode, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PostInstall {
+    /// Files written into the installed system
+    #[knus(children(name = "file"))]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<PostInstallFile>,
+
+    /// Packages installed with `pkg -R /a install`
+    #[knus(child, default, unwrap(arguments))]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub packages: Vec<String>,
+
+    /// SMF manifests imported when the installed system boots for the first time
+    #[knus(children(name = "smf-manifest"))]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub smf_manifests: Vec<SmfManifest>,
+
+    /// Scripts run chrooted into the installed system
+    #[knus(children(name = "script"))]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub scripts: Vec<Script>,
+}
+
+impl PostInstall {
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+            && self.packages.is_empty()
+            && self.smf_manifests.is_empty()
+            && self.scripts.is_empty()
+    }
+
+    fn write_kdl(&self, kdl: &mut String) {
+        let _ = writeln!(kdl, "postinstall {{");
+        for file in &self.files {
+            let _ = write!(kdl, "    file {}", kdl_string(&file.path));
+            for (name, value) in [
+                ("mode", &file.mode),
+                ("owner", &file.owner),
+                ("group", &file.group),
+            ] {
+                if let Some(value) = value {
+                    let _ = write!(kdl, " {}={}", name, kdl_string(value));
+                }
+            }
+            file.payload().write_kdl(kdl);
+        }
+        if !self.packages.is_empty() {
+            let _ = writeln!(kdl, "    packages {}", kdl_strings(&self.packages));
+        }
+        for manifest in &self.smf_manifests {
+            let _ = write!(kdl, "    smf-manifest {}", kdl_string(&manifest.name));
+            manifest.payload().write_kdl(kdl);
+        }
+        for script in &self.scripts {
+            let _ = write!(kdl, "    script {}", kdl_string(&script.name));
+            if let Some(interpreter) = &script.interpreter {
+                let _ = write!(kdl, " interpreter={}", kdl_string(interpreter));
+            }
+            script.payload().write_kdl(kdl);
+        }
+        let _ = writeln!(kdl, "}}");
+    }
+}
+
+/// Content of a file, a manifest or a script: given inline or read from a file on the
+/// installer, e.g. on the USB stick. Exactly one of both must be set.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Payload<'a> {
+    /// Path of a file on the installer to copy
+    pub source: Option<&'a str>,
+    /// Inline content
+    pub content: Option<&'a str>,
+}
+
+impl Payload<'_> {
+    /// Read the content from the installer or take the inline one
+    pub fn read(&self) -> Result<Vec<u8>, Error> {
+        match (self.source, self.content) {
+            (Some(source), None) => {
+                fs::read(source).map_err(|e| Error::Read(source.to_string(), e))
+            }
+            (None, Some(content)) => Ok(content.as_bytes().to_vec()),
+            _ => Err(Error::InvalidPayload),
+        }
+    }
+
+    /// Whether exactly one of source and content is set
+    pub fn is_valid(&self) -> bool {
+        self.source.is_some() != self.content.is_some()
+    }
+
+    fn write_kdl(&self, kdl: &mut String) {
+        for (name, value) in [("source", self.source), ("content", self.content)] {
+            if let Some(value) = value {
+                let _ = write!(kdl, " {}={}", name, kdl_string(value));
+            }
+        }
+        kdl.push('\n');
+    }
+}
+
+/// A file written into the installed system
+#[derive(Debug, Clone, knus::Decode, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PostInstallFile {
+    /// Absolute path of the file in the installed system
+    #[knus(argument)]
+    pub path: String,
+
+    /// Octal permissions, `0644` if not given
+    #[knus(property)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+
+    /// User name or id from the installed system owning the file, `root` if not given
+    #[knus(property)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+
+    /// Group name or id from the installed system owning the file, `root` if not given
+    #[knus(property)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+
+    /// Path of a file on the installer to copy
+    #[knus(property)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+
+    /// Inline content
+    #[knus(property)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+}
+
+impl PostInstallFile {
+    pub fn payload(&self) -> Payload<'_> {
+        payload(&self.source, &self.content)
+    }
+
+    /// The configured permissions
+    pub fn mode(&self) -> Result<u32, Error> {
+        match &self.mode {
+            Some(mode) => u32::from_str_radix(mode, 8)
+                .ok()
+                .filter(|mode| *mode <= 0o7777)
+                .ok_or_else(|| Error::InvalidFileMode(mode.clone())),
+            None => Ok(0o644),
+        }
+    }
+}
+
+/// An SMF manifest put into `/var/svc/manifest/site`, where `manifest-import` picks it up
+/// when the installed system boots for the first time
+#[derive(Debug, Clone, knus::Decode, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SmfManifest {
+    /// File name of the manifest, `.xml` is appended if missing
+    #[knus(argument)]
+    pub name: String,
+
+    /// Path of a file on the installer to copy
+    #[knus(property)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+
+    /// Inline content
+    #[knus(property)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+}
+
+impl SmfManifest {
+    pub fn payload(&self) -> Payload<'_> {
+        payload(&self.source, &self.content)
+    }
+
+    /// File name of the manifest in `/var/svc/manifest/site`
+    pub fn file_name(&self) -> String {
+        if self.name.ends_with(".xml") {
+            self.name.clone()
+        } else {
+            format!("{}.xml", self.name)
+        }
+    }
+}
+
+/// A script run chrooted into the installed system
+#[derive(Debug, Clone, knus::Decode, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Script {
+    /// Name of the script in progress messages
+    #[knus(argument)]
+    pub name: String,
+
+    /// Interpreter inside the installed system, [`DEFAULT_SCRIPT_INTERPRETER`] if not given
+    #[knus(property)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interpreter: Option<String>,
+
+    /// Path of a file on the installer to copy
+    #[knus(property)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+
+    /// Inline content
+    #[knus(property)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+}
+
+impl Script {
+    pub fn payload(&self) -> Payload<'_> {
+        payload(&self.source, &self.content)
+    }
+
+    pub fn interpreter(&self) -> &str {
+        self.interpreter
+            .as_deref()
+            .unwrap_or(DEFAULT_SCRIPT_INTERPRETER)
+    }
+}
+
+fn payload<'a>(source: &'a Option<String>, content: &'a Option<String>) -> Payload<'a> {
+    Payload {
+        source: source.as_deref(),
+        content: content.as_deref(),
+    }
+}
+
+/// Interpreter of scripts that do not name one
+pub const DEFAULT_SCRIPT_INTERPRETER: &str = "/bin/sh";
@@ -1,6 +1,42 @@
+/// Messages an install report is made of. They are written into the installed system as JSON,
+/// the other messages of the API have no business being serialized.
+const REPORT_MESSAGES: [&str; 20] = [
+    "InstallReport",
+    "SystemInfoResponse",
+    "DiskInfo",
+    "NetworkInterface",
+    "PartitionInfo",
+    "PciDevice",
+    "CpuTopology",
+    "FirmwareInfo",
+    "SmbiosInfo",
+    "BiosInfo",
+    "SystemInfo",
+    "BaseboardInfo",
+    "ChassisInfo",
+    "ProcessorInfo",
+    "MemoryArrayInfo",
+    "MemoryDeviceInfo",
+    "MemoryArrayMappedAddressInfo",
+    "SystemBootInfo",
+    "CacheInfo",
+    "SystemSlotInfo",
+];
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tonic_build::configure()
-        .build_server(true)
+    let mut config = tonic_build::configure().build_server(true);
+    for message in REPORT_MESSAGES {
+        config = config.type_attribute(
+            format!(".machined.{}", message),
+            "#[derive(serde::Serialize)]",
+        );
+    }
+    config
+        // Enum fields are plain numbers in prost, write their names instead
+        .field_attribute(
+            ".machined.FirmwareInfo.mode",
+            "#[serde(serialize_with = \"crate::report::serialize_firmware_mode\")]",
+        )
         .out_dir("src")
         .compile_protos(&["proto/machined.proto"], &["proto"])?;
     Ok(())
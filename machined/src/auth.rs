@@ -0,0 +1,426 @@
+use crate::error::TokenError;
+use crate::machined::TokenScope;
+use jwt_simple::prelude::*;
+use jwt_simple::JWTError;
+use std::fmt;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tonic::codegen::{http, Service};
+use tonic::metadata::MetadataMap;
+use tonic::server::NamedService;
+use tonic::service::Interceptor;
+use tonic::{Request, Status};
+
+/// How long a claim token is valid, a refresh extends it by the same time
+const TOKEN_LIFETIME_HOURS: u64 = 2;
+/// How long tokens can be refreshed after the machine has been claimed
+pub const MAX_CLAIM_AGE_HOURS: u64 = 24;
+
+/// Group of RPCs a claim token grants access to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// Read system information, install sessions and the blob cache, validate configurations
+    Info,
+    /// Start and cancel installs, prune the blob cache, export pools and wipe disks
+    Install,
+    /// Reboot or power off the machine and choose the boot environment it boots
+    Reboot,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 3] = [Scope::Info, Scope::Install, Scope::Reboot];
+
+    pub fn from_proto(scope: i32) -> Option<Scope> {
+        match TokenScope::try_from(scope).ok()? {
+            TokenScope::Info => Some(Scope::Info),
+            TokenScope::Install => Some(Scope::Install),
+            TokenScope::Reboot => Some(Scope::Reboot),
+            TokenScope::Unspecified => None,
+        }
+    }
+
+    pub fn to_proto(self) -> TokenScope {
+        match self {
+            Scope::Info => TokenScope::Info,
+            Scope::Install => TokenScope::Install,
+            Scope::Reboot => TokenScope::Reboot,
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scope::Info => write!(f, "info"),
+            Scope::Install => write!(f, "install"),
+            Scope::Reboot => write!(f, "reboot"),
+        }
+    }
+}
+
+/// Claims machined puts into the tokens it issues
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenClaims {
+    /// Who claimed the machine
+    pub operator: String,
+    pub scopes: Vec<Scope>,
+    /// Machine the token is valid for
+    pub machine: String,
+    /// Unix timestamp of the claim, refreshed tokens keep it
+    pub claimed_at: u64,
+}
+
+impl TokenClaims {
+    pub fn allows(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope)
+    }
+}
+
+/// A freshly signed claim token
+#[derive(Debug)]
+pub struct IssuedToken {
+    pub token: String,
+    /// Unix timestamp the token expires at
+    pub expires_at: u64,
+    pub claims: TokenClaims,
+}
+
+/// Issues and verifies the claim tokens of this machine
+pub struct TokenAuthority {
+    key: HS256Key,
+    machine: String,
+}
+
+impl fmt::Debug for TokenAuthority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TokenAuthority")
+            .field("machine", &self.machine)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TokenAuthority {
+    /// `machine` identifies this machine in the tokens, the SMBIOS UUID where there is one
+    pub fn new(key_bytes: &[u8], machine: String) -> Self {
+        TokenAuthority {
+            key: HS256Key::from_bytes(key_bytes),
+            machine,
+        }
+    }
+
+    pub fn issue(&self, operator: &str, scopes: Vec<Scope>) -> Result<IssuedToken, TokenError> {
+        self.sign(
+            TokenClaims {
+                operator: operator.to_string(),
+                scopes,
+                machine: self.machine.clone(),
+                claimed_at: Clock::now_since_epoch().as_secs(),
+            },
+            Duration::from_hours(TOKEN_LIFETIME_HOURS),
+        )
+    }
+
+    /// Issue a new token for the claim of a verified token.
+    ///
+    /// The new token never outlives the maximum claim age, after that the machine has to be
+    /// claimed again.
+    pub fn refresh(&self, claims: &TokenClaims) -> Result<IssuedToken, TokenError> {
+        let claim_ends = claims.claimed_at + MAX_CLAIM_AGE_HOURS * 3600;
+        let now = Clock::now_since_epoch().as_secs();
+        if now >= claim_ends {
+            return Err(TokenError::ClaimTooOld(MAX_CLAIM_AGE_HOURS));
+        }
+        let lifetime = (claim_ends - now).min(TOKEN_LIFETIME_HOURS * 3600);
+        self.sign(claims.clone(), Duration::from_secs(lifetime))
+    }
+
+    pub fn verify(&self, token: &str) -> Result<TokenClaims, TokenError> {
+        let claims = self
+            .key
+            .verify_token::<TokenClaims>(token, None)
+            .map_err(|e| match e.downcast_ref::<JWTError>() {
+                Some(JWTError::TokenHasExpired) => TokenError::Expired,
+                _ => TokenError::Invalid(e.to_string()),
+            })?;
+        if claims.custom.machine != self.machine {
+            return Err(TokenError::WrongMachine(claims.custom.machine));
+        }
+        Ok(claims.custom)
+    }
+
+    fn sign(&self, claims: TokenClaims, lifetime: Duration) -> Result<IssuedToken, TokenError> {
+        let jwt_claims = Claims::with_custom_claims(claims.clone(), lifetime);
+        let expires_at = jwt_claims
+            .expires_at
+            .map(|expires_at| expires_at.as_secs())
+            .unwrap_or_default();
+        let token = self
+            .key
+            .authenticate(jwt_claims)
+            .map_err(|e| TokenError::SigningFailed(e.to_string()))?;
+        Ok(IssuedToken {
+            token,
+            expires_at,
+            claims,
+        })
+    }
+}
+
+/// Scopes to grant for the scopes a claim requested, all allowed scopes when it requested none
+pub fn grant_scopes(requested: &[i32], allowed: &[Scope]) -> Result<Vec<Scope>, Status> {
+    if requested.is_empty() {
+        return Ok(allowed.to_vec());
+    }
+    let mut granted = Vec::new();
+    for scope in requested {
+        let scope = Scope::from_proto(*scope)
+            .ok_or_else(|| Status::invalid_argument(format!("unknown token scope {}", scope)))?;
+        if !allowed.contains(&scope) {
+            return Err(Status::permission_denied(format!(
+                "the claim secret does not grant the {} scope",
+                scope
+            )));
+        }
+        if !granted.contains(&scope) {
+            granted.push(scope);
+        }
+    }
+    Ok(granted)
+}
+
+/// What a caller needs to call an RPC
+#[derive(Debug, PartialEq)]
+enum Access {
+    Public,
+    /// Any valid token of this machine
+    Token,
+    Scope(Scope),
+}
+
+/// Access rules of the RPCs of the machine service, RPCs not listed here are denied
+fn rpc_access(method: &str) -> Option<Access> {
+    match method {
+        "Claim" => Some(Access::Public),
+        "RefreshToken" => Some(Access::Token),
+        "GetSystemInfo"
+        | "ListInstallSessions"
+        | "AttachInstallSession"
+        | "ValidateConfig"
+        | "GetBlobCache"
+        | "ListBootEnvironments"
+        | "GetLog"
+        | "GetInstallReport" => Some(Access::Scope(Scope::Info)),
+        "Install" | "CancelInstallSession" | "PruneBlobCache" | "ExportPools" | "PrepareDisks" => {
+            Some(Access::Scope(Scope::Install))
+        }
+        "Reboot" | "PowerOff" | "ActivateBootEnvironment" => Some(Access::Scope(Scope::Reboot)),
+        _ => None,
+    }
+}
+
+/// Name of the RPC a request calls, see [`WithRpcMethod`]
+#[derive(Debug, Clone)]
+pub struct RpcMethod(pub String);
+
+/// Records the RPC each request calls in its extensions.
+///
+/// tonic interceptors only see the metadata of a request, not its path, so the service is
+/// wrapped in this to let [`Authorizer`] know which RPC it is checking.
+#[derive(Debug, Clone)]
+pub struct WithRpcMethod<S>(pub S);
+
+impl<S, B> Service<http::Request<B>> for WithRpcMethod<S>
+where
+    S: Service<http::Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: http::Request<B>) -> Self::Future {
+        let method = request
+            .uri()
+            .path()
+            .rsplit('/')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        request.extensions_mut().insert(RpcMethod(method));
+        self.0.call(request)
+    }
+}
+
+impl<S: NamedService> NamedService for WithRpcMethod<S> {
+    const NAME: &'static str = S::NAME;
+}
+
+/// Interceptor checking the claim token of every request against the RPC it calls.
+///
+/// The claims of accepted tokens are added to the extensions of the request.
+#[derive(Debug, Clone)]
+pub struct Authorizer {
+    tokens: Arc<TokenAuthority>,
+}
+
+impl Authorizer {
+    pub fn new(tokens: Arc<TokenAuthority>) -> Self {
+        Authorizer { tokens }
+    }
+}
+
+impl Interceptor for Authorizer {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let method = request
+            .extensions()
+            .get::<RpcMethod>()
+            .map(|method| method.0.clone())
+            .unwrap_or_default();
+        let access = rpc_access(&method)
+            .ok_or_else(|| Status::permission_denied(format!("{} is not allowed", method)))?;
+        if access == Access::Public {
+            return Ok(request);
+        }
+
+        let token = bearer_token(request.metadata())?;
+        let claims = self
+            .tokens
+            .verify(token)
+            .map_err(|e| Status::unauthenticated(e.to_string()))?;
+        if let Access::Scope(scope) = access {
+            if !claims.allows(scope) {
+                return Err(Status::permission_denied(format!(
+                    "the token of {} does not grant the {} scope needed for {}",
+                    claims.operator, scope, method
+                )));
+            }
+        }
+        request.extensions_mut().insert(claims);
+        Ok(request)
+    }
+}
+
+/// The token of the authorization header, with or without a `Bearer` prefix
+fn bearer_token(metadata: &MetadataMap) -> Result<&str, Status> {
+    let header = metadata
+        .get("authorization")
+        .ok_or_else(|| Status::unauthenticated("missing authorization header"))?
+        .to_str()
+        .map_err(|_| Status::unauthenticated("authorization header is not valid ASCII"))?;
+    Ok(header.strip_prefix("Bearer ").unwrap_or(header).trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::Code;
+
+    const MACHINE: &str = "4c4c4544-0046-3510-8051-b7c04f565931";
+
+    fn authority() -> Arc<TokenAuthority> {
+        Arc::new(TokenAuthority::new(
+            &HS256Key::generate().to_bytes(),
+            MACHINE.to_string(),
+        ))
+    }
+
+    fn call(
+        authority: &Arc<TokenAuthority>,
+        method: &str,
+        token: Option<&str>,
+    ) -> Result<Request<()>, Status> {
+        let mut request = Request::new(());
+        request
+            .extensions_mut()
+            .insert(RpcMethod(method.to_string()));
+        if let Some(token) = token {
+            request.metadata_mut().insert(
+                "authorization",
+                format!("Bearer {}", token).parse().unwrap(),
+            );
+        }
+        Authorizer::new(authority.clone()).call(request)
+    }
+
+    #[test]
+    fn enforces_token_scopes() {
+        let authority = authority();
+        let token = authority.issue("alice", vec![Scope::Info]).unwrap().token;
+
+        let request = call(&authority, "GetSystemInfo", Some(&token)).unwrap();
+        let claims = request.extensions().get::<TokenClaims>().unwrap();
+        assert_eq!(claims.operator, "alice");
+        assert_eq!(claims.machine, MACHINE);
+
+        let denied = call(&authority, "Install", Some(&token)).unwrap_err();
+        assert_eq!(denied.code(), Code::PermissionDenied);
+        let denied = call(&authority, "Reboot", Some(&token)).unwrap_err();
+        assert_eq!(denied.code(), Code::PermissionDenied);
+        let denied = call(&authority, "Shutdown", Some(&token)).unwrap_err();
+        assert_eq!(denied.code(), Code::PermissionDenied);
+        let missing = call(&authority, "GetSystemInfo", None).unwrap_err();
+        assert_eq!(missing.code(), Code::Unauthenticated);
+        assert!(call(&authority, "Claim", None).is_ok());
+    }
+
+    #[test]
+    fn rejects_foreign_tokens() {
+        let authority = authority();
+        let own = authority.issue("alice", Scope::ALL.to_vec()).unwrap();
+        let foreign = self::authority()
+            .issue("alice", Scope::ALL.to_vec())
+            .unwrap();
+        assert!(authority.verify(&own.token).is_ok());
+        assert!(matches!(
+            authority.verify(&foreign.token),
+            Err(TokenError::Invalid(_))
+        ));
+
+        let key = HS256Key::generate().to_bytes();
+        let here = TokenAuthority::new(&key, MACHINE.to_string());
+        let elsewhere = TokenAuthority::new(&key, "another-machine".to_string());
+        let token = elsewhere.issue("alice", vec![Scope::Info]).unwrap();
+        assert!(matches!(
+            here.verify(&token.token),
+            Err(TokenError::WrongMachine(_))
+        ));
+    }
+
+    #[test]
+    fn refreshes_keep_the_claim() {
+        let authority = authority();
+        let issued = authority.issue("alice", vec![Scope::Install]).unwrap();
+        let claims = authority.verify(&issued.token).unwrap();
+        let refreshed = authority.refresh(&claims).unwrap();
+        assert_eq!(authority.verify(&refreshed.token).unwrap(), claims);
+        assert!(refreshed.expires_at >= issued.expires_at);
+
+        let stale = TokenClaims {
+            claimed_at: claims.claimed_at - MAX_CLAIM_AGE_HOURS * 3600,
+            ..claims
+        };
+        assert!(matches!(
+            authority.refresh(&stale),
+            Err(TokenError::ClaimTooOld(_))
+        ));
+    }
+
+    #[test]
+    fn grants_requested_scopes() {
+        let info = TokenScope::Info as i32;
+        let reboot = TokenScope::Reboot as i32;
+        assert_eq!(grant_scopes(&[], &Scope::ALL).unwrap(), Scope::ALL.to_vec());
+        assert_eq!(
+            grant_scopes(&[info, info], &Scope::ALL).unwrap(),
+            vec![Scope::Info]
+        );
+        let denied = grant_scopes(&[reboot], &[Scope::Info]).unwrap_err();
+        assert_eq!(denied.code(), Code::PermissionDenied);
+        let unknown = grant_scopes(&[TokenScope::Unspecified as i32], &Scope::ALL).unwrap_err();
+        assert_eq!(unknown.code(), Code::InvalidArgument);
+    }
+}
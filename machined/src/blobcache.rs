@@ -0,0 +1,421 @@
+use crate::error::InstallationError;
+#[cfg(any(target_os = "illumos", target_os = "linux"))]
+#[cfg(not(feature = "mock"))]
+use ociclient::digest::OciDigest;
+#[cfg(any(target_os = "illumos", target_os = "linux"))]
+#[cfg(not(feature = "mock"))]
+use sha2::{Digest, Sha256, Sha512};
+use std::fs;
+#[cfg(any(target_os = "illumos", target_os = "linux"))]
+#[cfg(not(feature = "mock"))]
+use std::fs::{File, OpenOptions};
+use std::io;
+#[cfg(any(target_os = "illumos", target_os = "linux"))]
+#[cfg(not(feature = "mock"))]
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+#[cfg(any(target_os = "illumos", target_os = "linux"))]
+#[cfg(not(feature = "mock"))]
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+/// Suffix of blobs that are still being written to the cache
+const PARTIAL_SUFFIX: &str = ".partial";
+
+/// Computes the digest of a blob with the algorithm the expected digest names
+#[cfg(any(target_os = "illumos", target_os = "linux"))]
+#[cfg(not(feature = "mock"))]
+pub enum BlobHasher {
+    Sha256(Sha256),
+    Sha512(Sha512),
+}
+
+#[cfg(any(target_os = "illumos", target_os = "linux"))]
+#[cfg(not(feature = "mock"))]
+impl BlobHasher {
+    pub fn for_digest(digest: &OciDigest) -> Result<Self, InstallationError> {
+        match digest.algorithm() {
+            "sha256" => Ok(BlobHasher::Sha256(Sha256::new())),
+            "sha512" => Ok(BlobHasher::Sha512(Sha512::new())),
+            algorithm => Err(InstallationError::UnsupportedDigestAlgorithm(
+                algorithm.to_string(),
+            )),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            BlobHasher::Sha256(hasher) => hasher.update(data),
+            BlobHasher::Sha512(hasher) => hasher.update(data),
+        }
+    }
+
+    /// Compare the digest of everything hashed so far with the expected one
+    pub fn verify(self, expected: &OciDigest) -> Result<(), InstallationError> {
+        let hex = match self {
+            BlobHasher::Sha256(hasher) => format!("{:x}", hasher.finalize()),
+            BlobHasher::Sha512(hasher) => format!("{:x}", hasher.finalize()),
+        };
+        if hex.eq_ignore_ascii_case(expected.hex()) {
+            Ok(())
+        } else {
+            Err(InstallationError::DigestMismatch {
+                blob: expected.as_str(),
+                digest: format!("{}:{}", expected.algorithm(), hex),
+            })
+        }
+    }
+}
+
+/// Hashes everything read through it, to check a blob while it is being extracted
+#[cfg(any(target_os = "illumos", target_os = "linux"))]
+#[cfg(not(feature = "mock"))]
+pub struct HashingReader<R> {
+    inner: R,
+    hasher: BlobHasher,
+}
+
+#[cfg(any(target_os = "illumos", target_os = "linux"))]
+#[cfg(not(feature = "mock"))]
+impl<R: Read> HashingReader<R> {
+    pub fn new(inner: R, hasher: BlobHasher) -> Self {
+        HashingReader { inner, hasher }
+    }
+
+    /// Read the rest of the blob and compare its digest with the expected one
+    pub fn verify(mut self, expected: &OciDigest) -> Result<(), InstallationError> {
+        io::copy(&mut self, &mut io::sink())?;
+        self.hasher.verify(expected)
+    }
+}
+
+#[cfg(any(target_os = "illumos", target_os = "linux"))]
+#[cfg(not(feature = "mock"))]
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// A blob kept in the cache
+#[derive(Debug, Clone, PartialEq)]
+pub struct CachedBlob {
+    pub digest: String,
+    pub size: u64,
+    /// Unix timestamp the blob was last installed from or added to the cache
+    pub last_used: i64,
+    path: PathBuf,
+}
+
+/// Blobs removed from the cache by a prune
+#[derive(Debug, Default, PartialEq)]
+pub struct PruneResult {
+    pub removed: u32,
+    pub freed_bytes: u64,
+}
+
+/// Image blobs kept on disk by their digest, so a reinstall does not download them again.
+///
+/// Blobs live at `<dir>/<algorithm>/<hex>` and are only added once their digest was verified.
+/// The least recently used blobs are removed when the cache grows beyond `max_size` bytes, a
+/// `max_size` of 0 disables caching.
+#[derive(Debug, Clone)]
+pub struct BlobCache {
+    dir: PathBuf,
+    max_size: u64,
+}
+
+impl BlobCache {
+    pub fn new(dir: impl Into<PathBuf>, max_size: u64) -> Self {
+        BlobCache {
+            dir: dir.into(),
+            max_size,
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn max_size(&self) -> u64 {
+        self.max_size
+    }
+
+    /// All blobs in the cache, least recently used first
+    pub fn blobs(&self) -> Result<Vec<CachedBlob>, InstallationError> {
+        let mut blobs = Vec::new();
+        let algorithms = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(blobs),
+            Err(e) => return Err(e.into()),
+        };
+        for algorithm in algorithms {
+            let algorithm = algorithm?;
+            if !algorithm.file_type()?.is_dir() {
+                continue;
+            }
+            for blob in fs::read_dir(algorithm.path())? {
+                let blob = blob?;
+                let name = blob.file_name().to_string_lossy().to_string();
+                if name.ends_with(PARTIAL_SUFFIX) {
+                    continue;
+                }
+                let meta = blob.metadata()?;
+                let last_used = meta
+                    .modified()?
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs() as i64)
+                    .unwrap_or_default();
+                blobs.push(CachedBlob {
+                    digest: format!("{}:{}", algorithm.file_name().to_string_lossy(), name),
+                    size: meta.len(),
+                    last_used,
+                    path: blob.path(),
+                });
+            }
+        }
+        blobs.sort_by(|a, b| {
+            a.last_used
+                .cmp(&b.last_used)
+                .then_with(|| a.digest.cmp(&b.digest))
+        });
+        Ok(blobs)
+    }
+
+    /// Remove the least recently used blobs until at most `keep_bytes` are left
+    pub fn prune(&self, keep_bytes: u64) -> Result<PruneResult, InstallationError> {
+        let blobs = self.blobs()?;
+        let mut size = blobs.iter().map(|blob| blob.size).sum::<u64>();
+        let mut result = PruneResult::default();
+        for blob in blobs {
+            if size <= keep_bytes {
+                break;
+            }
+            fs::remove_file(&blob.path)?;
+            size -= blob.size;
+            result.removed += 1;
+            result.freed_bytes += blob.size;
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(any(target_os = "illumos", target_os = "linux"))]
+#[cfg(not(feature = "mock"))]
+impl BlobCache {
+    fn blob_path(&self, digest: &OciDigest) -> PathBuf {
+        self.dir.join(digest.algorithm()).join(digest.hex())
+    }
+
+    /// Open a cached blob after checking it still matches its digest, blobs that do not are
+    /// removed from the cache
+    pub fn lookup(&self, digest: &OciDigest) -> Result<Option<File>, InstallationError> {
+        let path = self.blob_path(digest);
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let reader = HashingReader::new(file, BlobHasher::for_digest(digest)?);
+        if reader.verify(digest).is_err() {
+            fs::remove_file(&path)?;
+            return Ok(None);
+        }
+        touch(&path)?;
+        Ok(Some(File::open(&path)?))
+    }
+
+    /// Start adding a blob to the cache, `None` if caching is disabled or the blob is larger
+    /// than the whole cache
+    pub fn writer(
+        &self,
+        digest: &OciDigest,
+        size: Option<u64>,
+    ) -> Result<Option<CacheWriter>, InstallationError> {
+        if self.max_size == 0 || size.is_some_and(|size| size > self.max_size) {
+            return Ok(None);
+        }
+        let path = self.blob_path(digest);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let partial = partial_path(&path);
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&partial)?;
+        Ok(Some(CacheWriter {
+            cache: self.clone(),
+            file: Some(file),
+            partial,
+            path,
+            written: 0,
+        }))
+    }
+}
+
+/// A blob being added to the cache. It only becomes visible once committed, dropping the
+/// writer throws away what was written so far.
+#[cfg(any(target_os = "illumos", target_os = "linux"))]
+#[cfg(not(feature = "mock"))]
+pub struct CacheWriter {
+    cache: BlobCache,
+    file: Option<File>,
+    partial: PathBuf,
+    path: PathBuf,
+    written: u64,
+}
+
+#[cfg(any(target_os = "illumos", target_os = "linux"))]
+#[cfg(not(feature = "mock"))]
+impl CacheWriter {
+    pub fn write_all(&mut self, data: &[u8]) -> Result<(), InstallationError> {
+        if let Some(file) = &mut self.file {
+            file.write_all(data)?;
+            self.written += data.len() as u64;
+        }
+        Ok(())
+    }
+
+    /// Add the blob to the cache after its digest was verified, then shrink the cache back to
+    /// its size limit
+    pub fn commit(mut self) -> Result<(), InstallationError> {
+        if let Some(file) = self.file.take() {
+            file.sync_all()?;
+        }
+        if self.written > self.cache.max_size {
+            return Ok(());
+        }
+        fs::rename(&self.partial, &self.path)?;
+        touch(&self.path)?;
+        self.cache.prune(self.cache.max_size)?;
+        Ok(())
+    }
+}
+
+#[cfg(any(target_os = "illumos", target_os = "linux"))]
+#[cfg(not(feature = "mock"))]
+impl Drop for CacheWriter {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.partial);
+    }
+}
+
+#[cfg(any(target_os = "illumos", target_os = "linux"))]
+#[cfg(not(feature = "mock"))]
+fn partial_path(path: &Path) -> PathBuf {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(PARTIAL_SUFFIX);
+    PathBuf::from(partial)
+}
+
+/// Mark a blob as just used, the modification time orders blobs for pruning
+#[cfg(any(target_os = "illumos", target_os = "linux"))]
+#[cfg(not(feature = "mock"))]
+fn touch(path: &Path) -> io::Result<()> {
+    OpenOptions::new()
+        .append(true)
+        .open(path)?
+        .set_modified(SystemTime::now())
+}
+
+#[cfg(test)]
+#[cfg(any(target_os = "illumos", target_os = "linux"))]
+#[cfg(not(feature = "mock"))]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    const HELLO_SHA256: &str =
+        "sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+    const HELLO_SHA512: &str = "sha512:9b71d224bd62f3785d96d46ad3ea3d73319bfbc2890caadae2dff72519673ca72323c3d99ba5c11d7c7acc6e14b8c5da0c4663475c2e5c3adef46f73bcdec043";
+
+    fn scratch_cache(name: &str, max_size: u64) -> BlobCache {
+        let dir = std::env::temp_dir().join(format!(
+            "machined-blobcache-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        BlobCache::new(dir, max_size)
+    }
+
+    fn add(cache: &BlobCache, digest: &OciDigest, data: &[u8]) {
+        let mut writer = cache
+            .writer(digest, Some(data.len() as u64))
+            .unwrap()
+            .unwrap();
+        writer.write_all(data).unwrap();
+        writer.commit().unwrap();
+    }
+
+    #[test]
+    fn verifies_sha256_and_sha512_digests() {
+        for digest in [HELLO_SHA256, HELLO_SHA512] {
+            let digest = OciDigest::from_str(digest).unwrap();
+            let mut hasher = BlobHasher::for_digest(&digest).unwrap();
+            hasher.update(b"hello");
+            assert!(hasher.verify(&digest).is_ok());
+
+            let mut hasher = BlobHasher::for_digest(&digest).unwrap();
+            hasher.update(b"hello!");
+            assert!(matches!(
+                hasher.verify(&digest),
+                Err(InstallationError::DigestMismatch { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn drops_corrupted_blobs() {
+        let cache = scratch_cache("corrupt", 1024);
+        let digest = OciDigest::from_str(HELLO_SHA256).unwrap();
+        add(&cache, &digest, b"hello");
+        assert!(cache.lookup(&digest).unwrap().is_some());
+
+        fs::write(cache.blob_path(&digest), b"jello").unwrap();
+        assert!(cache.lookup(&digest).unwrap().is_none());
+        assert!(cache.blobs().unwrap().is_empty());
+        fs::remove_dir_all(cache.dir()).unwrap();
+    }
+
+    #[test]
+    fn prunes_least_recently_used_blobs() {
+        let cache = scratch_cache("prune", 12);
+        let digests = ["a", "b", "c"]
+            .map(|c| OciDigest::from_str(&format!("sha256:{}", c.repeat(64))).unwrap());
+        for (idx, digest) in digests.iter().enumerate() {
+            add(&cache, digest, b"12345");
+            let used = UNIX_EPOCH + Duration::from_secs(1_000 + idx as u64);
+            File::options()
+                .append(true)
+                .open(cache.blob_path(digest))
+                .unwrap()
+                .set_modified(used)
+                .unwrap();
+        }
+        // adding the third blob already evicted the first one
+        let cached = cache
+            .blobs()
+            .unwrap()
+            .into_iter()
+            .map(|blob| blob.digest)
+            .collect::<Vec<_>>();
+        assert_eq!(cached, vec![digests[1].as_str(), digests[2].as_str()]);
+
+        assert_eq!(
+            cache.prune(5).unwrap(),
+            PruneResult {
+                removed: 1,
+                freed_bytes: 5
+            }
+        );
+        assert_eq!(cache.blobs().unwrap().len(), 1);
+        assert!(cache.writer(&digests[0], Some(13)).unwrap().is_none());
+        fs::remove_dir_all(cache.dir()).unwrap();
+    }
+}
@@ -0,0 +1,610 @@
+use crate::claim;
+use crate::config::{MachinedConfig, WireguardConfig};
+use crate::error::CallHomeError;
+use crate::machined::command_service_client::CommandServiceClient;
+use crate::machined::{
+    MachineAssignment, RegisterMachineRequest, SystemInfoRequest, WaitForAssignmentRequest,
+};
+use crate::session::InstallSessions;
+use crate::{platform, validate};
+use jwt_simple::prelude::*;
+use machineconfig::MachineConfig;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tonic::codec::CompressionEncoding;
+use tonic::transport::{Channel, ClientTlsConfig, Uri};
+use tracing::{info, warn};
+
+/// Longest wait between attempts to reach the command server
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// How often to check whether an operator started an install while waiting for an assignment
+const SESSION_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Claims of the signature of an assignment besides the machine UUID as subject, binding it to
+/// the configuration and session it was made for
+#[derive(Debug, Serialize, Deserialize)]
+struct AssignmentClaims {
+    /// SHA-256 of the assigned machine configuration as hex
+    config_sha256: String,
+    #[serde(default)]
+    session_name: String,
+}
+
+/// How this machine introduces itself to the command server
+pub struct Registration {
+    pub machine_uuid: String,
+    pub tls_fingerprint: Option<String>,
+}
+
+/// Register with the command server and install the first valid configuration it assigns.
+///
+/// Runs until an install session has been started, connection failures are retried with
+/// an increasing delay so machines booted before the command server find it eventually.
+/// Only assignments signed with the key of the command server are installed, and none once
+/// an operator started an install over the API.
+pub async fn call_home(
+    config: Arc<MachinedConfig>,
+    sessions: Arc<InstallSessions>,
+    registration: Registration,
+) {
+    let Some(server) = config
+        .server
+        .as_ref()
+        .filter(|server| !server.url.is_empty())
+    else {
+        return;
+    };
+    let public_key = match check_server_url(&server.url, config.wireguard.as_ref())
+        .and_then(|_| Ok(claim::decode_public_key(&server.public_key)?))
+    {
+        Ok(public_key) => public_key,
+        Err(e) => {
+            warn!("not calling home to {}: {}", server.url, e);
+            return;
+        }
+    };
+    let mut delay = Duration::from_secs(1);
+    loop {
+        let registered = tokio::select! {
+            biased;
+            session = started_session(&sessions) => {
+                info!("install session {} has been started, no longer calling home", session);
+                return;
+            }
+            registered = async {
+                match registration_request(&config, &registration) {
+                    Ok(request) => register_and_wait(&server.url, request).await,
+                    Err(e) => Err(e),
+                }
+            } => registered,
+        };
+        match registered {
+            Ok(Some(assignment)) => {
+                let started =
+                    verify_assignment(&assignment, &public_key, &registration.machine_uuid)
+                        .and_then(|_| start_install(assignment, &server.url, &config, &sessions));
+                match started {
+                    Ok(session) => {
+                        info!(
+                            "installing the assigned configuration in session {}",
+                            session
+                        );
+                        return;
+                    }
+                    Err(e @ CallHomeError::SessionExists(_)) => {
+                        warn!("not installing assigned configuration: {}", e);
+                        return;
+                    }
+                    Err(e) => warn!("not installing assigned configuration: {}", e),
+                }
+            }
+            Ok(None) => info!("command server closed the assignment stream, waiting again"),
+            Err(e) => warn!("calling home to {} failed: {}", server.url, e),
+        }
+        tokio::select! {
+            biased;
+            session = started_session(&sessions) => {
+                info!("install session {} has been started, no longer calling home", session);
+                return;
+            }
+            _ = tokio::time::sleep(delay) => {}
+        }
+        delay = (delay * 2).min(MAX_RETRY_DELAY);
+    }
+}
+
+/// Name of the latest install session once there is one, whoever started it
+async fn started_session(sessions: &InstallSessions) -> String {
+    loop {
+        if let Some(session) = sessions.list().pop() {
+            return session.name;
+        }
+        tokio::time::sleep(SESSION_POLL_INTERVAL).await;
+    }
+}
+
+/// What the command server learns about this machine when it registers
+fn registration_request(
+    config: &MachinedConfig,
+    registration: &Registration,
+) -> Result<RegisterMachineRequest, CallHomeError> {
+    Ok(RegisterMachineRequest {
+        machine_uuid: registration.machine_uuid.clone(),
+        addresses: reachable_addresses(),
+        port: config
+            .listen
+            .parse::<SocketAddr>()
+            .map(|addr| addr.port() as u32)
+            .unwrap_or_default(),
+        tls_fingerprint: registration.tls_fingerprint.clone().unwrap_or_default(),
+        system_info: Some(crate::sysinfo::get_system_info(
+            &SystemInfoRequest::default(),
+        )?),
+    })
+}
+
+async fn register_and_wait(
+    url: &str,
+    request: RegisterMachineRequest,
+) -> Result<Option<MachineAssignment>, CallHomeError> {
+    let mut client = connect(url).await?;
+    let machine_uuid = request.machine_uuid.clone();
+    info!(
+        "registering with command server {} as {}",
+        url, machine_uuid
+    );
+    client.register_machine(request).await?;
+    wait_for_assignment(&mut client, &machine_uuid).await
+}
+
+async fn connect(url: &str) -> Result<CommandServiceClient<Channel>, CallHomeError> {
+    let mut endpoint =
+        Channel::from_shared(url.to_string()).map_err(|_| CallHomeError::InvalidUrl(url.into()))?;
+    if url.starts_with("https://") {
+        endpoint = endpoint.tls_config(ClientTlsConfig::new().with_native_roots())?;
+    }
+    Ok(CommandServiceClient::new(endpoint.connect().await?)
+        .send_compressed(CompressionEncoding::Zstd)
+        .accept_compressed(CompressionEncoding::Zstd))
+}
+
+/// Wait for the first assignment, `None` if the command server ended the stream without one
+async fn wait_for_assignment(
+    client: &mut CommandServiceClient<Channel>,
+    machine_uuid: &str,
+) -> Result<Option<MachineAssignment>, CallHomeError> {
+    let mut assignments = client
+        .wait_for_assignment(WaitForAssignmentRequest {
+            machine_uuid: machine_uuid.to_string(),
+        })
+        .await?
+        .into_inner();
+    Ok(assignments.message().await?)
+}
+
+/// Command servers are trusted with installing the machine, so plaintext is only accepted for
+/// addresses routed through the WireGuard tunnel
+fn check_server_url(url: &str, wireguard: Option<&WireguardConfig>) -> Result<(), CallHomeError> {
+    let uri = url
+        .parse::<Uri>()
+        .map_err(|_| CallHomeError::InvalidUrl(url.into()))?;
+    match uri.scheme_str() {
+        Some("https") => return Ok(()),
+        Some("http") => {}
+        _ => return Err(CallHomeError::InvalidUrl(url.into())),
+    }
+    let in_tunnel = uri
+        .host()
+        .and_then(|host| host.trim_matches(['[', ']']).parse::<IpAddr>().ok())
+        .zip(wireguard)
+        .is_some_and(|(ip, wireguard)| {
+            wireguard
+                .peers
+                .iter()
+                .any(|network| in_network(&ip, network))
+        });
+    if in_tunnel {
+        Ok(())
+    } else {
+        Err(CallHomeError::PlaintextOutsideTunnel(url.into()))
+    }
+}
+
+/// Whether `ip` is part of `network`, given as address and prefix length like `10.100.0.0/24`
+fn in_network(ip: &IpAddr, network: &str) -> bool {
+    let Some((address, prefix)) = network.split_once('/') else {
+        return false;
+    };
+    let Ok(prefix) = prefix.parse::<u32>() else {
+        return false;
+    };
+    match (ip, address.parse::<IpAddr>()) {
+        (IpAddr::V4(ip), Ok(IpAddr::V4(network))) if prefix <= 32 => {
+            let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+            u32::from(*ip) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(ip), Ok(IpAddr::V6(network))) if prefix <= 128 => {
+            let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+            u128::from(*ip) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+/// Verify that the command server signed an assignment for this machine, the configuration and
+/// the session name it carries. Like claim payloads, signatures without an expiry are refused.
+fn verify_assignment(
+    assignment: &MachineAssignment,
+    public_key: &Ed25519PublicKey,
+    machine_uuid: &str,
+) -> Result<(), CallHomeError> {
+    if assignment.signature.is_empty() {
+        return Err(CallHomeError::UnsignedAssignment);
+    }
+    let claims = public_key
+        .verify_token::<AssignmentClaims>(&assignment.signature, None)
+        .map_err(|e| CallHomeError::InvalidSignature(e.to_string()))?;
+    if claims.expires_at.is_none() {
+        return Err(CallHomeError::InvalidSignature(
+            "it does not carry an expiry".into(),
+        ));
+    }
+    match &claims.subject {
+        Some(subject) if subject.eq_ignore_ascii_case(machine_uuid) => {}
+        Some(subject) => {
+            return Err(CallHomeError::InvalidSignature(format!(
+                "it is for machine {} not this one",
+                subject
+            )))
+        }
+        None => {
+            return Err(CallHomeError::InvalidSignature(
+                "it does not name a machine".into(),
+            ))
+        }
+    }
+    let digest = format!("{:x}", Sha256::digest(assignment.machineconfig.as_bytes()));
+    if !claims.custom.config_sha256.eq_ignore_ascii_case(&digest)
+        || claims.custom.session_name != assignment.session_name
+    {
+        return Err(CallHomeError::InvalidSignature(
+            "it was made for another configuration".into(),
+        ));
+    }
+    Ok(())
+}
+
+/// Validate an assigned configuration like the Install RPC does and start a session for it,
+/// recording the command server at `url` as its operator
+fn start_install(
+    assignment: MachineAssignment,
+    url: &str,
+    config: &Arc<MachinedConfig>,
+    sessions: &InstallSessions,
+) -> Result<String, CallHomeError> {
+    if let Some(session) = sessions.list().pop() {
+        return Err(CallHomeError::SessionExists(session.name));
+    }
+    let mc: MachineConfig =
+        machineconfig::parse_config("assigned_config", &assignment.machineconfig)
+            .map_err(|e| CallHomeError::InvalidAssignment(e.to_string()))?;
+    let hardware = platform::discover_hardware()?;
+    let report = validate::validate_config(&mc, hardware.as_ref());
+    if !report.valid {
+        return Err(CallHomeError::InvalidAssignment(
+            report
+                .issues
+                .iter()
+                .map(|i| format!("{}: {}", i.subject, i.message))
+                .collect::<Vec<_>>()
+                .join("; "),
+        ));
+    }
+    let session = sessions.start(assignment.session_name, url, mc, config.clone())?;
+    Ok(session.info().name)
+}
+
+/// Addresses of this machine the command server can reach it at, without loopback and
+/// link-local addresses
+pub fn reachable_addresses() -> Vec<String> {
+    let Ok(addrs) = nix::ifaddrs::getifaddrs() else {
+        return Vec::new();
+    };
+    unique_reachable(addrs.filter_map(|ifaddr| {
+        let address = ifaddr.address?;
+        if let Some(v4) = address.as_sockaddr_in() {
+            Some(IpAddr::V4(v4.ip()))
+        } else {
+            address.as_sockaddr_in6().map(|v6| IpAddr::V6(v6.ip()))
+        }
+    }))
+}
+
+/// Reachable addresses without duplicates, which interfaces with several address families
+/// list more than once and not necessarily next to each other
+fn unique_reachable(ips: impl Iterator<Item = IpAddr>) -> Vec<String> {
+    ips.filter(is_reachable)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .map(|ip| ip.to_string())
+        .collect()
+}
+
+fn is_reachable(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => !v4.is_loopback() && !v4.is_link_local() && !v4.is_unspecified(),
+        IpAddr::V6(v6) => {
+            !v6.is_loopback() && !v6.is_unspecified() && (v6.segments()[0] & 0xffc0) != 0xfe80
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machined::command_service_server::{CommandService, CommandServiceServer};
+    use crate::machined::{RegisterMachineResponse, SystemInfoResponse};
+    use std::sync::Mutex;
+    use tokio::sync::mpsc;
+    use tonic::codegen::tokio_stream::wrappers::ReceiverStream;
+    use tonic::transport::server::TcpIncoming;
+    use tonic::transport::Server;
+    use tonic::{Request, Response, Status};
+
+    /// Stand-in command server that records registrations and assigns a fixed configuration
+    #[derive(Default)]
+    struct StandInServer {
+        registrations: Arc<Mutex<Vec<RegisterMachineRequest>>>,
+        assignment: Option<MachineAssignment>,
+    }
+
+    #[tonic::async_trait]
+    impl CommandService for StandInServer {
+        async fn register_machine(
+            &self,
+            request: Request<RegisterMachineRequest>,
+        ) -> Result<Response<RegisterMachineResponse>, Status> {
+            self.registrations
+                .lock()
+                .unwrap()
+                .push(request.into_inner());
+            Ok(Response::new(RegisterMachineResponse {}))
+        }
+
+        type WaitForAssignmentStream = ReceiverStream<Result<MachineAssignment, Status>>;
+
+        async fn wait_for_assignment(
+            &self,
+            request: Request<WaitForAssignmentRequest>,
+        ) -> Result<Response<Self::WaitForAssignmentStream>, Status> {
+            let registered = self
+                .registrations
+                .lock()
+                .unwrap()
+                .iter()
+                .any(|r| r.machine_uuid == request.get_ref().machine_uuid);
+            if !registered {
+                return Err(Status::not_found("machine is not registered"));
+            }
+            let (tx, rx) = mpsc::channel(1);
+            if let Some(assignment) = self.assignment.clone() {
+                tx.send(Ok(assignment)).await.unwrap();
+            }
+            Ok(Response::new(ReceiverStream::new(rx)))
+        }
+    }
+
+    async fn serve(server: StandInServer) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+        tokio::spawn(
+            Server::builder()
+                .add_service(
+                    CommandServiceServer::new(server)
+                        .accept_compressed(CompressionEncoding::Zstd)
+                        .send_compressed(CompressionEncoding::Zstd),
+                )
+                .serve_with_incoming(incoming),
+        );
+        url
+    }
+
+    #[tokio::test]
+    async fn receives_assignment_after_registering() {
+        let registrations = Arc::new(Mutex::new(Vec::new()));
+        let url = serve(StandInServer {
+            registrations: registrations.clone(),
+            assignment: Some(MachineAssignment {
+                machineconfig: "image \"oci://aopc.cloud/openindiana/hipster:2024.12\"".into(),
+                session_name: "zero-touch".into(),
+                ..Default::default()
+            }),
+        })
+        .await;
+        let request = RegisterMachineRequest {
+            machine_uuid: "4c4c4544-0046-3510-8051-b7c04f565931".into(),
+            addresses: vec!["192.168.1.100".into()],
+            port: 50051,
+            tls_fingerprint: "3A:1F".into(),
+            system_info: Some(SystemInfoResponse::default()),
+        };
+
+        let assignment = register_and_wait(&url, request.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(assignment.session_name, "zero-touch");
+        let registrations = registrations.lock().unwrap();
+        assert_eq!(*registrations, vec![request]);
+    }
+
+    #[tokio::test]
+    async fn rejects_unregistered_machines() {
+        let url = serve(StandInServer::default()).await;
+        let mut client = connect(&url).await.unwrap();
+        assert!(matches!(
+            wait_for_assignment(&mut client, "unknown").await,
+            Err(CallHomeError::Status(status)) if status.code() == tonic::Code::NotFound
+        ));
+    }
+
+    fn signed_assignment(key_pair: &Ed25519KeyPair, machine_uuid: &str) -> MachineAssignment {
+        let machineconfig = "image \"oci://aopc.cloud/openindiana/hipster:2024.12\"";
+        let claims = AssignmentClaims {
+            config_sha256: format!("{:x}", Sha256::digest(machineconfig.as_bytes())),
+            session_name: "zero-touch".into(),
+        };
+        let signature = key_pair
+            .sign(
+                Claims::with_custom_claims(claims, jwt_simple::prelude::Duration::from_mins(10))
+                    .with_subject(machine_uuid),
+            )
+            .unwrap();
+        MachineAssignment {
+            machineconfig: machineconfig.into(),
+            session_name: "zero-touch".into(),
+            signature,
+        }
+    }
+
+    #[test]
+    fn installs_only_signed_assignments() {
+        let machine_uuid = "4c4c4544-0046-3510-8051-b7c04f565931";
+        let key_pair = Ed25519KeyPair::generate();
+        let public_key = key_pair.public_key();
+        let assignment = signed_assignment(&key_pair, machine_uuid);
+        assert!(verify_assignment(&assignment, &public_key, machine_uuid).is_ok());
+
+        let unsigned = MachineAssignment {
+            signature: String::new(),
+            ..assignment.clone()
+        };
+        assert!(matches!(
+            verify_assignment(&unsigned, &public_key, machine_uuid),
+            Err(CallHomeError::UnsignedAssignment)
+        ));
+        let other_machine = signed_assignment(&key_pair, "00000000-0000-0000-0000-000000000000");
+        let other_key = signed_assignment(&Ed25519KeyPair::generate(), machine_uuid);
+        let other_config = MachineAssignment {
+            machineconfig: "image \"oci://evil.example.com/rootkit:latest\"".into(),
+            ..assignment.clone()
+        };
+        let other_session = MachineAssignment {
+            session_name: "other".into(),
+            ..assignment
+        };
+        for assignment in [other_machine, other_key, other_config, other_session] {
+            assert!(matches!(
+                verify_assignment(&assignment, &public_key, machine_uuid),
+                Err(CallHomeError::InvalidSignature(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn stops_calling_home_once_a_session_exists() {
+        let dir = std::env::temp_dir().join(format!("machined-callhome-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let info = crate::machined::InstallSession {
+            id: "0b6c3a1e-5f0d-4a43-9d59-4c1f2f6d1a10".into(),
+            name: "by-hand".into(),
+            started_at: 1_700_000_000,
+            ..Default::default()
+        };
+        std::fs::write(
+            dir.join(format!("{}.session", info.id)),
+            prost::Message::encode_to_vec(&info),
+        )
+        .unwrap();
+        let sessions = Arc::new(InstallSessions::open(&dir, 20).unwrap());
+
+        let machine_uuid = "4c4c4544-0046-3510-8051-b7c04f565931";
+        let key_pair = Ed25519KeyPair::generate();
+        let registrations = Arc::new(Mutex::new(Vec::new()));
+        let url = serve(StandInServer {
+            registrations: registrations.clone(),
+            assignment: Some(signed_assignment(&key_pair, machine_uuid)),
+        })
+        .await;
+        let config = Arc::new(MachinedConfig {
+            server: Some(crate::config::CommandServer {
+                url: url.replace("http://", "https://"),
+                public_key: key_pair.public_key().to_pem(),
+            }),
+            ..Default::default()
+        });
+        let registration = Registration {
+            machine_uuid: machine_uuid.into(),
+            tls_fingerprint: None,
+        };
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            call_home(config.clone(), sessions.clone(), registration),
+        )
+        .await
+        .unwrap();
+        assert!(registrations.lock().unwrap().is_empty());
+
+        // An assignment arriving after an operator started an install is not installed either
+        let assignment = signed_assignment(&key_pair, machine_uuid);
+        assert!(matches!(
+            start_install(assignment, &url, &config, &sessions),
+            Err(CallHomeError::SessionExists(name)) if name == "by-hand"
+        ));
+        assert_eq!(sessions.list().len(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn plaintext_only_inside_the_tunnel() {
+        let wireguard = WireguardConfig {
+            peers: vec!["10.100.0.0/24".into(), "fd00:100::/64".into()],
+            ..Default::default()
+        };
+        assert!(check_server_url("https://cmd.example.com:50052", None).is_ok());
+        assert!(check_server_url("http://10.100.0.1:50052", Some(&wireguard)).is_ok());
+        assert!(check_server_url("http://[fd00:100::1]:50052", Some(&wireguard)).is_ok());
+        for url in [
+            "http://10.100.1.1:50052",
+            "http://cmd.example.com:50052",
+            "http://[fd00:200::1]:50052",
+        ] {
+            assert!(matches!(
+                check_server_url(url, Some(&wireguard)),
+                Err(CallHomeError::PlaintextOutsideTunnel(_))
+            ));
+        }
+        assert!(check_server_url("http://10.100.0.1:50052", None).is_err());
+        assert!(check_server_url("ftp://10.100.0.1", Some(&wireguard)).is_err());
+    }
+
+    #[test]
+    fn skips_unreachable_addresses() {
+        assert!(is_reachable(&"192.168.1.10".parse().unwrap()));
+        assert!(is_reachable(&"2001:db8::10".parse().unwrap()));
+        assert!(!is_reachable(&"127.0.0.1".parse().unwrap()));
+        assert!(!is_reachable(&"169.254.3.4".parse().unwrap()));
+        assert!(!is_reachable(&"fe80::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn lists_each_address_once() {
+        let ips = [
+            "192.168.1.10",
+            "2001:db8::10",
+            "127.0.0.1",
+            "192.168.1.10",
+            "2001:db8::10",
+        ];
+        assert_eq!(
+            unique_reachable(ips.iter().map(|ip| ip.parse().unwrap())),
+            vec!["192.168.1.10", "2001:db8::10"]
+        );
+    }
+}
@@ -0,0 +1,205 @@
+use crate::auth::Scope;
+use crate::error::ClaimError;
+use base64::Engine;
+use jwt_simple::prelude::*;
+use jwt_simple::JWTError;
+
+/// Decode the public key of the command server.
+///
+/// The key can either be PEM encoded or the raw 32 key bytes encoded as base64,
+/// the same way the claim key is stored in the config.
+pub fn decode_public_key(public_key: &str) -> Result<Ed25519PublicKey, ClaimError> {
+    let public_key = public_key.trim();
+    if public_key.starts_with("-----BEGIN") {
+        Ed25519PublicKey::from_pem(public_key)
+            .map_err(|e| ClaimError::InvalidPublicKey(e.to_string()))
+    } else {
+        let bytes = base64::prelude::BASE64_STANDARD
+            .decode(public_key)
+            .map_err(|e| ClaimError::InvalidPublicKey(e.to_string()))?;
+        Ed25519PublicKey::from_bytes(&bytes)
+            .map_err(|e| ClaimError::InvalidPublicKey(e.to_string()))
+    }
+}
+
+/// Optional claims of a claim payload restricting the token issued for it
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PayloadClaims {
+    /// Operator the command server claims the machine for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub operator: Option<String>,
+    /// Scopes the issued token may grant at most, all scopes when missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scopes: Option<Vec<Scope>>,
+}
+
+/// Verify a claim payload signed by the command server.
+///
+/// The payload is an Ed25519 signed JWT whose subject is the SMBIOS UUID of the machine
+/// it is allowed to claim. Payloads without an expiry are rejected so a leaked payload
+/// cannot be used forever.
+pub fn verify_claim_payload(
+    payload: &str,
+    public_key: &Ed25519PublicKey,
+    machine_uuid: &str,
+) -> Result<JWTClaims<PayloadClaims>, ClaimError> {
+    let claims = public_key
+        .verify_token::<PayloadClaims>(payload, None)
+        .map_err(|e| match e.downcast_ref::<JWTError>() {
+            Some(JWTError::TokenHasExpired) => ClaimError::Expired,
+            _ => ClaimError::VerificationFailed(e.to_string()),
+        })?;
+
+    if claims.expires_at.is_none() {
+        return Err(ClaimError::MissingExpiry);
+    }
+
+    match &claims.subject {
+        Some(subject) if subject.eq_ignore_ascii_case(machine_uuid) => Ok(claims),
+        Some(subject) => Err(ClaimError::WrongMachine(subject.clone())),
+        None => Err(ClaimError::MissingMachineId),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MACHINE_UUID: &str = "4c4c4544-0046-3510-8051-b7c04f565931";
+
+    fn sign(key_pair: &Ed25519KeyPair, claims: JWTClaims<NoCustomClaims>) -> String {
+        key_pair.sign(claims).unwrap()
+    }
+
+    #[test]
+    fn accepts_valid_payload() {
+        let key_pair = Ed25519KeyPair::generate();
+        let payload = sign(
+            &key_pair,
+            Claims::create(Duration::from_mins(10)).with_subject(MACHINE_UUID),
+        );
+        let claims = verify_claim_payload(&payload, &key_pair.public_key(), MACHINE_UUID).unwrap();
+        assert_eq!(claims.subject.as_deref(), Some(MACHINE_UUID));
+        assert!(claims.custom.operator.is_none() && claims.custom.scopes.is_none());
+    }
+
+    #[test]
+    fn reads_operator_and_scopes() {
+        let key_pair = Ed25519KeyPair::generate();
+        let custom = PayloadClaims {
+            operator: Some("provisioning".to_string()),
+            scopes: Some(vec![Scope::Info, Scope::Install]),
+        };
+        let payload = key_pair
+            .sign(
+                Claims::with_custom_claims(custom, Duration::from_mins(10))
+                    .with_subject(MACHINE_UUID),
+            )
+            .unwrap();
+        let claims = verify_claim_payload(&payload, &key_pair.public_key(), MACHINE_UUID).unwrap();
+        assert_eq!(claims.custom.operator.as_deref(), Some("provisioning"));
+        assert_eq!(
+            claims.custom.scopes,
+            Some(vec![Scope::Info, Scope::Install])
+        );
+    }
+
+    #[test]
+    fn machine_uuid_is_case_insensitive() {
+        let key_pair = Ed25519KeyPair::generate();
+        let payload = sign(
+            &key_pair,
+            Claims::create(Duration::from_mins(10)).with_subject(MACHINE_UUID.to_uppercase()),
+        );
+        assert!(verify_claim_payload(&payload, &key_pair.public_key(), MACHINE_UUID).is_ok());
+    }
+
+    #[test]
+    fn rejects_expired_payload() {
+        let key_pair = Ed25519KeyPair::generate();
+        let mut claims = Claims::create(Duration::from_mins(10)).with_subject(MACHINE_UUID);
+        claims.issued_at = Some(Clock::now_since_epoch() - Duration::from_hours(2));
+        claims.invalid_before = claims.issued_at;
+        claims.expires_at = Some(Clock::now_since_epoch() - Duration::from_hours(1));
+        let payload = sign(&key_pair, claims);
+        let err = verify_claim_payload(&payload, &key_pair.public_key(), MACHINE_UUID).unwrap_err();
+        assert!(matches!(err, ClaimError::Expired), "{err}");
+    }
+
+    #[test]
+    fn rejects_payload_without_expiry() {
+        let key_pair = Ed25519KeyPair::generate();
+        let mut claims = Claims::create(Duration::from_mins(10)).with_subject(MACHINE_UUID);
+        claims.expires_at = None;
+        let payload = sign(&key_pair, claims);
+        let err = verify_claim_payload(&payload, &key_pair.public_key(), MACHINE_UUID).unwrap_err();
+        assert!(matches!(err, ClaimError::MissingExpiry), "{err}");
+    }
+
+    #[test]
+    fn rejects_payload_for_other_machine() {
+        let key_pair = Ed25519KeyPair::generate();
+        let payload = sign(
+            &key_pair,
+            Claims::create(Duration::from_mins(10))
+                .with_subject("00000000-0000-0000-0000-000000000000"),
+        );
+        let err = verify_claim_payload(&payload, &key_pair.public_key(), MACHINE_UUID).unwrap_err();
+        assert!(matches!(err, ClaimError::WrongMachine(_)), "{err}");
+    }
+
+    #[test]
+    fn rejects_payload_without_machine() {
+        let key_pair = Ed25519KeyPair::generate();
+        let payload = sign(&key_pair, Claims::create(Duration::from_mins(10)));
+        let err = verify_claim_payload(&payload, &key_pair.public_key(), MACHINE_UUID).unwrap_err();
+        assert!(matches!(err, ClaimError::MissingMachineId), "{err}");
+    }
+
+    #[test]
+    fn rejects_bad_signature() {
+        let key_pair = Ed25519KeyPair::generate();
+        let other_key_pair = Ed25519KeyPair::generate();
+        let payload = sign(
+            &other_key_pair,
+            Claims::create(Duration::from_mins(10)).with_subject(MACHINE_UUID),
+        );
+        let err = verify_claim_payload(&payload, &key_pair.public_key(), MACHINE_UUID).unwrap_err();
+        assert!(matches!(err, ClaimError::VerificationFailed(_)), "{err}");
+    }
+
+    #[test]
+    fn rejects_tampered_payload() {
+        let key_pair = Ed25519KeyPair::generate();
+        let payload = sign(
+            &key_pair,
+            Claims::create(Duration::from_mins(10)).with_subject(MACHINE_UUID),
+        );
+        let mut parts: Vec<&str> = payload.split('.').collect();
+        let forged_claims = Base64UrlSafeNoPadding::encode_to_string(format!(
+            r#"{{"exp":{},"sub":"{}"}}"#,
+            (Clock::now_since_epoch() + Duration::from_days(365)).as_secs(),
+            MACHINE_UUID
+        ))
+        .unwrap();
+        parts[1] = &forged_claims;
+        let err = verify_claim_payload(&parts.join("."), &key_pair.public_key(), MACHINE_UUID)
+            .unwrap_err();
+        assert!(matches!(err, ClaimError::VerificationFailed(_)), "{err}");
+    }
+
+    #[test]
+    fn decodes_pem_and_base64_public_keys() {
+        let key_pair = Ed25519KeyPair::generate();
+        let public_key = key_pair.public_key();
+        let from_pem = decode_public_key(&public_key.to_pem()).unwrap();
+        assert_eq!(from_pem.to_bytes(), public_key.to_bytes());
+        let encoded = base64::prelude::BASE64_STANDARD.encode(public_key.to_bytes());
+        let from_base64 = decode_public_key(&encoded).unwrap();
+        assert_eq!(from_base64.to_bytes(), public_key.to_bytes());
+        assert!(matches!(
+            decode_public_key("not a key"),
+            Err(ClaimError::InvalidPublicKey(_))
+        ));
+    }
+}
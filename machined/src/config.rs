@@ -1,7 +1,10 @@
+use crate::blobcache::BlobCache;
+use crate::diskselect::parse_size;
 use config::File;
 use miette::{miette, IntoDiagnostic, Result};
 use passwords::PasswordGenerator;
 use serde::Deserialize;
+use std::path::Path;
 
 #[derive(Deserialize, Debug, Default, Clone)]
 pub struct MachinedConfig {
@@ -10,20 +13,157 @@ pub struct MachinedConfig {
     pub claim_key: Option<ClaimKey>,
     pub claim_password: String,
     pub wireguard: Option<WireguardConfig>,
+    #[serde(default)]
+    pub tls: TlsConfig,
+    #[serde(default)]
+    pub console: ConsoleConfig,
     #[serde(default = "default_oci_registry")]
     pub default_oci_registry: String,
+    #[serde(default = "default_session_dir")]
+    pub session_dir: String,
+    /// Finished install sessions kept in `session_dir`, older ones are removed with their
+    /// progress and report
+    #[serde(default = "default_session_retention")]
+    pub session_retention: usize,
+    #[serde(default = "default_blob_cache_dir")]
+    pub blob_cache_dir: String,
+    /// Size the blob cache is pruned to after adding a blob, e.g. `4G`, `0` disables caching
+    #[serde(default = "default_blob_cache_size")]
+    pub blob_cache_size: String,
+    /// Hardware fixture the mock platform simulates, a built-in server when not set
+    #[cfg(feature = "mock")]
+    pub mock_fixture: Option<String>,
+}
+
+impl MachinedConfig {
+    pub fn blob_cache(&self) -> BlobCache {
+        BlobCache::new(
+            &self.blob_cache_dir,
+            parse_size(&self.blob_cache_size).unwrap_or_default(),
+        )
+    }
 }
 
 fn default_oci_registry() -> String {
     "aopc.cloud".to_string()
 }
 
+fn default_session_dir() -> String {
+    "/var/tmp/machined/sessions".to_string()
+}
+
+fn default_session_retention() -> usize {
+    20
+}
+
+fn default_blob_cache_dir() -> String {
+    "/var/tmp/machined/blobs".to_string()
+}
+
+/// The default blob cache directory is on the RAM disk of the installer, so caching is opt-in
+fn default_blob_cache_size() -> String {
+    "0".to_string()
+}
+
+/// Userspace WireGuard tunnel to the command server
 #[derive(Deserialize, Debug, Default, Clone)]
 pub struct WireguardConfig {
+    /// Endpoint of the command server, `host:port`
     pub server: String,
+    /// Base64 encoded private key of this machine
     pub private_key: String,
     pub server_public_key: String,
+    /// Networks routed through the tunnel, e.g. `10.100.0.0/24`
     pub peers: Vec<String>,
+    /// Address of this machine in the tunnel with its prefix length, e.g. `10.100.0.2/24`
+    pub address: String,
+    #[serde(default = "default_wireguard_interface")]
+    pub interface: String,
+}
+
+/// The illumos port of wireguard-go only creates tun devices
+fn default_wireguard_interface() -> String {
+    if cfg!(target_os = "illumos") {
+        "tun0".to_string()
+    } else {
+        "wg0".to_string()
+    }
+}
+
+/// TLS of the gRPC endpoint
+#[derive(Deserialize, Debug, Clone)]
+pub struct TlsConfig {
+    /// Serve TLS when true, plaintext gRPC otherwise
+    #[serde(default = "default_tls_enabled")]
+    pub enabled: bool,
+    /// PEM certificate to serve, a self-signed certificate is generated in `dir` when not set
+    pub cert: Option<String>,
+    /// PEM private key of `cert`
+    pub key: Option<String>,
+    #[serde(default = "default_tls_dir")]
+    pub dir: String,
+    /// PEM CA certificates to verify client certificates against, clients must present a
+    /// certificate signed by one of them when set
+    pub client_ca: Option<String>,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        TlsConfig {
+            enabled: default_tls_enabled(),
+            cert: None,
+            key: None,
+            dir: default_tls_dir(),
+            client_ca: None,
+        }
+    }
+}
+
+fn default_tls_enabled() -> bool {
+    true
+}
+
+fn default_tls_dir() -> String {
+    "/var/tmp/machined/tls".to_string()
+}
+
+/// Interactive installer on the console of the machine
+#[derive(Deserialize, Debug, Clone)]
+pub struct ConsoleConfig {
+    /// Offer the console installer when no machine configuration is found on the USB key
+    #[serde(default)]
+    pub enabled: bool,
+    /// Terminal device the installer is drawn on, e.g. `/dev/term/a` for a serial console
+    #[serde(default = "default_console_device")]
+    pub device: String,
+    /// Serial consoles do not report their size, so it is configured
+    #[serde(default = "default_console_width")]
+    pub width: u16,
+    #[serde(default = "default_console_height")]
+    pub height: u16,
+}
+
+impl Default for ConsoleConfig {
+    fn default() -> Self {
+        ConsoleConfig {
+            enabled: false,
+            device: default_console_device(),
+            width: default_console_width(),
+            height: default_console_height(),
+        }
+    }
+}
+
+fn default_console_device() -> String {
+    "/dev/console".to_string()
+}
+
+fn default_console_width() -> u16 {
+    80
+}
+
+fn default_console_height() -> u16 {
+    24
 }
 
 #[derive(Deserialize, Debug, Default, Clone)]
@@ -33,7 +173,10 @@ pub struct ClaimKey {
 
 #[derive(Deserialize, Debug, Default, Clone)]
 pub struct CommandServer {
+    /// gRPC endpoint of the command server machined registers with at boot, call-home is
+    /// disabled when empty
     pub url: String,
+    /// Ed25519 public key claim payloads of the command server are verified with
     pub public_key: String,
 }
 
@@ -52,17 +195,32 @@ pub fn load_config() -> Result<MachinedConfig> {
         .generate_one()
         .map_err(|e| miette!("error generating password {e}"))?;
 
-    let cfg = config::Config::builder()
+    let mut builder = config::Config::builder()
         // In the installer the defaults get backed in under /etc so we read them first
         // we do not make them mandatory for local debugging
         .add_source(File::with_name("/etc/machined").required(false))
         // We assume that the first USB key gets mounted on /usb so we look for a machined config there
-        .add_source(File::with_name("/usb/machined").required(false))
+        .add_source(File::with_name("/usb/machined").required(false));
+    // Outside of the installer, e.g. when tests run machined, the configuration is passed explicitly
+    if let Ok(path) = std::env::var("MACHINED_CONFIG") {
+        builder = builder.add_source(File::from(Path::new(&path)));
+    }
+    let cfg = builder
         .set_default("listen", "0.0.0.0:50051")
         .into_diagnostic()?
         .set_default("claim_password", claim_password)
         .into_diagnostic()?
         .build()
         .into_diagnostic()?;
-    Ok(cfg.try_deserialize().into_diagnostic()?)
+    let cfg: MachinedConfig = cfg.try_deserialize().into_diagnostic()?;
+    if cfg.tls.cert.is_some() != cfg.tls.key.is_some() {
+        return Err(miette!("tls.cert and tls.key must be configured together"));
+    }
+    if parse_size(&cfg.blob_cache_size).is_none() {
+        return Err(miette!(
+            "blob_cache_size {} is not a valid size",
+            cfg.blob_cache_size
+        ));
+    }
+    Ok(cfg)
 }
@@ -0,0 +1,97 @@
+/// A key pressed on the console
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Key {
+    Up,
+    Down,
+    Left,
+    Right,
+    Enter,
+    Tab,
+    BackTab,
+    Backspace,
+    Esc,
+    CtrlC,
+    Char(char),
+}
+
+/// Decode the bytes read from a serial or VGA console into keys.
+///
+/// Terminals send cursor keys as `ESC [ A` or, in application mode, `ESC O A`. Sequences
+/// that are not understood are dropped so a stray escape code does not type into a field.
+pub fn parse_keys(input: &[u8]) -> Vec<Key> {
+    let text = String::from_utf8_lossy(input);
+    let mut chars = text.chars().peekable();
+    let mut keys = Vec::new();
+    while let Some(c) = chars.next() {
+        let key = match c {
+            '\x1b' => match chars.peek() {
+                Some('[') | Some('O') => {
+                    chars.next();
+                    // Parameters like the `1;5` of modified keys come before the final byte
+                    let mut last = None;
+                    for c in chars.by_ref() {
+                        if ('\x40'..='\x7e').contains(&c) {
+                            last = Some(c);
+                            break;
+                        }
+                    }
+                    match last {
+                        Some('A') => Key::Up,
+                        Some('B') => Key::Down,
+                        Some('C') => Key::Right,
+                        Some('D') => Key::Left,
+                        Some('Z') => Key::BackTab,
+                        _ => continue,
+                    }
+                }
+                _ => Key::Esc,
+            },
+            '\r' | '\n' => {
+                // Terminals sending CR LF for Enter press it once
+                if c == '\r' && chars.peek() == Some(&'\n') {
+                    chars.next();
+                }
+                Key::Enter
+            }
+            '\t' => Key::Tab,
+            '\x7f' | '\x08' => Key::Backspace,
+            '\x03' => Key::CtrlC,
+            c if c.is_control() => continue,
+            c => Key::Char(c),
+        };
+        keys.push(key);
+    }
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_terminal_input() {
+        assert_eq!(
+            parse_keys(b"\x1b[A\x1bOB\x1b[1;5C\x1b[Dab\r\n\t\x1b[Z\x7f\x08\x1b\x03"),
+            vec![
+                Key::Up,
+                Key::Down,
+                Key::Right,
+                Key::Left,
+                Key::Char('a'),
+                Key::Char('b'),
+                Key::Enter,
+                Key::Tab,
+                Key::BackTab,
+                Key::Backspace,
+                Key::Backspace,
+                Key::Esc,
+                Key::CtrlC,
+            ]
+        );
+        assert_eq!(parse_keys(b"\x1b[5~x"), vec![Key::Char('x')]);
+        assert_eq!(
+            parse_keys("ä\n".as_bytes()),
+            vec![Key::Char('ä'), Key::Enter]
+        );
+    }
+}
@@ -0,0 +1,419 @@
+mod keys;
+mod wizard;
+
+use crate::callhome::reachable_addresses;
+use crate::config::MachinedConfig;
+use crate::error::ConsoleError;
+use crate::machined::install_progress::Message;
+use crate::machined::{InstallPhase, InstallProgress, InstallSessionState};
+use crate::session::{InstallSessions, Session};
+use crate::sysinfo::{get_disk_info, get_network_info};
+use crate::validate::Hardware;
+use keys::{parse_keys, Key};
+use nix::sys::termios::{self, SetArg};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Gauge, Paragraph, Wrap};
+use ratatui::{Frame, Terminal, TerminalOptions, Viewport};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tonic::Status;
+use tracing::{info, warn};
+use wizard::{Action, Wizard};
+
+/// Progress lines kept for the install screen
+const MAX_PROGRESS_LINES: usize = 200;
+
+/// Operator recorded in the install report of installs started from the console
+const CONSOLE_OPERATOR: &str = "console";
+
+type Progress = mpsc::Receiver<Result<InstallProgress, Status>>;
+
+/// What the console shows an operator before anything is installed
+pub struct Welcome {
+    pub claim_password: String,
+    pub port: u16,
+    pub tls_fingerprint: Option<String>,
+}
+
+/// Serve the interactive installer on the console until the console goes away.
+///
+/// The console keeps offering to claim the machine over the network, an operator at the
+/// console can instead walk through a configuration and install it like the Install RPC.
+pub async fn run_console(
+    config: Arc<MachinedConfig>,
+    sessions: Arc<InstallSessions>,
+    welcome: Welcome,
+) {
+    info!("serving the installer on console {}", config.console.device);
+    if let Err(e) = run(config, sessions, welcome).await {
+        warn!("console installer stopped: {}", e);
+    }
+}
+
+async fn run(
+    config: Arc<MachinedConfig>,
+    sessions: Arc<InstallSessions>,
+    welcome: Welcome,
+) -> Result<(), ConsoleError> {
+    let device = &config.console.device;
+    let console = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(device)
+        .map_err(|e| ConsoleError::Open(device.clone(), e))?;
+    let saved = termios::tcgetattr(&console)?;
+    let mut raw = saved.clone();
+    termios::cfmakeraw(&mut raw);
+    termios::tcsetattr(&console, SetArg::TCSANOW, &raw)?;
+
+    let mut keys = read_keys(console.try_clone()?);
+    // Serial consoles do not report their size, so the configured size is drawn
+    let area = Rect::new(0, 0, config.console.width, config.console.height);
+    let mut terminal = Terminal::with_options(
+        CrosstermBackend::new(console.try_clone()?),
+        TerminalOptions {
+            viewport: Viewport::Fixed(area),
+        },
+    )?;
+    terminal.clear()?;
+    terminal.hide_cursor()?;
+
+    let mut state = Console::new(config.clone(), sessions, welcome);
+    let mut follow: Option<Progress> = None;
+    let mut tick = tokio::time::interval(Duration::from_secs(1));
+    let result = loop {
+        if let Err(e) = terminal.draw(|frame| state.render(frame)) {
+            break Err(e.into());
+        }
+        tokio::select! {
+            key = keys.recv() => match key {
+                Some(key) => {
+                    if let Some(progress) = state.handle_key(key) {
+                        follow = Some(progress);
+                    }
+                }
+                // The console was closed or hung up
+                None => break Ok(()),
+            },
+            progress = next_progress(&mut follow) => match progress {
+                Some(progress) => state.record(progress),
+                None => follow = None,
+            },
+            _ = tick.tick() => {
+                if let Some(progress) = state.tick() {
+                    follow = Some(progress);
+                }
+            }
+        }
+    };
+    termios::tcsetattr(&console, SetArg::TCSANOW, &saved)?;
+    result
+}
+
+/// Read keys from the console on a thread of its own as reads on terminals block
+fn read_keys(mut console: File) -> mpsc::Receiver<Key> {
+    let (tx, rx) = mpsc::channel(32);
+    std::thread::spawn(move || {
+        let mut buf = [0u8; 64];
+        loop {
+            let read = match console.read(&mut buf) {
+                Ok(0) => return,
+                Ok(read) => read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    warn!("failed to read from the console: {}", e);
+                    return;
+                }
+            };
+            for key in parse_keys(&buf[..read]) {
+                if tx.blocking_send(key).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    rx
+}
+
+/// The next progress message of the followed session, never ready without one
+async fn next_progress(follow: &mut Option<Progress>) -> Option<Result<InstallProgress, Status>> {
+    match follow {
+        Some(progress) => progress.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+enum Screen {
+    Welcome,
+    Wizard(Box<Wizard>),
+    Installing(Installing),
+}
+
+/// An install session followed on the console
+struct Installing {
+    session: Arc<Session>,
+    lines: VecDeque<Line<'static>>,
+    /// Phase and byte counters of the item being downloaded or unpacked
+    transfer: Option<(String, u64, u64)>,
+}
+
+struct Console {
+    config: Arc<MachinedConfig>,
+    sessions: Arc<InstallSessions>,
+    welcome: Welcome,
+    addresses: Vec<String>,
+    screen: Screen,
+    message: Option<String>,
+}
+
+impl Console {
+    fn new(config: Arc<MachinedConfig>, sessions: Arc<InstallSessions>, welcome: Welcome) -> Self {
+        Console {
+            config,
+            sessions,
+            welcome,
+            addresses: reachable_addresses(),
+            screen: Screen::Welcome,
+            message: None,
+        }
+    }
+
+    /// Handle a key, returning the progress of an install session started by it
+    fn handle_key(&mut self, key: Key) -> Option<Progress> {
+        match &mut self.screen {
+            Screen::Welcome => {
+                if key == Key::Enter {
+                    match discover_hardware() {
+                        Ok(hardware) => {
+                            self.message = None;
+                            self.screen = Screen::Wizard(Box::new(Wizard::new(
+                                hardware,
+                                &self.config.default_oci_registry,
+                            )));
+                        }
+                        Err(status) => self.message = Some(status.message().to_string()),
+                    }
+                }
+                None
+            }
+            Screen::Wizard(wizard) => match wizard.handle_key(key) {
+                Action::None => None,
+                Action::Quit => {
+                    self.screen = Screen::Welcome;
+                    None
+                }
+                Action::Install(mc) => {
+                    match self.sessions.start(
+                        String::new(),
+                        CONSOLE_OPERATOR,
+                        *mc,
+                        self.config.clone(),
+                    ) {
+                        Ok(session) => Some(self.follow(session)),
+                        Err(status) => {
+                            self.message = Some(status.message().to_string());
+                            self.screen = Screen::Welcome;
+                            None
+                        }
+                    }
+                }
+            },
+            Screen::Installing(installing) => {
+                if key == Key::Enter && !installing.session.is_running() {
+                    self.screen = Screen::Welcome;
+                }
+                None
+            }
+        }
+    }
+
+    /// Refresh what the console shows, following an install started over the network
+    fn tick(&mut self) -> Option<Progress> {
+        self.addresses = reachable_addresses();
+        if matches!(self.screen, Screen::Installing(_)) {
+            return None;
+        }
+        let session = self.sessions.running()?;
+        Some(self.follow(session))
+    }
+
+    fn follow(&mut self, session: Arc<Session>) -> Progress {
+        let progress = session.follow();
+        self.message = None;
+        self.screen = Screen::Installing(Installing {
+            session,
+            lines: VecDeque::new(),
+            transfer: None,
+        });
+        progress
+    }
+
+    fn record(&mut self, progress: Result<InstallProgress, Status>) {
+        let Screen::Installing(installing) = &mut self.screen else {
+            return;
+        };
+        let progress = match progress {
+            Ok(progress) => progress,
+            Err(status) => InstallProgress {
+                message: Some(Message::Error(status.message().to_string())),
+                ..Default::default()
+            },
+        };
+        let phase = progress.phase();
+        if progress.bytes_total > 0 {
+            installing.transfer = Some((
+                format!(
+                    "{} {}/{}",
+                    phase_name(phase),
+                    progress.step,
+                    progress.total_steps
+                ),
+                progress.bytes_done,
+                progress.bytes_total,
+            ));
+            return;
+        }
+        let line = match &progress.message {
+            Some(Message::Info(text)) if phase == InstallPhase::Unspecified => {
+                Line::from(text.clone())
+            }
+            Some(Message::Info(text)) => Line::from(format!(
+                "[{} {}/{}] {}",
+                phase_name(phase),
+                progress.step,
+                progress.total_steps,
+                text
+            )),
+            Some(Message::Error(text)) => {
+                Line::from(text.clone()).style(Style::default().add_modifier(Modifier::BOLD))
+            }
+            None => return,
+        };
+        if installing.lines.len() == MAX_PROGRESS_LINES {
+            installing.lines.pop_front();
+        }
+        installing.lines.push_back(line);
+    }
+
+    fn render(&self, frame: &mut Frame) {
+        let [title, body, message] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(3),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        frame.render_widget(
+            Paragraph::new("machined installer")
+                .style(Style::default().add_modifier(Modifier::REVERSED)),
+            title,
+        );
+        match &self.screen {
+            Screen::Welcome => self.render_welcome(frame, body),
+            Screen::Wizard(wizard) => wizard.render(frame, body),
+            Screen::Installing(installing) => render_installing(installing, frame, body),
+        }
+        if let Some(text) = &self.message {
+            frame.render_widget(
+                Paragraph::new(text.as_str()).style(Style::default().add_modifier(Modifier::BOLD)),
+                message,
+            );
+        }
+    }
+
+    fn render_welcome(&self, frame: &mut Frame, area: Rect) {
+        let mut lines = vec![
+            Line::from("Claim this machine over the network with installadm:"),
+            Line::default(),
+            Line::from(format!("  Password     {}", self.welcome.claim_password)),
+            Line::from(format!("  Port         {}", self.welcome.port)),
+        ];
+        if self.addresses.is_empty() {
+            lines.push(Line::from(
+                "  Addresses    none yet, waiting for the network",
+            ));
+        }
+        for (idx, address) in self.addresses.iter().enumerate() {
+            let label = if idx == 0 { "Addresses" } else { "" };
+            lines.push(Line::from(format!("  {:<12} {}", label, address)));
+        }
+        if let Some(fingerprint) = &self.welcome.tls_fingerprint {
+            lines.push(Line::from(format!("  TLS          {}", fingerprint)));
+        }
+        lines.push(Line::default());
+        lines.push(Line::from("Or press Enter to install from this console."));
+        frame.render_widget(
+            Paragraph::new(lines)
+                .wrap(Wrap { trim: false })
+                .block(Block::default().borders(Borders::ALL).title(" Welcome ")),
+            area,
+        );
+    }
+}
+
+fn render_installing(installing: &Installing, frame: &mut Frame, area: Rect) {
+    let info = installing.session.info();
+    let [log, gauge, help] = Layout::vertical([
+        Constraint::Min(3),
+        Constraint::Length(1),
+        Constraint::Length(1),
+    ])
+    .areas(area);
+    let block = Block::default().borders(Borders::ALL).title(format!(
+        " Session {}: {} ",
+        info.name,
+        info.state().as_str_name().to_lowercase()
+    ));
+    // Show the newest lines that fit
+    let height = block.inner(log).height as usize;
+    let skip = installing.lines.len().saturating_sub(height);
+    let lines = installing
+        .lines
+        .iter()
+        .skip(skip)
+        .cloned()
+        .collect::<Vec<_>>();
+    frame.render_widget(Paragraph::new(lines).block(block), log);
+
+    if let Some((label, done, total)) = &installing.transfer {
+        frame.render_widget(
+            Gauge::default()
+                .label(format!(
+                    "{} {}/{} MiB",
+                    label,
+                    done / (1024 * 1024),
+                    total / (1024 * 1024)
+                ))
+                .ratio((*done as f64 / *total as f64).min(1.0)),
+            gauge,
+        );
+    }
+    let text = match info.state() {
+        InstallSessionState::Running => "Installing, this can take a while",
+        InstallSessionState::Succeeded => "Installed, reboot into the new system. Enter returns",
+        _ => "Installation did not finish. Enter returns",
+    };
+    frame.render_widget(Paragraph::new(text), help);
+}
+
+/// Name of a phase as the install screen shows it, e.g. `unpack` for INSTALL_PHASE_UNPACK
+fn phase_name(phase: InstallPhase) -> String {
+    phase
+        .as_str_name()
+        .trim_start_matches("INSTALL_PHASE_")
+        .to_lowercase()
+}
+
+/// Disks and links the wizard offers, read again each time it starts so hot-plugged disks show up
+fn discover_hardware() -> Result<Hardware, Status> {
+    Ok(Hardware {
+        disks: get_disk_info()?,
+        links: get_network_info()?,
+    })
+}
@@ -0,0 +1,684 @@
+use super::keys::Key;
+use crate::validate::{self, Hardware};
+use machineconfig::{
+    AddressKind, AddressObject, ImageSource, Interface, MachineConfig, Pool, SysConfig, VDev,
+    VDevType,
+};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::{Line, Text};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::Frame;
+
+/// Pool the console installer creates from the selected disks
+const POOL_NAME: &str = "rpool";
+
+/// Layouts offered for the selected disks, `raidz` is left out as it is `raidz1`
+const LAYOUTS: [VDevType; 5] = [
+    VDevType::Stripe,
+    VDevType::Mirror,
+    VDevType::Raidz1,
+    VDevType::Raidz2,
+    VDevType::Raidz3,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Step {
+    Disks,
+    Layout,
+    Image,
+    Hostname,
+    Network,
+    Preview,
+}
+
+impl Step {
+    fn title(&self) -> &'static str {
+        match self {
+            Step::Disks => "Disks",
+            Step::Layout => "Pool Layout",
+            Step::Image => "Image",
+            Step::Hostname => "Hostname",
+            Step::Network => "Network",
+            Step::Preview => "Review",
+        }
+    }
+
+    fn help(&self) -> &'static str {
+        match self {
+            Step::Disks => "Up/Down move  Space select  Enter next  Esc back",
+            Step::Layout | Step::Image | Step::Hostname => "Enter next  Esc back",
+            Step::Network => "Tab next field  Left/Right change  Enter next  Esc back",
+            Step::Preview => "Up/Down scroll  Enter install  Esc back",
+        }
+    }
+
+    fn previous(&self) -> Option<Step> {
+        match self {
+            Step::Disks => None,
+            Step::Layout => Some(Step::Disks),
+            Step::Image => Some(Step::Layout),
+            Step::Hostname => Some(Step::Image),
+            Step::Network => Some(Step::Hostname),
+            Step::Preview => Some(Step::Network),
+        }
+    }
+}
+
+/// Fields of the network step, in the order Tab moves through them
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum NetworkField {
+    Link,
+    Mode,
+    Address,
+    Nameservers,
+}
+
+/// What the console does after the wizard handled a key
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    None,
+    Install(Box<MachineConfig>),
+    Quit,
+}
+
+/// Walks an operator at the console through a machine configuration.
+///
+/// The configuration is kept simple on purpose: one pool of the selected disks, an image,
+/// a hostname and at most one network link. Anything more elaborate is installed from a
+/// configuration file or over the network.
+pub struct Wizard {
+    hardware: Hardware,
+    step: Step,
+    cursor: usize,
+    selected: Vec<bool>,
+    layout: VDevType,
+    image: String,
+    hostname: String,
+    /// Index of the configured link in `hardware.links`, no network when `None`
+    link: Option<usize>,
+    dhcp: bool,
+    address: String,
+    nameservers: String,
+    field: NetworkField,
+    scroll: u16,
+    message: Option<String>,
+}
+
+impl Wizard {
+    pub fn new(hardware: Hardware, default_oci_registry: &str) -> Self {
+        let selected = vec![false; hardware.disks.len()];
+        let link = if hardware.links.is_empty() {
+            None
+        } else {
+            Some(0)
+        };
+        Wizard {
+            hardware,
+            step: Step::Disks,
+            cursor: 0,
+            selected,
+            layout: VDevType::Stripe,
+            image: format!("oci://{}/", default_oci_registry),
+            hostname: String::new(),
+            link,
+            dhcp: true,
+            address: String::new(),
+            nameservers: String::new(),
+            field: NetworkField::Link,
+            scroll: 0,
+            message: None,
+        }
+    }
+
+    /// Disks the operator selected for the pool
+    fn selected_disks(&self) -> Vec<String> {
+        self.hardware
+            .disks
+            .iter()
+            .zip(&self.selected)
+            .filter(|(_, selected)| **selected)
+            .map(|(disk, _)| disk.device.clone())
+            .collect()
+    }
+
+    /// Layouts the number of selected disks allows
+    fn layouts(&self) -> Vec<VDevType> {
+        let count = self.selected.iter().filter(|s| **s).count();
+        LAYOUTS
+            .into_iter()
+            .filter(|layout| layout.minimum_disks() <= count)
+            .collect()
+    }
+
+    /// The machine configuration entered so far
+    pub fn machine_config(&self) -> MachineConfig {
+        let mut interfaces = Vec::new();
+        if let Some(link) = self.link.and_then(|idx| self.hardware.links.get(idx)) {
+            let (kind, address) = if self.dhcp {
+                (AddressKind::Dhcp4, None)
+            } else {
+                (AddressKind::Static, Some(self.address.trim().to_string()))
+            };
+            interfaces.push(Interface {
+                name: Some(link.name.clone()),
+                selector: (!link.mac_address.is_empty())
+                    .then(|| format!("mac:{}", link.mac_address)),
+                addresses: vec![AddressObject {
+                    name: String::from("v4"),
+                    kind,
+                    address,
+                }],
+            });
+        }
+        MachineConfig {
+            pools: vec![Pool {
+                name: POOL_NAME.to_string(),
+                vdevs: vec![VDev {
+                    kind: self.layout.clone(),
+                    disks: self.selected_disks(),
+                }],
+                ..Default::default()
+            }],
+            image: self.image.trim().to_string(),
+            sysconfig: SysConfig {
+                hostname: self.hostname.trim().to_string(),
+                nameservers: self
+                    .nameservers
+                    .split([',', ' '])
+                    .filter(|ns| !ns.is_empty())
+                    .map(String::from)
+                    .collect(),
+                interfaces,
+            },
+            ..Default::default()
+        }
+    }
+
+    pub fn handle_key(&mut self, key: Key) -> Action {
+        if key == Key::CtrlC {
+            return Action::Quit;
+        }
+        if key == Key::Esc {
+            return match self.step.previous() {
+                Some(step) => {
+                    self.enter(step);
+                    Action::None
+                }
+                None => Action::Quit,
+            };
+        }
+        match self.step {
+            Step::Disks => self.disks_key(key),
+            Step::Layout => self.layout_key(key),
+            Step::Image => {
+                if key == Key::Enter {
+                    match ImageSource::parse(self.image.trim()) {
+                        Ok(_) => self.enter(Step::Hostname),
+                        Err(e) => self.message = Some(e.to_string()),
+                    }
+                } else {
+                    edit(&mut self.image, key);
+                }
+            }
+            Step::Hostname => {
+                if key == Key::Enter {
+                    if self.hostname.trim().is_empty() {
+                        self.message = Some(String::from("enter a hostname"));
+                    } else {
+                        self.enter(Step::Network);
+                    }
+                } else {
+                    edit(&mut self.hostname, key);
+                }
+            }
+            Step::Network => self.network_key(key),
+            Step::Preview => match key {
+                Key::Up => self.scroll = self.scroll.saturating_sub(1),
+                Key::Down => self.scroll = self.scroll.saturating_add(1),
+                Key::Enter => {
+                    let mc = self.machine_config();
+                    if validate::validate_config(&mc, Some(&self.hardware)).valid {
+                        return Action::Install(Box::new(mc));
+                    }
+                    self.message = Some(String::from("fix the issues before installing"));
+                }
+                _ => {}
+            },
+        }
+        Action::None
+    }
+
+    fn enter(&mut self, step: Step) {
+        self.cursor = match step {
+            Step::Layout => self
+                .layouts()
+                .iter()
+                .position(|layout| *layout == self.layout)
+                .unwrap_or_default(),
+            _ => 0,
+        };
+        self.field = NetworkField::Link;
+        self.scroll = 0;
+        self.message = None;
+        self.step = step;
+    }
+
+    fn disks_key(&mut self, key: Key) {
+        match key {
+            Key::Up => self.cursor = self.cursor.saturating_sub(1),
+            Key::Down if self.cursor + 1 < self.selected.len() => self.cursor += 1,
+            Key::Char(' ') => {
+                if let Some(selected) = self.selected.get_mut(self.cursor) {
+                    *selected = !*selected;
+                }
+            }
+            Key::Enter => {
+                if self.selected.iter().any(|s| *s) {
+                    self.enter(Step::Layout);
+                } else {
+                    self.message = Some(String::from("select at least one disk"));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn layout_key(&mut self, key: Key) {
+        let layouts = self.layouts();
+        match key {
+            Key::Up => self.cursor = self.cursor.saturating_sub(1),
+            Key::Down if self.cursor + 1 < layouts.len() => self.cursor += 1,
+            Key::Enter => {
+                if let Some(layout) = layouts.get(self.cursor) {
+                    self.layout = layout.clone();
+                    self.enter(Step::Image);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn network_key(&mut self, key: Key) {
+        let fields = self.network_fields();
+        let current = fields
+            .iter()
+            .position(|field| *field == self.field)
+            .unwrap_or_default();
+        match key {
+            Key::Tab | Key::Down => self.field = fields[(current + 1) % fields.len()],
+            Key::BackTab | Key::Up => {
+                self.field = fields[(current + fields.len() - 1) % fields.len()]
+            }
+            Key::Left | Key::Right if self.field == NetworkField::Link => {
+                // Cycle through the links and no network at all
+                let choices = self.hardware.links.len() + 1;
+                let current = self.link.map(|idx| idx + 1).unwrap_or_default();
+                let next = if key == Key::Right {
+                    (current + 1) % choices
+                } else {
+                    (current + choices - 1) % choices
+                };
+                self.link = next.checked_sub(1);
+            }
+            Key::Left | Key::Right | Key::Char(' ') if self.field == NetworkField::Mode => {
+                self.dhcp = !self.dhcp;
+            }
+            Key::Enter => {
+                if self.link.is_some() && !self.dhcp && self.address.trim().is_empty() {
+                    self.message =
+                        Some(String::from("enter a static address like 192.168.1.10/24"));
+                } else {
+                    self.enter(Step::Preview);
+                }
+            }
+            key => match self.field {
+                NetworkField::Address => edit(&mut self.address, key),
+                NetworkField::Nameservers => edit(&mut self.nameservers, key),
+                _ => {}
+            },
+        }
+    }
+
+    /// Fields of the network step that apply to the chosen link and mode
+    fn network_fields(&self) -> Vec<NetworkField> {
+        match (self.link, self.dhcp) {
+            (None, _) => vec![NetworkField::Link],
+            (Some(_), true) => vec![
+                NetworkField::Link,
+                NetworkField::Mode,
+                NetworkField::Nameservers,
+            ],
+            (Some(_), false) => vec![
+                NetworkField::Link,
+                NetworkField::Mode,
+                NetworkField::Address,
+                NetworkField::Nameservers,
+            ],
+        }
+    }
+
+    pub fn render(&self, frame: &mut Frame, area: Rect) {
+        let [body, message, help] = Layout::vertical([
+            Constraint::Min(3),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(area);
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(format!(" Install: {} ", self.step.title()));
+        let inner = block.inner(body);
+        frame.render_widget(block, body);
+
+        match self.step {
+            Step::Disks => {
+                let items =
+                    self.hardware
+                        .disks
+                        .iter()
+                        .zip(&self.selected)
+                        .map(|(disk, selected)| {
+                            ListItem::new(format!(
+                                "[{}] {:<12} {:>10}  {} {}",
+                                if *selected { "x" } else { " " },
+                                disk.device,
+                                human_size(disk.size_bytes),
+                                disk.vendor.trim(),
+                                disk.product.trim()
+                            ))
+                        });
+                render_list(frame, inner, items, self.cursor);
+            }
+            Step::Layout => {
+                let count = self.selected.iter().filter(|s| **s).count();
+                let items = self
+                    .layouts()
+                    .into_iter()
+                    .map(|layout| ListItem::new(format!("{} of {} disks", layout.name(), count)));
+                render_list(frame, inner, items, self.cursor);
+            }
+            Step::Image => render_field(frame, inner, "OCI image or layout", &self.image),
+            Step::Hostname => render_field(frame, inner, "Hostname", &self.hostname),
+            Step::Network => {
+                let link = match self.link.and_then(|idx| self.hardware.links.get(idx)) {
+                    Some(link) => format!("< {} {} >", link.name, link.mac_address),
+                    None => String::from("< no network >"),
+                };
+                let mode = if self.dhcp { "< DHCP >" } else { "< static >" };
+                let lines = self
+                    .network_fields()
+                    .into_iter()
+                    .map(|field| {
+                        let (label, value) = match field {
+                            NetworkField::Link => ("Link", link.as_str()),
+                            NetworkField::Mode => ("Address", mode),
+                            NetworkField::Address => ("Static address", self.address.as_str()),
+                            NetworkField::Nameservers => ("Nameservers", self.nameservers.as_str()),
+                        };
+                        let line = Line::from(format!("{:<16}{}", label, value));
+                        if field == self.field {
+                            line.style(Style::default().add_modifier(Modifier::REVERSED))
+                        } else {
+                            line
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                frame.render_widget(Paragraph::new(lines), inner);
+            }
+            Step::Preview => {
+                let mc = self.machine_config();
+                let report = validate::validate_config(&mc, Some(&self.hardware));
+                let mut text = Text::default();
+                for issue in &report.issues {
+                    text.push_line(
+                        Line::from(format!("! {}: {}", issue.subject, issue.message))
+                            .style(Style::default().add_modifier(Modifier::BOLD)),
+                    );
+                }
+                if report.valid {
+                    text.push_line(Line::from(format!(
+                        "All data on {} will be erased.",
+                        self.selected_disks().join(", ")
+                    )));
+                }
+                text.push_line(Line::default());
+                for line in mc.to_kdl().lines() {
+                    text.push_line(Line::from(line.to_string()));
+                }
+                frame.render_widget(
+                    Paragraph::new(text)
+                        .wrap(Wrap { trim: false })
+                        .scroll((self.scroll, 0)),
+                    inner,
+                );
+            }
+        }
+
+        if let Some(text) = &self.message {
+            frame.render_widget(
+                Paragraph::new(text.as_str()).style(Style::default().add_modifier(Modifier::BOLD)),
+                message,
+            );
+        }
+        frame.render_widget(Paragraph::new(self.step.help()), help);
+    }
+}
+
+/// Apply a key to a text field
+fn edit(text: &mut String, key: Key) {
+    match key {
+        Key::Char(c) => text.push(c),
+        Key::Backspace => {
+            text.pop();
+        }
+        _ => {}
+    }
+}
+
+fn render_list<'a>(
+    frame: &mut Frame,
+    area: Rect,
+    items: impl Iterator<Item = ListItem<'a>>,
+    cursor: usize,
+) {
+    let list = List::new(items).highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut state = ListState::default().with_selected(Some(cursor));
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+fn render_field(frame: &mut Frame, area: Rect, label: &str, value: &str) {
+    frame.render_widget(
+        Paragraph::new(vec![
+            Line::from(format!("{}:", label)),
+            Line::from(format!("> {}_", value)),
+        ]),
+        area,
+    );
+}
+
+/// Disk size with a binary unit, the way the installer UI shows it
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machined::{DiskInfo, NetworkInterface};
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    fn hardware() -> Hardware {
+        Hardware {
+            disks: ["c1t0d0", "c1t1d0", "c1t2d0"]
+                .iter()
+                .map(|device| DiskInfo {
+                    device: device.to_string(),
+                    vendor: String::from("ATA"),
+                    product: String::from("SAMSUNG"),
+                    size_bytes: 64 * 1024 * 1024 * 1024,
+                    paths: vec![format!("/dev/dsk/{}", device)],
+                    ..Default::default()
+                })
+                .collect(),
+            links: vec![NetworkInterface {
+                name: String::from("net0"),
+                link: String::from("e1000g0"),
+                mac_address: String::from("2:8:20:a:b:c"),
+                ..Default::default()
+            }],
+        }
+    }
+
+    fn press(wizard: &mut Wizard, keys: &[Key]) -> Action {
+        let mut action = Action::None;
+        for key in keys {
+            action = wizard.handle_key(*key);
+        }
+        action
+    }
+
+    fn type_text(wizard: &mut Wizard, text: &str) {
+        for c in text.chars() {
+            wizard.handle_key(Key::Char(c));
+        }
+    }
+
+    /// Select the first two disks as a mirror with a static address
+    fn mirror_config(wizard: &mut Wizard) -> Action {
+        press(
+            wizard,
+            &[Key::Char(' '), Key::Down, Key::Char(' '), Key::Enter],
+        );
+        press(wizard, &[Key::Down, Key::Enter]);
+        type_text(wizard, "openindiana/hipster:2024.12");
+        wizard.handle_key(Key::Enter);
+        type_text(wizard, "node01");
+        wizard.handle_key(Key::Enter);
+        press(wizard, &[Key::Tab, Key::Right, Key::Tab]);
+        type_text(wizard, "192.168.1.10/24");
+        wizard.handle_key(Key::Tab);
+        type_text(wizard, "9.9.9.9, 1.1.1.1");
+        press(wizard, &[Key::Enter, Key::Enter])
+    }
+
+    #[test]
+    fn builds_configuration_from_keys() {
+        let mut wizard = Wizard::new(hardware(), "aopc.cloud");
+        let Action::Install(mc) = mirror_config(&mut wizard) else {
+            panic!("wizard did not finish");
+        };
+        assert_eq!(
+            mc.to_kdl(),
+            r#"pool "rpool" {
+    vdev "mirror" {
+        disks "c1t0d0" "c1t1d0"
+    }
+}
+
+image "oci://aopc.cloud/openindiana/hipster:2024.12"
+
+sysconfig {
+    hostname "node01"
+    nameserver "9.9.9.9"
+    nameserver "1.1.1.1"
+    interface "net0" selector="mac:2:8:20:a:b:c" {
+        address name="v4" kind="static" "192.168.1.10/24"
+    }
+}
+"#
+        );
+    }
+
+    #[test]
+    fn checks_each_step() {
+        let mut wizard = Wizard::new(hardware(), "aopc.cloud");
+        wizard.handle_key(Key::Enter);
+        assert_eq!(wizard.step, Step::Disks);
+        assert!(wizard.message.is_some());
+
+        // A single disk only allows a stripe
+        press(&mut wizard, &[Key::Char(' '), Key::Enter]);
+        assert_eq!(wizard.layouts(), vec![VDevType::Stripe]);
+        press(&mut wizard, &[Key::Enter, Key::Enter]);
+        assert_eq!(wizard.step, Step::Hostname);
+        wizard.handle_key(Key::Enter);
+        assert_eq!(wizard.step, Step::Hostname);
+        press(&mut wizard, &[Key::Esc, Key::Esc, Key::Esc]);
+        assert_eq!(wizard.step, Step::Disks);
+        assert_eq!(wizard.handle_key(Key::Esc), Action::Quit);
+    }
+
+    #[test]
+    fn renders_the_review() {
+        let mut wizard = Wizard::new(hardware(), "aopc.cloud");
+        press(
+            &mut wizard,
+            &[Key::Char(' '), Key::Down, Key::Char(' '), Key::Enter],
+        );
+        press(&mut wizard, &[Key::Down, Key::Enter]);
+        type_text(&mut wizard, "openindiana/hipster:2024.12");
+        wizard.handle_key(Key::Enter);
+        type_text(&mut wizard, "node01");
+        press(&mut wizard, &[Key::Enter, Key::Enter]);
+        assert_eq!(wizard.step, Step::Preview);
+
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        terminal
+            .draw(|frame| wizard.render(frame, frame.area()))
+            .unwrap();
+        let screen = terminal
+            .backend()
+            .buffer()
+            .content()
+            .chunks(80)
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert!(screen.contains("Install: Review"));
+        assert!(screen.contains("All data on c1t0d0, c1t1d0 will be erased."));
+        assert!(screen.contains("vdev \"mirror\""));
+        assert!(screen.contains("kind=\"dhcp4\""));
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn installs_on_the_mock_platform() {
+        use crate::config::MachinedConfig;
+        use crate::machined::InstallSessionState;
+        use crate::session::InstallSessions;
+        use std::sync::Arc;
+
+        let mut wizard = Wizard::new(hardware(), "aopc.cloud");
+        let Action::Install(mc) = mirror_config(&mut wizard) else {
+            panic!("wizard did not finish");
+        };
+        let dir = std::env::temp_dir().join(format!("machined-console-{}", uuid::Uuid::new_v4()));
+        let sessions = InstallSessions::open(&dir, 20).unwrap();
+        let session = sessions
+            .start(
+                String::new(),
+                super::super::CONSOLE_OPERATOR,
+                *mc,
+                Arc::new(MachinedConfig::default()),
+            )
+            .unwrap();
+        let mut follower = session.follow();
+        while follower.recv().await.is_some() {}
+        assert_eq!(session.info().state(), InstallSessionState::Succeeded);
+        let report = session.report().unwrap();
+        assert_eq!(report.operator, "console");
+        assert!(report.boot_environment.starts_with("mock-"));
+        assert!(report.manifest_digest.starts_with("sha256:"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
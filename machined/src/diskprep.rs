@@ -0,0 +1,236 @@
+use crate::diskselect::disk_name;
+use crate::error::PrepareDisksError;
+use crate::machined::DiskInfo;
+use sha2::{Digest, Sha256};
+
+/// Number of hex digits of a confirmation token, short enough to type from a console
+const CONFIRMATION_LEN: usize = 16;
+
+/// Mount points of the running installer, the disks behind them are not wiped without force
+#[cfg(any(target_os = "illumos", target_os = "linux"))]
+#[cfg(not(feature = "mock"))]
+const PROTECTED_MOUNTS: [&str; 2] = ["/", "/usb"];
+
+/// Rows of `zpool list -v` heading the cache, log and spare devices of the pool above them
+#[cfg(any(target_os = "illumos", target_os = "linux"))]
+#[cfg(not(feature = "mock"))]
+const VDEV_CLASSES: [&str; 5] = ["cache", "dedup", "logs", "spare", "special"];
+
+/// A disk the running system uses, with what uses it
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiskInUse {
+    pub device: String,
+    /// `pool <name>` or the mount point the disk holds
+    pub used_by: String,
+}
+
+/// Look up the disks a PrepareDisks request names among the disks of the machine.
+///
+/// Only disks system information reports can be wiped, partitions and unknown devices
+/// are refused so a typo does not hit a disk nobody meant. Disks of imported pools and the
+/// disks holding the root or /usb of the installer are refused unless `force` is set.
+pub fn select_disks(
+    requested: &[String],
+    disks: &[DiskInfo],
+    in_use: &[DiskInUse],
+    force: bool,
+) -> Result<Vec<DiskInfo>, PrepareDisksError> {
+    if requested.is_empty() {
+        return Err(PrepareDisksError::NoDisks);
+    }
+    let mut selected: Vec<DiskInfo> = Vec::new();
+    for name in requested {
+        let disk = disks
+            .iter()
+            .find(|disk| disk.device == *name || disk.paths.contains(name))
+            .ok_or_else(|| {
+                if disk_name(name) != name.trim_start_matches("/dev/") {
+                    PrepareDisksError::NotAWholeDisk(name.clone())
+                } else {
+                    PrepareDisksError::UnknownDisk(name.clone())
+                }
+            })?;
+        if let Some(used) = in_use.iter().find(|used| used.device == disk.device) {
+            if !force {
+                return Err(PrepareDisksError::DiskInUse(
+                    disk.device.clone(),
+                    used.used_by.clone(),
+                ));
+            }
+        }
+        if !selected.iter().any(|s| s.device == disk.device) {
+            selected.push(disk.clone());
+        }
+    }
+    selected.sort_by(|a, b| a.device.cmp(&b.device));
+    Ok(selected)
+}
+
+/// Devices of the imported pools in the output of `zpool list -H -P -v -o name`, as pool
+/// name and device path
+#[cfg(any(target_os = "illumos", target_os = "linux"))]
+#[cfg(not(feature = "mock"))]
+pub fn pool_devices(listing: &str) -> Vec<(String, String)> {
+    let mut devices = Vec::new();
+    let mut pool = "";
+    for line in listing.lines() {
+        let name = line.trim_start().split('\t').next().unwrap_or_default();
+        if !line.starts_with(char::is_whitespace) {
+            if !VDEV_CLASSES.contains(&name) {
+                pool = name;
+            }
+        } else if name.starts_with('/') {
+            // Grouping rows like mirror-0, cache or logs name no device
+            devices.push((pool.to_string(), name.to_string()));
+        }
+    }
+    devices
+}
+
+/// Devices mounted at the protected mount points in `/etc/mnttab` or `/proc/self/mounts`, as
+/// mount point and device path. Datasets and pseudo file systems are left out.
+#[cfg(any(target_os = "illumos", target_os = "linux"))]
+#[cfg(not(feature = "mock"))]
+pub fn protected_mounts(mounts: &str) -> Vec<(String, String)> {
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let device = fields.next()?;
+            let mountpoint = fields.next()?;
+            (device.starts_with("/dev/") && PROTECTED_MOUNTS.contains(&mountpoint))
+                .then(|| (mountpoint.to_string(), device.to_string()))
+        })
+        .collect()
+}
+
+/// Token confirming that exactly these disks are wiped on this machine.
+///
+/// It is derived from the machine id and the names and serial numbers of the disks, so a
+/// token read off one machine does not wipe another or a disk swapped in meanwhile.
+pub fn confirmation_token(machine_id: &str, disks: &[DiskInfo], secure_erase: bool) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(machine_id.as_bytes());
+    hasher.update([secure_erase as u8]);
+    for disk in disks {
+        hasher.update([0]);
+        hasher.update(disk.device.as_bytes());
+        hasher.update([0]);
+        hasher.update(disk.serial.as_bytes());
+    }
+    let mut token = format!("{:x}", hasher.finalize());
+    token.truncate(CONFIRMATION_LEN);
+    token
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn disks() -> Vec<DiskInfo> {
+        ["c1t0d0", "c1t1d0", "c2t0d0"]
+            .iter()
+            .enumerate()
+            .map(|(idx, device)| DiskInfo {
+                device: device.to_string(),
+                serial: format!("S{}", idx),
+                paths: vec![format!("/dev/dsk/{}", device)],
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn selects_only_reported_disks() {
+        let selected = select_disks(
+            &["/dev/dsk/c1t1d0".to_string(), "c1t0d0".to_string()],
+            &disks(),
+            &[],
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            selected
+                .iter()
+                .map(|d| d.device.as_str())
+                .collect::<Vec<_>>(),
+            vec!["c1t0d0", "c1t1d0"]
+        );
+        assert_eq!(
+            select_disks(&[], &disks(), &[], false),
+            Err(PrepareDisksError::NoDisks)
+        );
+        assert_eq!(
+            select_disks(&["c3t0d0".to_string()], &disks(), &[], false),
+            Err(PrepareDisksError::UnknownDisk("c3t0d0".to_string()))
+        );
+        assert_eq!(
+            select_disks(&["c1t0d0s0".to_string()], &disks(), &[], false),
+            Err(PrepareDisksError::NotAWholeDisk("c1t0d0s0".to_string()))
+        );
+    }
+
+    #[test]
+    #[cfg(any(target_os = "illumos", target_os = "linux"))]
+    #[cfg(not(feature = "mock"))]
+    fn lists_disks_in_use() {
+        let listing = "rpool\t59.5G\t1.2G\t58.3G\n\
+                       \tmirror-0\t59.5G\t1.2G\t58.3G\n\
+                       \t  /dev/dsk/c1t0d0s0\t-\t-\t-\n\
+                       \t  /dev/dsk/c1t1d0s0\t-\t-\t-\n\
+                       cache\t-\t-\t-\n\
+                       \t/dev/dsk/c2t5d0\t-\t-\t-\n";
+        assert_eq!(
+            pool_devices(listing),
+            vec![
+                ("rpool".to_string(), "/dev/dsk/c1t0d0s0".to_string()),
+                ("rpool".to_string(), "/dev/dsk/c1t1d0s0".to_string()),
+                ("rpool".to_string(), "/dev/dsk/c2t5d0".to_string()),
+            ]
+        );
+        let mnttab = "/devices/ramdisk:a\t/\tufs\trw\t1700000000\n\
+                      swap\t/tmp\ttmpfs\txattr\t1700000000\n\
+                      /dev/dsk/c2t0d0p1\t/usb\tpcfs\trw\t1700000000\n";
+        assert_eq!(
+            protected_mounts(mnttab),
+            vec![("/usb".to_string(), "/dev/dsk/c2t0d0p1".to_string())]
+        );
+    }
+
+    #[test]
+    fn refuses_disks_in_use_unless_forced() {
+        let in_use = [DiskInUse {
+            device: "c1t0d0".to_string(),
+            used_by: "pool rpool".to_string(),
+        }];
+        let requested = ["c1t0d0".to_string(), "c2t0d0".to_string()];
+        assert_eq!(
+            select_disks(&requested, &disks(), &in_use, false),
+            Err(PrepareDisksError::DiskInUse(
+                "c1t0d0".to_string(),
+                "pool rpool".to_string()
+            ))
+        );
+        assert_eq!(
+            select_disks(&requested, &disks(), &in_use, true)
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn binds_confirmation_to_machine_and_disks() {
+        let disks = disks();
+        let token = confirmation_token("machine-a", &disks[..2], false);
+        assert_eq!(token.len(), CONFIRMATION_LEN);
+        assert_eq!(token, confirmation_token("machine-a", &disks[..2], false));
+        assert_ne!(token, confirmation_token("machine-b", &disks[..2], false));
+        assert_ne!(token, confirmation_token("machine-a", &disks[..1], false));
+        assert_ne!(token, confirmation_token("machine-a", &disks[..2], true));
+
+        let mut swapped = disks[..2].to_vec();
+        swapped[1].serial = "S9".to_string();
+        assert_ne!(token, confirmation_token("machine-a", &swapped, false));
+    }
+}
@@ -0,0 +1,399 @@
+use crate::error::DiskSelectorError;
+use crate::machined::DiskInfo;
+use machineconfig::Pool;
+use std::collections::HashSet;
+use std::str::FromStr;
+
+/// A vdev disk entry that picks disks by their properties instead of naming them.
+///
+/// Selectors are comma separated filters, e.g. `type:ssd,size:100G..2T,smallest:2`:
+/// - `serial:<serial>` - the disk with this serial number
+/// - `bay:<bay>` - the disk in this chassis bay or at this location code
+/// - `size:<min>..<max>` - disks within the size range, either bound may be left out
+/// - `type:ssd` or `type:hdd` - solid state or rotating disks
+/// - `smallest:<n>` - the n smallest of the matching disks instead of all of them
+///
+/// Removable disks and disks already used elsewhere in the configuration are never selected.
+#[derive(Debug, Default, PartialEq)]
+pub struct DiskSelector {
+    pub serial: Option<String>,
+    pub bay: Option<String>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub solid_state: Option<bool>,
+    pub smallest: Option<usize>,
+}
+
+/// Disks a selector of a pool was resolved to
+#[derive(Debug, PartialEq)]
+pub struct ResolvedSelector {
+    pub pool: String,
+    pub selector: String,
+    pub disks: Vec<String>,
+}
+
+impl FromStr for DiskSelector {
+    type Err = DiskSelectorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || DiskSelectorError::InvalidSelector(s.to_string());
+        let mut selector = DiskSelector::default();
+        for filter in s.split(',') {
+            let (key, value) = filter.split_once(':').ok_or_else(invalid)?;
+            let value = value.trim();
+            match key.trim() {
+                "serial" => selector.serial = Some(value.to_string()),
+                "bay" => selector.bay = Some(value.to_string()),
+                "size" => {
+                    let (min, max) = value.split_once("..").ok_or_else(invalid)?;
+                    if !min.is_empty() {
+                        selector.min_size = Some(parse_size(min).ok_or_else(invalid)?);
+                    }
+                    if !max.is_empty() {
+                        selector.max_size = Some(parse_size(max).ok_or_else(invalid)?);
+                    }
+                }
+                "type" => match value.to_lowercase().as_str() {
+                    "ssd" => selector.solid_state = Some(true),
+                    "hdd" => selector.solid_state = Some(false),
+                    _ => return Err(invalid()),
+                },
+                "smallest" => {
+                    let count = value.parse::<usize>().map_err(|_| invalid())?;
+                    if count == 0 {
+                        return Err(invalid());
+                    }
+                    selector.smallest = Some(count);
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(selector)
+    }
+}
+
+impl DiskSelector {
+    fn matches(&self, disk: &DiskInfo) -> bool {
+        if disk.removable {
+            return false;
+        }
+        if let Some(serial) = &self.serial {
+            if !disk.serial.eq_ignore_ascii_case(serial) {
+                return false;
+            }
+        }
+        if let Some(bay) = &self.bay {
+            if disk.chassis_bay.trim() != bay && disk.location_code.trim() != bay {
+                return false;
+            }
+        }
+        if self.min_size.is_some_and(|min| disk.size_bytes < min) {
+            return false;
+        }
+        if self.max_size.is_some_and(|max| disk.size_bytes > max) {
+            return false;
+        }
+        if self
+            .solid_state
+            .is_some_and(|solid_state| disk.solid_state != solid_state)
+        {
+            return false;
+        }
+        true
+    }
+}
+
+/// Keys of the filters selectors are made of
+const SELECTOR_KEYS: [&str; 5] = ["serial", "bay", "size", "type", "smallest"];
+
+/// Whether a vdev disk entry is a selector rather than a device name.
+///
+/// Device names may contain colons as well, e.g. `/dev/disk/by-path/pci-0000:00:1f.2-ata-1` on
+/// Linux, so only entries starting with the key of a filter are selectors.
+pub fn is_selector(entry: &str) -> bool {
+    entry
+        .split_once(':')
+        .is_some_and(|(key, _)| SELECTOR_KEYS.contains(&key.trim()))
+}
+
+/// Name of the whole disk a vdev member refers to (`/dev/dsk/c0t0d0s0` -> `c0t0d0`)
+pub fn disk_name(disk: &str) -> &str {
+    let name = disk
+        .strip_prefix("/dev/dsk/")
+        .or_else(|| disk.strip_prefix("/dev/"))
+        .unwrap_or(disk);
+    match name.rfind(['s', 'p']) {
+        Some(idx)
+            if idx + 1 < name.len()
+                && name[idx + 1..].chars().all(|c| c.is_ascii_digit())
+                && name[..idx].contains('d') =>
+        {
+            &name[..idx]
+        }
+        _ => name,
+    }
+}
+
+/// Device of the disk a literal vdev entry names, looked up among the paths of the disks so
+/// `/dev/disk/by-id` links and their `-part<n>` partitions match the disk they point at
+fn literal_device(entry: &str, disks: &[DiskInfo]) -> String {
+    let whole_disk = entry
+        .rsplit_once("-part")
+        .filter(|(_, part)| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+        .map_or(entry, |(disk, _)| disk);
+    let name = disk_name(entry);
+    disks
+        .iter()
+        .find(|disk| {
+            disk.device == name
+                || disk
+                    .paths
+                    .iter()
+                    .any(|path| path == entry || path == whole_disk)
+        })
+        .map_or_else(|| name.to_string(), |disk| disk.device.clone())
+}
+
+/// Replace the selectors in the vdevs, cache and spare devices of `pools` with the names of
+/// the disks they select.
+///
+/// Selectors are resolved in configuration order, so earlier selectors get the first pick.
+pub fn resolve_disk_selectors(
+    pools: &mut [Pool],
+    disks: &[DiskInfo],
+) -> Result<Vec<ResolvedSelector>, DiskSelectorError> {
+    let mut used = pools
+        .iter()
+        .flat_map(|pool| pool.disks())
+        .filter(|entry| !is_selector(entry))
+        .map(|entry| literal_device(entry, disks))
+        .collect::<HashSet<_>>();
+
+    let mut resolved = Vec::new();
+    for pool in pools.iter_mut() {
+        let pool_name = pool.name.clone();
+        for disk_list in pool.disk_lists_mut() {
+            let mut names = Vec::with_capacity(disk_list.len());
+            for entry in disk_list.iter() {
+                if !is_selector(entry) {
+                    names.push(entry.clone());
+                    continue;
+                }
+                let selector = entry.parse::<DiskSelector>()?;
+                let mut candidates = disks
+                    .iter()
+                    .filter(|disk| !used.contains(&disk.device) && selector.matches(disk))
+                    .collect::<Vec<_>>();
+                candidates.sort_by(|a, b| {
+                    a.size_bytes
+                        .cmp(&b.size_bytes)
+                        .then_with(|| a.device.cmp(&b.device))
+                });
+                if let Some(count) = selector.smallest {
+                    if candidates.len() < count {
+                        return Err(DiskSelectorError::NotEnoughDisks {
+                            selector: entry.clone(),
+                            wanted: count,
+                            found: candidates.len(),
+                        });
+                    }
+                    candidates.truncate(count);
+                }
+                if candidates.is_empty() {
+                    return Err(DiskSelectorError::NoMatch(entry.clone()));
+                }
+                let selected = candidates
+                    .iter()
+                    .map(|disk| disk.device.clone())
+                    .collect::<Vec<_>>();
+                used.extend(selected.iter().cloned());
+                names.extend(selected.iter().cloned());
+                resolved.push(ResolvedSelector {
+                    pool: pool_name.clone(),
+                    selector: entry.clone(),
+                    disks: selected,
+                });
+            }
+            *disk_list = names;
+        }
+    }
+    Ok(resolved)
+}
+
+/// Parse a size like `512G` or `1.5T` into bytes, units are powers of 1024
+pub fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim().to_uppercase();
+    let size = size
+        .strip_suffix("IB")
+        .or_else(|| size.strip_suffix('B'))
+        .unwrap_or(&size);
+    let (number, multiplier) = match size.chars().last()? {
+        'K' => (&size[..size.len() - 1], 1u64 << 10),
+        'M' => (&size[..size.len() - 1], 1 << 20),
+        'G' => (&size[..size.len() - 1], 1 << 30),
+        'T' => (&size[..size.len() - 1], 1 << 40),
+        'P' => (&size[..size.len() - 1], 1 << 50),
+        _ => (size, 1),
+    };
+    let number = number.trim().parse::<f64>().ok()?;
+    if number < 0.0 {
+        return None;
+    }
+    Some((number * multiplier as f64) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use machineconfig::{VDev, VDevType};
+
+    const GIB: u64 = 1 << 30;
+
+    fn disk(device: &str, serial: &str, bay: &str, size_gib: u64, ssd: bool) -> DiskInfo {
+        DiskInfo {
+            device: device.to_string(),
+            serial: serial.to_string(),
+            chassis_bay: bay.to_string(),
+            size_bytes: size_gib * GIB,
+            solid_state: ssd,
+            ..Default::default()
+        }
+    }
+
+    fn disks() -> Vec<DiskInfo> {
+        let mut usb = disk("c9t0d0", "USB1", "", 16, false);
+        usb.removable = true;
+        vec![
+            disk("c1t0d0", "S1", "Slot 0", 480, true),
+            disk("c1t1d0", "S2", "Slot 1", 240, true),
+            disk("c2t0d0", "H1", "Slot 2", 4000, false),
+            disk("c2t1d0", "H2", "Slot 3", 4000, false),
+            disk("c2t2d0", "H3", "Slot 4", 8000, false),
+            usb,
+        ]
+    }
+
+    fn pool(vdevs: &[&[&str]]) -> Vec<Pool> {
+        vec![Pool {
+            name: String::from("rpool"),
+            vdevs: vdevs
+                .iter()
+                .map(|disks| VDev {
+                    kind: VDevType::Mirror,
+                    disks: disks.iter().map(|d| d.to_string()).collect(),
+                })
+                .collect(),
+            ..Default::default()
+        }]
+    }
+
+    #[test]
+    fn parses_selectors() {
+        assert_eq!(
+            "type:ssd,size:100G..1.5T,smallest:2"
+                .parse::<DiskSelector>()
+                .unwrap(),
+            DiskSelector {
+                min_size: Some(100 * GIB),
+                max_size: Some(1536 * GIB),
+                solid_state: Some(true),
+                smallest: Some(2),
+                ..Default::default()
+            }
+        );
+        assert!("size:100G".parse::<DiskSelector>().is_err());
+        assert!("type:nvme".parse::<DiskSelector>().is_err());
+        assert!("smallest:0".parse::<DiskSelector>().is_err());
+        assert!("colour:red".parse::<DiskSelector>().is_err());
+    }
+
+    #[test]
+    fn resolves_selectors_in_order() {
+        let mut pools = pool(&[&["smallest:2"], &["serial:h3", "bay:Slot 2"], &["type:hdd"]]);
+        let resolved = resolve_disk_selectors(&mut pools, &disks()).unwrap();
+        let vdevs = pools[0].disk_lists().cloned().collect::<Vec<_>>();
+        assert_eq!(
+            vdevs,
+            vec![
+                vec!["c1t1d0", "c1t0d0"],
+                vec!["c2t2d0", "c2t0d0"],
+                vec!["c2t1d0"],
+                vec![],
+                vec![],
+            ]
+        );
+        assert_eq!(resolved.len(), 4);
+        assert_eq!(resolved[0].selector, "smallest:2");
+        assert_eq!(resolved[0].disks, vec!["c1t1d0", "c1t0d0"]);
+    }
+
+    #[test]
+    fn resolves_selectors_of_cache_and_spare_devices() {
+        let mut pools = pool(&[&["type:hdd,smallest:2"]]);
+        pools[0].cache = vec![String::from("type:ssd,smallest:1")];
+        pools[0].spare = vec![String::from("type:hdd")];
+        resolve_disk_selectors(&mut pools, &disks()).unwrap();
+        assert_eq!(pools[0].vdevs[0].disks, vec!["c2t0d0", "c2t1d0"]);
+        assert_eq!(pools[0].cache, vec!["c1t1d0"]);
+        assert_eq!(pools[0].spare, vec!["c2t2d0"]);
+    }
+
+    #[test]
+    fn literal_disks_are_not_selected() {
+        let mut pools = pool(&[&["c1t1d0", "type:ssd"]]);
+        resolve_disk_selectors(&mut pools, &disks()).unwrap();
+        assert_eq!(pools[0].vdevs[0].disks, vec!["c1t1d0", "c1t0d0"]);
+    }
+
+    #[test]
+    fn device_names_with_colons_are_not_selectors() {
+        let by_path = "/dev/disk/by-path/pci-0000:00:1f.2-ata-1";
+        assert!(!is_selector(by_path));
+        assert!(!is_selector("usb-SanDisk_Cruzer_Blade_4C530001-0:0"));
+        assert!(is_selector("type:ssd,smallest:1"));
+
+        let mut disks = disks();
+        disks.push(disk(by_path, "L1", "", 960, true));
+        let mut pools = pool(&[&[by_path, "type:ssd,smallest:1"]]);
+        resolve_disk_selectors(&mut pools, &disks).unwrap();
+        assert_eq!(pools[0].vdevs[0].disks, vec![by_path, "c1t1d0"]);
+    }
+
+    #[test]
+    fn literal_disk_links_are_not_selected() {
+        let by_id = "/dev/disk/by-id/nvme-Samsung_SSD_980_S1";
+        let mut disks = disks();
+        for disk in disks.iter_mut() {
+            disk.paths = vec![format!("/dev/dsk/{}", disk.device)];
+        }
+        disks[1].paths.push(by_id.to_string());
+
+        let mut pools = pool(&[&[by_id, "type:ssd,smallest:1"]]);
+        resolve_disk_selectors(&mut pools, &disks).unwrap();
+        assert_eq!(pools[0].vdevs[0].disks, vec![by_id, "c1t0d0"]);
+
+        let part = format!("{}-part1", by_id);
+        let mut pools = pool(&[&[part.as_str(), "type:ssd,smallest:1"]]);
+        resolve_disk_selectors(&mut pools, &disks).unwrap();
+        assert_eq!(pools[0].vdevs[0].disks, vec![part.as_str(), "c1t0d0"]);
+    }
+
+    #[test]
+    fn reports_unsatisfiable_selectors() {
+        let mut pools = pool(&[&["type:ssd,smallest:3"]]);
+        assert_eq!(
+            resolve_disk_selectors(&mut pools, &disks()),
+            Err(DiskSelectorError::NotEnoughDisks {
+                selector: String::from("type:ssd,smallest:3"),
+                wanted: 3,
+                found: 2,
+            })
+        );
+
+        let mut pools = pool(&[&["serial:USB1"]]);
+        assert_eq!(
+            resolve_disk_selectors(&mut pools, &disks()),
+            Err(DiskSelectorError::NoMatch(String::from("serial:USB1")))
+        );
+    }
+}
@@ -10,24 +10,90 @@ use tokio::sync::mpsc::error::SendError;
 pub enum InstallationError {
     #[error(transparent)]
     SendError(#[from] SendError<InstallProgress>),
+    #[cfg(any(target_os = "illumos", target_os = "linux"))]
+    #[cfg(not(feature = "mock"))]
     #[error("failed to create zfs pool\n {0}")]
     ZpoolCreateFailed(String),
-    #[error("boot environment name is invalid")]
-    InvalidBootEnvironmentName,
+    #[error("boot environment name is invalid: {0}")]
+    InvalidBootEnvironmentName(String),
+    #[cfg(all(target_os = "illumos", not(feature = "mock")))]
     #[error("beadm execution failed: {0}")]
     BeadmFailed(String),
+    #[cfg(any(target_os = "illumos", target_os = "linux"))]
+    #[cfg(not(feature = "mock"))]
     #[error("bootloader installation failed: {0}")]
     InstallBootLoaderFailed(String),
-    #[error("failed to create rpool/ROOT dataset {0}")]
-    BaseRootDSCreateFailed(String),
+    #[cfg(any(target_os = "illumos", target_os = "linux"))]
+    #[cfg(not(feature = "mock"))]
+    #[error("failed to create dataset {0}")]
+    DatasetCreateFailed(String),
+    #[cfg(any(target_os = "illumos", target_os = "linux"))]
+    #[cfg(not(feature = "mock"))]
+    #[error("failed to create volume {0}")]
+    VolumeCreateFailed(String),
+    #[cfg(any(target_os = "illumos", target_os = "linux"))]
+    #[cfg(not(feature = "mock"))]
+    #[error("failed to destroy zfs pool {0}")]
+    ZpoolDestroyFailed(String),
+    #[cfg(all(target_os = "illumos", not(feature = "mock")))]
+    #[error("failed to destroy dataset {0}")]
+    ZfsDestroyFailed(String),
+    #[cfg(all(target_os = "illumos", not(feature = "mock")))]
+    #[error("failed to unmount {0}")]
+    UnmountFailed(String),
+    #[cfg(any(target_os = "illumos", target_os = "linux"))]
+    #[cfg(not(feature = "mock"))]
+    #[error("failed to list zfs pools or datasets {0}")]
+    ZfsListFailed(String),
+    #[cfg(any(target_os = "illumos", target_os = "linux"))]
+    #[cfg(not(feature = "mock"))]
+    #[error("failed to export zfs pool {0}")]
+    PoolExportFailed(String),
+    #[cfg(any(target_os = "illumos", target_os = "linux"))]
+    #[cfg(not(feature = "mock"))]
+    #[error("failed to wipe disk {0}: {1}")]
+    WipeDiskFailed(String, String),
+    #[cfg(all(target_os = "illumos", not(feature = "mock")))]
+    #[error("secure erase is not supported on this platform")]
+    SecureEraseUnsupported,
+    #[error("no boot environment named {0}")]
+    BootEnvironmentNotFound(String),
+    #[cfg(any(target_os = "illumos", target_os = "linux"))]
+    #[cfg(not(feature = "mock"))]
+    #[error("failed to {0}: {1}")]
+    PowerControlFailed(String, String),
+    #[error("no network link matches {0}")]
+    InterfaceNotFound(String),
+    #[error("interface selector {0} is not supported, expected mac:<address>")]
+    InvalidInterfaceSelector(String),
+    #[cfg(any(target_os = "illumos", target_os = "linux"))]
+    #[cfg(not(feature = "mock"))]
+    #[error("static address {0} has no address configured")]
+    MissingStaticAddress(String),
+    #[cfg(all(target_os = "linux", not(feature = "mock")))]
+    #[error("failed to partition disk {0}")]
+    PartitionFailed(String),
+    #[cfg(any(target_os = "illumos", target_os = "linux"))]
+    #[cfg(not(feature = "mock"))]
+    #[error("failed to mount {0}")]
+    MountFailed(String),
+    #[cfg(all(target_os = "linux", not(feature = "mock")))]
+    #[error("configuration has no pool named {0} to install into")]
+    NoRootPool(String),
+    #[cfg(any(target_os = "illumos", target_os = "linux"))]
+    #[cfg(not(feature = "mock"))]
     #[error("failed to set property {0}")]
     ZfsSetFailed(String),
+    #[cfg(any(target_os = "illumos", target_os = "linux"))]
+    #[cfg(not(feature = "mock"))]
     #[error("requested installation image does not exist")]
     NoManifestFound,
+    #[cfg(any(target_os = "illumos", target_os = "linux"))]
+    #[cfg(not(feature = "mock"))]
     #[error("requested image does not support {0}")]
     NoManifestMatchesArch(String),
-    #[error("cache base directory does not exist")]
-    BaseDirDoesNotExist,
+    #[cfg(any(target_os = "illumos", target_os = "linux"))]
+    #[cfg(not(feature = "mock"))]
     #[error("cannot create image reference {0}")]
     CannotCreateImageReference(String),
     #[error(transparent)]
@@ -38,14 +104,201 @@ pub enum InstallationError {
     JsonError(#[from] serde_json::error::Error),
     #[error(transparent)]
     StringConvert(#[from] std::string::FromUtf8Error),
-    #[error("failed to download blob")]
-    BlobDownloadFailed,
+    #[cfg(any(target_os = "illumos", target_os = "linux"))]
+    #[cfg(not(feature = "mock"))]
+    #[error("blob {blob} has digest {digest}")]
+    DigestMismatch { blob: String, digest: String },
+    #[cfg(any(target_os = "illumos", target_os = "linux"))]
+    #[cfg(not(feature = "mock"))]
+    #[error("layer {layer} has digest {digest}, the files extracted from it cannot be trusted")]
+    UnverifiedLayer { layer: String, digest: String },
+    #[cfg(any(target_os = "illumos", target_os = "linux"))]
+    #[cfg(not(feature = "mock"))]
+    #[error("digest algorithm {0} is not supported")]
+    UnsupportedDigestAlgorithm(String),
+    #[cfg(any(target_os = "illumos", target_os = "linux"))]
+    #[cfg(not(feature = "mock"))]
+    #[error("{0} is not an OCI image layout")]
+    InvalidImageLayout(String),
+    #[cfg(any(target_os = "illumos", target_os = "linux"))]
+    #[cfg(not(feature = "mock"))]
+    #[error("image layout {0} holds several images, select one by tag or digest")]
+    AmbiguousImageLayout(String),
+    #[cfg(any(target_os = "illumos", target_os = "linux"))]
+    #[cfg(not(feature = "mock"))]
     #[error("Artifact manifests are not supported for download")]
     ArtifactManifestsNotSupported,
-    #[error("tar return non-zero exit code")]
-    TarReturnNonzeroExitCode,
+    #[cfg(any(target_os = "illumos", target_os = "linux"))]
+    #[cfg(not(feature = "mock"))]
+    #[error("layers compressed with {0} are not supported")]
+    UnsupportedLayerCompression(String),
+    #[cfg(any(target_os = "illumos", target_os = "linux"))]
+    #[cfg(not(feature = "mock"))]
+    #[error("layer entry {0} is outside of the image root")]
+    InvalidLayerEntry(String),
+    #[cfg(any(target_os = "illumos", target_os = "linux"))]
+    #[cfg(not(feature = "mock"))]
+    #[error("too many levels of symbolic links resolving {0} in the image")]
+    SymlinkLoop(String),
+    #[cfg(any(target_os = "illumos", target_os = "linux"))]
+    #[cfg(not(feature = "mock"))]
+    #[error("failed to set extended attribute {0} of {1}: {2}")]
+    XattrFailed(String, String, io::Error),
+    #[cfg(any(target_os = "illumos", target_os = "linux"))]
+    #[cfg(not(feature = "mock"))]
+    #[error("layer extraction failed: {0}")]
+    LayerExtractionFailed(String),
+    #[cfg(any(target_os = "illumos", target_os = "linux"))]
+    #[cfg(not(feature = "mock"))]
+    #[error("postinstall path {0} must be an absolute path inside the installed system")]
+    InvalidPostinstallPath(String),
+    #[cfg(any(target_os = "illumos", target_os = "linux"))]
+    #[cfg(not(feature = "mock"))]
+    #[error("user or group {0} does not exist in the installed system")]
+    UnknownOwner(String),
+    #[cfg(all(target_os = "illumos", not(feature = "mock")))]
+    #[error("package installation failed: {0}")]
+    PackageInstallFailed(String),
+    #[cfg(any(target_os = "illumos", target_os = "linux"))]
+    #[cfg(not(feature = "mock"))]
+    #[error("postinstall script {0} failed: {1}")]
+    PostinstallScriptFailed(String, String),
+    #[cfg(all(target_os = "linux", not(feature = "mock")))]
+    #[error("postinstall {0} are not supported on this platform")]
+    PostinstallUnsupported(String),
+    #[error(transparent)]
+    MachineConfig(#[from] machineconfig::Error),
+    #[cfg(any(target_os = "illumos", target_os = "linux"))]
+    #[cfg(not(feature = "mock"))]
     #[error("Send Failed")]
     SendFailed,
     #[error(transparent)]
     JwtError(#[from] JwtError),
+    #[error(transparent)]
+    DiskSelector(#[from] DiskSelectorError),
+    #[error("failed to write install report {0}: {1}")]
+    WriteReport(String, io::Error),
+    /// Failure the hardware fixture of the mock platform injects into an install
+    #[cfg(any(feature = "mock", not(any(target_os = "illumos", target_os = "linux"))))]
+    #[error("{0}")]
+    Simulated(String),
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum DiskSelectorError {
+    #[error("disk selector {0} is not valid")]
+    InvalidSelector(String),
+    #[error("no disk matches selector {0}")]
+    NoMatch(String),
+    #[error("disk selector {selector} wants {wanted} disks but only {found} match")]
+    NotEnoughDisks {
+        selector: String,
+        wanted: usize,
+        found: usize,
+    },
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum PrepareDisksError {
+    #[error("no disks given to prepare")]
+    NoDisks,
+    #[error("disk {0} is not reported by this machine")]
+    UnknownDisk(String),
+    #[error("{0} is a partition, only whole disks can be prepared")]
+    NotAWholeDisk(String),
+    #[error("disk {0} is used by {1} of the running system, force to wipe it anyway")]
+    DiskInUse(String, String),
+    #[error("confirmation does not match these disks on this machine")]
+    ConfirmationMismatch,
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum SmbiosError {
+    #[error("no SMBIOS entry point found")]
+    NoEntryPoint,
+    #[error("SMBIOS entry point checksum is invalid")]
+    BadChecksum,
+    #[error("SMBIOS structure table is truncated at offset {0}")]
+    Truncated(usize),
+    #[error("SMBIOS structure table at {0:#x} is outside of the data read")]
+    TableOutOfRange(u64),
+}
+
+#[derive(Debug, Error)]
+pub enum ClaimError {
+    #[error("command server public key is invalid: {0}")]
+    InvalidPublicKey(String),
+    #[error("claim payload verification failed: {0}")]
+    VerificationFailed(String),
+    #[error("claim payload has expired")]
+    Expired,
+    #[error("claim payload does not carry an expiry")]
+    MissingExpiry,
+    #[error("claim payload does not name a machine")]
+    MissingMachineId,
+    #[error("claim payload is for machine {0} not this one")]
+    WrongMachine(String),
+}
+
+#[derive(Debug, Error)]
+pub enum TokenError {
+    #[error("claim token is invalid: {0}")]
+    Invalid(String),
+    #[error("claim token has expired")]
+    Expired,
+    #[error("claim token is for machine {0} not this one")]
+    WrongMachine(String),
+    #[error("the machine was claimed more than {0} hours ago, claim it again")]
+    ClaimTooOld(u64),
+    #[error("failed to sign claim token: {0}")]
+    SigningFailed(String),
+}
+
+#[derive(Debug, Error)]
+pub enum CallHomeError {
+    #[error("command server URL {0} is invalid")]
+    InvalidUrl(String),
+    #[error("failed to connect to the command server: {0}")]
+    Transport(#[from] tonic::transport::Error),
+    #[error("command server responded with error: {0}")]
+    Status(#[from] tonic::Status),
+    #[error("assigned machine configuration is invalid: {0}")]
+    InvalidAssignment(String),
+    #[error("assignment is not signed by the command server")]
+    UnsignedAssignment,
+    #[error("assignment signature is invalid: {0}")]
+    InvalidSignature(String),
+    #[error("command server {0} is neither https:// nor inside the WireGuard tunnel")]
+    PlaintextOutsideTunnel(String),
+    #[error("install session {0} has already been started on this machine")]
+    SessionExists(String),
+    #[error(transparent)]
+    Claim(#[from] ClaimError),
+}
+
+#[derive(Debug, Error)]
+pub enum ConsoleError {
+    #[error("failed to open console {0}: {1}")]
+    Open(String, io::Error),
+    #[error("failed to switch the console to raw mode: {0}")]
+    RawMode(#[from] nix::errno::Errno),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+#[cfg(any(feature = "mock", not(any(target_os = "illumos", target_os = "linux"))))]
+#[derive(Debug, Error)]
+pub enum FixtureError {
+    #[error("failed to load hardware fixture {0}: {1}")]
+    Load(String, config::ConfigError),
+    #[error("failed to read SMBIOS dump {0}: {1}")]
+    ReadSmbios(String, io::Error),
+    #[error("SMBIOS dump {0} is invalid: {1}")]
+    InvalidSmbios(String, SmbiosError),
+    #[error("size {0} is not valid")]
+    InvalidSize(String),
+    #[error("firmware {0} is not known, expected uefi or bios")]
+    UnknownFirmware(String),
+    #[error("install phase {0} is not known")]
+    UnknownPhase(String),
 }
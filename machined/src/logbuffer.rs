@@ -0,0 +1,96 @@
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use tracing_subscriber::fmt::MakeWriter;
+
+/// Number of log lines kept for the GetLog RPC
+const MAX_LOG_LINES: usize = 5000;
+
+/// Keeps the last lines machined logged so operators can fetch them without the console
+#[derive(Debug, Clone, Default)]
+pub struct LogBuffer {
+    lines: Arc<Mutex<VecDeque<String>>>,
+}
+
+impl LogBuffer {
+    /// The last `count` lines, oldest first, all kept lines if `count` is 0
+    pub fn tail(&self, count: usize) -> Vec<String> {
+        let lines = self.lines.lock().unwrap();
+        let skip = if count == 0 {
+            0
+        } else {
+            lines.len().saturating_sub(count)
+        };
+        lines.iter().skip(skip).cloned().collect()
+    }
+
+    fn push(&self, data: &[u8]) {
+        let mut lines = self.lines.lock().unwrap();
+        for line in String::from_utf8_lossy(data).lines() {
+            if line.is_empty() {
+                continue;
+            }
+            if lines.len() == MAX_LOG_LINES {
+                lines.pop_front();
+            }
+            lines.push_back(line.to_string());
+        }
+    }
+}
+
+impl<'a> MakeWriter<'a> for LogBuffer {
+    type Writer = LogWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        LogWriter {
+            buffer: self.clone(),
+            pending: Vec::new(),
+        }
+    }
+}
+
+/// Collects one event and adds its lines to the buffer when it is dropped
+pub struct LogWriter {
+    buffer: LogBuffer,
+    pending: Vec<u8>,
+}
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for LogWriter {
+    fn drop(&mut self) {
+        self.buffer.push(&self.pending);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_last_lines() {
+        let buffer = LogBuffer::default();
+        for idx in 0..MAX_LOG_LINES + 2 {
+            let mut writer = buffer.make_writer();
+            writeln!(writer, "line {}", idx).unwrap();
+        }
+        assert_eq!(buffer.tail(0).len(), MAX_LOG_LINES);
+        assert_eq!(buffer.tail(0)[0], "line 2");
+        assert_eq!(
+            buffer.tail(2),
+            vec![
+                format!("line {}", MAX_LOG_LINES),
+                format!("line {}", MAX_LOG_LINES + 1)
+            ]
+        );
+    }
+}
@@ -1,6 +1,12 @@
 // This file is @generated by prost-build.
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ClaimRequest {
+    /// Who claims the machine, recorded in the token. A claim payload naming an operator overrides it
+    #[prost(string, tag = "3")]
+    pub operator: ::prost::alloc::string::String,
+    /// Scopes to request, all scopes the secret allows when empty
+    #[prost(enumeration = "TokenScope", repeated, tag = "4")]
+    pub scopes: ::prost::alloc::vec::Vec<i32>,
     #[prost(oneof = "claim_request::ClaimSecret", tags = "1, 2")]
     pub claim_secret: ::core::option::Option<claim_request::ClaimSecret>,
 }
@@ -18,16 +24,49 @@ pub mod claim_request {
 pub struct ClaimResponse {
     #[prost(string, tag = "1")]
     pub claim_token: ::prost::alloc::string::String,
+    /// Unix timestamp the token expires at
+    #[prost(int64, tag = "2")]
+    pub expires_at: i64,
+    /// Operator the token was issued to
+    #[prost(string, tag = "3")]
+    pub operator: ::prost::alloc::string::String,
+    /// Scopes the token grants
+    #[prost(enumeration = "TokenScope", repeated, tag = "4")]
+    pub scopes: ::prost::alloc::vec::Vec<i32>,
 }
+/// Issue a new token with the operator and scopes of the token the request carries
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct RefreshTokenRequest {}
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct InstallConfig {
     #[prost(string, tag = "2")]
     pub machineconfig: ::prost::alloc::string::String,
+    /// Optional human readable name of the install session
+    #[prost(string, tag = "3")]
+    pub session_name: ::prost::alloc::string::String,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct InstallProgress {
     #[prost(enumeration = "ProgressLevel", tag = "1")]
     pub level: i32,
+    /// Install session this progress message belongs to
+    #[prost(string, tag = "4")]
+    pub session_id: ::prost::alloc::string::String,
+    /// Phase the installation is in
+    #[prost(enumeration = "InstallPhase", tag = "5")]
+    pub phase: i32,
+    /// 1-based index of the current item (pool, layer) in the phase
+    #[prost(uint32, tag = "6")]
+    pub step: u32,
+    /// Number of items in the phase (0 if unknown)
+    #[prost(uint32, tag = "7")]
+    pub total_steps: u32,
+    /// Bytes downloaded or extracted of the current item
+    #[prost(uint64, tag = "8")]
+    pub bytes_done: u64,
+    /// Size of the current item in bytes (0 if unknown)
+    #[prost(uint64, tag = "9")]
+    pub bytes_total: u64,
     #[prost(oneof = "install_progress::Message", tags = "2, 3")]
     pub message: ::core::option::Option<install_progress::Message>,
 }
@@ -41,12 +80,66 @@ pub mod install_progress {
         Error(::prost::alloc::string::String),
     }
 }
-/// Request message for GetSystemInfo RPC
-///
-/// Empty for now, may add filters or options in the future
+/// An install tracked by machined, independent of the client that started it
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct InstallSession {
+    /// Unique session id
+    #[prost(string, tag = "1")]
+    pub id: ::prost::alloc::string::String,
+    /// Human readable name
+    #[prost(string, tag = "2")]
+    pub name: ::prost::alloc::string::String,
+    /// Current state
+    #[prost(enumeration = "InstallSessionState", tag = "3")]
+    pub state: i32,
+    /// Unix timestamp the install started
+    #[prost(int64, tag = "4")]
+    pub started_at: i64,
+    /// Unix timestamp the install finished (0 while running)
+    #[prost(int64, tag = "5")]
+    pub finished_at: i64,
+    /// Number of recorded progress messages
+    #[prost(uint64, tag = "6")]
+    pub events: u64,
+}
 #[derive(Clone, Copy, PartialEq, ::prost::Message)]
-pub struct SystemInfoRequest {}
+pub struct ListInstallSessionsRequest {}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListInstallSessionsResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub sessions: ::prost::alloc::vec::Vec<InstallSession>,
+}
+/// Reattach to a session. The recorded history is replayed first,
+/// then the stream follows the install until it finishes.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AttachInstallSessionRequest {
+    /// Session id or name
+    #[prost(string, tag = "1")]
+    pub session: ::prost::alloc::string::String,
+}
+/// Cancel a running install. It stops before its next step and rolls back the
+/// steps it completed, the session is CANCELLED once the rollback is done.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CancelInstallSessionRequest {
+    /// Session id or name
+    #[prost(string, tag = "1")]
+    pub session: ::prost::alloc::string::String,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CancelInstallSessionResponse {
+    #[prost(message, optional, tag = "1")]
+    pub session: ::core::option::Option<InstallSession>,
+}
+/// Request message for GetSystemInfo RPC. PCI devices, CPU, memory and firmware
+/// are left empty when they cannot be collected, unless they are asked for.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SystemInfoRequest {
+    /// Sections to collect, all of them when empty
+    #[prost(enumeration = "SystemInfoSection", repeated, tag = "1")]
+    pub sections: ::prost::alloc::vec::Vec<i32>,
+}
 /// Disk information structure
+#[derive(serde::Serialize)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct DiskInfo {
     /// Device name (e.g., c0t0d0)
@@ -84,6 +177,7 @@ pub struct DiskInfo {
     pub chassis_bay: ::prost::alloc::string::String,
 }
 /// Partition information structure
+#[derive(serde::Serialize)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct PartitionInfo {
     /// Partition device name (e.g., c0t0d0s0 or c0t0d0p1)
@@ -97,6 +191,7 @@ pub struct PartitionInfo {
     pub parent_device: ::prost::alloc::string::String,
 }
 /// Network interface information structure
+#[derive(serde::Serialize)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct NetworkInterface {
     /// Interface name (e.g., net0)
@@ -128,6 +223,7 @@ pub struct NetworkInterface {
     pub mtu: ::prost::alloc::string::String,
 }
 /// BIOS information structure (SMBIOS Type 0)
+#[derive(serde::Serialize)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct BiosInfo {
     /// Vendor name
@@ -162,6 +258,7 @@ pub struct BiosInfo {
     pub version_number: ::prost::alloc::string::String,
 }
 /// System information structure (SMBIOS Type 1)
+#[derive(serde::Serialize)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct SystemInfo {
     /// Manufacturer name
@@ -190,6 +287,7 @@ pub struct SystemInfo {
     pub family: ::prost::alloc::string::String,
 }
 /// Baseboard information structure (SMBIOS Type 2)
+#[derive(serde::Serialize)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct BaseboardInfo {
     /// Manufacturer name
@@ -221,6 +319,7 @@ pub struct BaseboardInfo {
     pub board_type: u32,
 }
 /// Chassis information structure (SMBIOS Type 3)
+#[derive(serde::Serialize)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ChassisInfo {
     /// Manufacturer name
@@ -267,6 +366,7 @@ pub struct ChassisInfo {
     pub element_records: u32,
 }
 /// Processor information structure (SMBIOS Type 4)
+#[derive(serde::Serialize)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ProcessorInfo {
     /// Manufacturer name
@@ -343,6 +443,7 @@ pub struct ProcessorInfo {
     pub threads_enabled: u32,
 }
 /// Memory array information structure (SMBIOS Type 16)
+#[derive(serde::Serialize)]
 #[derive(Clone, Copy, PartialEq, ::prost::Message)]
 pub struct MemoryArrayInfo {
     /// Location
@@ -362,6 +463,7 @@ pub struct MemoryArrayInfo {
     pub max_capacity: u64,
 }
 /// Memory device information structure (SMBIOS Type 17)
+#[derive(serde::Serialize)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct MemoryDeviceInfo {
     /// Manufacturer name
@@ -432,6 +534,7 @@ pub struct MemoryDeviceInfo {
     pub configured_voltage: ::prost::alloc::string::String,
 }
 /// Memory array mapped address information structure (SMBIOS Type 19)
+#[derive(serde::Serialize)]
 #[derive(Clone, Copy, PartialEq, ::prost::Message)]
 pub struct MemoryArrayMappedAddressInfo {
     /// Physical memory array handle
@@ -448,13 +551,79 @@ pub struct MemoryArrayMappedAddressInfo {
     pub size: u64,
 }
 /// System boot information structure (SMBIOS Type 32)
+#[derive(serde::Serialize)]
 #[derive(Clone, Copy, PartialEq, ::prost::Message)]
 pub struct SystemBootInfo {
     /// Boot status code
     #[prost(uint32, tag = "1")]
     pub status_code: u32,
 }
+/// Cache information structure (SMBIOS Type 7)
+#[derive(serde::Serialize)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CacheInfo {
+    /// Structure handle, referenced by the processor cache handles
+    #[prost(uint32, tag = "1")]
+    pub handle: u32,
+    /// Socket designation
+    #[prost(string, tag = "2")]
+    pub socket_designation: ::prost::alloc::string::String,
+    /// Cache level (1 for L1)
+    #[prost(uint32, tag = "3")]
+    pub level: u32,
+    /// Whether the cache is enabled
+    #[prost(bool, tag = "4")]
+    pub enabled: bool,
+    /// Maximum size in bytes
+    #[prost(uint64, tag = "5")]
+    pub max_size: u64,
+    /// Installed size in bytes
+    #[prost(uint64, tag = "6")]
+    pub installed_size: u64,
+    /// System cache type
+    #[prost(uint32, tag = "7")]
+    pub cache_type: u32,
+    /// Associativity
+    #[prost(uint32, tag = "8")]
+    pub associativity: u32,
+    /// Error correction type
+    #[prost(uint32, tag = "9")]
+    pub error_correction: u32,
+}
+/// System slot information structure (SMBIOS Type 9)
+#[derive(serde::Serialize)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SystemSlotInfo {
+    /// Slot designation
+    #[prost(string, tag = "1")]
+    pub designation: ::prost::alloc::string::String,
+    /// Slot type
+    #[prost(uint32, tag = "2")]
+    pub slot_type: u32,
+    /// Data bus width
+    #[prost(uint32, tag = "3")]
+    pub data_bus_width: u32,
+    /// Current usage (3 available, 4 in use)
+    #[prost(uint32, tag = "4")]
+    pub current_usage: u32,
+    /// Slot length
+    #[prost(uint32, tag = "5")]
+    pub length: u32,
+    /// Slot ID
+    #[prost(uint32, tag = "6")]
+    pub slot_id: u32,
+    /// Slot characteristics 1
+    #[prost(uint32, tag = "7")]
+    pub characteristics1: u32,
+    /// Slot characteristics 2
+    #[prost(uint32, tag = "8")]
+    pub characteristics2: u32,
+    /// PCI address of the slot (segment:bus:device.function)
+    #[prost(string, tag = "9")]
+    pub bus_address: ::prost::alloc::string::String,
+}
 /// SMBIOS information structure
+#[derive(serde::Serialize)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct SmbiosInfo {
     /// BIOS information
@@ -486,8 +655,73 @@ pub struct SmbiosInfo {
     /// System boot information
     #[prost(message, optional, tag = "9")]
     pub boot: ::core::option::Option<SystemBootInfo>,
+    /// Cache information
+    #[prost(message, repeated, tag = "10")]
+    pub caches: ::prost::alloc::vec::Vec<CacheInfo>,
+    /// System slot information
+    #[prost(message, repeated, tag = "11")]
+    pub slots: ::prost::alloc::vec::Vec<SystemSlotInfo>,
+}
+/// PCI device
+#[derive(serde::Serialize)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PciDevice {
+    /// PCI address (segment:bus:device.function)
+    #[prost(string, tag = "1")]
+    pub address: ::prost::alloc::string::String,
+    /// Vendor ID
+    #[prost(uint32, tag = "2")]
+    pub vendor_id: u32,
+    /// Device ID
+    #[prost(uint32, tag = "3")]
+    pub device_id: u32,
+    /// Subsystem vendor ID
+    #[prost(uint32, tag = "4")]
+    pub subsystem_vendor_id: u32,
+    /// Subsystem device ID
+    #[prost(uint32, tag = "5")]
+    pub subsystem_device_id: u32,
+    /// Class, subclass and programming interface (0 if unknown)
+    #[prost(uint32, tag = "6")]
+    pub class_code: u32,
+    /// Revision ID
+    #[prost(uint32, tag = "7")]
+    pub revision: u32,
+    /// Driver bound to the device, empty if there is none
+    #[prost(string, tag = "8")]
+    pub driver: ::prost::alloc::string::String,
+}
+/// Processor topology of the running system
+#[derive(serde::Serialize)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CpuTopology {
+    /// Model name of the processors
+    #[prost(string, tag = "1")]
+    pub model: ::prost::alloc::string::String,
+    /// Number of populated sockets
+    #[prost(uint32, tag = "2")]
+    pub sockets: u32,
+    /// Number of physical cores over all sockets
+    #[prost(uint32, tag = "3")]
+    pub cores: u32,
+    /// Number of logical CPUs
+    #[prost(uint32, tag = "4")]
+    pub threads: u32,
+}
+/// Boot firmware information
+#[derive(serde::Serialize)]
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct FirmwareInfo {
+    /// Firmware the system was booted with
+    #[prost(enumeration = "FirmwareMode", tag = "1")]
+    #[serde(serialize_with = "crate::report::serialize_firmware_mode")]
+    pub mode: i32,
+    /// Whether UEFI secure boot is enabled
+    #[prost(bool, tag = "2")]
+    pub secure_boot: bool,
 }
 /// Response message for GetSystemInfo RPC
+#[derive(serde::Serialize)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct SystemInfoResponse {
     /// List of disks
@@ -502,6 +736,323 @@ pub struct SystemInfoResponse {
     /// List of partitions/slices
     #[prost(message, repeated, tag = "4")]
     pub partitions: ::prost::alloc::vec::Vec<PartitionInfo>,
+    /// List of PCI devices
+    #[prost(message, repeated, tag = "5")]
+    pub pci_devices: ::prost::alloc::vec::Vec<PciDevice>,
+    /// CPU topology
+    #[prost(message, optional, tag = "6")]
+    pub cpu: ::core::option::Option<CpuTopology>,
+    /// Physical memory in bytes
+    #[prost(uint64, tag = "7")]
+    pub memory_bytes: u64,
+    /// Boot firmware information
+    #[prost(message, optional, tag = "8")]
+    pub firmware: ::core::option::Option<FirmwareInfo>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ValidateConfigRequest {
+    /// Machine configuration in KDL format
+    #[prost(string, tag = "1")]
+    pub machineconfig: ::prost::alloc::string::String,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ValidationIssue {
+    /// Part of the configuration the issue is about (e.g. pool rpool disk c5t0d0)
+    #[prost(string, tag = "1")]
+    pub subject: ::prost::alloc::string::String,
+    /// Description of the problem
+    #[prost(string, tag = "2")]
+    pub message: ::prost::alloc::string::String,
+}
+/// Result of checking a machine configuration against the hardware of the machine.
+/// Install returns this report in the details of a FAILED_PRECONDITION status when validation fails.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ValidateConfigResponse {
+    /// Whether the configuration can be installed
+    #[prost(bool, tag = "1")]
+    pub valid: bool,
+    /// Problems found in the configuration
+    #[prost(message, repeated, tag = "2")]
+    pub issues: ::prost::alloc::vec::Vec<ValidationIssue>,
+    /// Whether disks and interfaces were checked against discovered hardware
+    #[prost(bool, tag = "3")]
+    pub hardware_checked: bool,
+}
+/// A verified image blob kept in the blob cache of machined
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CachedBlob {
+    /// Digest of the blob (e.g. sha256:...)
+    #[prost(string, tag = "1")]
+    pub digest: ::prost::alloc::string::String,
+    /// Size of the blob in bytes
+    #[prost(uint64, tag = "2")]
+    pub size: u64,
+    /// Unix timestamp the blob was last installed from or added to the cache
+    #[prost(int64, tag = "3")]
+    pub last_used: i64,
+}
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct GetBlobCacheRequest {}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct BlobCacheInfo {
+    /// Directory the cache is kept in
+    #[prost(string, tag = "1")]
+    pub path: ::prost::alloc::string::String,
+    /// Bytes used by all cached blobs
+    #[prost(uint64, tag = "2")]
+    pub size_bytes: u64,
+    /// Size the cache is pruned to after adding a blob (0 if caching is disabled)
+    #[prost(uint64, tag = "3")]
+    pub max_size_bytes: u64,
+    /// Cached blobs, least recently used first
+    #[prost(message, repeated, tag = "4")]
+    pub blobs: ::prost::alloc::vec::Vec<CachedBlob>,
+}
+/// Remove the least recently used blobs until at most keep_bytes are left, 0 empties the cache
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct PruneBlobCacheRequest {
+    #[prost(uint64, tag = "1")]
+    pub keep_bytes: u64,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PruneBlobCacheResponse {
+    /// Number of blobs removed
+    #[prost(uint32, tag = "1")]
+    pub removed: u32,
+    /// Bytes freed
+    #[prost(uint64, tag = "2")]
+    pub freed_bytes: u64,
+    /// The cache after pruning
+    #[prost(message, optional, tag = "3")]
+    pub cache: ::core::option::Option<BlobCacheInfo>,
+}
+/// Reboot the machine, refused while an install session is running unless forced
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RebootRequest {
+    #[prost(bool, tag = "1")]
+    pub force: bool,
+    /// Boot environment to activate before rebooting, the active one if empty
+    #[prost(string, tag = "2")]
+    pub boot_environment: ::prost::alloc::string::String,
+}
+/// Power off the machine, refused while an install session is running unless forced
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct PowerOffRequest {
+    #[prost(bool, tag = "1")]
+    pub force: bool,
+}
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct PowerResponse {
+    /// The machine goes down after this many seconds, so the response reaches the client
+    #[prost(uint32, tag = "1")]
+    pub delay_seconds: u32,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct BootEnvironment {
+    #[prost(string, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+    /// The running system booted from it
+    #[prost(bool, tag = "2")]
+    pub active: bool,
+    /// The next boot uses it
+    #[prost(bool, tag = "3")]
+    pub active_on_reboot: bool,
+    /// Where it is mounted, empty if it is not mounted
+    #[prost(string, tag = "4")]
+    pub mountpoint: ::prost::alloc::string::String,
+    /// Space used by the boot environment and its snapshots
+    #[prost(uint64, tag = "5")]
+    pub space_bytes: u64,
+    /// Unix timestamp of its creation
+    #[prost(int64, tag = "6")]
+    pub created: i64,
+}
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct ListBootEnvironmentsRequest {}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListBootEnvironmentsResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub boot_environments: ::prost::alloc::vec::Vec<BootEnvironment>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ActivateBootEnvironmentRequest {
+    #[prost(string, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+}
+/// Unmount /a and export the pools imported for installing, so they are consistent before a reboot
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct ExportPoolsRequest {
+    /// Also while an install session is running
+    #[prost(bool, tag = "1")]
+    pub force: bool,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ExportPoolsResponse {
+    /// Pools that were exported
+    #[prost(string, repeated, tag = "1")]
+    pub pools: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct GetLogRequest {
+    /// Number of lines from the end of the log, 0 for all kept lines
+    #[prost(uint32, tag = "1")]
+    pub lines: u32,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetLogResponse {
+    /// Log lines of machined, oldest first
+    #[prost(string, repeated, tag = "1")]
+    pub lines: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+/// Clear old ZFS labels and partition tables so zpool create accepts disks reused from
+/// other systems. Without a confirmation nothing is written, the response carries the
+/// token that confirms wiping exactly these disks on this machine in a second call.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PrepareDisksRequest {
+    /// Disks as GetSystemInfo reports them
+    #[prost(string, repeated, tag = "1")]
+    pub disks: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// Also erase all data with the secure erase of the disks, can take hours. Linux only, UNIMPLEMENTED on illumos
+    #[prost(bool, tag = "2")]
+    pub secure_erase: bool,
+    /// Confirmation token returned for the same disks
+    #[prost(string, tag = "3")]
+    pub confirmation: ::prost::alloc::string::String,
+    /// Also wipe disks of imported pools and the disks the installer runs from
+    #[prost(bool, tag = "4")]
+    pub force: bool,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PrepareDisksResponse {
+    /// The disks selected
+    #[prost(message, repeated, tag = "1")]
+    pub disks: ::prost::alloc::vec::Vec<DiskInfo>,
+    /// Whether the disks were wiped or only checked
+    #[prost(bool, tag = "2")]
+    pub wiped: bool,
+    /// Token to confirm the wipe with, empty once wiped
+    #[prost(string, tag = "3")]
+    pub confirmation: ::prost::alloc::string::String,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetInstallReportRequest {
+    /// Session id or name, the last successful install if empty
+    #[prost(string, tag = "1")]
+    pub session: ::prost::alloc::string::String,
+}
+/// What a successful install put on the machine. machined writes it as JSON to
+/// /var/log/machined/install-report.json in the new boot environment.
+#[derive(serde::Serialize)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct InstallReport {
+    #[prost(string, tag = "1")]
+    pub session_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub session_name: ::prost::alloc::string::String,
+    /// Operator of the claim token, "console", the command server or the USB configuration file that started the install
+    #[prost(string, tag = "3")]
+    pub operator: ::prost::alloc::string::String,
+    #[prost(string, tag = "4")]
+    pub machined_version: ::prost::alloc::string::String,
+    /// Image reference of the configuration
+    #[prost(string, tag = "5")]
+    pub image: ::prost::alloc::string::String,
+    /// Digest of the installed manifest, the one for this platform of manifest lists
+    #[prost(string, tag = "6")]
+    pub manifest_digest: ::prost::alloc::string::String,
+    /// Layers unpacked, bottom layer first
+    #[prost(string, repeated, tag = "7")]
+    pub layer_digests: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// Boot environment the image was installed into
+    #[prost(string, tag = "8")]
+    pub boot_environment: ::prost::alloc::string::String,
+    /// Machine configuration as JSON, inline post-install content redacted
+    #[prost(string, tag = "9")]
+    pub machineconfig: ::prost::alloc::string::String,
+    /// Hardware the system was installed on
+    #[prost(message, optional, tag = "10")]
+    pub hardware: ::core::option::Option<SystemInfoResponse>,
+    /// Unix timestamp the install started
+    #[prost(int64, tag = "11")]
+    pub started_at: i64,
+    /// Unix timestamp the install finished
+    #[prost(int64, tag = "12")]
+    pub finished_at: i64,
+}
+/// Sent by machined in call-home mode when it starts
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RegisterMachineRequest {
+    /// SMBIOS UUID, the subject of claim payloads for this machine
+    #[prost(string, tag = "1")]
+    pub machine_uuid: ::prost::alloc::string::String,
+    /// Addresses machined can be reached at
+    #[prost(string, repeated, tag = "2")]
+    pub addresses: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// Port machined listens on
+    #[prost(uint32, tag = "3")]
+    pub port: u32,
+    /// SHA-256 fingerprint of the certificate machined serves, empty without TLS
+    #[prost(string, tag = "4")]
+    pub tls_fingerprint: ::prost::alloc::string::String,
+    #[prost(message, optional, tag = "5")]
+    pub system_info: ::core::option::Option<SystemInfoResponse>,
+}
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct RegisterMachineResponse {}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct WaitForAssignmentRequest {
+    #[prost(string, tag = "1")]
+    pub machine_uuid: ::prost::alloc::string::String,
+}
+/// Machine configuration a command server assigned to a machine
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct MachineAssignment {
+    #[prost(string, tag = "1")]
+    pub machineconfig: ::prost::alloc::string::String,
+    /// Name of the install session machined starts for it, generated if empty
+    #[prost(string, tag = "2")]
+    pub session_name: ::prost::alloc::string::String,
+    /// Ed25519 signed JWT of the command server with the machine UUID as subject, an expiry and
+    /// the claims `config_sha256`, the hex SHA-256 of machineconfig, and `session_name`.
+    /// Assignments without a valid signature are not installed
+    #[prost(string, tag = "3")]
+    pub signature: ::prost::alloc::string::String,
+}
+/// Groups of RPCs a claim token can grant
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum TokenScope {
+    Unspecified = 0,
+    /// Read system information, install sessions and the blob cache, validate configurations
+    Info = 1,
+    /// Start and cancel installs, prune the blob cache
+    Install = 2,
+    /// Reboot or power off the machine
+    Reboot = 3,
+}
+impl TokenScope {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Self::Unspecified => "TOKEN_SCOPE_UNSPECIFIED",
+            Self::Info => "TOKEN_SCOPE_INFO",
+            Self::Install => "TOKEN_SCOPE_INSTALL",
+            Self::Reboot => "TOKEN_SCOPE_REBOOT",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "TOKEN_SCOPE_UNSPECIFIED" => Some(Self::Unspecified),
+            "TOKEN_SCOPE_INFO" => Some(Self::Info),
+            "TOKEN_SCOPE_INSTALL" => Some(Self::Install),
+            "TOKEN_SCOPE_REBOOT" => Some(Self::Reboot),
+            _ => None,
+        }
+    }
 }
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
 #[repr(i32)]
@@ -535,6 +1086,187 @@ impl ProgressLevel {
         }
     }
 }
+/// Phases of an installation in the order they run
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum InstallPhase {
+    /// Message is not tied to a phase
+    Unspecified = 0,
+    /// Creating the zpools
+    Pools = 1,
+    /// Creating and mounting the boot environment datasets
+    Datasets = 2,
+    /// Resolving the image manifest and configuration
+    Fetch = 3,
+    /// Streaming the image layers into the boot environment
+    Unpack = 4,
+    /// Writing the system configuration
+    Sysconfig = 5,
+    /// Activating the boot environment and installing the bootloader
+    Bootloader = 6,
+    /// Undoing the steps of a failed installation
+    Rollback = 7,
+    /// Adding files, packages, SMF manifests and running scripts
+    Postinstall = 8,
+}
+impl InstallPhase {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Self::Unspecified => "INSTALL_PHASE_UNSPECIFIED",
+            Self::Pools => "INSTALL_PHASE_POOLS",
+            Self::Datasets => "INSTALL_PHASE_DATASETS",
+            Self::Fetch => "INSTALL_PHASE_FETCH",
+            Self::Unpack => "INSTALL_PHASE_UNPACK",
+            Self::Sysconfig => "INSTALL_PHASE_SYSCONFIG",
+            Self::Bootloader => "INSTALL_PHASE_BOOTLOADER",
+            Self::Rollback => "INSTALL_PHASE_ROLLBACK",
+            Self::Postinstall => "INSTALL_PHASE_POSTINSTALL",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "INSTALL_PHASE_UNSPECIFIED" => Some(Self::Unspecified),
+            "INSTALL_PHASE_POOLS" => Some(Self::Pools),
+            "INSTALL_PHASE_DATASETS" => Some(Self::Datasets),
+            "INSTALL_PHASE_FETCH" => Some(Self::Fetch),
+            "INSTALL_PHASE_UNPACK" => Some(Self::Unpack),
+            "INSTALL_PHASE_SYSCONFIG" => Some(Self::Sysconfig),
+            "INSTALL_PHASE_BOOTLOADER" => Some(Self::Bootloader),
+            "INSTALL_PHASE_ROLLBACK" => Some(Self::Rollback),
+            "INSTALL_PHASE_POSTINSTALL" => Some(Self::Postinstall),
+            _ => None,
+        }
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum InstallSessionState {
+    Running = 0,
+    Succeeded = 1,
+    Failed = 2,
+    Cancelled = 3,
+    /// machined restarted while the install was running
+    Interrupted = 4,
+}
+impl InstallSessionState {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Self::Running => "RUNNING",
+            Self::Succeeded => "SUCCEEDED",
+            Self::Failed => "FAILED",
+            Self::Cancelled => "CANCELLED",
+            Self::Interrupted => "INTERRUPTED",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "RUNNING" => Some(Self::Running),
+            "SUCCEEDED" => Some(Self::Succeeded),
+            "FAILED" => Some(Self::Failed),
+            "CANCELLED" => Some(Self::Cancelled),
+            "INTERRUPTED" => Some(Self::Interrupted),
+            _ => None,
+        }
+    }
+}
+/// Parts of the system information GetSystemInfo can collect
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum SystemInfoSection {
+    Unspecified = 0,
+    /// Disks
+    Disks = 1,
+    /// Network interfaces
+    Network = 2,
+    /// SMBIOS tables
+    Smbios = 3,
+    /// Partitions and slices of the disks
+    Partitions = 4,
+    /// PCI devices
+    Pci = 5,
+    /// CPU topology
+    Cpu = 6,
+    /// Physical memory
+    Memory = 7,
+    /// Firmware boot mode
+    Firmware = 8,
+}
+impl SystemInfoSection {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Self::Unspecified => "SYSTEM_INFO_SECTION_UNSPECIFIED",
+            Self::Disks => "SYSTEM_INFO_SECTION_DISKS",
+            Self::Network => "SYSTEM_INFO_SECTION_NETWORK",
+            Self::Smbios => "SYSTEM_INFO_SECTION_SMBIOS",
+            Self::Partitions => "SYSTEM_INFO_SECTION_PARTITIONS",
+            Self::Pci => "SYSTEM_INFO_SECTION_PCI",
+            Self::Cpu => "SYSTEM_INFO_SECTION_CPU",
+            Self::Memory => "SYSTEM_INFO_SECTION_MEMORY",
+            Self::Firmware => "SYSTEM_INFO_SECTION_FIRMWARE",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "SYSTEM_INFO_SECTION_UNSPECIFIED" => Some(Self::Unspecified),
+            "SYSTEM_INFO_SECTION_DISKS" => Some(Self::Disks),
+            "SYSTEM_INFO_SECTION_NETWORK" => Some(Self::Network),
+            "SYSTEM_INFO_SECTION_SMBIOS" => Some(Self::Smbios),
+            "SYSTEM_INFO_SECTION_PARTITIONS" => Some(Self::Partitions),
+            "SYSTEM_INFO_SECTION_PCI" => Some(Self::Pci),
+            "SYSTEM_INFO_SECTION_CPU" => Some(Self::Cpu),
+            "SYSTEM_INFO_SECTION_MEMORY" => Some(Self::Memory),
+            "SYSTEM_INFO_SECTION_FIRMWARE" => Some(Self::Firmware),
+            _ => None,
+        }
+    }
+}
+/// How the system firmware booted
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum FirmwareMode {
+    Unspecified = 0,
+    /// Legacy BIOS or CSM boot
+    Bios = 1,
+    /// UEFI boot
+    Uefi = 2,
+}
+impl FirmwareMode {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Self::Unspecified => "FIRMWARE_MODE_UNSPECIFIED",
+            Self::Bios => "FIRMWARE_MODE_BIOS",
+            Self::Uefi => "FIRMWARE_MODE_UEFI",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "FIRMWARE_MODE_UNSPECIFIED" => Some(Self::Unspecified),
+            "FIRMWARE_MODE_BIOS" => Some(Self::Bios),
+            "FIRMWARE_MODE_UEFI" => Some(Self::Uefi),
+            _ => None,
+        }
+    }
+}
 /// Generated client implementations.
 pub mod machine_service_client {
     #![allow(
@@ -647,7 +1379,28 @@ pub mod machine_service_client {
                 .insert(GrpcMethod::new("machined.MachineService", "Claim"));
             self.inner.unary(req, path, codec).await
         }
-        pub async fn install(
+        pub async fn refresh_token(
+            &mut self,
+            request: impl tonic::IntoRequest<super::RefreshTokenRequest>,
+        ) -> std::result::Result<tonic::Response<super::ClaimResponse>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/machined.MachineService/RefreshToken",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("machined.MachineService", "RefreshToken"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn install(
             &mut self,
             request: impl tonic::IntoRequest<super::InstallConfig>,
         ) -> std::result::Result<
@@ -671,34 +1424,1586 @@ pub mod machine_service_client {
                 .insert(GrpcMethod::new("machined.MachineService", "Install"));
             self.inner.server_streaming(req, path, codec).await
         }
-        pub async fn get_system_info(
-            &mut self,
-            request: impl tonic::IntoRequest<super::SystemInfoRequest>,
-        ) -> std::result::Result<
-            tonic::Response<super::SystemInfoResponse>,
-            tonic::Status,
-        > {
-            self.inner
-                .ready()
-                .await
-                .map_err(|e| {
-                    tonic::Status::unknown(
-                        format!("Service was not ready: {}", e.into()),
-                    )
-                })?;
-            let codec = tonic::codec::ProstCodec::default();
-            let path = http::uri::PathAndQuery::from_static(
-                "/machined.MachineService/GetSystemInfo",
-            );
-            let mut req = request.into_request();
-            req.extensions_mut()
-                .insert(GrpcMethod::new("machined.MachineService", "GetSystemInfo"));
-            self.inner.unary(req, path, codec).await
+        pub async fn get_system_info(
+            &mut self,
+            request: impl tonic::IntoRequest<super::SystemInfoRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::SystemInfoResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/machined.MachineService/GetSystemInfo",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("machined.MachineService", "GetSystemInfo"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn list_install_sessions(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ListInstallSessionsRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::ListInstallSessionsResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/machined.MachineService/ListInstallSessions",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new("machined.MachineService", "ListInstallSessions"),
+                );
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn attach_install_session(
+            &mut self,
+            request: impl tonic::IntoRequest<super::AttachInstallSessionRequest>,
+        ) -> std::result::Result<
+            tonic::Response<tonic::codec::Streaming<super::InstallProgress>>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/machined.MachineService/AttachInstallSession",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new("machined.MachineService", "AttachInstallSession"),
+                );
+            self.inner.server_streaming(req, path, codec).await
+        }
+        pub async fn cancel_install_session(
+            &mut self,
+            request: impl tonic::IntoRequest<super::CancelInstallSessionRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::CancelInstallSessionResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/machined.MachineService/CancelInstallSession",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new("machined.MachineService", "CancelInstallSession"),
+                );
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn validate_config(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ValidateConfigRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::ValidateConfigResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/machined.MachineService/ValidateConfig",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("machined.MachineService", "ValidateConfig"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn get_blob_cache(
+            &mut self,
+            request: impl tonic::IntoRequest<super::GetBlobCacheRequest>,
+        ) -> std::result::Result<tonic::Response<super::BlobCacheInfo>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/machined.MachineService/GetBlobCache",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("machined.MachineService", "GetBlobCache"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn prune_blob_cache(
+            &mut self,
+            request: impl tonic::IntoRequest<super::PruneBlobCacheRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::PruneBlobCacheResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/machined.MachineService/PruneBlobCache",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("machined.MachineService", "PruneBlobCache"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn reboot(
+            &mut self,
+            request: impl tonic::IntoRequest<super::RebootRequest>,
+        ) -> std::result::Result<tonic::Response<super::PowerResponse>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/machined.MachineService/Reboot",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("machined.MachineService", "Reboot"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn power_off(
+            &mut self,
+            request: impl tonic::IntoRequest<super::PowerOffRequest>,
+        ) -> std::result::Result<tonic::Response<super::PowerResponse>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/machined.MachineService/PowerOff",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("machined.MachineService", "PowerOff"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn list_boot_environments(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ListBootEnvironmentsRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::ListBootEnvironmentsResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/machined.MachineService/ListBootEnvironments",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new("machined.MachineService", "ListBootEnvironments"),
+                );
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn activate_boot_environment(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ActivateBootEnvironmentRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::BootEnvironment>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/machined.MachineService/ActivateBootEnvironment",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new("machined.MachineService", "ActivateBootEnvironment"),
+                );
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn export_pools(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ExportPoolsRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::ExportPoolsResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/machined.MachineService/ExportPools",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("machined.MachineService", "ExportPools"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn get_log(
+            &mut self,
+            request: impl tonic::IntoRequest<super::GetLogRequest>,
+        ) -> std::result::Result<tonic::Response<super::GetLogResponse>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/machined.MachineService/GetLog",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("machined.MachineService", "GetLog"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn prepare_disks(
+            &mut self,
+            request: impl tonic::IntoRequest<super::PrepareDisksRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::PrepareDisksResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/machined.MachineService/PrepareDisks",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("machined.MachineService", "PrepareDisks"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn get_install_report(
+            &mut self,
+            request: impl tonic::IntoRequest<super::GetInstallReportRequest>,
+        ) -> std::result::Result<tonic::Response<super::InstallReport>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/machined.MachineService/GetInstallReport",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("machined.MachineService", "GetInstallReport"));
+            self.inner.unary(req, path, codec).await
+        }
+    }
+}
+/// Generated client implementations.
+pub mod command_service_client {
+    #![allow(
+        unused_variables,
+        dead_code,
+        missing_docs,
+        clippy::wildcard_imports,
+        clippy::let_unit_value,
+    )]
+    use tonic::codegen::*;
+    use tonic::codegen::http::Uri;
+    /// Implemented by command servers. machined registers with it at boot and installs the
+    /// first valid configuration it is assigned
+    #[derive(Debug, Clone)]
+    pub struct CommandServiceClient<T> {
+        inner: tonic::client::Grpc<T>,
+    }
+    impl CommandServiceClient<tonic::transport::Channel> {
+        /// Attempt to create a new client by connecting to a given endpoint.
+        pub async fn connect<D>(dst: D) -> Result<Self, tonic::transport::Error>
+        where
+            D: TryInto<tonic::transport::Endpoint>,
+            D::Error: Into<StdError>,
+        {
+            let conn = tonic::transport::Endpoint::new(dst)?.connect().await?;
+            Ok(Self::new(conn))
+        }
+    }
+    impl<T> CommandServiceClient<T>
+    where
+        T: tonic::client::GrpcService<tonic::body::BoxBody>,
+        T::Error: Into<StdError>,
+        T::ResponseBody: Body<Data = Bytes> + std::marker::Send + 'static,
+        <T::ResponseBody as Body>::Error: Into<StdError> + std::marker::Send,
+    {
+        pub fn new(inner: T) -> Self {
+            let inner = tonic::client::Grpc::new(inner);
+            Self { inner }
+        }
+        pub fn with_origin(inner: T, origin: Uri) -> Self {
+            let inner = tonic::client::Grpc::with_origin(inner, origin);
+            Self { inner }
+        }
+        pub fn with_interceptor<F>(
+            inner: T,
+            interceptor: F,
+        ) -> CommandServiceClient<InterceptedService<T, F>>
+        where
+            F: tonic::service::Interceptor,
+            T::ResponseBody: Default,
+            T: tonic::codegen::Service<
+                http::Request<tonic::body::BoxBody>,
+                Response = http::Response<
+                    <T as tonic::client::GrpcService<tonic::body::BoxBody>>::ResponseBody,
+                >,
+            >,
+            <T as tonic::codegen::Service<
+                http::Request<tonic::body::BoxBody>,
+            >>::Error: Into<StdError> + std::marker::Send + std::marker::Sync,
+        {
+            CommandServiceClient::new(InterceptedService::new(inner, interceptor))
+        }
+        /// Compress requests with the given encoding.
+        ///
+        /// This requires the server to support it otherwise it might respond with an
+        /// error.
+        #[must_use]
+        pub fn send_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.inner = self.inner.send_compressed(encoding);
+            self
+        }
+        /// Enable decompressing responses.
+        #[must_use]
+        pub fn accept_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.inner = self.inner.accept_compressed(encoding);
+            self
+        }
+        /// Limits the maximum size of a decoded message.
+        ///
+        /// Default: `4MB`
+        #[must_use]
+        pub fn max_decoding_message_size(mut self, limit: usize) -> Self {
+            self.inner = self.inner.max_decoding_message_size(limit);
+            self
+        }
+        /// Limits the maximum size of an encoded message.
+        ///
+        /// Default: `usize::MAX`
+        #[must_use]
+        pub fn max_encoding_message_size(mut self, limit: usize) -> Self {
+            self.inner = self.inner.max_encoding_message_size(limit);
+            self
+        }
+        pub async fn register_machine(
+            &mut self,
+            request: impl tonic::IntoRequest<super::RegisterMachineRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::RegisterMachineResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/machined.CommandService/RegisterMachine",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("machined.CommandService", "RegisterMachine"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// Stays open until the command server assigns a configuration to the machine
+        pub async fn wait_for_assignment(
+            &mut self,
+            request: impl tonic::IntoRequest<super::WaitForAssignmentRequest>,
+        ) -> std::result::Result<
+            tonic::Response<tonic::codec::Streaming<super::MachineAssignment>>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/machined.CommandService/WaitForAssignment",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("machined.CommandService", "WaitForAssignment"));
+            self.inner.server_streaming(req, path, codec).await
+        }
+    }
+}
+/// Generated server implementations.
+pub mod machine_service_server {
+    #![allow(
+        unused_variables,
+        dead_code,
+        missing_docs,
+        clippy::wildcard_imports,
+        clippy::let_unit_value,
+    )]
+    use tonic::codegen::*;
+    /// Generated trait containing gRPC methods that should be implemented for use with MachineServiceServer.
+    #[async_trait]
+    pub trait MachineService: std::marker::Send + std::marker::Sync + 'static {
+        async fn claim(
+            &self,
+            request: tonic::Request<super::ClaimRequest>,
+        ) -> std::result::Result<tonic::Response<super::ClaimResponse>, tonic::Status>;
+        async fn refresh_token(
+            &self,
+            request: tonic::Request<super::RefreshTokenRequest>,
+        ) -> std::result::Result<tonic::Response<super::ClaimResponse>, tonic::Status>;
+        /// Server streaming response type for the Install method.
+        type InstallStream: tonic::codegen::tokio_stream::Stream<
+                Item = std::result::Result<super::InstallProgress, tonic::Status>,
+            >
+            + std::marker::Send
+            + 'static;
+        async fn install(
+            &self,
+            request: tonic::Request<super::InstallConfig>,
+        ) -> std::result::Result<tonic::Response<Self::InstallStream>, tonic::Status>;
+        async fn get_system_info(
+            &self,
+            request: tonic::Request<super::SystemInfoRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::SystemInfoResponse>,
+            tonic::Status,
+        >;
+        async fn list_install_sessions(
+            &self,
+            request: tonic::Request<super::ListInstallSessionsRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::ListInstallSessionsResponse>,
+            tonic::Status,
+        >;
+        /// Server streaming response type for the AttachInstallSession method.
+        type AttachInstallSessionStream: tonic::codegen::tokio_stream::Stream<
+                Item = std::result::Result<super::InstallProgress, tonic::Status>,
+            >
+            + std::marker::Send
+            + 'static;
+        async fn attach_install_session(
+            &self,
+            request: tonic::Request<super::AttachInstallSessionRequest>,
+        ) -> std::result::Result<
+            tonic::Response<Self::AttachInstallSessionStream>,
+            tonic::Status,
+        >;
+        async fn cancel_install_session(
+            &self,
+            request: tonic::Request<super::CancelInstallSessionRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::CancelInstallSessionResponse>,
+            tonic::Status,
+        >;
+        async fn validate_config(
+            &self,
+            request: tonic::Request<super::ValidateConfigRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::ValidateConfigResponse>,
+            tonic::Status,
+        >;
+        async fn get_blob_cache(
+            &self,
+            request: tonic::Request<super::GetBlobCacheRequest>,
+        ) -> std::result::Result<tonic::Response<super::BlobCacheInfo>, tonic::Status>;
+        async fn prune_blob_cache(
+            &self,
+            request: tonic::Request<super::PruneBlobCacheRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::PruneBlobCacheResponse>,
+            tonic::Status,
+        >;
+        async fn reboot(
+            &self,
+            request: tonic::Request<super::RebootRequest>,
+        ) -> std::result::Result<tonic::Response<super::PowerResponse>, tonic::Status>;
+        async fn power_off(
+            &self,
+            request: tonic::Request<super::PowerOffRequest>,
+        ) -> std::result::Result<tonic::Response<super::PowerResponse>, tonic::Status>;
+        async fn list_boot_environments(
+            &self,
+            request: tonic::Request<super::ListBootEnvironmentsRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::ListBootEnvironmentsResponse>,
+            tonic::Status,
+        >;
+        async fn activate_boot_environment(
+            &self,
+            request: tonic::Request<super::ActivateBootEnvironmentRequest>,
+        ) -> std::result::Result<tonic::Response<super::BootEnvironment>, tonic::Status>;
+        async fn export_pools(
+            &self,
+            request: tonic::Request<super::ExportPoolsRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::ExportPoolsResponse>,
+            tonic::Status,
+        >;
+        async fn get_log(
+            &self,
+            request: tonic::Request<super::GetLogRequest>,
+        ) -> std::result::Result<tonic::Response<super::GetLogResponse>, tonic::Status>;
+        async fn prepare_disks(
+            &self,
+            request: tonic::Request<super::PrepareDisksRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::PrepareDisksResponse>,
+            tonic::Status,
+        >;
+        async fn get_install_report(
+            &self,
+            request: tonic::Request<super::GetInstallReportRequest>,
+        ) -> std::result::Result<tonic::Response<super::InstallReport>, tonic::Status>;
+    }
+    #[derive(Debug)]
+    pub struct MachineServiceServer<T> {
+        inner: Arc<T>,
+        accept_compression_encodings: EnabledCompressionEncodings,
+        send_compression_encodings: EnabledCompressionEncodings,
+        max_decoding_message_size: Option<usize>,
+        max_encoding_message_size: Option<usize>,
+    }
+    impl<T> MachineServiceServer<T> {
+        pub fn new(inner: T) -> Self {
+            Self::from_arc(Arc::new(inner))
+        }
+        pub fn from_arc(inner: Arc<T>) -> Self {
+            Self {
+                inner,
+                accept_compression_encodings: Default::default(),
+                send_compression_encodings: Default::default(),
+                max_decoding_message_size: None,
+                max_encoding_message_size: None,
+            }
+        }
+        pub fn with_interceptor<F>(
+            inner: T,
+            interceptor: F,
+        ) -> InterceptedService<Self, F>
+        where
+            F: tonic::service::Interceptor,
+        {
+            InterceptedService::new(Self::new(inner), interceptor)
+        }
+        /// Enable decompressing requests with the given encoding.
+        #[must_use]
+        pub fn accept_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.accept_compression_encodings.enable(encoding);
+            self
+        }
+        /// Compress responses with the given encoding, if the client supports it.
+        #[must_use]
+        pub fn send_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.send_compression_encodings.enable(encoding);
+            self
+        }
+        /// Limits the maximum size of a decoded message.
+        ///
+        /// Default: `4MB`
+        #[must_use]
+        pub fn max_decoding_message_size(mut self, limit: usize) -> Self {
+            self.max_decoding_message_size = Some(limit);
+            self
+        }
+        /// Limits the maximum size of an encoded message.
+        ///
+        /// Default: `usize::MAX`
+        #[must_use]
+        pub fn max_encoding_message_size(mut self, limit: usize) -> Self {
+            self.max_encoding_message_size = Some(limit);
+            self
+        }
+    }
+    impl<T, B> tonic::codegen::Service<http::Request<B>> for MachineServiceServer<T>
+    where
+        T: MachineService,
+        B: Body + std::marker::Send + 'static,
+        B::Error: Into<StdError> + std::marker::Send + 'static,
+    {
+        type Response = http::Response<tonic::body::BoxBody>;
+        type Error = std::convert::Infallible;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+        fn poll_ready(
+            &mut self,
+            _cx: &mut Context<'_>,
+        ) -> Poll<std::result::Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+        fn call(&mut self, req: http::Request<B>) -> Self::Future {
+            match req.uri().path() {
+                "/machined.MachineService/Claim" => {
+                    #[allow(non_camel_case_types)]
+                    struct ClaimSvc<T: MachineService>(pub Arc<T>);
+                    impl<
+                        T: MachineService,
+                    > tonic::server::UnaryService<super::ClaimRequest> for ClaimSvc<T> {
+                        type Response = super::ClaimResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ClaimRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MachineService>::claim(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = ClaimSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/machined.MachineService/RefreshToken" => {
+                    #[allow(non_camel_case_types)]
+                    struct RefreshTokenSvc<T: MachineService>(pub Arc<T>);
+                    impl<
+                        T: MachineService,
+                    > tonic::server::UnaryService<super::RefreshTokenRequest>
+                    for RefreshTokenSvc<T> {
+                        type Response = super::ClaimResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::RefreshTokenRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MachineService>::refresh_token(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = RefreshTokenSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/machined.MachineService/Install" => {
+                    #[allow(non_camel_case_types)]
+                    struct InstallSvc<T: MachineService>(pub Arc<T>);
+                    impl<
+                        T: MachineService,
+                    > tonic::server::ServerStreamingService<super::InstallConfig>
+                    for InstallSvc<T> {
+                        type Response = super::InstallProgress;
+                        type ResponseStream = T::InstallStream;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::ResponseStream>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::InstallConfig>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MachineService>::install(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = InstallSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.server_streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/machined.MachineService/GetSystemInfo" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetSystemInfoSvc<T: MachineService>(pub Arc<T>);
+                    impl<
+                        T: MachineService,
+                    > tonic::server::UnaryService<super::SystemInfoRequest>
+                    for GetSystemInfoSvc<T> {
+                        type Response = super::SystemInfoResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::SystemInfoRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MachineService>::get_system_info(&inner, request)
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = GetSystemInfoSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/machined.MachineService/ListInstallSessions" => {
+                    #[allow(non_camel_case_types)]
+                    struct ListInstallSessionsSvc<T: MachineService>(pub Arc<T>);
+                    impl<
+                        T: MachineService,
+                    > tonic::server::UnaryService<super::ListInstallSessionsRequest>
+                    for ListInstallSessionsSvc<T> {
+                        type Response = super::ListInstallSessionsResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ListInstallSessionsRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MachineService>::list_install_sessions(
+                                        &inner,
+                                        request,
+                                    )
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = ListInstallSessionsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/machined.MachineService/AttachInstallSession" => {
+                    #[allow(non_camel_case_types)]
+                    struct AttachInstallSessionSvc<T: MachineService>(pub Arc<T>);
+                    impl<
+                        T: MachineService,
+                    > tonic::server::ServerStreamingService<
+                        super::AttachInstallSessionRequest,
+                    > for AttachInstallSessionSvc<T> {
+                        type Response = super::InstallProgress;
+                        type ResponseStream = T::AttachInstallSessionStream;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::ResponseStream>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::AttachInstallSessionRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MachineService>::attach_install_session(
+                                        &inner,
+                                        request,
+                                    )
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = AttachInstallSessionSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.server_streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/machined.MachineService/CancelInstallSession" => {
+                    #[allow(non_camel_case_types)]
+                    struct CancelInstallSessionSvc<T: MachineService>(pub Arc<T>);
+                    impl<
+                        T: MachineService,
+                    > tonic::server::UnaryService<super::CancelInstallSessionRequest>
+                    for CancelInstallSessionSvc<T> {
+                        type Response = super::CancelInstallSessionResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::CancelInstallSessionRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MachineService>::cancel_install_session(
+                                        &inner,
+                                        request,
+                                    )
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = CancelInstallSessionSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/machined.MachineService/ValidateConfig" => {
+                    #[allow(non_camel_case_types)]
+                    struct ValidateConfigSvc<T: MachineService>(pub Arc<T>);
+                    impl<
+                        T: MachineService,
+                    > tonic::server::UnaryService<super::ValidateConfigRequest>
+                    for ValidateConfigSvc<T> {
+                        type Response = super::ValidateConfigResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ValidateConfigRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MachineService>::validate_config(&inner, request)
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = ValidateConfigSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/machined.MachineService/GetBlobCache" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetBlobCacheSvc<T: MachineService>(pub Arc<T>);
+                    impl<
+                        T: MachineService,
+                    > tonic::server::UnaryService<super::GetBlobCacheRequest>
+                    for GetBlobCacheSvc<T> {
+                        type Response = super::BlobCacheInfo;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GetBlobCacheRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MachineService>::get_blob_cache(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = GetBlobCacheSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/machined.MachineService/PruneBlobCache" => {
+                    #[allow(non_camel_case_types)]
+                    struct PruneBlobCacheSvc<T: MachineService>(pub Arc<T>);
+                    impl<
+                        T: MachineService,
+                    > tonic::server::UnaryService<super::PruneBlobCacheRequest>
+                    for PruneBlobCacheSvc<T> {
+                        type Response = super::PruneBlobCacheResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::PruneBlobCacheRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MachineService>::prune_blob_cache(&inner, request)
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = PruneBlobCacheSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/machined.MachineService/Reboot" => {
+                    #[allow(non_camel_case_types)]
+                    struct RebootSvc<T: MachineService>(pub Arc<T>);
+                    impl<
+                        T: MachineService,
+                    > tonic::server::UnaryService<super::RebootRequest>
+                    for RebootSvc<T> {
+                        type Response = super::PowerResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::RebootRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MachineService>::reboot(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = RebootSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/machined.MachineService/PowerOff" => {
+                    #[allow(non_camel_case_types)]
+                    struct PowerOffSvc<T: MachineService>(pub Arc<T>);
+                    impl<
+                        T: MachineService,
+                    > tonic::server::UnaryService<super::PowerOffRequest>
+                    for PowerOffSvc<T> {
+                        type Response = super::PowerResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::PowerOffRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MachineService>::power_off(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = PowerOffSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/machined.MachineService/ListBootEnvironments" => {
+                    #[allow(non_camel_case_types)]
+                    struct ListBootEnvironmentsSvc<T: MachineService>(pub Arc<T>);
+                    impl<
+                        T: MachineService,
+                    > tonic::server::UnaryService<super::ListBootEnvironmentsRequest>
+                    for ListBootEnvironmentsSvc<T> {
+                        type Response = super::ListBootEnvironmentsResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ListBootEnvironmentsRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MachineService>::list_boot_environments(
+                                        &inner,
+                                        request,
+                                    )
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = ListBootEnvironmentsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/machined.MachineService/ActivateBootEnvironment" => {
+                    #[allow(non_camel_case_types)]
+                    struct ActivateBootEnvironmentSvc<T: MachineService>(pub Arc<T>);
+                    impl<
+                        T: MachineService,
+                    > tonic::server::UnaryService<super::ActivateBootEnvironmentRequest>
+                    for ActivateBootEnvironmentSvc<T> {
+                        type Response = super::BootEnvironment;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<
+                                super::ActivateBootEnvironmentRequest,
+                            >,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MachineService>::activate_boot_environment(
+                                        &inner,
+                                        request,
+                                    )
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = ActivateBootEnvironmentSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/machined.MachineService/ExportPools" => {
+                    #[allow(non_camel_case_types)]
+                    struct ExportPoolsSvc<T: MachineService>(pub Arc<T>);
+                    impl<
+                        T: MachineService,
+                    > tonic::server::UnaryService<super::ExportPoolsRequest>
+                    for ExportPoolsSvc<T> {
+                        type Response = super::ExportPoolsResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ExportPoolsRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MachineService>::export_pools(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = ExportPoolsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/machined.MachineService/GetLog" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetLogSvc<T: MachineService>(pub Arc<T>);
+                    impl<
+                        T: MachineService,
+                    > tonic::server::UnaryService<super::GetLogRequest>
+                    for GetLogSvc<T> {
+                        type Response = super::GetLogResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GetLogRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MachineService>::get_log(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = GetLogSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/machined.MachineService/PrepareDisks" => {
+                    #[allow(non_camel_case_types)]
+                    struct PrepareDisksSvc<T: MachineService>(pub Arc<T>);
+                    impl<
+                        T: MachineService,
+                    > tonic::server::UnaryService<super::PrepareDisksRequest>
+                    for PrepareDisksSvc<T> {
+                        type Response = super::PrepareDisksResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::PrepareDisksRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MachineService>::prepare_disks(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = PrepareDisksSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/machined.MachineService/GetInstallReport" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetInstallReportSvc<T: MachineService>(pub Arc<T>);
+                    impl<
+                        T: MachineService,
+                    > tonic::server::UnaryService<super::GetInstallReportRequest>
+                    for GetInstallReportSvc<T> {
+                        type Response = super::InstallReport;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GetInstallReportRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MachineService>::get_install_report(&inner, request)
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = GetInstallReportSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                _ => {
+                    Box::pin(async move {
+                        let mut response = http::Response::new(empty_body());
+                        let headers = response.headers_mut();
+                        headers
+                            .insert(
+                                tonic::Status::GRPC_STATUS,
+                                (tonic::Code::Unimplemented as i32).into(),
+                            );
+                        headers
+                            .insert(
+                                http::header::CONTENT_TYPE,
+                                tonic::metadata::GRPC_CONTENT_TYPE,
+                            );
+                        Ok(response)
+                    })
+                }
+            }
+        }
+    }
+    impl<T> Clone for MachineServiceServer<T> {
+        fn clone(&self) -> Self {
+            let inner = self.inner.clone();
+            Self {
+                inner,
+                accept_compression_encodings: self.accept_compression_encodings,
+                send_compression_encodings: self.send_compression_encodings,
+                max_decoding_message_size: self.max_decoding_message_size,
+                max_encoding_message_size: self.max_encoding_message_size,
+            }
         }
     }
+    /// Generated gRPC service name
+    pub const SERVICE_NAME: &str = "machined.MachineService";
+    impl<T> tonic::server::NamedService for MachineServiceServer<T> {
+        const NAME: &'static str = SERVICE_NAME;
+    }
 }
 /// Generated server implementations.
-pub mod machine_service_server {
+pub mod command_service_server {
     #![allow(
         unused_variables,
         dead_code,
@@ -707,40 +3012,42 @@ pub mod machine_service_server {
         clippy::let_unit_value,
     )]
     use tonic::codegen::*;
-    /// Generated trait containing gRPC methods that should be implemented for use with MachineServiceServer.
+    /// Generated trait containing gRPC methods that should be implemented for use with CommandServiceServer.
     #[async_trait]
-    pub trait MachineService: std::marker::Send + std::marker::Sync + 'static {
-        async fn claim(
+    pub trait CommandService: std::marker::Send + std::marker::Sync + 'static {
+        async fn register_machine(
             &self,
-            request: tonic::Request<super::ClaimRequest>,
-        ) -> std::result::Result<tonic::Response<super::ClaimResponse>, tonic::Status>;
-        /// Server streaming response type for the Install method.
-        type InstallStream: tonic::codegen::tokio_stream::Stream<
-                Item = std::result::Result<super::InstallProgress, tonic::Status>,
+            request: tonic::Request<super::RegisterMachineRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::RegisterMachineResponse>,
+            tonic::Status,
+        >;
+        /// Server streaming response type for the WaitForAssignment method.
+        type WaitForAssignmentStream: tonic::codegen::tokio_stream::Stream<
+                Item = std::result::Result<super::MachineAssignment, tonic::Status>,
             >
             + std::marker::Send
             + 'static;
-        async fn install(
-            &self,
-            request: tonic::Request<super::InstallConfig>,
-        ) -> std::result::Result<tonic::Response<Self::InstallStream>, tonic::Status>;
-        async fn get_system_info(
+        /// Stays open until the command server assigns a configuration to the machine
+        async fn wait_for_assignment(
             &self,
-            request: tonic::Request<super::SystemInfoRequest>,
+            request: tonic::Request<super::WaitForAssignmentRequest>,
         ) -> std::result::Result<
-            tonic::Response<super::SystemInfoResponse>,
+            tonic::Response<Self::WaitForAssignmentStream>,
             tonic::Status,
         >;
     }
+    /// Implemented by command servers. machined registers with it at boot and installs the
+    /// first valid configuration it is assigned
     #[derive(Debug)]
-    pub struct MachineServiceServer<T> {
+    pub struct CommandServiceServer<T> {
         inner: Arc<T>,
         accept_compression_encodings: EnabledCompressionEncodings,
         send_compression_encodings: EnabledCompressionEncodings,
         max_decoding_message_size: Option<usize>,
         max_encoding_message_size: Option<usize>,
     }
-    impl<T> MachineServiceServer<T> {
+    impl<T> CommandServiceServer<T> {
         pub fn new(inner: T) -> Self {
             Self::from_arc(Arc::new(inner))
         }
@@ -791,9 +3098,9 @@ pub mod machine_service_server {
             self
         }
     }
-    impl<T, B> tonic::codegen::Service<http::Request<B>> for MachineServiceServer<T>
+    impl<T, B> tonic::codegen::Service<http::Request<B>> for CommandServiceServer<T>
     where
-        T: MachineService,
+        T: CommandService,
         B: Body + std::marker::Send + 'static,
         B::Error: Into<StdError> + std::marker::Send + 'static,
     {
@@ -808,24 +3115,26 @@ pub mod machine_service_server {
         }
         fn call(&mut self, req: http::Request<B>) -> Self::Future {
             match req.uri().path() {
-                "/machined.MachineService/Claim" => {
+                "/machined.CommandService/RegisterMachine" => {
                     #[allow(non_camel_case_types)]
-                    struct ClaimSvc<T: MachineService>(pub Arc<T>);
+                    struct RegisterMachineSvc<T: CommandService>(pub Arc<T>);
                     impl<
-                        T: MachineService,
-                    > tonic::server::UnaryService<super::ClaimRequest> for ClaimSvc<T> {
-                        type Response = super::ClaimResponse;
+                        T: CommandService,
+                    > tonic::server::UnaryService<super::RegisterMachineRequest>
+                    for RegisterMachineSvc<T> {
+                        type Response = super::RegisterMachineResponse;
                         type Future = BoxFuture<
                             tonic::Response<Self::Response>,
                             tonic::Status,
                         >;
                         fn call(
                             &mut self,
-                            request: tonic::Request<super::ClaimRequest>,
+                            request: tonic::Request<super::RegisterMachineRequest>,
                         ) -> Self::Future {
                             let inner = Arc::clone(&self.0);
                             let fut = async move {
-                                <T as MachineService>::claim(&inner, request).await
+                                <T as CommandService>::register_machine(&inner, request)
+                                    .await
                             };
                             Box::pin(fut)
                         }
@@ -836,7 +3145,7 @@ pub mod machine_service_server {
                     let max_encoding_message_size = self.max_encoding_message_size;
                     let inner = self.inner.clone();
                     let fut = async move {
-                        let method = ClaimSvc(inner);
+                        let method = RegisterMachineSvc(inner);
                         let codec = tonic::codec::ProstCodec::default();
                         let mut grpc = tonic::server::Grpc::new(codec)
                             .apply_compression_config(
@@ -852,71 +3161,27 @@ pub mod machine_service_server {
                     };
                     Box::pin(fut)
                 }
-                "/machined.MachineService/Install" => {
+                "/machined.CommandService/WaitForAssignment" => {
                     #[allow(non_camel_case_types)]
-                    struct InstallSvc<T: MachineService>(pub Arc<T>);
+                    struct WaitForAssignmentSvc<T: CommandService>(pub Arc<T>);
                     impl<
-                        T: MachineService,
-                    > tonic::server::ServerStreamingService<super::InstallConfig>
-                    for InstallSvc<T> {
-                        type Response = super::InstallProgress;
-                        type ResponseStream = T::InstallStream;
+                        T: CommandService,
+                    > tonic::server::ServerStreamingService<
+                        super::WaitForAssignmentRequest,
+                    > for WaitForAssignmentSvc<T> {
+                        type Response = super::MachineAssignment;
+                        type ResponseStream = T::WaitForAssignmentStream;
                         type Future = BoxFuture<
                             tonic::Response<Self::ResponseStream>,
                             tonic::Status,
                         >;
                         fn call(
                             &mut self,
-                            request: tonic::Request<super::InstallConfig>,
-                        ) -> Self::Future {
-                            let inner = Arc::clone(&self.0);
-                            let fut = async move {
-                                <T as MachineService>::install(&inner, request).await
-                            };
-                            Box::pin(fut)
-                        }
-                    }
-                    let accept_compression_encodings = self.accept_compression_encodings;
-                    let send_compression_encodings = self.send_compression_encodings;
-                    let max_decoding_message_size = self.max_decoding_message_size;
-                    let max_encoding_message_size = self.max_encoding_message_size;
-                    let inner = self.inner.clone();
-                    let fut = async move {
-                        let method = InstallSvc(inner);
-                        let codec = tonic::codec::ProstCodec::default();
-                        let mut grpc = tonic::server::Grpc::new(codec)
-                            .apply_compression_config(
-                                accept_compression_encodings,
-                                send_compression_encodings,
-                            )
-                            .apply_max_message_size_config(
-                                max_decoding_message_size,
-                                max_encoding_message_size,
-                            );
-                        let res = grpc.server_streaming(method, req).await;
-                        Ok(res)
-                    };
-                    Box::pin(fut)
-                }
-                "/machined.MachineService/GetSystemInfo" => {
-                    #[allow(non_camel_case_types)]
-                    struct GetSystemInfoSvc<T: MachineService>(pub Arc<T>);
-                    impl<
-                        T: MachineService,
-                    > tonic::server::UnaryService<super::SystemInfoRequest>
-                    for GetSystemInfoSvc<T> {
-                        type Response = super::SystemInfoResponse;
-                        type Future = BoxFuture<
-                            tonic::Response<Self::Response>,
-                            tonic::Status,
-                        >;
-                        fn call(
-                            &mut self,
-                            request: tonic::Request<super::SystemInfoRequest>,
+                            request: tonic::Request<super::WaitForAssignmentRequest>,
                         ) -> Self::Future {
                             let inner = Arc::clone(&self.0);
                             let fut = async move {
-                                <T as MachineService>::get_system_info(&inner, request)
+                                <T as CommandService>::wait_for_assignment(&inner, request)
                                     .await
                             };
                             Box::pin(fut)
@@ -928,7 +3193,7 @@ pub mod machine_service_server {
                     let max_encoding_message_size = self.max_encoding_message_size;
                     let inner = self.inner.clone();
                     let fut = async move {
-                        let method = GetSystemInfoSvc(inner);
+                        let method = WaitForAssignmentSvc(inner);
                         let codec = tonic::codec::ProstCodec::default();
                         let mut grpc = tonic::server::Grpc::new(codec)
                             .apply_compression_config(
@@ -939,7 +3204,7 @@ pub mod machine_service_server {
                                 max_decoding_message_size,
                                 max_encoding_message_size,
                             );
-                        let res = grpc.unary(method, req).await;
+                        let res = grpc.server_streaming(method, req).await;
                         Ok(res)
                     };
                     Box::pin(fut)
@@ -964,7 +3229,7 @@ pub mod machine_service_server {
             }
         }
     }
-    impl<T> Clone for MachineServiceServer<T> {
+    impl<T> Clone for CommandServiceServer<T> {
         fn clone(&self) -> Self {
             let inner = self.inner.clone();
             Self {
@@ -977,8 +3242,8 @@ pub mod machine_service_server {
         }
     }
     /// Generated gRPC service name
-    pub const SERVICE_NAME: &str = "machined.MachineService";
-    impl<T> tonic::server::NamedService for MachineServiceServer<T> {
+    pub const SERVICE_NAME: &str = "machined.CommandService";
+    impl<T> tonic::server::NamedService for CommandServiceServer<T> {
         const NAME: &'static str = SERVICE_NAME;
     }
 }
@@ -1,43 +1,193 @@
 mod machined;
 mod platform;
 
+mod auth;
+mod blobcache;
+mod callhome;
+mod claim;
 mod config;
+mod console;
 mod devprop;
+mod diskprep;
+mod diskselect;
 mod error;
+mod logbuffer;
 mod sysinfo;
 mod process;
+mod report;
+mod session;
+mod tls;
 mod util;
+mod validate;
+mod wireguard;
 
+use crate::auth::{Authorizer, IssuedToken, Scope, TokenAuthority, TokenClaims, WithRpcMethod};
+use crate::claim::PayloadClaims;
 use crate::config::{load_config, MachinedConfig};
 use crate::machined::claim_request::ClaimSecret;
 use crate::machined::install_progress;
 use crate::machined::machine_service_server::MachineServiceServer;
-use crate::machined::{ClaimRequest, ClaimResponse, InstallConfig, InstallProgress, SystemInfoRequest, SystemInfoResponse};
+use crate::machined::{
+    ActivateBootEnvironmentRequest, AttachInstallSessionRequest, BlobCacheInfo, BootEnvironment,
+    CachedBlob, CancelInstallSessionRequest, CancelInstallSessionResponse, ClaimRequest,
+    ClaimResponse, ExportPoolsRequest, ExportPoolsResponse, GetBlobCacheRequest,
+    GetInstallReportRequest, GetLogRequest, GetLogResponse, InstallConfig, InstallProgress,
+    InstallReport, InstallSession, ListBootEnvironmentsRequest, ListBootEnvironmentsResponse,
+    ListInstallSessionsRequest, ListInstallSessionsResponse, PowerOffRequest, PowerResponse,
+    PrepareDisksRequest, PrepareDisksResponse, PruneBlobCacheRequest, PruneBlobCacheResponse,
+    RebootRequest, RefreshTokenRequest, SystemInfoRequest, SystemInfoResponse,
+    ValidateConfigRequest, ValidateConfigResponse,
+};
+use crate::error::{InstallationError, PrepareDisksError};
+use crate::logbuffer::LogBuffer;
+use crate::platform::PowerAction;
+use crate::session::{InstallSessions, Session};
 use base64::Engine;
 use jwt_simple::prelude::*;
 use machineconfig::MachineConfig;
 use machined::machine_service_server::MachineService;
 use miette::IntoDiagnostic;
 use std::fs::{self, OpenOptions};
+use std::net::SocketAddr;
 use std::path::Path;
 use std::pin::Pin;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::mpsc;
+use prost::Message;
+use tokio_util::sync::CancellationToken;
 use tonic::codec::CompressionEncoding;
+use tonic::service::interceptor::InterceptedService;
 use tonic::codegen::tokio_stream::wrappers::ReceiverStream;
 use tonic::codegen::tokio_stream::Stream;
 use tonic::transport::Server;
-use tonic::{Request, Response, Status};
-use tracing::{info, warn};
+use tonic::{Code, Request, Response, Status};
+use tracing::{error, info, warn};
 use tracing_subscriber::{self, prelude::*, Registry};
 
 type ProgressMessage = Result<InstallProgress, Status>;
 type ResponseStream = Pin<Box<dyn Stream<Item = ProgressMessage> + Send>>;
 
-#[derive(Debug, Default)]
+/// Seconds machined waits before rebooting or powering off, so the response reaches the client
+const POWER_DELAY_SECS: u32 = 2;
+
+#[derive(Debug)]
 struct Svc {
     config: Arc<MachinedConfig>,
-    private_key_bytes: Arc<Vec<u8>>,
+    /// SMBIOS UUID of the machine, or an id for this run of machined without one
+    machine_id: String,
+    tokens: Arc<TokenAuthority>,
+    sessions: Arc<InstallSessions>,
+    log: LogBuffer,
+}
+
+impl Svc {
+    /// Validate a machine configuration against the hardware of this machine
+    fn check_config(&self, mc: &MachineConfig) -> Result<ValidateConfigResponse, Status> {
+        let hardware = platform::discover_hardware()?;
+        Ok(validate::validate_config(mc, hardware.as_ref()))
+    }
+
+    /// Describe the blob cache and the blobs in it
+    fn blob_cache_info(&self) -> Result<BlobCacheInfo, Status> {
+        let cache = self.config.blob_cache();
+        let blobs = cache
+            .blobs()
+            .map_err(|e| Status::internal(e.to_string()))?
+            .into_iter()
+            .map(|blob| CachedBlob {
+                digest: blob.digest,
+                size: blob.size,
+                last_used: blob.last_used,
+            })
+            .collect::<Vec<_>>();
+        Ok(BlobCacheInfo {
+            path: cache.dir().display().to_string(),
+            size_bytes: blobs.iter().map(|blob| blob.size).sum(),
+            max_size_bytes: cache.max_size(),
+            blobs,
+        })
+    }
+
+    fn find_session(&self, session: &str) -> Result<Arc<Session>, Status> {
+        self.sessions
+            .get(session)
+            .ok_or_else(|| Status::not_found(format!("no install session {}", session)))
+    }
+
+    /// Refuse to touch pools or power while an install is writing to them, unless forced
+    fn ensure_no_install_running(&self, force: bool) -> Result<(), Status> {
+        match self.sessions.running() {
+            Some(session) if !force => Err(Status::failed_precondition(format!(
+                "install session {} is still running",
+                session.info().name
+            ))),
+            Some(session) => {
+                warn!("forced while install session {} is running", session.info().name);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn verify_claim_payload(&self, payload: &str) -> Result<PayloadClaims, Status> {
+        let server = self.config.server.as_ref().ok_or_else(|| {
+            Status::failed_precondition("no command server configured to verify claim payloads")
+        })?;
+        let public_key = claim::decode_public_key(&server.public_key)
+            .map_err(|e| Status::internal(e.to_string()))?;
+        let machine_uuid = smbios_uuid()
+            .ok_or_else(|| Status::internal("could not determine the SMBIOS UUID of this machine"))?;
+        let claims = claim::verify_claim_payload(payload, &public_key, &machine_uuid)
+            .map_err(|e| Status::permission_denied(e.to_string()))?;
+        Ok(claims.custom)
+    }
+}
+
+/// SMBIOS UUID of this machine, if it has one
+fn smbios_uuid() -> Option<String> {
+    crate::sysinfo::get_smbios_info()
+        .ok()?
+        .system
+        .map(|system| system.uuid)
+        .filter(|uuid| !uuid.is_empty())
+}
+
+/// Reboot or power off after a short delay so the RPC can still respond
+fn schedule_power(action: PowerAction) -> PowerResponse {
+    info!("going to {} in {} seconds", action, POWER_DELAY_SECS);
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_secs(POWER_DELAY_SECS.into())).await;
+        if let Err(e) = platform::power(action) {
+            error!("failed to {}: {}", action, e);
+        }
+    });
+    PowerResponse {
+        delay_seconds: POWER_DELAY_SECS,
+    }
+}
+
+fn platform_status(e: InstallationError) -> Status {
+    match e {
+        InstallationError::BootEnvironmentNotFound(_) => Status::not_found(e.to_string()),
+        #[cfg(all(target_os = "illumos", not(feature = "mock")))]
+        InstallationError::SecureEraseUnsupported => Status::unimplemented(e.to_string()),
+        e => Status::internal(e.to_string()),
+    }
+}
+
+fn claim_response(issued: IssuedToken) -> ClaimResponse {
+    ClaimResponse {
+        claim_token: issued.token,
+        expires_at: issued.expires_at as i64,
+        operator: issued.claims.operator,
+        scopes: issued
+            .claims
+            .scopes
+            .into_iter()
+            .map(|scope| scope.to_proto() as i32)
+            .collect(),
+    }
 }
 
 #[tonic::async_trait]
@@ -48,28 +198,44 @@ impl MachineService for Svc {
     ) -> Result<Response<ClaimResponse>, Status> {
         // first we check the password with the local configuration
         let claim_request = request.into_inner();
-        if let Some(secret) = claim_request.claim_secret {
-            match secret {
-                ClaimSecret::ClaimPassword(password) => {
-                    if self.config.claim_password == password {
-                        let key = HS256Key::from_bytes(&self.private_key_bytes);
-
-                        let claims = Claims::create(Duration::from_hours(2));
-                        let claim_token = key
-                            .authenticate(claims)
-                            .map_err(|_| Status::permission_denied("wrong claims"))?;
-                        Ok(Response::new(ClaimResponse { claim_token }))
-                    } else {
-                        Err(Status::permission_denied("wrong password"))
-                    }
-                }
-                ClaimSecret::ClaimPayload(_) => {
-                    todo!()
+        let payload_claims = match &claim_request.claim_secret {
+            Some(ClaimSecret::ClaimPassword(password)) => {
+                if self.config.claim_password != *password {
+                    return Err(Status::permission_denied("wrong password"));
                 }
+                PayloadClaims::default()
             }
-        } else {
-            Err(Status::invalid_argument("Claim secret required"))
-        }
+            Some(ClaimSecret::ClaimPayload(payload)) => self.verify_claim_payload(payload)?,
+            None => return Err(Status::invalid_argument("Claim secret required")),
+        };
+        // The command server decides who claims the machine with a payload
+        let operator = payload_claims
+            .operator
+            .or(Some(claim_request.operator).filter(|operator| !operator.is_empty()))
+            .unwrap_or_else(|| "anonymous".to_string());
+        let allowed = payload_claims.scopes.unwrap_or_else(|| Scope::ALL.to_vec());
+        let scopes = auth::grant_scopes(&claim_request.scopes, &allowed)?;
+        let issued = self
+            .tokens
+            .issue(&operator, scopes)
+            .map_err(|e| Status::internal(e.to_string()))?;
+        info!("claimed by {} with scopes {:?}", operator, issued.claims.scopes);
+        Ok(Response::new(claim_response(issued)))
+    }
+
+    async fn refresh_token(
+        &self,
+        request: Request<RefreshTokenRequest>,
+    ) -> Result<Response<ClaimResponse>, Status> {
+        let claims = request
+            .extensions()
+            .get::<TokenClaims>()
+            .ok_or_else(|| Status::unauthenticated("missing claim token"))?;
+        let issued = self
+            .tokens
+            .refresh(claims)
+            .map_err(|e| Status::unauthenticated(e.to_string()))?;
+        Ok(Response::new(claim_response(issued)))
     }
 
     type InstallStream = ResponseStream;
@@ -78,46 +244,246 @@ impl MachineService for Svc {
         &self,
         request: Request<InstallConfig>,
     ) -> Result<Response<Self::InstallStream>, Status> {
-        let key = HS256Key::from_bytes(&self.private_key_bytes);
-        if let Some(auth_header) = request.metadata().get("Authorization") {
-            let header_token_str = auth_header
-                .to_str()
-                .map_err(|_| Status::permission_denied("bad token"))?;
-            let _ = key
-                .verify_token::<NoCustomClaims>(header_token_str, None)
-                .map_err(|_| Status::permission_denied("token verification failed"))?;
-            let config = request.into_inner();
-            let (tx, rc) = mpsc::channel(1);
-            let mc: MachineConfig = knus::parse("install_config", &config.machineconfig)
+        let operator = request
+            .extensions()
+            .get::<TokenClaims>()
+            .map(|claims| claims.operator.clone())
+            .unwrap_or_default();
+        let config = request.into_inner();
+        let mc: MachineConfig =
+            machineconfig::parse_config("install_config", &config.machineconfig)
                 .map_err(|e| Status::invalid_argument(e.to_string()))?;
-            let cfg = self.config.clone();
-            tokio::spawn(async move {
-                match platform::install_system(&mc, cfg.clone(), tx).await {
-                    Ok(_) => {}
-                    Err(_) => {}
-                }
-            });
-
-            let output_stream = ReceiverStream::new(rc);
-            Ok(Response::new(Box::pin(output_stream)))
-        } else {
-            Err(Status::not_found("Missing authorization header"))
+        let report = self.check_config(&mc)?;
+        if !report.valid {
+            return Err(Status::with_details(
+                Code::FailedPrecondition,
+                format!(
+                    "machine configuration is not valid: {}",
+                    report
+                        .issues
+                        .iter()
+                        .map(|i| format!("{}: {}", i.subject, i.message))
+                        .collect::<Vec<_>>()
+                        .join("; ")
+                ),
+                report.encode_to_vec().into(),
+            ));
         }
+        let session =
+            self.sessions
+                .start(config.session_name, &operator, mc, self.config.clone())?;
+
+        let output_stream = ReceiverStream::new(session.follow());
+        Ok(Response::new(Box::pin(output_stream)))
     }
-    
+
+    async fn validate_config(
+        &self,
+        request: Request<ValidateConfigRequest>,
+    ) -> Result<Response<ValidateConfigResponse>, Status> {
+        let mc: MachineConfig =
+            machineconfig::parse_config("validate_config", &request.into_inner().machineconfig)
+                .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        Ok(Response::new(self.check_config(&mc)?))
+    }
+
     async fn get_system_info(
         &self,
-        _request: Request<SystemInfoRequest>,
+        request: Request<SystemInfoRequest>,
     ) -> Result<Response<SystemInfoResponse>, Status> {
         // Call the get_system_info function from the sysinfo module
-        match crate::sysinfo::get_system_info() {
+        match crate::sysinfo::get_system_info(request.get_ref()) {
             Ok(system_info) => Ok(Response::new(system_info)),
             Err(status) => Err(status),
         }
     }
+
+    async fn list_install_sessions(
+        &self,
+        _request: Request<ListInstallSessionsRequest>,
+    ) -> Result<Response<ListInstallSessionsResponse>, Status> {
+        Ok(Response::new(ListInstallSessionsResponse {
+            sessions: self.sessions.list(),
+        }))
+    }
+
+    type AttachInstallSessionStream = ResponseStream;
+
+    async fn attach_install_session(
+        &self,
+        request: Request<AttachInstallSessionRequest>,
+    ) -> Result<Response<Self::AttachInstallSessionStream>, Status> {
+        let session = self.find_session(&request.into_inner().session)?;
+        let output_stream = ReceiverStream::new(session.follow());
+        Ok(Response::new(Box::pin(output_stream)))
+    }
+
+    async fn cancel_install_session(
+        &self,
+        request: Request<CancelInstallSessionRequest>,
+    ) -> Result<Response<CancelInstallSessionResponse>, Status> {
+        let session = self.find_session(&request.into_inner().session)?;
+        session.cancel()?;
+        Ok(Response::new(CancelInstallSessionResponse {
+            session: Some(session.info()),
+        }))
+    }
+
+    async fn get_blob_cache(
+        &self,
+        _request: Request<GetBlobCacheRequest>,
+    ) -> Result<Response<BlobCacheInfo>, Status> {
+        Ok(Response::new(self.blob_cache_info()?))
+    }
+
+    async fn prune_blob_cache(
+        &self,
+        request: Request<PruneBlobCacheRequest>,
+    ) -> Result<Response<PruneBlobCacheResponse>, Status> {
+        let pruned = self
+            .config
+            .blob_cache()
+            .prune(request.into_inner().keep_bytes)
+            .map_err(|e| Status::internal(e.to_string()))?;
+        Ok(Response::new(PruneBlobCacheResponse {
+            removed: pruned.removed,
+            freed_bytes: pruned.freed_bytes,
+            cache: Some(self.blob_cache_info()?),
+        }))
+    }
+
+    async fn reboot(
+        &self,
+        request: Request<RebootRequest>,
+    ) -> Result<Response<PowerResponse>, Status> {
+        let request = request.into_inner();
+        self.ensure_no_install_running(request.force)?;
+        if !request.boot_environment.is_empty() {
+            platform::activate_boot_environment(&request.boot_environment)
+                .map_err(platform_status)?;
+        }
+        Ok(Response::new(schedule_power(PowerAction::Reboot)))
+    }
+
+    async fn power_off(
+        &self,
+        request: Request<PowerOffRequest>,
+    ) -> Result<Response<PowerResponse>, Status> {
+        self.ensure_no_install_running(request.into_inner().force)?;
+        Ok(Response::new(schedule_power(PowerAction::PowerOff)))
+    }
+
+    async fn list_boot_environments(
+        &self,
+        _request: Request<ListBootEnvironmentsRequest>,
+    ) -> Result<Response<ListBootEnvironmentsResponse>, Status> {
+        Ok(Response::new(ListBootEnvironmentsResponse {
+            boot_environments: platform::list_boot_environments().map_err(platform_status)?,
+        }))
+    }
+
+    async fn activate_boot_environment(
+        &self,
+        request: Request<ActivateBootEnvironmentRequest>,
+    ) -> Result<Response<BootEnvironment>, Status> {
+        let name = request.into_inner().name;
+        let be = platform::activate_boot_environment(&name).map_err(platform_status)?;
+        info!("boot environment {} is active on reboot", be.name);
+        Ok(Response::new(be))
+    }
+
+    async fn export_pools(
+        &self,
+        request: Request<ExportPoolsRequest>,
+    ) -> Result<Response<ExportPoolsResponse>, Status> {
+        self.ensure_no_install_running(request.into_inner().force)?;
+        let pools = platform::export_pools().map_err(platform_status)?;
+        info!("exported pools {}", pools.join(", "));
+        Ok(Response::new(ExportPoolsResponse { pools }))
+    }
+
+    async fn get_log(
+        &self,
+        request: Request<GetLogRequest>,
+    ) -> Result<Response<GetLogResponse>, Status> {
+        Ok(Response::new(GetLogResponse {
+            lines: self.log.tail(request.into_inner().lines as usize),
+        }))
+    }
+
+    async fn prepare_disks(
+        &self,
+        request: Request<PrepareDisksRequest>,
+    ) -> Result<Response<PrepareDisksResponse>, Status> {
+        let request = request.into_inner();
+        self.ensure_no_install_running(false)?;
+        let disks = diskprep::select_disks(
+            &request.disks,
+            &sysinfo::get_disk_info()?,
+            &platform::disks_in_use().map_err(platform_status)?,
+            request.force,
+        )
+        .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let confirmation =
+            diskprep::confirmation_token(&self.machine_id, &disks, request.secure_erase);
+        if request.confirmation.is_empty() {
+            return Ok(Response::new(PrepareDisksResponse {
+                disks,
+                wiped: false,
+                confirmation,
+            }));
+        }
+        if request.confirmation != confirmation {
+            return Err(Status::failed_precondition(
+                PrepareDisksError::ConfirmationMismatch.to_string(),
+            ));
+        }
+
+        // A secure erase takes hours, keep it off the threads serving requests
+        let secure_erase = request.secure_erase;
+        let wiped = disks.clone();
+        tokio::task::spawn_blocking(move || {
+            for disk in &wiped {
+                info!("wiping disk {}", disk.device);
+                platform::wipe_disk(disk, secure_erase)?;
+            }
+            Ok::<_, InstallationError>(())
+        })
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?
+        .map_err(platform_status)?;
+        Ok(Response::new(PrepareDisksResponse {
+            disks,
+            wiped: true,
+            confirmation: String::new(),
+        }))
+    }
+
+    async fn get_install_report(
+        &self,
+        request: Request<GetInstallReportRequest>,
+    ) -> Result<Response<InstallReport>, Status> {
+        let session = request.into_inner().session;
+        if session.is_empty() {
+            return self
+                .sessions
+                .last_report()
+                .map(Response::new)
+                .ok_or_else(|| Status::not_found("no install has succeeded yet"));
+        }
+        self.find_session(&session)?
+            .report()
+            .map(Response::new)
+            .ok_or_else(|| {
+                Status::failed_precondition(format!(
+                    "install session {} has not succeeded, it has no report",
+                    session
+                ))
+            })
+    }
 }
 
-/// Check for a KDL configuration file in /usb
+/// Check for a machine configuration file in /usb
 /// Returns the content of the file if found, None otherwise
 fn check_usb_config() -> Option<(String, String)> {
     // Check for files with various extensions
@@ -138,35 +504,58 @@ fn check_usb_config() -> Option<(String, String)> {
     None
 }
 
-#[tokio::main(flavor = "current_thread")]
+#[tokio::main]
 async fn main() -> miette::Result<()> {
+    let cfg = load_config()?;
+
     // install global collector configured based on RUST_LOG env var.
+    // /dev/msglog only exists on illumos, elsewhere e.g. the mock platform logs to stdout only
     let msg_log = OpenOptions::new()
         .append(true)
         .create(false)
         .open("/dev/msglog")
-        .into_diagnostic()?;
+        .ok();
+    let log = LogBuffer::default();
     let subscriber = Registry::default()
         .with(
             // msglog layer, to view everything in the console as smf service have their output rerouted to a logfile by smf
+            msg_log.map(|msg_log| {
+                tracing_subscriber::fmt::layer()
+                    .compact()
+                    .with_writer(msg_log)
+                    .with_ansi(true)
+                    // log lines would draw over the console installer
+                    .with_filter(if cfg.console.enabled {
+                        tracing_subscriber::filter::LevelFilter::OFF
+                    } else {
+                        tracing_subscriber::filter::LevelFilter::DEBUG
+                    })
+            }),
+        )
+        .with(
+            // stdout layer, to view everything in the console
             tracing_subscriber::fmt::layer()
                 .compact()
-                .with_writer(msg_log)
                 .with_ansi(true)
                 .with_filter(tracing_subscriber::filter::LevelFilter::DEBUG),
         )
         .with(
-            // stdout layer, to view everything in the console
+            // kept in memory for the GetLog RPC
             tracing_subscriber::fmt::layer()
                 .compact()
-                .with_ansi(true)
+                .with_writer(log.clone())
+                .with_ansi(false)
                 .with_filter(tracing_subscriber::filter::LevelFilter::DEBUG),
         );
     tracing::subscriber::set_global_default(subscriber).into_diagnostic()?;
 
-    let cfg = load_config()?;
+    #[cfg(feature = "mock")]
+    if let Some(fixture) = &cfg.mock_fixture {
+        platform::load_fixture(fixture).into_diagnostic()?;
+        info!("Simulating the machine of hardware fixture {}", fixture);
+    }
 
-    // Check for a KDL configuration file in /usb
+    // Check for a machine configuration file in /usb
     if let Some((file_path, config_content)) = check_usb_config() {
         info!("Found configuration file: {}", file_path);
         info!("Running installation based on USB configuration");
@@ -216,8 +605,19 @@ async fn main() -> miette::Result<()> {
             }
         });
 
-        // Run the installation
-        match platform::install_system(&mc, Arc::new(cfg), tx).await {
+        // Installs from the USB stick run without a session, their report names the
+        // configuration file as the operator
+        let session = InstallSession {
+            id: uuid::Uuid::new_v4().as_hyphenated().to_string(),
+            name: "usb".to_string(),
+            started_at: chrono::Utc::now().timestamp(),
+            ..Default::default()
+        };
+        let mut report = report::start_report(&session, &file_path, &mc);
+
+        // Run the installation, there is no session to cancel it through
+        let cancel = CancellationToken::new();
+        match platform::install_system(&mc, Arc::new(cfg), &mut report, &cancel, tx).await {
             Ok(_) => {
                 info!("Installation completed successfully");
             }
@@ -246,10 +646,32 @@ async fn main() -> miette::Result<()> {
         }
     }
 
-    info!(
-        "claim this installer with the password {}",
-        &cfg.claim_password
-    );
+    if let Some(wireguard) = &cfg.wireguard {
+        wireguard::start_tunnel(wireguard)?;
+    }
+
+    let mut server = Server::builder();
+    let mut tls_fingerprint = None;
+    if cfg.tls.enabled {
+        let identity = tls::load_identity(&cfg.tls)?;
+        server = server
+            .tls_config(tls::server_tls_config(&cfg.tls, &identity)?)
+            .into_diagnostic()?;
+        if cfg.tls.client_ca.is_some() {
+            info!("clients need a certificate signed by the configured client CA");
+        }
+        info!(
+            "claim this installer with the password {} after checking the TLS certificate fingerprint {}",
+            &cfg.claim_password, identity.fingerprint
+        );
+        tls_fingerprint = Some(identity.fingerprint);
+    } else {
+        warn!("TLS is disabled, claim tokens and configurations are sent in plaintext");
+        info!(
+            "claim this installer with the password {}",
+            &cfg.claim_password
+        );
+    }
     let key = if let Some(claim_secret) = &cfg.claim_key {
         let key = base64::prelude::BASE64_STANDARD
             .decode(&claim_secret.private_key)
@@ -258,20 +680,55 @@ async fn main() -> miette::Result<()> {
     } else {
         HS256Key::generate().to_bytes()
     };
+    let machine_id = smbios_uuid().unwrap_or_else(|| {
+        warn!("no SMBIOS UUID found, claim tokens are bound to this run of machined");
+        uuid::Uuid::new_v4().as_hyphenated().to_string()
+    });
+    let tokens = Arc::new(TokenAuthority::new(&key, machine_id.clone()));
     // Now we listen for requests to claim the server from a
+    let sessions = InstallSessions::open(&cfg.session_dir, cfg.session_retention).into_diagnostic()?;
     info!("starting server on {}", &cfg.listen);
-    let addr = cfg.listen.parse().into_diagnostic()?;
+    let addr: SocketAddr = cfg.listen.parse().into_diagnostic()?;
     let machined = Svc {
         config: Arc::new(cfg),
-        private_key_bytes: Arc::new(key),
+        machine_id: machine_id.clone(),
+        tokens: tokens.clone(),
+        sessions: Arc::new(sessions),
+        log,
     };
 
-    Server::builder()
-        .add_service(
+    // Let an operator at the machine install from the console instead of claiming it
+    if machined.config.console.enabled {
+        tokio::spawn(console::run_console(
+            machined.config.clone(),
+            machined.sessions.clone(),
+            console::Welcome {
+                claim_password: machined.config.claim_password.clone(),
+                port: addr.port(),
+                tls_fingerprint: tls_fingerprint.clone(),
+            },
+        ));
+    }
+
+    // Without an operator claiming the machine, ask the command server what to install
+    if machined.config.server.is_some() {
+        tokio::spawn(callhome::call_home(
+            machined.config.clone(),
+            machined.sessions.clone(),
+            callhome::Registration {
+                machine_uuid: machine_id,
+                tls_fingerprint,
+            },
+        ));
+    }
+
+    server
+        .add_service(WithRpcMethod(InterceptedService::new(
             MachineServiceServer::new(machined)
                 .send_compressed(CompressionEncoding::Zstd)
                 .accept_compressed(CompressionEncoding::Zstd),
-        )
+            Authorizer::new(tokens),
+        )))
         .serve(addr)
         .await
         .into_diagnostic()?;
@@ -0,0 +1,99 @@
+use crate::diskprep::select_disks;
+use crate::diskselect::{is_selector, resolve_disk_selectors};
+use crate::machined::InstallProgress;
+use crate::sysinfo::{get_disk_info, get_network_info};
+use crate::util::{check_cancelled, report_install_error, report_install_info};
+use crate::validate::Hardware;
+use machineconfig::{Pool, WipeDisks};
+use tokio::sync::mpsc::error::SendError;
+use tokio::sync::mpsc::Sender;
+use tokio_util::sync::CancellationToken;
+use tonic::Status;
+
+/// Discover the disks and network links a machine configuration is validated against
+pub fn discover_hardware() -> Result<Option<Hardware>, Status> {
+    Ok(Some(Hardware {
+        disks: get_disk_info()?,
+        links: get_network_info()?,
+    }))
+}
+
+/// Replace the disk selectors of `pools` with the disks they select, reporting each resolution
+pub async fn resolve_pool_disks(
+    pools: &[Pool],
+    tx: &Sender<Result<InstallProgress, Status>>,
+) -> Result<Vec<Pool>, SendError<Result<InstallProgress, Status>>> {
+    let mut pools = pools.to_vec();
+    if !pools
+        .iter()
+        .flat_map(|pool| pool.disks())
+        .any(|disk| is_selector(disk))
+    {
+        return Ok(pools);
+    }
+
+    let disks = get_disk_info().map_err(|status| SendError(Err(status)))?;
+    match resolve_disk_selectors(&mut pools, &disks) {
+        Ok(resolved) => {
+            for selection in resolved {
+                tx.send(report_install_info(
+                    format!(
+                        "Pool {}: disk selector {} resolved to {}",
+                        selection.pool,
+                        selection.selector,
+                        selection.disks.join(" ")
+                    )
+                    .as_str(),
+                ))
+                .await?;
+            }
+            Ok(pools)
+        }
+        Err(e) => {
+            tx.send(report_install_error(&e)).await?;
+            Err(SendError(Err(Status::failed_precondition(e.to_string()))))
+        }
+    }
+}
+
+/// Wipe the disks of `pools` before they are created, as the `wipe-disks` option asks
+pub async fn wipe_pool_disks(
+    pools: &[Pool],
+    wipe: &WipeDisks,
+    cancel: &CancellationToken,
+    tx: &Sender<Result<InstallProgress, Status>>,
+) -> Result<(), SendError<Result<InstallProgress, Status>>> {
+    let names = pools
+        .iter()
+        .flat_map(|pool| pool.disks())
+        .cloned()
+        .collect::<Vec<_>>();
+    let disks = get_disk_info().map_err(|status| SendError(Err(status)))?;
+    let in_use = match super::disks_in_use() {
+        Ok(in_use) => in_use,
+        Err(e) => {
+            tx.send(report_install_error(&e)).await?;
+            return Err(SendError(Err(Status::internal(e.to_string()))));
+        }
+    };
+    // The configuration has no way to force it, export pools to wipe their disks
+    let disks = match select_disks(&names, &disks, &in_use, false) {
+        Ok(disks) => disks,
+        Err(e) => {
+            tx.send(report_install_error(&e)).await?;
+            return Err(SendError(Err(Status::failed_precondition(e.to_string()))));
+        }
+    };
+    for disk in &disks {
+        check_cancelled(cancel)?;
+        tx.send(report_install_info(
+            format!("Wiping disk {}", disk.device).as_str(),
+        ))
+        .await?;
+        if let Err(e) = super::wipe_disk(disk, wipe.secure_erase) {
+            tx.send(report_install_error(&e)).await?;
+            return Err(SendError(Err(Status::internal(e.to_string()))));
+        }
+    }
+    Ok(())
+}
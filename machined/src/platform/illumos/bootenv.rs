@@ -0,0 +1,77 @@
+use super::BEADM_BIN;
+use crate::error::InstallationError;
+use crate::machined::BootEnvironment;
+use std::process::Command;
+
+pub fn list_boot_environments() -> Result<Vec<BootEnvironment>, InstallationError> {
+    let out = Command::new(BEADM_BIN).args(["list", "-H"]).output()?;
+    if !out.status.success() {
+        return Err(InstallationError::BeadmFailed(String::from_utf8(
+            out.stderr,
+        )?));
+    }
+    Ok(parse_beadm_list(&String::from_utf8(out.stdout)?))
+}
+
+/// Make the next boot use the boot environment `name`
+pub fn activate_boot_environment(name: &str) -> Result<BootEnvironment, InstallationError> {
+    if !list_boot_environments()?.iter().any(|be| be.name == name) {
+        return Err(InstallationError::BootEnvironmentNotFound(name.to_string()));
+    }
+    let out = Command::new(BEADM_BIN).args(["activate", name]).output()?;
+    if !out.status.success() {
+        return Err(InstallationError::BeadmFailed(String::from_utf8(
+            out.stderr,
+        )?));
+    }
+    list_boot_environments()?
+        .into_iter()
+        .find(|be| be.name == name)
+        .ok_or_else(|| InstallationError::BootEnvironmentNotFound(name.to_string()))
+}
+
+/// Parse the machine readable output of `beadm list -H`:
+/// `name;uuid;active;mountpoint;space;policy;created`
+fn parse_beadm_list(output: &str) -> Vec<BootEnvironment> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let fields = line.split(';').collect::<Vec<_>>();
+            if fields.len() < 7 {
+                return None;
+            }
+            Some(BootEnvironment {
+                name: fields[0].to_string(),
+                active: fields[2].contains('N'),
+                active_on_reboot: fields[2].contains('R'),
+                mountpoint: match fields[3] {
+                    "-" => String::new(),
+                    mountpoint => mountpoint.to_string(),
+                },
+                space_bytes: fields[4].parse().unwrap_or_default(),
+                created: fields[6].parse().unwrap_or_default(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_beadm_list() {
+        let output = "openindiana;4b3e1c9a-6f2d-4a8e-9c1b-2d7f5e8a9b0c;N;/;2684354560;static;1733000000\n\
+                      openindiana-2024-12-01;8d2f6a1b-3c4e-4f5a-b6c7-d8e9f0a1b2c3;R;/a;1073741824;static;1733100000\n\
+                      old;0b1c2d3e-4f5a-6b7c-8d9e-0f1a2b3c4d5e;;;52428800;static;1700000000\n";
+        let bes = parse_beadm_list(output);
+        assert_eq!(bes.len(), 3);
+        assert!(bes[0].active && !bes[0].active_on_reboot);
+        assert_eq!(bes[1].name, "openindiana-2024-12-01");
+        assert!(bes[1].active_on_reboot);
+        assert_eq!(bes[1].mountpoint, "/a");
+        assert_eq!(bes[1].space_bytes, 1073741824);
+        assert_eq!(bes[2].mountpoint, "");
+        assert_eq!(bes[2].created, 1700000000);
+    }
+}
@@ -1,41 +1,127 @@
 use crate::config::MachinedConfig;
+use crate::diskprep::{protected_mounts, DiskInUse};
+use crate::diskselect::disk_name;
 use crate::error::InstallationError;
-use crate::machined::InstallProgress;
-use crate::platform::illumos::image::{build_image_ref, fetch_image, install_image};
+use crate::machined::{InstallPhase, InstallProgress, InstallReport};
+use crate::platform::hardware::{resolve_pool_disks, wipe_pool_disks};
+use crate::platform::illumos::rollback::{Rollback, RollbackStep};
+use crate::platform::illumos::sysconfig::{
+    apply_dump_device, apply_hostname, apply_interfaces, apply_nameservers, apply_swap,
+};
 use crate::platform::illumos::zpool::{
-    create_boot_environment, create_boot_environment_base_dataset, create_pool,
-    mount_boot_environment,
+    altroot_pools, create_boot_environment, create_boot_environment_base_dataset, create_dataset,
+    create_pool, create_volume, export_pool, imported_pool_devices, mount_boot_environment,
+    ALTROOT,
+};
+use crate::platform::image::{install_image, resolve_image};
+use crate::platform::postinstall::apply_postinstall;
+use crate::platform::PowerAction;
+use crate::report::write_report;
+use crate::sysinfo::get_network_info;
+use crate::util::{
+    check_cancelled, report_install_debug, report_install_error, report_install_info,
+    report_install_step, report_install_warning, unverified_image,
 };
-use crate::util::{report_install_debug, report_install_error, report_install_info};
-use machineconfig::MachineConfig;
+use machineconfig::{MachineConfig, Pool, SysConfig, VolumeUsage};
+use std::path::Path;
 use std::process::Command;
 use std::sync::Arc;
 use tokio::sync::mpsc::error::SendError;
 use tokio::sync::mpsc::Sender;
+use tokio_util::sync::CancellationToken;
 use tonic::Status;
 
-mod image;
+mod bootenv;
+mod rollback;
 mod sysconfig;
 mod zpool;
 
+pub use bootenv::{activate_boot_environment, list_boot_environments};
+pub use zpool::wipe_disk;
+
 const BEADM_BIN: &str = "/sbin/beadm";
 
 const BOOTADM_BIN: &str = "/sbin/bootadm";
 
+const REBOOT_BIN: &str = "/usr/sbin/reboot";
+
+const POWEROFF_BIN: &str = "/usr/sbin/poweroff";
+
+const MNTTAB: &str = "/etc/mnttab";
+
+/// Boot environment name template used when the configuration has neither name nor template
+const DEFAULT_BE_TEMPLATE: &str = "openindiana-{date}:{time}";
+
 pub async fn install_system(
     mc: &MachineConfig,
     config: Arc<MachinedConfig>,
+    report: &mut InstallReport,
+    cancel: &CancellationToken,
     tx: Sender<Result<InstallProgress, Status>>,
+) -> Result<(), SendError<Result<InstallProgress, Status>>> {
+    let mut rollback = Rollback::default();
+    let result = run_install(mc, config, report, cancel, &tx, &mut rollback).await;
+    if result.is_err() {
+        let outcome = if cancel.is_cancelled() {
+            "cancelled"
+        } else {
+            "failed"
+        };
+        if mc.skip_rollback && !unverified_image(&result) {
+            tx.send(report_install_info(
+                format!(
+                    "Installation {}, leaving pools and datasets in place",
+                    outcome
+                )
+                .as_str(),
+            ))
+            .await?;
+        } else {
+            if mc.skip_rollback {
+                tx.send(report_install_warning(
+                    "Ignoring skip-rollback, a layer did not match its digest after it was extracted",
+                ))
+                .await?;
+            }
+            tx.send(report_install_info(
+                format!("Installation {}, rolling back completed steps", outcome).as_str(),
+            ))
+            .await?;
+            rollback.undo(&tx).await?;
+        }
+    }
+    result
+}
+
+async fn run_install(
+    mc: &MachineConfig,
+    config: Arc<MachinedConfig>,
+    report: &mut InstallReport,
+    cancel: &CancellationToken,
+    tx: &Sender<Result<InstallProgress, Status>>,
+    rollback: &mut Rollback,
 ) -> Result<(), SendError<Result<InstallProgress, Status>>> {
     tx.send(report_install_debug("Starting installation"))
         .await?;
-    for pool in &mc.pools {
-        tx.send(report_install_debug(
+
+    let pools = resolve_pool_disks(&mc.pools, tx).await?;
+    if let Some(wipe) = &mc.wipe_disks {
+        wipe_pool_disks(&pools, wipe, cancel, tx).await?;
+    }
+
+    let pool_count = pools.len() as u32;
+    for (idx, pool) in pools.iter().enumerate() {
+        check_cancelled(cancel)?;
+        tx.send(report_install_step(
+            InstallPhase::Pools,
+            idx as u32 + 1,
+            pool_count,
             format!("Setting up pool {}", &pool.name).as_str(),
         ))
         .await?;
         match create_pool(&pool) {
             Ok(_) => {
+                rollback.record(RollbackStep::DestroyPool(pool.name.clone()));
                 tx.send(report_install_debug(
                     format!("Pool {} created", &pool.name).as_str(),
                 ))
@@ -48,8 +134,18 @@ pub async fn install_system(
         }
     }
 
-    match create_boot_environment_base_dataset() {
-        Ok(_) => {
+    check_cancelled(cancel)?;
+    tx.send(report_install_step(
+        InstallPhase::Datasets,
+        1,
+        4,
+        "Creating base root dataset",
+    ))
+    .await?;
+    let root_pool = mc.root_pool();
+    match create_boot_environment_base_dataset(root_pool) {
+        Ok(base) => {
+            rollback.record(RollbackStep::DestroyDataset(base));
             tx.send(report_install_debug("base root Dataset created"))
                 .await?;
         }
@@ -59,20 +155,50 @@ pub async fn install_system(
         }
     }
 
-    let be_path = match create_boot_environment(mc.boot_environment_name.clone()) {
-        Ok(be_path) => {
-            tx.send(report_install_debug("boot environment created"))
-                .await?;
-            be_path
-        }
+    check_cancelled(cancel)?;
+    tx.send(report_install_step(
+        InstallPhase::Datasets,
+        2,
+        4,
+        "Creating boot environment",
+    ))
+    .await?;
+    let be_name = match mc.boot_environment(DEFAULT_BE_TEMPLATE, chrono::Utc::now()) {
+        Ok(be_name) => be_name,
         Err(e) => {
+            let e = InstallationError::InvalidBootEnvironmentName(e.to_string());
             tx.send(report_install_error(&e)).await?;
             return Err(SendError(Err(Status::internal("Internal error"))));
         }
     };
+    report.boot_environment = be_name.clone();
+    let be_path =
+        match create_boot_environment(root_pool, &be_name, &mc.boot_environment_properties) {
+            Ok(be_path) => {
+                rollback.record(RollbackStep::DestroyDataset(be_path.clone()));
+                tx.send(report_install_debug(
+                    format!("boot environment {} created", be_path).as_str(),
+                ))
+                .await?;
+                be_path
+            }
+            Err(e) => {
+                tx.send(report_install_error(&e)).await?;
+                return Err(SendError(Err(Status::internal("Internal error"))));
+            }
+        };
 
+    check_cancelled(cancel)?;
+    tx.send(report_install_step(
+        InstallPhase::Datasets,
+        3,
+        4,
+        "Mounting boot environment to /a",
+    ))
+    .await?;
     match mount_boot_environment(&be_path) {
         Ok(_) => {
+            rollback.record(RollbackStep::Unmount(be_path.clone()));
             tx.send(report_install_debug("boot environment mounted to /a"))
                 .await?;
         }
@@ -82,28 +208,86 @@ pub async fn install_system(
         }
     }
 
-    let image_ref = build_image_ref(&mc.image).map_err(|e| {
+    check_cancelled(cancel)?;
+    tx.send(report_install_step(
+        InstallPhase::Datasets,
+        4,
+        4,
+        "Creating datasets and volumes",
+    ))
+    .await?;
+    for pool in &pools {
+        for (name, dataset) in pool.dataset_tree() {
+            if let Err(e) = create_dataset(&name, false, Some(&dataset.create_properties())) {
+                tx.send(report_install_error(&e)).await?;
+                return Err(SendError(Err(Status::internal("Internal error"))));
+            }
+            rollback.record(RollbackStep::DestroyDataset(name.clone()));
+            tx.send(report_install_debug(
+                format!("dataset {} created", name).as_str(),
+            ))
+            .await?;
+        }
+        for volume in &pool.volumes {
+            let name = format!("{}/{}", pool.name, volume.name);
+            let opts = volume
+                .properties
+                .iter()
+                .map(|opt| (opt.name.as_str(), opt.value.as_str()))
+                .collect::<Vec<_>>();
+            if let Err(e) = create_volume(&name, &volume.size, &opts) {
+                tx.send(report_install_error(&e)).await?;
+                return Err(SendError(Err(Status::internal("Internal error"))));
+            }
+            rollback.record(RollbackStep::DestroyDataset(name.clone()));
+            tx.send(report_install_debug(
+                format!("volume {} of {} created", name, volume.size).as_str(),
+            ))
+            .await?;
+        }
+    }
+
+    check_cancelled(cancel)?;
+    let image_source = mc.image_source().map_err(|e| {
         SendError(Err(Status::internal(format!(
             "Parsing image reference failed: {}",
             e
         ))))
     })?;
 
-    let image_config = match fetch_image(&image_ref, &config.default_oci_registry, tx.clone()).await
-    {
-        Ok(image_config) => {
-            tx.send(report_install_debug("image fetched")).await?;
-            image_config
+    let image = match resolve_image(&image_source, &config.default_oci_registry, tx.clone()).await {
+        Ok(image) => {
+            tx.send(report_install_debug("image manifest resolved"))
+                .await?;
+            image
         }
         Err(e) => {
             tx.send(report_install_error(e)).await?;
             return Err(SendError(Err(Status::internal("Internal error"))));
         }
     };
+    report.manifest_digest = image.manifest().to_string();
+    report.layer_digests = image
+        .layers()
+        .iter()
+        .map(|layer| layer.to_string())
+        .collect();
+
+    install_image(image, &config.blob_cache(), cancel, tx).await?;
 
-    install_image(&image_ref, image_config, &tx).await?;
+    check_cancelled(cancel)?;
+    apply_sysconfig(&mc.sysconfig, &pools, tx).await?;
+    apply_postinstall(&mc.postinstall, cancel, tx).await?;
 
-    match make_be_bootable(&be_path) {
+    check_cancelled(cancel)?;
+    tx.send(report_install_step(
+        InstallPhase::Bootloader,
+        1,
+        1,
+        "Activating boot environment and installing bootloader",
+    ))
+    .await?;
+    match make_be_bootable(root_pool, &be_name) {
         Ok(_) => {
             tx.send(report_install_info("bootenvironment activated"))
                 .await?;
@@ -114,14 +298,131 @@ pub async fn install_system(
         }
     }
 
+    match write_report(report, Path::new(ALTROOT)) {
+        Ok(path) => {
+            tx.send(report_install_info(
+                format!("install report written to {}", path.display()).as_str(),
+            ))
+            .await?;
+        }
+        Err(e) => {
+            tx.send(report_install_error(e)).await?;
+            return Err(SendError(Err(Status::internal("Internal error"))));
+        }
+    }
+
     Ok(())
 }
 
-pub fn make_be_bootable(be_name: &str) -> Result<(), InstallationError> {
-    let pool_name = be_name
-        .split("/")
-        .next()
-        .ok_or_else(|| InstallationError::InvalidBootEnvironmentName)?;
+/// Write the system configuration into the boot environment mounted at /a
+async fn apply_sysconfig(
+    sc: &SysConfig,
+    pools: &[Pool],
+    tx: &Sender<Result<InstallProgress, Status>>,
+) -> Result<(), SendError<Result<InstallProgress, Status>>> {
+    let root = Path::new(ALTROOT);
+
+    tx.send(report_install_step(
+        InstallPhase::Sysconfig,
+        1,
+        4,
+        "Setting hostname",
+    ))
+    .await?;
+    if sc.hostname.is_empty() {
+        tx.send(report_install_debug("no hostname configured"))
+            .await?;
+    } else if let Err(e) = apply_hostname(root, &sc.hostname) {
+        tx.send(report_install_error(e)).await?;
+        return Err(SendError(Err(Status::internal("Internal error"))));
+    }
+
+    tx.send(report_install_step(
+        InstallPhase::Sysconfig,
+        2,
+        4,
+        "Configuring nameservers",
+    ))
+    .await?;
+    if sc.nameservers.is_empty() {
+        tx.send(report_install_debug("no nameservers configured"))
+            .await?;
+    } else if let Err(e) = apply_nameservers(root, &sc.nameservers) {
+        tx.send(report_install_error(e)).await?;
+        return Err(SendError(Err(Status::internal("Internal error"))));
+    }
+
+    tx.send(report_install_step(
+        InstallPhase::Sysconfig,
+        3,
+        4,
+        "Configuring network interfaces",
+    ))
+    .await?;
+    if sc.interfaces.is_empty() {
+        tx.send(report_install_debug("no network interfaces configured"))
+            .await?;
+    } else {
+        let links = if sc.interfaces.iter().any(|iface| iface.selector.is_some()) {
+            get_network_info().map_err(|status| SendError(Err(status)))?
+        } else {
+            vec![]
+        };
+        if let Err(e) = apply_interfaces(root, &sc.interfaces, &links) {
+            tx.send(report_install_error(e)).await?;
+            return Err(SendError(Err(Status::internal("Internal error"))));
+        }
+        tx.send(report_install_debug(
+            "network interfaces will be configured on first boot",
+        ))
+        .await?;
+    }
+
+    tx.send(report_install_step(
+        InstallPhase::Sysconfig,
+        4,
+        4,
+        "Configuring swap and dump volumes",
+    ))
+    .await?;
+    let volume_devices = |usage: VolumeUsage| {
+        pools
+            .iter()
+            .flat_map(|pool| {
+                pool.volumes
+                    .iter()
+                    .filter(move |volume| volume.usage == Some(usage))
+                    .map(|volume| format!("/dev/zvol/dsk/{}/{}", pool.name, volume.name))
+            })
+            .collect::<Vec<_>>()
+    };
+    let swap = volume_devices(VolumeUsage::Swap);
+    if !swap.is_empty() {
+        if let Err(e) = apply_swap(root, &swap) {
+            tx.send(report_install_error(e)).await?;
+            return Err(SendError(Err(Status::internal("Internal error"))));
+        }
+        tx.send(report_install_debug(
+            format!("swap on {}", swap.join(" ")).as_str(),
+        ))
+        .await?;
+    }
+    if let Some(dump) = volume_devices(VolumeUsage::Dump).first() {
+        if let Err(e) = apply_dump_device(root, dump) {
+            tx.send(report_install_error(e)).await?;
+            return Err(SendError(Err(Status::internal("Internal error"))));
+        }
+        tx.send(report_install_debug(
+            format!("crash dumps go to {}", dump).as_str(),
+        ))
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Activate the boot environment and install the bootloader on the disks of the root pool
+pub fn make_be_bootable(root_pool: &str, be_name: &str) -> Result<(), InstallationError> {
     let beadm_out = Command::new(BEADM_BIN)
         .arg("activate")
         .arg(be_name)
@@ -138,9 +439,9 @@ pub fn make_be_bootable(be_name: &str) -> Result<(), InstallationError> {
             "-M",
             "-f",
             "-P",
-            &pool_name,
+            root_pool,
             "-R",
-            "/a",
+            ALTROOT,
         ])
         .output()?;
     if !bootadm_install_out.status.success() {
@@ -150,7 +451,7 @@ pub fn make_be_bootable(be_name: &str) -> Result<(), InstallationError> {
     }
 
     let bootadm_archive_out = Command::new(BOOTADM_BIN)
-        .args(["update-archive", "-f", "-R", "/a"])
+        .args(["update-archive", "-f", "-R", ALTROOT])
         .output()?;
     if !bootadm_archive_out.status.success() {
         return Err(InstallationError::InstallBootLoaderFailed(
@@ -160,3 +461,48 @@ pub fn make_be_bootable(be_name: &str) -> Result<(), InstallationError> {
 
     Ok(())
 }
+
+/// Export the pools created for the installation, which unmounts everything below /a
+pub fn export_pools() -> Result<Vec<String>, InstallationError> {
+    let pools = altroot_pools()?;
+    for pool in &pools {
+        export_pool(pool)?;
+    }
+    Ok(pools)
+}
+
+/// Disks of the imported pools and the disks the root and /usb of the installer are mounted
+/// from, which PrepareDisks does not wipe without force
+pub fn disks_in_use() -> Result<Vec<DiskInUse>, InstallationError> {
+    let mut in_use = imported_pool_devices()?
+        .into_iter()
+        .map(|(pool, device)| DiskInUse {
+            device: disk_name(&device).to_string(),
+            used_by: format!("pool {}", pool),
+        })
+        .collect::<Vec<_>>();
+    in_use.extend(
+        protected_mounts(&std::fs::read_to_string(MNTTAB)?)
+            .into_iter()
+            .map(|(mountpoint, device)| DiskInUse {
+                device: disk_name(&device).to_string(),
+                used_by: mountpoint,
+            }),
+    );
+    Ok(in_use)
+}
+
+pub fn power(action: PowerAction) -> Result<(), InstallationError> {
+    let bin = match action {
+        PowerAction::Reboot => REBOOT_BIN,
+        PowerAction::PowerOff => POWEROFF_BIN,
+    };
+    let out = Command::new(bin).output()?;
+    if !out.status.success() {
+        return Err(InstallationError::PowerControlFailed(
+            action.to_string(),
+            String::from_utf8(out.stderr)?,
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,112 @@
+use crate::error::InstallationError;
+use crate::machined::{InstallPhase, InstallProgress};
+use crate::platform::illumos::zpool::{destroy_dataset, destroy_pool, unmount_boot_environment};
+use crate::util::{report_install_error, report_install_step};
+use tokio::sync::mpsc::error::SendError;
+use tokio::sync::mpsc::Sender;
+use tonic::Status;
+
+/// A completed installation step that has to be undone when a later step fails
+#[derive(Debug, Clone, PartialEq)]
+pub enum RollbackStep {
+    DestroyPool(String),
+    DestroyDataset(String),
+    Unmount(String),
+}
+
+impl RollbackStep {
+    fn describe(&self) -> String {
+        match self {
+            RollbackStep::DestroyPool(pool) => format!("Destroying pool {}", pool),
+            RollbackStep::DestroyDataset(dataset) => format!("Destroying dataset {}", dataset),
+            RollbackStep::Unmount(dataset) => format!("Unmounting {} from /a", dataset),
+        }
+    }
+
+    fn undo(&self) -> Result<(), InstallationError> {
+        match self {
+            RollbackStep::DestroyPool(pool) => destroy_pool(pool),
+            RollbackStep::DestroyDataset(dataset) => destroy_dataset(dataset),
+            RollbackStep::Unmount(dataset) => unmount_boot_environment(dataset),
+        }
+    }
+}
+
+/// Journal of the steps an installation run has completed
+#[derive(Debug, Default)]
+pub struct Rollback {
+    steps: Vec<RollbackStep>,
+}
+
+impl Rollback {
+    pub fn record(&mut self, step: RollbackStep) {
+        self.steps.push(step);
+    }
+
+    /// Steps in the order they will be undone
+    pub fn pending(&self) -> impl Iterator<Item = &RollbackStep> {
+        self.steps
+            .iter()
+            .rev()
+            .filter(|step| !self.covered_by_pool(step))
+    }
+
+    /// Datasets live inside a pool created in this run and go away with it,
+    /// but still have to be unmounted before the pool can be destroyed.
+    fn covered_by_pool(&self, step: &RollbackStep) -> bool {
+        let RollbackStep::DestroyDataset(dataset) = step else {
+            return false;
+        };
+        let pool = dataset.split('/').next().unwrap_or_default();
+        self.steps
+            .contains(&RollbackStep::DestroyPool(pool.to_string()))
+    }
+
+    /// Undo all recorded steps in reverse order.
+    ///
+    /// Failures are reported but do not stop the remaining steps from being attempted.
+    pub async fn undo(
+        self,
+        tx: &Sender<Result<InstallProgress, Status>>,
+    ) -> Result<(), SendError<Result<InstallProgress, Status>>> {
+        let pending = self.pending().cloned().collect::<Vec<_>>();
+        let total = pending.len() as u32;
+        for (idx, step) in pending.iter().enumerate() {
+            tx.send(report_install_step(
+                InstallPhase::Rollback,
+                idx as u32 + 1,
+                total,
+                step.describe().as_str(),
+            ))
+            .await?;
+            if let Err(e) = step.undo() {
+                tx.send(report_install_error(e)).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undoes_in_reverse_and_skips_datasets_of_destroyed_pools() {
+        let mut rollback = Rollback::default();
+        rollback.record(RollbackStep::DestroyPool("rpool".into()));
+        rollback.record(RollbackStep::DestroyDataset("rpool/ROOT".into()));
+        rollback.record(RollbackStep::DestroyDataset("tank/ROOT/be".into()));
+        rollback.record(RollbackStep::Unmount("rpool/ROOT/be".into()));
+
+        let pending = rollback.pending().cloned().collect::<Vec<_>>();
+        assert_eq!(
+            pending,
+            vec![
+                RollbackStep::Unmount("rpool/ROOT/be".into()),
+                RollbackStep::DestroyDataset("tank/ROOT/be".into()),
+                RollbackStep::DestroyPool("rpool".into()),
+            ]
+        );
+    }
+}
@@ -1 +1,318 @@
+use crate::error::InstallationError;
+use crate::machined::NetworkInterface;
+use crate::validate::same_mac;
+use machineconfig::{AddressKind, Interface};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
 
+/// Script run once on first boot to create the persistent network configuration
+const NETCFG_SCRIPT: &str = "etc/machined/netcfg.sh";
+
+/// Site manifest imported on first boot that runs [`NETCFG_SCRIPT`]
+const NETCFG_MANIFEST: &str = "lib/svc/manifest/site/machined-netcfg.xml";
+
+const NETCFG_FMRI: &str = "svc:/site/machined-netcfg:default";
+
+/// Write the hostname into `/etc/nodename` and the loopback entries of `/etc/inet/hosts`
+pub fn apply_hostname(root: &Path, hostname: &str) -> Result<(), InstallationError> {
+    fs::write(root.join("etc/nodename"), format!("{}\n", hostname))?;
+
+    let hosts_path = root.join("etc/inet/hosts");
+    let hosts = fs::read_to_string(&hosts_path).unwrap_or_default();
+    fs::write(&hosts_path, hosts_with_hostname(&hosts, hostname))?;
+    Ok(())
+}
+
+/// Write `/etc/resolv.conf` and switch name lookups in `/etc/nsswitch.conf` to DNS
+pub fn apply_nameservers(root: &Path, nameservers: &[String]) -> Result<(), InstallationError> {
+    let content = nameservers
+        .iter()
+        .map(|ns| format!("nameserver {}\n", ns))
+        .collect::<String>();
+    fs::write(root.join("etc/resolv.conf"), content)?;
+
+    let nsswitch_dns = root.join("etc/nsswitch.dns");
+    if nsswitch_dns.exists() {
+        fs::copy(nsswitch_dns, root.join("etc/nsswitch.conf"))?;
+    }
+    Ok(())
+}
+
+/// Install a first boot service that creates the configured interfaces and addresses with ipadm.
+///
+/// The persistent ipadm configuration can only be written by ipmgmtd, so this has to happen on
+/// the installed system itself. Interfaces matched by a selector are resolved against the links
+/// of the running system, which is the same hardware the installed system will boot on.
+pub fn apply_interfaces(
+    root: &Path,
+    interfaces: &[Interface],
+    links: &[NetworkInterface],
+) -> Result<(), InstallationError> {
+    let script = netcfg_script(interfaces, links)?;
+
+    let script_path = root.join(NETCFG_SCRIPT);
+    if let Some(parent) = script_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&script_path, script)?;
+    fs::set_permissions(&script_path, fs::Permissions::from_mode(0o755))?;
+
+    let manifest_path = root.join(NETCFG_MANIFEST);
+    if let Some(parent) = manifest_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&manifest_path, netcfg_manifest())?;
+    Ok(())
+}
+
+/// Add swap volumes to `/etc/vfstab` so they are used from the first boot on
+pub fn apply_swap(root: &Path, devices: &[String]) -> Result<(), InstallationError> {
+    let vfstab_path = root.join("etc/vfstab");
+    let vfstab = fs::read_to_string(&vfstab_path).unwrap_or_default();
+    fs::write(&vfstab_path, vfstab_with_swap(&vfstab, devices))?;
+    Ok(())
+}
+
+/// Make a volume the dump device in `/etc/dumpadm.conf`, which dumpadm reads on boot
+pub fn apply_dump_device(root: &Path, device: &str) -> Result<(), InstallationError> {
+    let conf_path = root.join("etc/dumpadm.conf");
+    let conf = fs::read_to_string(&conf_path).unwrap_or_default();
+    fs::write(&conf_path, dumpadm_conf_with_device(&conf, device))?;
+    Ok(())
+}
+
+fn vfstab_with_swap(vfstab: &str, devices: &[String]) -> String {
+    let mut content = String::from(vfstab);
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+    for device in devices {
+        let present = vfstab
+            .lines()
+            .any(|line| line.split_whitespace().next() == Some(device.as_str()));
+        if !present {
+            content.push_str(&format!("{}\t-\t-\tswap\t-\tno\t-\n", device));
+        }
+    }
+    content
+}
+
+fn dumpadm_conf_with_device(conf: &str, device: &str) -> String {
+    let line = format!("DUMPADM_DEVICE={}", device);
+    let mut found = false;
+    let mut content = String::new();
+    for existing in conf.lines() {
+        if existing.starts_with("DUMPADM_DEVICE=") {
+            found = true;
+            content.push_str(&line);
+        } else {
+            content.push_str(existing);
+        }
+        content.push('\n');
+    }
+    if !found {
+        content.push_str(&line);
+        content.push('\n');
+    }
+    content
+}
+
+fn hosts_with_hostname(hosts: &str, hostname: &str) -> String {
+    let mut content = String::new();
+    let mut found_loopback = false;
+    for line in hosts.lines() {
+        let mut fields = line.split_whitespace();
+        match fields.next() {
+            Some(addr @ ("127.0.0.1" | "::1")) => {
+                found_loopback = true;
+                let mut names = fields.collect::<Vec<_>>();
+                if !names.contains(&hostname) {
+                    names.push(hostname);
+                }
+                content.push_str(&format!("{}\t{}\n", addr, names.join(" ")));
+            }
+            _ => {
+                content.push_str(line);
+                content.push('\n');
+            }
+        }
+    }
+    if !found_loopback {
+        content.push_str(&format!("::1\tlocalhost {}\n", hostname));
+        content.push_str(&format!("127.0.0.1\tlocalhost loghost {}\n", hostname));
+    }
+    content
+}
+
+/// Find the datalink an interface refers to.
+///
+/// Selectors have the form `mac:<address>`; without a selector the interface name is the link.
+fn resolve_link(
+    iface: &Interface,
+    links: &[NetworkInterface],
+) -> Result<String, InstallationError> {
+    match (&iface.selector, &iface.name) {
+        (Some(selector), _) => {
+            let mac = selector
+                .strip_prefix("mac:")
+                .ok_or_else(|| InstallationError::InvalidInterfaceSelector(selector.clone()))?;
+            links
+                .iter()
+                .find(|link| same_mac(&link.mac_address, mac))
+                .map(|link| link.link.clone())
+                .ok_or_else(|| InstallationError::InterfaceNotFound(selector.clone()))
+        }
+        (None, Some(name)) => Ok(name.clone()),
+        (None, None) => Err(InstallationError::InterfaceNotFound(String::from(
+            "interface without name or selector",
+        ))),
+    }
+}
+
+fn netcfg_script(
+    interfaces: &[Interface],
+    links: &[NetworkInterface],
+) -> Result<String, InstallationError> {
+    let mut script = String::from("#!/bin/sh\n# Generated by machined\n\n");
+    for iface in interfaces {
+        let link = resolve_link(iface, links)?;
+        let name = match &iface.name {
+            Some(name) if name != &link => {
+                script.push_str(&format!("/usr/sbin/dladm rename-link {} {}\n", link, name));
+                name.clone()
+            }
+            _ => link,
+        };
+        script.push_str(&format!("/usr/sbin/ipadm create-if {}\n", name));
+        for addr in &iface.addresses {
+            let addrobj = format!("{}/{}", name, addr.name);
+            let cmd = match (&addr.kind, &addr.address) {
+                (AddressKind::Static, Some(address)) => {
+                    format!("-T static -a {} {}", address, addrobj)
+                }
+                (AddressKind::Static, None) => {
+                    return Err(InstallationError::MissingStaticAddress(addrobj));
+                }
+                (AddressKind::Dhcp4, _) => format!("-T dhcp {}", addrobj),
+                (AddressKind::Dhcp6, _) => {
+                    format!("-T addrconf -p stateless=yes,stateful=yes {}", addrobj)
+                }
+                (AddressKind::Addrconf, _) => format!("-T addrconf {}", addrobj),
+            };
+            script.push_str(&format!("/usr/sbin/ipadm create-addr {}\n", cmd));
+        }
+    }
+    script.push_str(&format!(
+        "\n/usr/sbin/svcadm disable {}\nexit 0\n",
+        NETCFG_FMRI
+    ));
+    Ok(script)
+}
+
+fn netcfg_manifest() -> String {
+    format!(
+        r#"<?xml version="1.0" ?>
+<!DOCTYPE service_bundle SYSTEM "/usr/share/lib/xml/dtd/service_bundle.dtd.1">
+<service_bundle type='manifest' name='machined-netcfg'>
+    <service name='site/machined-netcfg' type='service' version='1'>
+        <create_default_instance enabled='true' />
+        <single_instance />
+        <dependency name='physical' grouping='require_all' restart_on='none' type='service'>
+            <service_fmri value='svc:/network/physical' />
+        </dependency>
+        <dependent name='machined-netcfg-network' grouping='optional_all' restart_on='none'>
+            <service_fmri value='svc:/milestone/network' />
+        </dependent>
+        <exec_method type='method' name='start' exec='/{}' timeout_seconds='60' />
+        <exec_method type='method' name='stop' exec=':true' timeout_seconds='60' />
+        <property_group name='startd' type='framework'>
+            <propval name='duration' type='astring' value='transient' />
+        </property_group>
+    </service>
+</service_bundle>
+"#,
+        NETCFG_SCRIPT
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use machineconfig::AddressObject;
+
+    #[test]
+    fn adds_hostname_to_loopback_entries() {
+        let hosts = "# comment\n::1 localhost\n127.0.0.1 localhost loghost\n10.0.0.1 gw\n";
+        assert_eq!(
+            hosts_with_hostname(hosts, "node01"),
+            "# comment\n::1\tlocalhost node01\n127.0.0.1\tlocalhost loghost node01\n10.0.0.1 gw\n"
+        );
+    }
+
+    #[test]
+    fn configures_swap_and_dump_volumes() {
+        let vfstab = "#device\tdevice\tmount\n/devices\t-\t/devices\tdevfs\t-\tno\t-";
+        let swap = vec![String::from("/dev/zvol/dsk/rpool/swap")];
+        let expected = format!(
+            "{}\n/dev/zvol/dsk/rpool/swap\t-\t-\tswap\t-\tno\t-\n",
+            vfstab
+        );
+        assert_eq!(vfstab_with_swap(vfstab, &swap), expected);
+        assert_eq!(vfstab_with_swap(&expected, &swap), expected);
+
+        let conf = "DUMPADM_DEVICE=swap\nDUMPADM_SAVDIR=/var/crash\n";
+        assert_eq!(
+            dumpadm_conf_with_device(conf, "/dev/zvol/dsk/rpool/dump"),
+            "DUMPADM_DEVICE=/dev/zvol/dsk/rpool/dump\nDUMPADM_SAVDIR=/var/crash\n"
+        );
+        assert_eq!(
+            dumpadm_conf_with_device("", "/dev/zvol/dsk/rpool/dump"),
+            "DUMPADM_DEVICE=/dev/zvol/dsk/rpool/dump\n"
+        );
+    }
+
+    #[test]
+    fn generates_ipadm_commands_for_selected_link() {
+        let links = vec![NetworkInterface {
+            link: String::from("e1000g0"),
+            mac_address: String::from("0:0:0:0:0:1"),
+            ..Default::default()
+        }];
+        let interfaces = vec![Interface {
+            name: Some(String::from("net0")),
+            selector: Some(String::from("mac:00:00:00:00:00:01")),
+            addresses: vec![
+                AddressObject {
+                    name: String::from("v4"),
+                    kind: AddressKind::Static,
+                    address: Some(String::from("192.168.1.200/24")),
+                },
+                AddressObject {
+                    name: String::from("v6"),
+                    kind: AddressKind::Addrconf,
+                    address: None,
+                },
+            ],
+        }];
+
+        let script = netcfg_script(&interfaces, &links).unwrap();
+        assert!(script.contains("dladm rename-link e1000g0 net0\n"));
+        assert!(script.contains("ipadm create-if net0\n"));
+        assert!(script.contains("ipadm create-addr -T static -a 192.168.1.200/24 net0/v4\n"));
+        assert!(script.contains("ipadm create-addr -T addrconf net0/v6\n"));
+    }
+
+    #[test]
+    fn unknown_selector_fails() {
+        let interfaces = vec![Interface {
+            name: Some(String::from("net0")),
+            selector: Some(String::from("mac:00:00:00:00:00:02")),
+            addresses: vec![],
+        }];
+        assert!(matches!(
+            netcfg_script(&interfaces, &[]),
+            Err(InstallationError::InterfaceNotFound(_))
+        ));
+    }
+}
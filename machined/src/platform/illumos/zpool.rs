@@ -1,21 +1,30 @@
+use crate::diskprep::pool_devices;
 use crate::error::InstallationError;
-use machineconfig::Pool;
+use crate::machined::DiskInfo;
+use machineconfig::{Pool, PoolOption};
+use std::fs::OpenOptions;
+use std::io::{self, Seek, SeekFrom, Write};
 use std::process::Command;
 use uuid::Uuid;
 
 const ZPOOL_BIN: &str = "/usr/sbin/zpool";
 const ZFS_BIN: &str = "/usr/sbin/zfs";
 
+/// Root the boot environment is mounted at, pools are imported with it as altroot so
+/// the datasets of the installation are mounted below it and not over the running system
+pub const ALTROOT: &str = "/a";
+
 pub fn create_pool(pool: &Pool) -> Result<(), InstallationError> {
     let mut zpool_cmd = Command::new(ZPOOL_BIN);
-    zpool_cmd.args(["create", &pool.name]);
+    zpool_cmd.args(["create", "-R", ALTROOT]);
     for opt in &pool.options {
         zpool_cmd.args(["-o", format!("{}={}", opt.name, opt.value).as_str()]);
     }
-    for vdev in &pool.vdevs {
-        zpool_cmd.arg(vdev.kind.to_string().as_str());
-        zpool_cmd.args(vdev.disks.iter().map(|d| d.as_str()));
+    for opt in &pool.filesystem_options {
+        zpool_cmd.args(["-O", format!("{}={}", opt.name, opt.value).as_str()]);
     }
+    zpool_cmd.arg(&pool.name);
+    zpool_cmd.args(pool.vdev_args());
     let out = zpool_cmd.output()?;
     if !out.status.success() {
         return Err(InstallationError::ZpoolCreateFailed(String::from_utf8(
@@ -25,17 +34,51 @@ pub fn create_pool(pool: &Pool) -> Result<(), InstallationError> {
     Ok(())
 }
 
-pub fn create_boot_environment_base_dataset() -> Result<(), InstallationError> {
+/// Size of the areas at the start and the end of a disk holding the MBR and the primary
+/// and backup GPT
+const LABEL_AREA: u64 = 1024 * 1024;
+
+/// Clear the ZFS labels of a disk and wipe its partition tables, so `zpool create` does
+/// not take it for a member of another pool
+pub fn wipe_disk(disk: &DiskInfo, secure_erase: bool) -> Result<(), InstallationError> {
+    // There is no secure erase for every kind of disk, refuse before anything is written
+    if secure_erase {
+        return Err(InstallationError::SecureEraseUnsupported);
+    }
+    // Pools on whole disks live in slice 0 of the EFI label, others in the fdisk partition
+    for slice in ["s0", "p0"] {
+        // Fails on devices that carry no label, which is fine
+        let _ = Command::new(ZPOOL_BIN)
+            .args(["labelclear", "-f"])
+            .arg(format!("/dev/rdsk/{}{}", disk.device, slice))
+            .output();
+    }
+    let wipe_failed =
+        |e: io::Error| InstallationError::WipeDiskFailed(disk.device.clone(), e.to_string());
+    let mut device = OpenOptions::new()
+        .write(true)
+        .open(format!("/dev/rdsk/{}p0", disk.device))
+        .map_err(wipe_failed)?;
+    let zeros = vec![0u8; LABEL_AREA as usize];
+    device.write_all(&zeros).map_err(wipe_failed)?;
+    if disk.size_bytes > 2 * LABEL_AREA {
+        device
+            .seek(SeekFrom::Start(disk.size_bytes - LABEL_AREA))
+            .map_err(wipe_failed)?;
+        device.write_all(&zeros).map_err(wipe_failed)?;
+    }
+    device.sync_all().map_err(wipe_failed)
+}
+
+/// Create the container dataset of the boot environments, `<root_pool>/ROOT`
+pub fn create_boot_environment_base_dataset(root_pool: &str) -> Result<String, InstallationError> {
+    let base = format!("{}/ROOT", root_pool);
     create_dataset(
-        "rpool/ROOT",
+        &base,
         false,
         Some(&[("canmount", "off"), ("mountpoint", "legacy")]),
-    )
-}
-
-fn generate_be_name() -> String {
-    let now = chrono::Utc::now();
-    format!("openindiana-{}", now.format("%Y-%m-%d:%H:%M").to_string())
+    )?;
+    Ok(base)
 }
 
 pub fn create_dataset<S>(
@@ -59,14 +102,34 @@ where
     zfs_cmd.arg(name);
     let out = zfs_cmd.output()?;
     if !out.status.success() {
-        return Err(InstallationError::BaseRootDSCreateFailed(
-            String::from_utf8(out.stderr)?,
-        ));
+        return Err(InstallationError::DatasetCreateFailed(String::from_utf8(
+            out.stderr,
+        )?));
     }
 
     Ok(())
 }
 
+/// Create a volume of `size`, e.g. `4G`
+pub fn create_volume<S>(name: &str, size: &str, opts: &[(S, S)]) -> Result<(), InstallationError>
+where
+    S: AsRef<str> + std::fmt::Display,
+{
+    let mut zfs_cmd = Command::new(ZFS_BIN);
+    zfs_cmd.args(["create", "-V", size]);
+    for (key, value) in opts {
+        zfs_cmd.args(["-o", format!("{}={}", key, value).as_str()]);
+    }
+    zfs_cmd.arg(name);
+    let out = zfs_cmd.output()?;
+    if !out.status.success() {
+        return Err(InstallationError::VolumeCreateFailed(String::from_utf8(
+            out.stderr,
+        )?));
+    }
+    Ok(())
+}
+
 pub fn set_dataset_property(name: &str, value: &str) -> Result<(), InstallationError> {
     let pair = format!("{}={}", name, value);
     let mut zfs_cmd = Command::new(ZFS_BIN);
@@ -82,38 +145,121 @@ pub fn set_dataset_property(name: &str, value: &str) -> Result<(), InstallationE
     Ok(())
 }
 
-pub fn create_boot_environment(be_name: Option<String>) -> Result<String, InstallationError> {
-    let be_name = if let Some(be_name) = be_name {
-        be_name.clone()
-    } else {
-        generate_be_name()
-    };
-
+/// Create the boot environment dataset `<root_pool>/ROOT/<be_name>` with the properties
+/// libbe expects, `properties` are added to them and take precedence
+pub fn create_boot_environment(
+    root_pool: &str,
+    be_name: &str,
+    properties: &[PoolOption],
+) -> Result<String, InstallationError> {
     let uuid = Uuid::new_v4().as_hyphenated().to_string();
+    let mut opts = vec![
+        (String::from("canmount"), String::from("noauto")),
+        (String::from("mountpoint"), String::from("legacy")),
+        (String::from("org.opensolaris.libbe:uuid"), uuid),
+        (
+            String::from("org.opensolaris.libbe:policy"),
+            String::from("static"),
+        ),
+    ];
+    for prop in properties {
+        opts.retain(|(name, _)| name != &prop.name);
+        opts.push((prop.name.clone(), prop.value.clone()));
+    }
 
-    let boot_env = format!("rpool/ROOT/{}", be_name);
-    create_dataset(
-        be_name.as_str(),
-        true,
-        Some(&[
-            ("canmount", "noauto"),
-            ("mountpoint", "legacy"),
-            ("org.opensolaris.libbe:uuid", &uuid),
-            ("org.opensolaris.libbe:policy", "static"),
-        ]),
-    )?;
+    let boot_env = format!("{}/ROOT/{}", root_pool, be_name);
+    create_dataset(&boot_env, false, Some(&opts))?;
     Ok(boot_env)
 }
 
 pub fn mount_boot_environment(be_path: &str) -> Result<(), InstallationError> {
     let mut zfs_cmd = Command::new(ZFS_BIN);
-    zfs_cmd.args(["mount", &be_path, "/a"]);
+    zfs_cmd.args(["mount", &be_path, ALTROOT]);
 
     let out = zfs_cmd.output()?;
     if !out.status.success() {
-        return Err(InstallationError::BaseRootDSCreateFailed(
-            String::from_utf8(out.stderr)?,
-        ));
+        return Err(InstallationError::MountFailed(String::from_utf8(
+            out.stderr,
+        )?));
+    }
+    Ok(())
+}
+
+pub fn destroy_pool(pool_name: &str) -> Result<(), InstallationError> {
+    let out = Command::new(ZPOOL_BIN)
+        .args(["destroy", "-f", pool_name])
+        .output()?;
+    if !out.status.success() {
+        return Err(InstallationError::ZpoolDestroyFailed(String::from_utf8(
+            out.stderr,
+        )?));
+    }
+    Ok(())
+}
+
+pub fn destroy_dataset(name: &str) -> Result<(), InstallationError> {
+    let out = Command::new(ZFS_BIN)
+        .args(["destroy", "-r", name])
+        .output()?;
+    if !out.status.success() {
+        return Err(InstallationError::ZfsDestroyFailed(String::from_utf8(
+            out.stderr,
+        )?));
+    }
+    Ok(())
+}
+
+pub fn unmount_boot_environment(be_path: &str) -> Result<(), InstallationError> {
+    let out = Command::new(ZFS_BIN)
+        .args(["unmount", "-f", be_path])
+        .output()?;
+    if !out.status.success() {
+        return Err(InstallationError::UnmountFailed(String::from_utf8(
+            out.stderr,
+        )?));
+    }
+    Ok(())
+}
+
+/// Pools imported with /a as altroot, these are the pools created for the installation
+pub fn altroot_pools() -> Result<Vec<String>, InstallationError> {
+    let out = Command::new(ZPOOL_BIN)
+        .args(["list", "-H", "-o", "name,altroot"])
+        .output()?;
+    if !out.status.success() {
+        return Err(InstallationError::ZfsListFailed(String::from_utf8(
+            out.stderr,
+        )?));
+    }
+    Ok(String::from_utf8(out.stdout)?
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .filter(|(_, altroot)| *altroot == ALTROOT)
+        .map(|(name, _)| name.to_string())
+        .collect())
+}
+
+/// Device paths of the imported pools, as pool name and device
+pub fn imported_pool_devices() -> Result<Vec<(String, String)>, InstallationError> {
+    let out = Command::new(ZPOOL_BIN)
+        .args(["list", "-H", "-P", "-v", "-o", "name"])
+        .output()?;
+    if !out.status.success() {
+        return Err(InstallationError::ZfsListFailed(String::from_utf8(
+            out.stderr,
+        )?));
+    }
+    Ok(pool_devices(&String::from_utf8(out.stdout)?))
+}
+
+pub fn export_pool(pool_name: &str) -> Result<(), InstallationError> {
+    let out = Command::new(ZPOOL_BIN)
+        .args(["export", pool_name])
+        .output()?;
+    if !out.status.success() {
+        return Err(InstallationError::PoolExportFailed(String::from_utf8(
+            out.stderr,
+        )?));
     }
     Ok(())
 }
@@ -0,0 +1,443 @@
+use crate::blobcache::{BlobCache, BlobHasher, HashingReader};
+use crate::error::InstallationError;
+use crate::error::InstallationError::CannotCreateImageReference;
+use crate::machined::{InstallPhase, InstallProgress};
+use crate::platform::layer::{decompress, extract_layer};
+use crate::platform::layout::ImageLayout;
+use crate::util::{
+    check_cancelled, report_install_bytes, report_install_debug, report_install_error,
+    report_install_info, report_install_step,
+};
+use machineconfig::ImageSource;
+use ociclient::client::{Client as Registry, ClientError, ClientSession as Session};
+use ociclient::digest::OciDigest;
+use ociclient::image_reference::ImageReference;
+use ociclient::models::ManifestVariant::{Artifact, List, Manifest};
+use ociclient::models::{AnyOciConfig, ImageManifest, ImageManifestList};
+use std::env;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::str::FromStr;
+use tokio::sync::mpsc::error::SendError;
+use tokio::sync::mpsc::{self, Sender};
+use tokio::task::{self, JoinHandle};
+use tokio_util::sync::CancellationToken;
+use tonic::Status;
+
+/// Root of the boot environment the image is installed into
+const TARGET_ROOT: &str = "/a";
+
+/// Downloaded chunks that may wait for the extractor before the download pauses
+const LAYER_CHANNEL_DEPTH: usize = 16;
+
+/// Send a byte counter update every time this many bytes have been processed
+const PROGRESS_REPORT_INTERVAL: u64 = 8 * 1024 * 1024;
+
+/// Where the layers of a resolved image are read from
+enum LayerSource {
+    /// Streamed from the registry unless they are in the blob cache
+    Registry(Session),
+    /// Read from an image layout directory, the blob cache is not used
+    Layout(ImageLayout),
+}
+
+/// An image whose manifest has been resolved, its layers are read from their source when it
+/// gets installed
+pub struct ResolvedImage {
+    source: LayerSource,
+    manifest: OciDigest,
+    layers: Vec<OciDigest>,
+}
+
+impl ResolvedImage {
+    /// Digest of the image manifest that gets installed, the one for this platform when the
+    /// reference names a manifest list
+    pub fn manifest(&self) -> &OciDigest {
+        &self.manifest
+    }
+
+    pub fn layers(&self) -> &[OciDigest] {
+        &self.layers
+    }
+}
+
+fn build_image_ref(image: &str) -> Result<ImageReference, InstallationError> {
+    ImageReference::from_str(image).map_err(|e| CannotCreateImageReference(e.to_string()))
+}
+
+/// Resolve the manifest of an image in a registry or an image layout, picking the manifest for
+/// this platform out of manifest lists
+pub async fn resolve_image(
+    source: &ImageSource,
+    default_registry: &str,
+    tx: Sender<Result<InstallProgress, Status>>,
+) -> Result<ResolvedImage, InstallationError> {
+    tx.send(report_install_step(
+        InstallPhase::Fetch,
+        1,
+        1,
+        "resolving image manifest",
+    ))
+    .await
+    .map_err(|_e| InstallationError::SendFailed)?;
+
+    match source {
+        ImageSource::Registry(reference) => {
+            let image_ref = build_image_ref(reference)?;
+            resolve_registry_image(&image_ref, default_registry, tx).await
+        }
+        ImageSource::Layout { path, reference } => {
+            tx.send(report_install_debug(
+                format!("reading image layout {}", path.display()).as_str(),
+            ))
+            .await
+            .map_err(|_e| InstallationError::SendFailed)?;
+            let layout = ImageLayout::open(path)?;
+            let image = layout.resolve(reference.as_deref())?;
+            Ok(ResolvedImage {
+                source: LayerSource::Layout(layout),
+                manifest: image.manifest,
+                layers: image.layers,
+            })
+        }
+    }
+}
+
+async fn resolve_registry_image(
+    image_ref: &ImageReference,
+    default_registry: &str,
+    tx: Sender<Result<InstallProgress, Status>>,
+) -> Result<ResolvedImage, InstallationError> {
+    let registry = if let Some(hostname) = image_ref.hostname.clone() {
+        Registry::new(format!("https://{}", hostname), None)
+    } else {
+        Registry::new(format!("https://{}", default_registry), None)
+    };
+    let mut session = registry.new_session(image_ref.name.clone());
+    let manifest = session
+        .query_manifest_with_digest(image_ref.tag.as_str())
+        .await?;
+    if let Some((manifest, digest)) = manifest {
+        match manifest {
+            Manifest(manifest) => fetch_config(manifest, digest, session).await,
+            List(manifest_list) => select_correct_manifest(manifest_list, session, tx).await,
+            Artifact(_) => Err(InstallationError::ArtifactManifestsNotSupported),
+        }
+    } else {
+        Err(InstallationError::NoManifestFound)
+    }
+}
+
+async fn select_correct_manifest(
+    list: ImageManifestList,
+    mut session: Session,
+    tx: Sender<Result<InstallProgress, Status>>,
+) -> Result<ResolvedImage, InstallationError> {
+    let cur_os_arch = format!("{}/{}", env::consts::OS, std::env::consts::ARCH);
+    for manifest in list.manifests.iter() {
+        if let Some(plat) = &manifest.platform {
+            let m_os_arch = format!("{}/{}", plat.os, plat.architecture);
+            if m_os_arch == cur_os_arch {
+                tx.send(report_install_debug(
+                    format!("selecting {} to install", manifest.digest.as_str()).as_str(),
+                ))
+                .await
+                .map_err(|_e| InstallationError::SendFailed)?;
+
+                let resp = session
+                    .fetch_blob_as::<ImageManifest>(&manifest.digest)
+                    .await?;
+                let digest = manifest.digest.clone();
+                let manifest = resp.ok_or(InstallationError::NoManifestFound)?;
+                return fetch_config(manifest, digest, session).await;
+            }
+        }
+    }
+    Err(InstallationError::NoManifestMatchesArch(cur_os_arch))
+}
+
+async fn fetch_config(
+    manifest: ImageManifest,
+    digest: OciDigest,
+    mut session: Session,
+) -> Result<ResolvedImage, InstallationError> {
+    let resp = session
+        .fetch_blob_as::<AnyOciConfig>(&manifest.config.digest)
+        .await?;
+    let config = resp.ok_or(InstallationError::NoManifestFound)?;
+    Ok(ResolvedImage {
+        source: LayerSource::Registry(session),
+        manifest: digest,
+        layers: config.layers(),
+    })
+}
+
+/// Extract the layers of an image into /a one after the other, streaming them from the
+/// blob cache, the registry or the image layout
+pub async fn install_image(
+    image: ResolvedImage,
+    cache: &BlobCache,
+    cancel: &CancellationToken,
+    tx: &Sender<Result<InstallProgress, Status>>,
+) -> Result<(), SendError<Result<InstallProgress, Status>>> {
+    let ResolvedImage {
+        mut source, layers, ..
+    } = image;
+    tx.send(report_install_info("installing image to root dataset"))
+        .await?;
+    let total_steps = layers.len() as u32;
+    for (idx, layer) in layers.iter().enumerate() {
+        let step = idx as u32 + 1;
+        check_cancelled(cancel)?;
+        tx.send(report_install_step(
+            InstallPhase::Unpack,
+            step,
+            total_steps,
+            format!("unpacking layer {}", layer.as_str()).as_str(),
+        ))
+        .await?;
+
+        let unpacked = match &mut source {
+            LayerSource::Registry(session) => {
+                unpack_layer(session, cache, layer, step, total_steps, tx).await
+            }
+            LayerSource::Layout(layout) => {
+                unpack_layout_layer(layout, layer, step, total_steps, tx).await
+            }
+        };
+        if let Err(e) = unpacked {
+            tx.send(report_install_error(&e)).await?;
+            let message = format!("unpacking layer {} failed", layer.as_str());
+            return Err(SendError(Err(match e {
+                InstallationError::UnverifiedLayer { .. } => Status::data_loss(message),
+                _ => Status::internal(message),
+            })));
+        }
+    }
+    Ok(())
+}
+
+/// Extract a layer into /a from the blob cache, or download it and extract it while it
+/// arrives, reporting the bytes downloaded so far.
+///
+/// The extractor runs on a blocking thread fed through a bounded channel, so at most a few
+/// chunks of the layer are held in memory. The digest of a downloaded layer can only be checked
+/// once all of it has been extracted, a mismatch fails the layer with
+/// [`InstallationError::UnverifiedLayer`] so the boot environment is rolled back, and keeps the
+/// layer out of the cache.
+async fn unpack_layer(
+    session: &mut Session,
+    cache: &BlobCache,
+    layer: &OciDigest,
+    step: u32,
+    total_steps: u32,
+    tx: &Sender<Result<InstallProgress, Status>>,
+) -> Result<(), InstallationError> {
+    let cached = {
+        let cache = cache.clone();
+        let layer = layer.clone();
+        task::spawn_blocking(move || match cache.lookup(&layer)? {
+            Some(blob) => {
+                let size = blob.metadata()?.len();
+                extract_layer(decompress(blob)?, Path::new(TARGET_ROOT))?;
+                Ok(Some(size))
+            }
+            None => Ok(None),
+        })
+    };
+    if let Some(size) = join_extractor(cached).await? {
+        tx.send(report_install_debug(
+            format!("layer {} installed from the blob cache", layer.as_str()).as_str(),
+        ))
+        .await
+        .map_err(|_e| InstallationError::SendFailed)?;
+        tx.send(report_install_bytes(
+            InstallPhase::Unpack,
+            step,
+            total_steps,
+            size,
+            size,
+        ))
+        .await
+        .map_err(|_e| InstallationError::SendFailed)?;
+        return Ok(());
+    }
+
+    let mut hasher = BlobHasher::for_digest(layer)?;
+    let mut stream = session
+        .fetch_blob_stream(layer)
+        .await
+        .map_err(ClientError::from)?;
+    let bytes_total = stream.content_length().unwrap_or(0);
+    let mut cache_writer = cache.writer(layer, stream.content_length())?;
+
+    let (chunk_tx, chunk_rx) = mpsc::channel(LAYER_CHANNEL_DEPTH);
+    let mut extractor = Some(task::spawn_blocking(move || {
+        let archive = decompress(ChannelReader::new(chunk_rx))?;
+        extract_layer(archive, Path::new(TARGET_ROOT))
+    }));
+    let mut chunk_tx = Some(chunk_tx);
+
+    let mut bytes_done = 0;
+    let mut bytes_reported = 0;
+    loop {
+        let chunk = match stream.chunk().await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break,
+            Err(e) => {
+                // Fail the extractor instead of letting it see a truncated archive
+                if let Some(chunk_tx) = chunk_tx.take() {
+                    let _ = chunk_tx
+                        .send(Err(io::Error::other("layer download failed")))
+                        .await;
+                }
+                if let Some(extractor) = extractor.take() {
+                    let _ = join_extractor(extractor).await;
+                }
+                return Err(ClientError::from(e).into());
+            }
+        };
+        hasher.update(&chunk);
+        if let Some(cache_writer) = &mut cache_writer {
+            cache_writer.write_all(&chunk)?;
+        }
+        bytes_done += chunk.len() as u64;
+
+        if let Some(sender) = &chunk_tx {
+            // The extractor stops reading at the end of the archive or when it failed, the
+            // rest of the blob is still downloaded to check its digest
+            if sender.send(Ok(chunk)).await.is_err() {
+                chunk_tx = None;
+                if let Some(extractor) = extractor.take() {
+                    join_extractor(extractor).await?;
+                }
+            }
+        }
+
+        if bytes_done - bytes_reported >= PROGRESS_REPORT_INTERVAL {
+            tx.send(report_install_bytes(
+                InstallPhase::Unpack,
+                step,
+                total_steps,
+                bytes_done,
+                bytes_total,
+            ))
+            .await
+            .map_err(|_e| InstallationError::SendFailed)?;
+            bytes_reported = bytes_done;
+        }
+    }
+    drop(chunk_tx);
+    if let Some(extractor) = extractor.take() {
+        join_extractor(extractor).await?;
+    }
+
+    hasher.verify(layer).map_err(unverified_layer)?;
+    if let Some(cache_writer) = cache_writer {
+        cache_writer.commit()?;
+    }
+
+    tx.send(report_install_bytes(
+        InstallPhase::Unpack,
+        step,
+        total_steps,
+        bytes_done,
+        bytes_total.max(bytes_done),
+    ))
+    .await
+    .map_err(|_e| InstallationError::SendFailed)?;
+    Ok(())
+}
+
+/// A layer that does not match its digest has already been extracted into /a when that is
+/// noticed, unlike other blobs
+fn unverified_layer(e: InstallationError) -> InstallationError {
+    match e {
+        InstallationError::DigestMismatch { blob, digest } => InstallationError::UnverifiedLayer {
+            layer: blob,
+            digest,
+        },
+        e => e,
+    }
+}
+
+/// Extract a layer of an image layout into /a, checking its digest while it is read
+async fn unpack_layout_layer(
+    layout: &ImageLayout,
+    layer: &OciDigest,
+    step: u32,
+    total_steps: u32,
+    tx: &Sender<Result<InstallProgress, Status>>,
+) -> Result<(), InstallationError> {
+    let blob_path = layout.blob_path(layer);
+    let digest = layer.clone();
+    let size = join_extractor(task::spawn_blocking(move || {
+        let blob = File::open(blob_path)?;
+        let size = blob.metadata()?.len();
+        let mut reader = HashingReader::new(blob, BlobHasher::for_digest(&digest)?);
+        extract_layer(decompress(&mut reader)?, Path::new(TARGET_ROOT))?;
+        reader.verify(&digest).map_err(unverified_layer)?;
+        Ok(size)
+    }))
+    .await?;
+
+    tx.send(report_install_bytes(
+        InstallPhase::Unpack,
+        step,
+        total_steps,
+        size,
+        size,
+    ))
+    .await
+    .map_err(|_e| InstallationError::SendFailed)?;
+    Ok(())
+}
+
+async fn join_extractor<T>(
+    extractor: JoinHandle<Result<T, InstallationError>>,
+) -> Result<T, InstallationError> {
+    extractor
+        .await
+        .map_err(|e| InstallationError::LayerExtractionFailed(e.to_string()))?
+}
+
+/// Reads the chunks of a blob from a channel as they are downloaded
+struct ChannelReader<T> {
+    rx: mpsc::Receiver<io::Result<T>>,
+    chunk: Option<T>,
+    pos: usize,
+}
+
+impl<T> ChannelReader<T> {
+    fn new(rx: mpsc::Receiver<io::Result<T>>) -> Self {
+        ChannelReader {
+            rx,
+            chunk: None,
+            pos: 0,
+        }
+    }
+}
+
+impl<T: AsRef<[u8]>> Read for ChannelReader<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if let Some(chunk) = &self.chunk {
+                let rest = &chunk.as_ref()[self.pos..];
+                if !rest.is_empty() {
+                    let n = rest.len().min(buf.len());
+                    buf[..n].copy_from_slice(&rest[..n]);
+                    self.pos += n;
+                    return Ok(n);
+                }
+            }
+            match self.rx.blocking_recv() {
+                Some(chunk) => {
+                    self.chunk = Some(chunk?);
+                    self.pos = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+    }
+}
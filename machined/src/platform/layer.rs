@@ -0,0 +1,464 @@
+use crate::error::InstallationError;
+use flate2::read::MultiGzDecoder;
+use std::collections::HashSet;
+use std::ffi::{CString, OsString};
+use std::fs;
+use std::io::{self, Cursor, Read};
+use std::path::{Component, Path, PathBuf};
+use tar::{Archive, Entry};
+
+/// Prefix of files that delete the file of the same name from the layers below
+const WHITEOUT_PREFIX: &str = ".wh.";
+/// File that hides everything the layers below put into its directory
+const OPAQUE_WHITEOUT: &str = ".wh..wh..opq";
+/// PAX record prefix under which tar stores extended attributes
+const XATTR_PAX_PREFIX: &[u8] = b"SCHILY.xattr.";
+/// Symlinks followed resolving one path before giving up, as Linux does
+const MAX_SYMLINKS: usize = 40;
+
+/// Wrap a layer blob in the decompressor its magic bytes call for, uncompressed layers are
+/// passed through as they are
+pub fn decompress<'a, R: Read + 'a>(reader: R) -> Result<Box<dyn Read + 'a>, InstallationError> {
+    let mut reader = reader;
+    let mut magic = Vec::with_capacity(6);
+    (&mut reader).take(6).read_to_end(&mut magic)?;
+    let reader = Cursor::new(magic.clone()).chain(reader);
+
+    if magic.starts_with(&[0x1f, 0x8b]) {
+        Ok(Box::new(MultiGzDecoder::new(reader)))
+    } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        Ok(Box::new(zstd::stream::read::Decoder::new(reader)?))
+    } else if magic.starts_with(b"BZh") {
+        Err(InstallationError::UnsupportedLayerCompression(
+            String::from("bzip2"),
+        ))
+    } else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+        Err(InstallationError::UnsupportedLayerCompression(
+            String::from("xz"),
+        ))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// Extract an uncompressed layer tarball on top of the layers already extracted into `root`.
+///
+/// OCI whiteouts delete files of the layers below instead of being extracted, files replace
+/// whatever was at their path before, and ownership, modes, timestamps, hardlinks and extended
+/// attributes are kept as they are recorded in the archive.
+pub fn extract_layer<R: Read>(reader: R, root: &Path) -> Result<(), InstallationError> {
+    let mut archive = Archive::new(reader);
+    archive.set_preserve_permissions(true);
+    archive.set_preserve_ownerships(true);
+    archive.set_preserve_mtime(true);
+    archive.set_overwrite(true);
+
+    // Whiteouts only apply to the layers below, never to what this layer extracted itself
+    let mut extracted = HashSet::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = layer_path(&entry.path()?)?;
+        let parent = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        if name == OPAQUE_WHITEOUT {
+            clear_directory(root, &parent, &extracted)?;
+            continue;
+        }
+        if let Some(hidden) = name.strip_prefix(WHITEOUT_PREFIX) {
+            remove_path(&resolve_in_root(root, &parent.join(hidden))?)?;
+            continue;
+        }
+
+        let dst = resolve_in_root(root, &path)?;
+        let is_dir = entry.header().entry_type().is_dir();
+        if let Ok(meta) = dst.symlink_metadata() {
+            // Only directories merge with the layers below, anything else is replaced so
+            // that writing a file never goes through a hardlink or symlink of a lower layer
+            if !(is_dir && meta.is_dir()) {
+                remove_path(&dst)?;
+            }
+        }
+        // Unpacked at the resolved path rather than with `unpack_in`, which would let the OS
+        // follow the symlinks of the image against the installer's own root
+        if let Some(dir) = dst.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        if entry.header().entry_type().is_hard_link() {
+            let target = entry
+                .link_name()?
+                .ok_or_else(|| InstallationError::InvalidLayerEntry(path.display().to_string()))?;
+            fs::hard_link(resolve_in_root(root, &layer_path(&target)?)?, &dst)?;
+        } else {
+            entry.unpack(&dst)?;
+        }
+        if !entry.header().entry_type().is_symlink() {
+            apply_xattrs(&mut entry, &dst)?;
+        }
+        extracted.insert(path);
+    }
+    Ok(())
+}
+
+/// Normalize the path of an archive entry to a path relative to the root of the image
+fn layer_path(path: &Path) -> Result<PathBuf, InstallationError> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::RootDir | Component::CurDir => {}
+            Component::ParentDir | Component::Prefix(_) => {
+                return Err(InstallationError::InvalidLayerEntry(
+                    path.display().to_string(),
+                ))
+            }
+        }
+    }
+    Ok(normalized)
+}
+
+/// Resolve `path` under `root` the way the installed system will see it, without leaving `root`.
+///
+/// Symlinks in the directories leading to `path` are followed inside `root` as if it was
+/// chrooted into: absolute targets start at `root` and `..` stops there. The last component is
+/// not followed, so a symlink at `path` is itself what gets replaced or removed.
+pub fn resolve_in_root(root: &Path, path: &Path) -> Result<PathBuf, InstallationError> {
+    let dir = follow_in_root(root, path.parent().unwrap_or(Path::new("")))?;
+    Ok(match path.file_name() {
+        Some(name) => dir.join(name),
+        None => dir,
+    })
+}
+
+/// Resolve `path` under `root` like [`resolve_in_root`], following a symlink at `path` too
+pub fn follow_in_root(root: &Path, path: &Path) -> Result<PathBuf, InstallationError> {
+    // Components still to resolve, the next one last; `None` stands for `..`
+    fn parts(path: &Path) -> impl Iterator<Item = Option<OsString>> + '_ {
+        path.components()
+            .rev()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(Some(name.to_os_string())),
+                Component::ParentDir => Some(None),
+                Component::RootDir | Component::CurDir | Component::Prefix(_) => None,
+            })
+    }
+
+    let mut resolved = PathBuf::new();
+    let mut pending = parts(path).collect::<Vec<_>>();
+    let mut followed = 0;
+    while let Some(part) = pending.pop() {
+        let Some(name) = part else {
+            resolved.pop();
+            continue;
+        };
+        let candidate = root.join(&resolved).join(&name);
+        match candidate.symlink_metadata() {
+            Ok(meta) if meta.file_type().is_symlink() => {
+                followed += 1;
+                if followed > MAX_SYMLINKS {
+                    return Err(InstallationError::SymlinkLoop(path.display().to_string()));
+                }
+                let target = fs::read_link(&candidate)?;
+                if target.is_absolute() {
+                    resolved.clear();
+                }
+                pending.extend(parts(&target));
+            }
+            _ => resolved.push(name),
+        }
+    }
+    Ok(root.join(resolved))
+}
+
+/// Remove everything from `dir` that was not extracted from the current layer
+fn clear_directory(
+    root: &Path,
+    dir: &Path,
+    extracted: &HashSet<PathBuf>,
+) -> Result<(), InstallationError> {
+    let entries = match fs::read_dir(follow_in_root(root, dir)?) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    for entry in entries {
+        let entry = entry?;
+        let path = dir.join(entry.file_name());
+        if !extracted.contains(&path) {
+            remove_path(&entry.path())?;
+        } else if entry.file_type()?.is_dir() {
+            clear_directory(root, &path, extracted)?;
+        }
+    }
+    Ok(())
+}
+
+/// Remove a file, symlink or directory tree, paths that do not exist are fine
+fn remove_path(path: &Path) -> Result<(), InstallationError> {
+    let removed = match path.symlink_metadata() {
+        Ok(meta) if meta.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(e) => Err(e),
+    };
+    match removed {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Set the extended attributes an entry carries in its PAX records on the extracted file
+fn apply_xattrs<R: Read>(entry: &mut Entry<R>, dst: &Path) -> Result<(), InstallationError> {
+    let Some(extensions) = entry.pax_extensions()? else {
+        return Ok(());
+    };
+    for extension in extensions {
+        let extension = extension?;
+        if let Some(name) = extension.key_bytes().strip_prefix(XATTR_PAX_PREFIX) {
+            set_xattr(dst, name, extension.value_bytes()).map_err(|e| {
+                InstallationError::XattrFailed(
+                    String::from_utf8_lossy(name).to_string(),
+                    dst.display().to_string(),
+                    e,
+                )
+            })?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn set_xattr(path: &Path, name: &[u8], value: &[u8]) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let name = CString::new(name)?;
+    let rc = unsafe {
+        libc::lsetxattr(
+            path.as_ptr(),
+            name.as_ptr(),
+            value.as_ptr().cast(),
+            value.len(),
+            0,
+        )
+    };
+    if rc == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Extended attributes are files in the attribute directory of a file on illumos
+#[cfg(target_os = "illumos")]
+fn set_xattr(path: &Path, name: &[u8], value: &[u8]) -> io::Result<()> {
+    use std::fs::File;
+    use std::io::Write;
+    use std::os::fd::{AsRawFd, FromRawFd};
+
+    let file = File::open(path)?;
+    let name = CString::new(name)?;
+    let fd = unsafe {
+        libc::openat(
+            file.as_raw_fd(),
+            name.as_ptr(),
+            libc::O_XATTR | libc::O_CREAT | libc::O_TRUNC | libc::O_WRONLY,
+            0o644 as libc::c_uint,
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut attr = unsafe { File::from_raw_fd(fd) };
+    attr.write_all(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tar::{Builder, EntryType, Header};
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("machined-layer-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn layer(entries: &[(&str, EntryType, &str)]) -> Vec<u8> {
+        let mut builder = Builder::new(Vec::new());
+        for (path, kind, content) in entries {
+            let mut header = Header::new_gnu();
+            header.set_entry_type(*kind);
+            header.set_mode(if kind.is_dir() { 0o755 } else { 0o644 });
+            header.set_mtime(1_700_000_000);
+            header.set_uid(0);
+            header.set_gid(0);
+            if kind.is_hard_link() || kind.is_symlink() {
+                header.set_size(0);
+                builder.append_link(&mut header, path, content).unwrap();
+            } else {
+                header.set_size(content.len() as u64);
+                builder
+                    .append_data(&mut header, path, content.as_bytes())
+                    .unwrap();
+            }
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn read(root: &Path, path: &str) -> Option<String> {
+        fs::read_to_string(root.join(path)).ok()
+    }
+
+    #[test]
+    fn applies_whiteouts_of_upper_layers() {
+        let root = scratch_dir("whiteouts");
+        let lower = layer(&[
+            ("etc/", EntryType::Directory, ""),
+            ("etc/motd", EntryType::Regular, "hello"),
+            ("etc/issue", EntryType::Regular, "lower"),
+            ("var/cache/", EntryType::Directory, ""),
+            ("var/cache/old", EntryType::Regular, "stale"),
+            ("var/cache/sub/", EntryType::Directory, ""),
+            ("var/cache/sub/deep", EntryType::Regular, "stale"),
+        ]);
+        extract_layer(lower.as_slice(), &root).unwrap();
+
+        let upper = layer(&[
+            ("etc/.wh.motd", EntryType::Regular, ""),
+            ("etc/issue", EntryType::Regular, "upper"),
+            ("var/cache/new", EntryType::Regular, "fresh"),
+            ("var/cache/.wh..wh..opq", EntryType::Regular, ""),
+        ]);
+        extract_layer(upper.as_slice(), &root).unwrap();
+
+        assert_eq!(read(&root, "etc/motd"), None);
+        assert_eq!(read(&root, "etc/issue").as_deref(), Some("upper"));
+        assert_eq!(read(&root, "var/cache/new").as_deref(), Some("fresh"));
+        assert_eq!(read(&root, "var/cache/old"), None);
+        assert!(!root.join("var/cache/sub").exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn extracts_links_and_replaces_directories() {
+        let root = scratch_dir("links");
+        let lower = layer(&[
+            ("usr/bin/", EntryType::Directory, ""),
+            ("usr/bin/ls", EntryType::Regular, "ls"),
+            ("usr/bin/dir", EntryType::Link, "usr/bin/ls"),
+            ("opt/", EntryType::Directory, ""),
+            ("opt/tool/", EntryType::Directory, ""),
+            ("opt/tool/file", EntryType::Regular, "tool"),
+        ]);
+        extract_layer(lower.as_slice(), &root).unwrap();
+        assert_eq!(read(&root, "usr/bin/dir").as_deref(), Some("ls"));
+
+        let upper = layer(&[
+            ("usr/bin/dir", EntryType::Regular, "dir"),
+            ("opt/tool", EntryType::Symlink, "../usr/bin"),
+        ]);
+        extract_layer(upper.as_slice(), &root).unwrap();
+        // replacing one name of a hardlink must not change the other
+        assert_eq!(read(&root, "usr/bin/ls").as_deref(), Some("ls"));
+        assert_eq!(read(&root, "usr/bin/dir").as_deref(), Some("dir"));
+        assert_eq!(read(&root, "opt/tool/ls").as_deref(), Some("ls"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn whiteouts_do_not_follow_symlinks_out_of_the_root() {
+        // Stands in for the /run of the installer the symlinks of the image point at
+        let host = scratch_dir("host");
+        fs::write(host.join("foo"), "host").unwrap();
+        fs::create_dir_all(host.join("keep")).unwrap();
+        fs::write(host.join("keep/file"), "host").unwrap();
+        let escape = format!("{}{}", "../".repeat(16), host.display());
+
+        let root = scratch_dir("symlinked-parents");
+        let lower = layer(&[
+            ("var/", EntryType::Directory, ""),
+            ("var/run", EntryType::Symlink, host.to_str().unwrap()),
+            ("var/spool", EntryType::Symlink, &escape),
+            ("data/cache/", EntryType::Directory, ""),
+            ("data/cache/old", EntryType::Regular, "stale"),
+            ("data/cache/kept", EntryType::Regular, "kept"),
+            ("var/cache", EntryType::Symlink, "/data/cache"),
+        ]);
+        extract_layer(lower.as_slice(), &root).unwrap();
+
+        let upper = layer(&[
+            ("var/run/.wh.foo", EntryType::Regular, ""),
+            ("var/run/.wh..wh..opq", EntryType::Regular, ""),
+            ("var/spool/.wh.foo", EntryType::Regular, ""),
+            ("var/spool/keep/.wh..wh..opq", EntryType::Regular, ""),
+            ("var/cache/.wh.old", EntryType::Regular, ""),
+            ("var/cache/new", EntryType::Regular, "new"),
+            ("var/run/pid", EntryType::Regular, "1"),
+        ]);
+        extract_layer(upper.as_slice(), &root).unwrap();
+
+        assert_eq!(read(&host, "foo").as_deref(), Some("host"));
+        assert_eq!(read(&host, "keep/file").as_deref(), Some("host"));
+        assert!(!host.join("pid").exists());
+        // Symlinks inside the root are followed like the installed system will
+        assert_eq!(read(&root, "data/cache/old"), None);
+        assert_eq!(read(&root, "data/cache/kept").as_deref(), Some("kept"));
+        assert_eq!(read(&root, "data/cache/new").as_deref(), Some("new"));
+        fs::remove_dir_all(&root).unwrap();
+        fs::remove_dir_all(&host).unwrap();
+    }
+
+    #[test]
+    fn rejects_entries_outside_the_root() {
+        let root = scratch_dir("escape");
+        let mut data = Vec::new();
+        {
+            let mut header = Header::new_gnu();
+            header.set_entry_type(EntryType::Regular);
+            header.set_size(1);
+            header.set_mode(0o644);
+            header.set_uid(0);
+            header.set_gid(0);
+            // append_data refuses .. so the name is written into the header directly
+            header.as_old_mut().name[..9].copy_from_slice(b"../escape");
+            header.set_cksum();
+            let mut builder = Builder::new(&mut data);
+            builder.append(&header, "x".as_bytes()).unwrap();
+            builder.finish().unwrap();
+        }
+        assert!(matches!(
+            extract_layer(data.as_slice(), &root),
+            Err(InstallationError::InvalidLayerEntry(_))
+        ));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn decompresses_by_magic() {
+        let tarball = layer(&[("motd", EntryType::Regular, "hello")]);
+
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        gzip.write_all(&tarball).unwrap();
+        let gzip = gzip.finish().unwrap();
+        let zstd = zstd::stream::encode_all(tarball.as_slice(), 0).unwrap();
+
+        for blob in [tarball.clone(), gzip, zstd] {
+            let mut unpacked = Vec::new();
+            decompress(blob.as_slice())
+                .unwrap()
+                .read_to_end(&mut unpacked)
+                .unwrap();
+            assert_eq!(unpacked, tarball);
+        }
+        assert!(matches!(
+            decompress(&b"BZh91AY"[..]),
+            Err(InstallationError::UnsupportedLayerCompression(_))
+        ));
+    }
+}
@@ -0,0 +1,326 @@
+use crate::blobcache::{BlobHasher, HashingReader};
+use crate::error::InstallationError;
+use jwt_simple::reexports::serde_json;
+use ociclient::digest::OciDigest;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// File marking a directory as an OCI image layout
+const LAYOUT_FILE: &str = "oci-layout";
+/// Image index listing the images of a layout
+const INDEX_FILE: &str = "index.json";
+/// Annotation holding the tag of an image in the index of a layout
+const REF_NAME_ANNOTATION: &str = "org.opencontainers.image.ref.name";
+/// Media types of indexes that list the manifests of an image for several platforms
+const INDEX_MEDIA_TYPES: [&str; 2] = [
+    "application/vnd.oci.image.index.v1+json",
+    "application/vnd.docker.distribution.manifest.list.v2+json",
+];
+
+#[derive(Debug, Deserialize)]
+struct Descriptor {
+    #[serde(default, rename = "mediaType", alias = "media_type")]
+    media_type: String,
+    digest: OciDigest,
+    #[serde(default)]
+    annotations: HashMap<String, String>,
+    platform: Option<Platform>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Platform {
+    os: String,
+    architecture: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Index {
+    manifests: Vec<Descriptor>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    layers: Vec<Descriptor>,
+}
+
+/// An OCI image layout directory as written by `installadm create-bootable-usb`, skopeo or
+/// other OCI tools
+pub struct ImageLayout {
+    path: PathBuf,
+}
+
+/// An image of a layout resolved to the manifest for this platform
+#[derive(Debug)]
+pub struct LayoutImage {
+    pub manifest: OciDigest,
+    pub layers: Vec<OciDigest>,
+}
+
+impl ImageLayout {
+    pub fn open(path: &Path) -> Result<Self, InstallationError> {
+        if !path.join(LAYOUT_FILE).is_file() || !path.join(INDEX_FILE).is_file() {
+            return Err(InstallationError::InvalidImageLayout(
+                path.display().to_string(),
+            ));
+        }
+        Ok(ImageLayout {
+            path: path.to_path_buf(),
+        })
+    }
+
+    pub fn blob_path(&self, digest: &OciDigest) -> PathBuf {
+        self.path
+            .join("blobs")
+            .join(digest.algorithm())
+            .join(digest.hex())
+    }
+
+    /// Manifest of the image to install and its layers, bottom layer first.
+    ///
+    /// `reference` selects the image by the tag it is annotated with or by its digest, without
+    /// one the layout must hold a single image. Indexes of multi-platform images are resolved
+    /// to the manifest for this platform.
+    pub fn resolve(&self, reference: Option<&str>) -> Result<LayoutImage, InstallationError> {
+        let index: Index = serde_json::from_slice(&fs::read(self.path.join(INDEX_FILE))?)?;
+        let mut candidates = index
+            .manifests
+            .iter()
+            .filter(|manifest| match reference {
+                Some(reference) if OciDigest::from_str(reference).is_ok() => {
+                    manifest.digest.as_str() == reference
+                }
+                Some(tag) => {
+                    manifest
+                        .annotations
+                        .get(REF_NAME_ANNOTATION)
+                        .map(String::as_str)
+                        == Some(tag)
+                }
+                None => true,
+            })
+            .collect::<Vec<_>>();
+        // A layout can hold the same image once per platform
+        if candidates.len() > 1 {
+            candidates.retain(|manifest| matches_platform(manifest));
+        }
+        let descriptor = match candidates.as_slice() {
+            [descriptor] => *descriptor,
+            [] => return Err(InstallationError::NoManifestFound),
+            _ => {
+                return Err(InstallationError::AmbiguousImageLayout(
+                    self.path.display().to_string(),
+                ))
+            }
+        };
+
+        let descriptor = if INDEX_MEDIA_TYPES.contains(&descriptor.media_type.as_str()) {
+            let index: Index = self.read_blob(&descriptor.digest)?;
+            let cur_os_arch = format!("{}/{}", env::consts::OS, env::consts::ARCH);
+            let manifest = index
+                .manifests
+                .into_iter()
+                .find(matches_platform)
+                .ok_or(InstallationError::NoManifestMatchesArch(cur_os_arch))?;
+            manifest.digest
+        } else {
+            descriptor.digest.clone()
+        };
+        let manifest: Manifest = self.read_blob(&descriptor)?;
+        Ok(LayoutImage {
+            manifest: descriptor,
+            layers: manifest
+                .layers
+                .into_iter()
+                .map(|layer| layer.digest)
+                .collect(),
+        })
+    }
+
+    /// Read a JSON blob of the layout after checking its digest
+    fn read_blob<T: DeserializeOwned>(&self, digest: &OciDigest) -> Result<T, InstallationError> {
+        let file = File::open(self.blob_path(digest))?;
+        let mut reader = HashingReader::new(file, BlobHasher::for_digest(digest)?);
+        let mut content = Vec::new();
+        reader.read_to_end(&mut content)?;
+        reader.verify(digest)?;
+        Ok(serde_json::from_slice(&content)?)
+    }
+}
+
+/// Whether a manifest is for this platform, manifests without platform are for every platform
+fn matches_platform(descriptor: &Descriptor) -> bool {
+    // OCI names architectures the way Go does
+    let oci_arch = match env::consts::ARCH {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        arch => arch,
+    };
+    match &descriptor.platform {
+        Some(platform) => {
+            platform.os == env::consts::OS
+                && (platform.architecture == oci_arch || platform.architecture == env::consts::ARCH)
+        }
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::{Digest, Sha256};
+
+    struct TestLayout {
+        path: PathBuf,
+    }
+
+    impl TestLayout {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "machined-layout-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(path.join("blobs/sha256")).unwrap();
+            fs::write(path.join(LAYOUT_FILE), r#"{"imageLayoutVersion":"1.0.0"}"#).unwrap();
+            TestLayout { path }
+        }
+
+        fn blob(&self, content: &str) -> String {
+            let hex = format!("{:x}", Sha256::digest(content.as_bytes()));
+            fs::write(self.path.join("blobs/sha256").join(&hex), content).unwrap();
+            format!("sha256:{}", hex)
+        }
+
+        fn manifest(&self, layers: &[&str]) -> String {
+            let layers = layers
+                .iter()
+                .map(|layer| {
+                    format!(
+                        r#"{{"mediaType":"application/vnd.oci.image.layer.v1.tar+gzip","digest":"{}","size":1}}"#,
+                        self.blob(layer)
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(",");
+            self.blob(&format!(
+                r#"{{"schemaVersion":2,"config":{{"digest":"{}","size":2}},"layers":[{}]}}"#,
+                self.blob("{}"),
+                layers
+            ))
+        }
+
+        fn index(&self, manifests: &[(&str, &str, Option<&str>)]) {
+            let manifests = manifests
+                .iter()
+                .map(|(media_type, digest, tag)| {
+                    let annotations = tag
+                        .map(|tag| {
+                            format!(r#","annotations":{{"{}":"{}"}}"#, REF_NAME_ANNOTATION, tag)
+                        })
+                        .unwrap_or_default();
+                    format!(
+                        r#"{{"mediaType":"{}","digest":"{}","size":1{}}}"#,
+                        media_type, digest, annotations
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(",");
+            fs::write(
+                self.path.join(INDEX_FILE),
+                format!(r#"{{"schemaVersion":2,"manifests":[{}]}}"#, manifests),
+            )
+            .unwrap();
+        }
+    }
+
+    impl Drop for TestLayout {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+
+    const MANIFEST: &str = "application/vnd.oci.image.manifest.v1+json";
+
+    #[test]
+    fn selects_images_by_tag_or_digest() {
+        let layout = TestLayout::new("select");
+        let base = layout.manifest(&["base"]);
+        let full = layout.manifest(&["base", "full"]);
+        layout.index(&[
+            (MANIFEST, &base, Some("2024.12-minimal")),
+            (MANIFEST, &full, Some("2024.12")),
+        ]);
+        let image = ImageLayout::open(&layout.path).unwrap();
+
+        let layers = image.resolve(Some("2024.12")).unwrap().layers;
+        assert_eq!(layers.len(), 2);
+        assert_eq!(
+            layers[1],
+            OciDigest::from_str(&layout.blob("full")).unwrap()
+        );
+        assert_eq!(image.resolve(Some(&base)).unwrap().layers.len(), 1);
+        assert!(matches!(
+            image.resolve(None),
+            Err(InstallationError::AmbiguousImageLayout(_))
+        ));
+        assert!(matches!(
+            image.resolve(Some("2025.06")),
+            Err(InstallationError::NoManifestFound)
+        ));
+    }
+
+    #[test]
+    fn resolves_platform_indexes() {
+        let layout = TestLayout::new("platforms");
+        let native = layout.manifest(&["native"]);
+        let foreign = layout.manifest(&["foreign"]);
+        let nested = layout.blob(&format!(
+            r#"{{"schemaVersion":2,"manifests":[
+                {{"mediaType":"{m}","digest":"{}","size":1,"platform":{{"os":"plan9","architecture":"mips"}}}},
+                {{"mediaType":"{m}","digest":"{}","size":1,"platform":{{"os":"{}","architecture":"{}"}}}}
+            ]}}"#,
+            foreign,
+            native,
+            env::consts::OS,
+            env::consts::ARCH,
+            m = MANIFEST
+        ));
+        layout.index(&[(INDEX_MEDIA_TYPES[0], &nested, None)]);
+
+        let image = ImageLayout::open(&layout.path)
+            .unwrap()
+            .resolve(None)
+            .unwrap();
+        assert_eq!(image.manifest, OciDigest::from_str(&native).unwrap());
+        assert_eq!(
+            image.layers,
+            vec![OciDigest::from_str(&layout.blob("native")).unwrap()]
+        );
+    }
+
+    #[test]
+    fn rejects_tampered_manifests() {
+        let layout = TestLayout::new("tampered");
+        let manifest = layout.manifest(&["base"]);
+        layout.index(&[(MANIFEST, &manifest, None)]);
+        let digest = OciDigest::from_str(&manifest).unwrap();
+        let image = ImageLayout::open(&layout.path).unwrap();
+        fs::write(image.blob_path(&digest), r#"{"layers":[]}"#).unwrap();
+        assert!(matches!(
+            image.resolve(None),
+            Err(InstallationError::DigestMismatch { .. })
+        ));
+
+        assert!(matches!(
+            ImageLayout::open(&layout.path.join("blobs")),
+            Err(InstallationError::InvalidImageLayout(_))
+        ));
+    }
+}
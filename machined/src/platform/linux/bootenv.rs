@@ -0,0 +1,92 @@
+use crate::error::InstallationError;
+use crate::machined::BootEnvironment;
+use crate::platform::linux::zfs::{list_child_datasets, pool_property, set_pool_property};
+
+/// Boot environments are the datasets below `<pool>/ROOT` of pools with a `bootfs`, the
+/// one `bootfs` points to is booted next
+pub fn list_boot_environments() -> Result<Vec<BootEnvironment>, InstallationError> {
+    Ok(pool_boot_environments()?
+        .into_iter()
+        .map(|(_, be)| be)
+        .collect())
+}
+
+/// Make the next boot use the boot environment `name` by pointing `bootfs` of its pool to it
+pub fn activate_boot_environment(name: &str) -> Result<BootEnvironment, InstallationError> {
+    let (pool, be) = pool_boot_environments()?
+        .into_iter()
+        .find(|(_, be)| be.name == name)
+        .ok_or_else(|| InstallationError::BootEnvironmentNotFound(name.to_string()))?;
+    set_pool_property(&pool, "bootfs", &format!("{}/ROOT/{}", pool, name))?;
+    Ok(BootEnvironment {
+        active_on_reboot: true,
+        ..be
+    })
+}
+
+fn pool_boot_environments() -> Result<Vec<(String, BootEnvironment)>, InstallationError> {
+    let mut boot_environments = Vec::new();
+    for (pool, bootfs) in pool_property("bootfs").map_err(InstallationError::ZfsListFailed)? {
+        if bootfs == "-" {
+            continue;
+        }
+        let listing = list_child_datasets(&format!("{}/ROOT", pool))
+            .map_err(InstallationError::ZfsListFailed)?;
+        boot_environments.extend(
+            parse_boot_environments(&pool, &listing, &bootfs)
+                .into_iter()
+                .map(|be| (pool.clone(), be)),
+        );
+    }
+    Ok(boot_environments)
+}
+
+/// Parse the output of [`list_child_datasets`] for `<pool>/ROOT`, skipping the container
+fn parse_boot_environments(pool: &str, listing: &str, bootfs: &str) -> Vec<BootEnvironment> {
+    let container = format!("{}/ROOT", pool);
+    listing
+        .lines()
+        .filter_map(|line| {
+            let fields = line.split('\t').collect::<Vec<_>>();
+            if fields.len() < 5 || fields[0] == container {
+                return None;
+            }
+            let mounted = fields[2] == "yes";
+            Some(BootEnvironment {
+                name: fields[0]
+                    .strip_prefix(&format!("{}/", container))?
+                    .to_string(),
+                active: mounted && fields[1] == "/",
+                active_on_reboot: fields[0] == bootfs,
+                mountpoint: if mounted {
+                    fields[1].to_string()
+                } else {
+                    String::new()
+                },
+                space_bytes: fields[3].parse().unwrap_or_default(),
+                created: fields[4].parse().unwrap_or_default(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_boot_environments() {
+        let listing = "rpool/ROOT\tnone\tno\t3221225472\t1733000000\n\
+                       rpool/ROOT/linux-2024-12-01\t/a\tyes\t2147483648\t1733100000\n\
+                       rpool/ROOT/linux-2024-11-01\t/\tno\t1073741824\t1730400000\n";
+        let bes = parse_boot_environments("rpool", listing, "rpool/ROOT/linux-2024-12-01");
+        assert_eq!(bes.len(), 2);
+        assert_eq!(bes[0].name, "linux-2024-12-01");
+        assert!(bes[0].active_on_reboot && !bes[0].active);
+        assert_eq!(bes[0].mountpoint, "/a");
+        assert_eq!(bes[0].space_bytes, 2147483648);
+        assert!(!bes[1].active_on_reboot);
+        assert_eq!(bes[1].mountpoint, "");
+        assert_eq!(bes[1].created, 1730400000);
+    }
+}
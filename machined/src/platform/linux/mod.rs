@@ -0,0 +1,522 @@
+use crate::config::MachinedConfig;
+use crate::diskprep::{protected_mounts, DiskInUse};
+use crate::error::InstallationError;
+use crate::machined::{InstallPhase, InstallProgress, InstallReport};
+use crate::platform::hardware::{resolve_pool_disks, wipe_pool_disks};
+use crate::platform::image::{install_image, resolve_image};
+use crate::platform::linux::sysconfig::{
+    apply_hostname, apply_interfaces, apply_nameservers, apply_swap,
+};
+use crate::platform::linux::zfs::{
+    create_dataset, create_pool, create_volume, destroy_pool, disk_path, export_pool,
+    imported_pool_devices, mount_dataset, partition_boot_disk, partition_path, pool_property, run,
+    set_pool_property, whole_disk, ESP_PARTITION, ZFS_PARTITION,
+};
+use crate::platform::postinstall::apply_postinstall;
+use crate::platform::PowerAction;
+use crate::report::write_report;
+use crate::sysinfo::get_network_info;
+use crate::util::{
+    check_cancelled, report_install_debug, report_install_error, report_install_info,
+    report_install_step, report_install_warning, unverified_image,
+};
+use machineconfig::{MachineConfig, Pool, SysConfig, VolumeUsage};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+use std::sync::Arc;
+use tokio::sync::mpsc::error::SendError;
+use tokio::sync::mpsc::Sender;
+use tokio_util::sync::CancellationToken;
+use tonic::Status;
+
+mod bootenv;
+mod sysconfig;
+mod zfs;
+
+pub use bootenv::{activate_boot_environment, list_boot_environments};
+pub use zfs::wipe_disk;
+
+/// Directory the new system is assembled in
+const ALTROOT: &str = "/a";
+
+/// Boot environment name template used when the configuration has neither name nor template
+const DEFAULT_BE_TEMPLATE: &str = "linux-{date}";
+
+const GRUB_INSTALL_BIN: &str = "grub-install";
+const GRUB_MKCONFIG_BIN: &str = "grub-mkconfig";
+
+pub async fn install_system(
+    mc: &MachineConfig,
+    config: Arc<MachinedConfig>,
+    report: &mut InstallReport,
+    cancel: &CancellationToken,
+    tx: Sender<Result<InstallProgress, Status>>,
+) -> Result<(), SendError<Result<InstallProgress, Status>>> {
+    let mut created_pools = Vec::new();
+    let result = run_install(mc, config, report, cancel, &tx, &mut created_pools).await;
+    if result.is_err() {
+        let outcome = if cancel.is_cancelled() {
+            "cancelled"
+        } else {
+            "failed"
+        };
+        if mc.skip_rollback && !unverified_image(&result) {
+            tx.send(report_install_info(
+                format!(
+                    "Installation {}, leaving pools and datasets in place",
+                    outcome
+                )
+                .as_str(),
+            ))
+            .await?;
+        } else {
+            if mc.skip_rollback {
+                tx.send(report_install_warning(
+                    "Ignoring skip-rollback, a layer did not match its digest after it was extracted",
+                ))
+                .await?;
+            }
+            tx.send(report_install_info(
+                format!("Installation {}, rolling back completed steps", outcome).as_str(),
+            ))
+            .await?;
+            rollback(&created_pools, &tx).await?;
+        }
+    }
+    result
+}
+
+async fn fail<T>(
+    tx: &Sender<Result<InstallProgress, Status>>,
+    e: InstallationError,
+) -> Result<T, SendError<Result<InstallProgress, Status>>> {
+    tx.send(report_install_error(e)).await?;
+    Err(SendError(Err(Status::internal("Internal error"))))
+}
+
+async fn run_install(
+    mc: &MachineConfig,
+    config: Arc<MachinedConfig>,
+    report: &mut InstallReport,
+    cancel: &CancellationToken,
+    tx: &Sender<Result<InstallProgress, Status>>,
+    created_pools: &mut Vec<String>,
+) -> Result<(), SendError<Result<InstallProgress, Status>>> {
+    tx.send(report_install_debug("Starting installation"))
+        .await?;
+
+    // The disks of the root pool are partitioned to be bootable
+    let root_pool = mc.root_pool();
+    let mut pools = resolve_pool_disks(&mc.pools, tx).await?;
+    if let Some(wipe) = &mc.wipe_disks {
+        wipe_pool_disks(&pools, wipe, cancel, tx).await?;
+    }
+    let mut boot_disks = Vec::new();
+    let pool_count = pools.len() as u32;
+    for (idx, pool) in pools.iter_mut().enumerate() {
+        check_cancelled(cancel)?;
+        tx.send(report_install_step(
+            InstallPhase::Pools,
+            idx as u32 + 1,
+            pool_count,
+            format!("Setting up pool {}", &pool.name).as_str(),
+        ))
+        .await?;
+        if pool.name == root_pool {
+            for vdev in pool.vdevs.iter_mut() {
+                for disk in vdev.disks.iter_mut() {
+                    if let Err(e) = partition_boot_disk(disk) {
+                        return fail(tx, e).await;
+                    }
+                    boot_disks.push(disk.clone());
+                    *disk = partition_path(disk, ZFS_PARTITION);
+                }
+            }
+            tx.send(report_install_debug(
+                format!("Partitioned {}", boot_disks.join(" ")).as_str(),
+            ))
+            .await?;
+        }
+        if let Err(e) = create_pool(pool, ALTROOT) {
+            return fail(tx, e).await;
+        }
+        created_pools.push(pool.name.clone());
+        tx.send(report_install_debug(
+            format!("Pool {} created", &pool.name).as_str(),
+        ))
+        .await?;
+    }
+    if boot_disks.is_empty() {
+        return fail(tx, InstallationError::NoRootPool(root_pool.to_string())).await;
+    }
+
+    let be_name = match mc.boot_environment(DEFAULT_BE_TEMPLATE, chrono::Utc::now()) {
+        Ok(be_name) => be_name,
+        Err(e) => {
+            return fail(
+                tx,
+                InstallationError::InvalidBootEnvironmentName(e.to_string()),
+            )
+            .await
+        }
+    };
+    let be_dataset = format!("{}/ROOT/{}", root_pool, be_name);
+    report.boot_environment = be_name;
+    check_cancelled(cancel)?;
+    tx.send(report_install_step(
+        InstallPhase::Datasets,
+        1,
+        3,
+        "Creating boot environment",
+    ))
+    .await?;
+    let mut be_opts = vec![("canmount", "noauto"), ("mountpoint", "/")];
+    for prop in &mc.boot_environment_properties {
+        be_opts.retain(|(name, _)| *name != prop.name);
+        be_opts.push((prop.name.as_str(), prop.value.as_str()));
+    }
+    let datasets = create_dataset(
+        &format!("{}/ROOT", root_pool),
+        &[("canmount", "off"), ("mountpoint", "none")],
+    )
+    .and_then(|_| create_dataset(&be_dataset, &be_opts))
+    .and_then(|_| set_pool_property(root_pool, "bootfs", &be_dataset));
+    if let Err(e) = datasets {
+        return fail(tx, e).await;
+    }
+
+    check_cancelled(cancel)?;
+    tx.send(report_install_step(
+        InstallPhase::Datasets,
+        2,
+        3,
+        "Mounting boot environment to /a",
+    ))
+    .await?;
+    if let Err(e) = mount_dataset(&be_dataset) {
+        return fail(tx, e).await;
+    }
+
+    // Created after the boot environment is mounted so their mountpoints end up inside it
+    check_cancelled(cancel)?;
+    tx.send(report_install_step(
+        InstallPhase::Datasets,
+        3,
+        3,
+        "Creating datasets and volumes",
+    ))
+    .await?;
+    for pool in &pools {
+        for (name, dataset) in pool.dataset_tree() {
+            let props = dataset.create_properties();
+            let opts = props
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.as_str()))
+                .collect::<Vec<_>>();
+            if let Err(e) = create_dataset(&name, &opts) {
+                return fail(tx, e).await;
+            }
+            tx.send(report_install_debug(
+                format!("dataset {} created", name).as_str(),
+            ))
+            .await?;
+        }
+        for volume in &pool.volumes {
+            let name = format!("{}/{}", pool.name, volume.name);
+            let opts = volume
+                .properties
+                .iter()
+                .map(|opt| (opt.name.as_str(), opt.value.as_str()))
+                .collect::<Vec<_>>();
+            if let Err(e) = create_volume(&name, &volume.size, &opts) {
+                return fail(tx, e).await;
+            }
+            tx.send(report_install_debug(
+                format!("volume {} of {} created", name, volume.size).as_str(),
+            ))
+            .await?;
+        }
+    }
+
+    check_cancelled(cancel)?;
+    let image_source = mc.image_source().map_err(|e| {
+        SendError(Err(Status::internal(format!(
+            "Parsing image reference failed: {}",
+            e
+        ))))
+    })?;
+    let image = match resolve_image(&image_source, &config.default_oci_registry, tx.clone()).await {
+        Ok(image) => {
+            tx.send(report_install_debug("image manifest resolved"))
+                .await?;
+            image
+        }
+        Err(e) => return fail(tx, e).await,
+    };
+    report.manifest_digest = image.manifest().to_string();
+    report.layer_digests = image
+        .layers()
+        .iter()
+        .map(|layer| layer.to_string())
+        .collect();
+    install_image(image, &config.blob_cache(), cancel, tx).await?;
+
+    check_cancelled(cancel)?;
+    apply_sysconfig(&mc.sysconfig, tx).await?;
+    configure_volumes(&pools, tx).await?;
+    apply_postinstall(&mc.postinstall, cancel, tx).await?;
+
+    check_cancelled(cancel)?;
+    tx.send(report_install_step(
+        InstallPhase::Bootloader,
+        1,
+        1,
+        "Installing GRUB",
+    ))
+    .await?;
+    if let Err(e) = install_bootloader(&boot_disks) {
+        return fail(tx, e).await;
+    }
+    tx.send(report_install_info("bootloader installed")).await?;
+
+    match write_report(report, Path::new(ALTROOT)) {
+        Ok(path) => {
+            tx.send(report_install_info(
+                format!("install report written to {}", path.display()).as_str(),
+            ))
+            .await?;
+        }
+        Err(e) => return fail(tx, e).await,
+    }
+
+    Ok(())
+}
+
+/// Write the system configuration into the root file system mounted at /a
+async fn apply_sysconfig(
+    sc: &SysConfig,
+    tx: &Sender<Result<InstallProgress, Status>>,
+) -> Result<(), SendError<Result<InstallProgress, Status>>> {
+    let root = Path::new(ALTROOT);
+
+    tx.send(report_install_step(
+        InstallPhase::Sysconfig,
+        1,
+        3,
+        "Setting hostname",
+    ))
+    .await?;
+    if !sc.hostname.is_empty() {
+        if let Err(e) = apply_hostname(root, &sc.hostname) {
+            return fail(tx, e).await;
+        }
+    }
+
+    tx.send(report_install_step(
+        InstallPhase::Sysconfig,
+        2,
+        3,
+        "Configuring nameservers",
+    ))
+    .await?;
+    if !sc.nameservers.is_empty() {
+        if let Err(e) = apply_nameservers(root, &sc.nameservers) {
+            return fail(tx, e).await;
+        }
+    }
+
+    tx.send(report_install_step(
+        InstallPhase::Sysconfig,
+        3,
+        3,
+        "Configuring network interfaces",
+    ))
+    .await?;
+    if sc.interfaces.is_empty() {
+        return Ok(());
+    }
+    let links = get_network_info().map_err(|status| SendError(Err(status)))?;
+    if let Err(e) = apply_interfaces(root, &sc.interfaces, &links) {
+        return fail(tx, e).await;
+    }
+    Ok(())
+}
+
+/// Format swap volumes and add them to `/etc/fstab`, Linux has no use for dump volumes
+async fn configure_volumes(
+    pools: &[Pool],
+    tx: &Sender<Result<InstallProgress, Status>>,
+) -> Result<(), SendError<Result<InstallProgress, Status>>> {
+    for pool in pools {
+        for volume in &pool.volumes {
+            let device = format!("/dev/zvol/{}/{}", pool.name, volume.name);
+            match volume.usage {
+                Some(VolumeUsage::Swap) => {
+                    if let Err(e) = apply_swap(Path::new(ALTROOT), &device) {
+                        return fail(tx, e).await;
+                    }
+                    tx.send(report_install_debug(format!("swap on {}", device).as_str()))
+                        .await?;
+                }
+                Some(VolumeUsage::Dump) => {
+                    tx.send(report_install_info(
+                        format!("dump volume {} is not used on Linux", device).as_str(),
+                    ))
+                    .await?;
+                }
+                None => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Set up the EFI system partition and install GRUB from inside the new system
+fn install_bootloader(boot_disks: &[String]) -> Result<(), InstallationError> {
+    let root = Path::new(ALTROOT);
+    let esp = partition_path(&boot_disks[0], ESP_PARTITION);
+    run(Command::new("mkfs.vfat").args(["-F", "32", "-n", "EFI", &esp]))
+        .map_err(InstallationError::PartitionFailed)?;
+    fs::create_dir_all(root.join("boot/efi"))?;
+    run(Command::new("mount").args([&esp, &format!("{}/boot/efi", ALTROOT)]))
+        .map_err(InstallationError::MountFailed)?;
+    let mut fstab = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(root.join("etc/fstab"))?;
+    let blkid_out = Command::new("blkid")
+        .args(["-s", "UUID", "-o", "value", &esp])
+        .output()?;
+    let esp_uuid = String::from_utf8(blkid_out.stdout)?;
+    writeln!(
+        fstab,
+        "UUID={} /boot/efi vfat umask=0077 0 1",
+        esp_uuid.trim()
+    )?;
+
+    for fs in ["dev", "proc", "sys"] {
+        fs::create_dir_all(root.join(fs))?;
+        run(Command::new("mount").args([
+            "--rbind",
+            &format!("/{}", fs),
+            &format!("{}/{}", ALTROOT, fs),
+        ]))
+        .map_err(InstallationError::MountFailed)?;
+    }
+
+    let result = grub_install(boot_disks);
+
+    for fs in ["sys", "proc", "dev", "boot/efi"] {
+        let _ = run(Command::new("umount").args(["-R", &format!("{}/{}", ALTROOT, fs)]));
+    }
+    result
+}
+
+fn grub_install(boot_disks: &[String]) -> Result<(), InstallationError> {
+    if Path::new("/sys/firmware/efi").exists() {
+        let target = if cfg!(target_arch = "aarch64") {
+            "--target=arm64-efi"
+        } else {
+            "--target=x86_64-efi"
+        };
+        run(Command::new("chroot").args([
+            ALTROOT,
+            GRUB_INSTALL_BIN,
+            target,
+            "--efi-directory=/boot/efi",
+            "--bootloader-id=linux",
+            "--recheck",
+        ]))
+        .map_err(InstallationError::InstallBootLoaderFailed)?;
+    } else {
+        for disk in boot_disks {
+            run(Command::new("chroot").args([
+                ALTROOT,
+                GRUB_INSTALL_BIN,
+                "--target=i386-pc",
+                &disk_path(disk),
+            ]))
+            .map_err(InstallationError::InstallBootLoaderFailed)?;
+        }
+    }
+    run(Command::new("chroot").args([ALTROOT, GRUB_MKCONFIG_BIN, "-o", "/boot/grub/grub.cfg"]))
+        .map_err(InstallationError::InstallBootLoaderFailed)
+}
+
+/// Unmount everything below /a and destroy the pools created in this run
+async fn rollback(
+    created_pools: &[String],
+    tx: &Sender<Result<InstallProgress, Status>>,
+) -> Result<(), SendError<Result<InstallProgress, Status>>> {
+    let total = created_pools.len() as u32 + 1;
+    tx.send(report_install_step(
+        InstallPhase::Rollback,
+        1,
+        total,
+        "Unmounting /a",
+    ))
+    .await?;
+    let _ = run(Command::new("umount").args(["-R", ALTROOT]));
+    for (idx, pool) in created_pools.iter().rev().enumerate() {
+        tx.send(report_install_step(
+            InstallPhase::Rollback,
+            idx as u32 + 2,
+            total,
+            format!("Destroying pool {}", pool).as_str(),
+        ))
+        .await?;
+        if let Err(e) = destroy_pool(pool) {
+            tx.send(report_install_error(e)).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Unmount everything below /a and export the pools created for the installation
+pub fn export_pools() -> Result<Vec<String>, InstallationError> {
+    // Nothing may be mounted below /a when no install ran yet
+    let _ = run(Command::new("umount").args(["-R", ALTROOT]));
+    let pools = pool_property("altroot")
+        .map_err(InstallationError::ZfsListFailed)?
+        .into_iter()
+        .filter(|(_, altroot)| altroot == ALTROOT)
+        .map(|(pool, _)| pool)
+        .collect::<Vec<_>>();
+    for pool in &pools {
+        export_pool(pool)?;
+    }
+    Ok(pools)
+}
+
+/// Disks of the imported pools and the disks the root and /usb of the installer are mounted
+/// from, which PrepareDisks does not wipe without force
+pub fn disks_in_use() -> Result<Vec<DiskInUse>, InstallationError> {
+    let mut in_use = imported_pool_devices()
+        .map_err(InstallationError::ZfsListFailed)?
+        .into_iter()
+        .map(|(pool, device)| DiskInUse {
+            device: whole_disk(&device),
+            used_by: format!("pool {}", pool),
+        })
+        .collect::<Vec<_>>();
+    in_use.extend(
+        protected_mounts(&fs::read_to_string("/proc/self/mounts")?)
+            .into_iter()
+            .map(|(mountpoint, device)| DiskInUse {
+                device: whole_disk(&device),
+                used_by: mountpoint,
+            }),
+    );
+    Ok(in_use)
+}
+
+pub fn power(action: PowerAction) -> Result<(), InstallationError> {
+    let bin = match action {
+        PowerAction::Reboot => "reboot",
+        PowerAction::PowerOff => "poweroff",
+    };
+    run(&mut Command::new(bin))
+        .map_err(|e| InstallationError::PowerControlFailed(action.to_string(), e))
+}
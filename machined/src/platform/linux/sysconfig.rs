@@ -0,0 +1,196 @@
+use crate::error::InstallationError;
+use crate::machined::NetworkInterface;
+use crate::platform::linux::zfs::run;
+use crate::validate::same_mac;
+use machineconfig::{AddressKind, Interface};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+const MKSWAP_BIN: &str = "mkswap";
+
+/// systemd-networkd configuration directory relative to the installed root
+const NETWORKD_DIR: &str = "etc/systemd/network";
+
+/// Write the hostname into `/etc/hostname` and `/etc/hosts`
+pub fn apply_hostname(root: &Path, hostname: &str) -> Result<(), InstallationError> {
+    fs::write(root.join("etc/hostname"), format!("{}\n", hostname))?;
+
+    let hosts_path = root.join("etc/hosts");
+    let hosts = fs::read_to_string(&hosts_path).unwrap_or_default();
+    let mut content = hosts
+        .lines()
+        .filter(|line| !line.starts_with("127.0.1.1"))
+        .map(|line| format!("{}\n", line))
+        .collect::<String>();
+    if !content.lines().any(|line| line.starts_with("127.0.0.1")) {
+        content.insert_str(0, "127.0.0.1\tlocalhost\n");
+    }
+    content.push_str(&format!("127.0.1.1\t{}\n", hostname));
+    fs::write(hosts_path, content)?;
+    Ok(())
+}
+
+/// Format a swap volume and add it to `/etc/fstab`
+pub fn apply_swap(root: &Path, device: &str) -> Result<(), InstallationError> {
+    run(Command::new(MKSWAP_BIN).arg(device)).map_err(InstallationError::VolumeCreateFailed)?;
+    let fstab_path = root.join("etc/fstab");
+    let fstab = fs::read_to_string(&fstab_path).unwrap_or_default();
+    if !fstab
+        .lines()
+        .any(|line| line.split_whitespace().next() == Some(device))
+    {
+        let mut content = fstab;
+        if !content.is_empty() && !content.ends_with('\n') {
+            content.push('\n');
+        }
+        content.push_str(&format!("{} none swap defaults 0 0\n", device));
+        fs::write(fstab_path, content)?;
+    }
+    Ok(())
+}
+
+/// Write `/etc/resolv.conf`, replacing the link to a resolver stub some images ship
+pub fn apply_nameservers(root: &Path, nameservers: &[String]) -> Result<(), InstallationError> {
+    let path = root.join("etc/resolv.conf");
+    if path.is_symlink() {
+        fs::remove_file(&path)?;
+    }
+    let content = nameservers
+        .iter()
+        .map(|ns| format!("nameserver {}\n", ns))
+        .collect::<String>();
+    fs::write(path, content)?;
+    Ok(())
+}
+
+/// Write systemd-networkd units for the configured interfaces.
+///
+/// Interfaces matched by a selector get a `.link` unit naming the link after the interface.
+pub fn apply_interfaces(
+    root: &Path,
+    interfaces: &[Interface],
+    links: &[NetworkInterface],
+) -> Result<(), InstallationError> {
+    let dir = root.join(NETWORKD_DIR);
+    fs::create_dir_all(&dir)?;
+    for (idx, iface) in interfaces.iter().enumerate() {
+        let units = networkd_units(iface, links)?;
+        let unit_name = iface
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("interface{}", idx));
+        if let Some(link) = units.link {
+            fs::write(dir.join(format!("10-{}.link", unit_name)), link)?;
+        }
+        fs::write(dir.join(format!("10-{}.network", unit_name)), units.network)?;
+    }
+    Ok(())
+}
+
+struct NetworkdUnits {
+    link: Option<String>,
+    network: String,
+}
+
+fn networkd_units(
+    iface: &Interface,
+    links: &[NetworkInterface],
+) -> Result<NetworkdUnits, InstallationError> {
+    let (matcher, link) = match (&iface.selector, &iface.name) {
+        (Some(selector), name) => {
+            let mac = selector
+                .strip_prefix("mac:")
+                .ok_or_else(|| InstallationError::InvalidInterfaceSelector(selector.clone()))?;
+            // The link has to exist even though it is matched by MAC address on first boot
+            links
+                .iter()
+                .find(|link| same_mac(&link.mac_address, mac))
+                .ok_or_else(|| InstallationError::InterfaceNotFound(selector.clone()))?;
+            let link = name
+                .as_ref()
+                .map(|name| format!("[Match]\nMACAddress={}\n\n[Link]\nName={}\n", mac, name));
+            (format!("MACAddress={}", mac), link)
+        }
+        (None, Some(name)) => (format!("Name={}", name), None),
+        (None, None) => {
+            return Err(InstallationError::InterfaceNotFound(String::from(
+                "interface without name or selector",
+            )))
+        }
+    };
+
+    let mut dhcp4 = false;
+    let mut dhcp6 = false;
+    let mut accept_ra = false;
+    let mut addresses = Vec::new();
+    for addr in &iface.addresses {
+        match (&addr.kind, &addr.address) {
+            (AddressKind::Static, Some(address)) => addresses.push(address.clone()),
+            (AddressKind::Static, None) => {
+                return Err(InstallationError::MissingStaticAddress(addr.name.clone()));
+            }
+            (AddressKind::Dhcp4, _) => dhcp4 = true,
+            (AddressKind::Dhcp6, _) => dhcp6 = true,
+            (AddressKind::Addrconf, _) => accept_ra = true,
+        }
+    }
+
+    let mut network = format!("[Match]\n{}\n\n[Network]\n", matcher);
+    let dhcp = match (dhcp4, dhcp6) {
+        (true, true) => "yes",
+        (true, false) => "ipv4",
+        (false, true) => "ipv6",
+        (false, false) => "no",
+    };
+    network.push_str(&format!("DHCP={}\n", dhcp));
+    network.push_str(&format!(
+        "IPv6AcceptRA={}\n",
+        if accept_ra || dhcp6 { "yes" } else { "no" }
+    ));
+    for address in addresses {
+        network.push_str(&format!("Address={}\n", address));
+    }
+    Ok(NetworkdUnits { link, network })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use machineconfig::AddressObject;
+
+    #[test]
+    fn generates_networkd_units() {
+        let links = vec![NetworkInterface {
+            link: String::from("enp3s0"),
+            mac_address: String::from("52:54:00:12:34:56"),
+            ..Default::default()
+        }];
+        let iface = Interface {
+            name: Some(String::from("net0")),
+            selector: Some(String::from("mac:52:54:00:12:34:56")),
+            addresses: vec![
+                AddressObject {
+                    name: String::from("v4"),
+                    kind: AddressKind::Static,
+                    address: Some(String::from("192.168.1.200/24")),
+                },
+                AddressObject {
+                    name: String::from("v6"),
+                    kind: AddressKind::Addrconf,
+                    address: None,
+                },
+            ],
+        };
+
+        let units = networkd_units(&iface, &links).unwrap();
+        assert_eq!(
+            units.link.unwrap(),
+            "[Match]\nMACAddress=52:54:00:12:34:56\n\n[Link]\nName=net0\n"
+        );
+        assert_eq!(
+            units.network,
+            "[Match]\nMACAddress=52:54:00:12:34:56\n\n[Network]\nDHCP=no\nIPv6AcceptRA=yes\nAddress=192.168.1.200/24\n"
+        );
+    }
+}
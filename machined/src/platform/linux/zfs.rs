@@ -0,0 +1,228 @@
+use crate::diskprep::pool_devices;
+use crate::error::InstallationError;
+use crate::machined::DiskInfo;
+use machineconfig::Pool;
+use std::process::Command;
+
+const ZPOOL_BIN: &str = "zpool";
+const ZFS_BIN: &str = "zfs";
+const SGDISK_BIN: &str = "sgdisk";
+const UDEVADM_BIN: &str = "udevadm";
+const LSBLK_BIN: &str = "lsblk";
+const WIPEFS_BIN: &str = "wipefs";
+const BLKDISCARD_BIN: &str = "blkdiscard";
+
+/// Partition number of the BIOS boot partition GRUB embeds its core image into
+pub const BIOS_BOOT_PARTITION: u32 = 1;
+/// Partition number of the EFI system partition
+pub const ESP_PARTITION: u32 = 2;
+/// Partition number of the partition holding the root pool
+pub const ZFS_PARTITION: u32 = 3;
+
+/// Path of a disk as given in a vdev, `sda` is taken to mean `/dev/sda`
+pub fn disk_path(disk: &str) -> String {
+    if disk.starts_with('/') {
+        disk.to_string()
+    } else {
+        format!("/dev/{}", disk)
+    }
+}
+
+/// Path of partition `number` of `disk`, following the naming of the kernel and udev
+pub fn partition_path(disk: &str, number: u32) -> String {
+    let path = disk_path(disk);
+    if path.starts_with("/dev/disk/") {
+        format!("{}-part{}", path, number)
+    } else if path.ends_with(|c: char| c.is_ascii_digit()) {
+        format!("{}p{}", path, number)
+    } else {
+        format!("{}{}", path, number)
+    }
+}
+
+/// Lay out a disk of the root pool with a BIOS boot partition, an EFI system partition
+/// and a partition for ZFS taking up the rest of the disk.
+pub fn partition_boot_disk(disk: &str) -> Result<(), InstallationError> {
+    let path = disk_path(disk);
+    run(Command::new(SGDISK_BIN).args(["--zap-all", &path]))
+        .map_err(InstallationError::PartitionFailed)?;
+    run(Command::new(SGDISK_BIN).args([
+        &format!("-n{}:1M:+1M", BIOS_BOOT_PARTITION),
+        &format!("-t{}:EF02", BIOS_BOOT_PARTITION),
+        &format!("-n{}:0:+512M", ESP_PARTITION),
+        &format!("-t{}:EF00", ESP_PARTITION),
+        &format!("-n{}:0:0", ZFS_PARTITION),
+        &format!("-t{}:BF00", ZFS_PARTITION),
+        &path,
+    ]))
+    .map_err(InstallationError::PartitionFailed)?;
+    // The partition device nodes are created by udev
+    run(Command::new(UDEVADM_BIN).arg("settle")).map_err(InstallationError::PartitionFailed)?;
+    Ok(())
+}
+
+/// Clear the ZFS labels of a disk and its partitions and wipe its partition tables, so
+/// `zpool create` does not take it for a member of another pool
+pub fn wipe_disk(disk: &DiskInfo, secure_erase: bool) -> Result<(), InstallationError> {
+    let path = disk_path(&disk.device);
+    let wipe_failed = |e| InstallationError::WipeDiskFailed(disk.device.clone(), e);
+    let devices =
+        run_output(Command::new(LSBLK_BIN).args(["-lnpo", "NAME", &path])).map_err(wipe_failed)?;
+    // Partitions come after their disk, their labels survive wiping the partition table
+    for device in devices.lines().rev() {
+        // Fails on devices that carry no label, which is fine
+        let _ = run(Command::new(ZPOOL_BIN).args(["labelclear", "-f", device]));
+    }
+    run(Command::new(WIPEFS_BIN).args(["--all", "--force", &path])).map_err(wipe_failed)?;
+    if secure_erase {
+        run(Command::new(BLKDISCARD_BIN).args(["--secure", &path])).map_err(wipe_failed)?;
+    }
+    run(Command::new(UDEVADM_BIN).arg("settle")).map_err(wipe_failed)?;
+    Ok(())
+}
+
+/// Create a pool with its datasets mounted below `altroot`
+pub fn create_pool(pool: &Pool, altroot: &str) -> Result<(), InstallationError> {
+    let mut zpool_cmd = Command::new(ZPOOL_BIN);
+    zpool_cmd.args(["create", "-R", altroot, "-O", "mountpoint=none"]);
+    for opt in &pool.options {
+        zpool_cmd.args(["-o", format!("{}={}", opt.name, opt.value).as_str()]);
+    }
+    for opt in &pool.filesystem_options {
+        zpool_cmd.args(["-O", format!("{}={}", opt.name, opt.value).as_str()]);
+    }
+    zpool_cmd.arg(&pool.name);
+    zpool_cmd.args(pool.vdev_args());
+    run(&mut zpool_cmd).map_err(InstallationError::ZpoolCreateFailed)
+}
+
+pub fn destroy_pool(pool_name: &str) -> Result<(), InstallationError> {
+    run(Command::new(ZPOOL_BIN).args(["destroy", "-f", pool_name]))
+        .map_err(InstallationError::ZpoolDestroyFailed)
+}
+
+pub fn set_pool_property(
+    pool_name: &str,
+    name: &str,
+    value: &str,
+) -> Result<(), InstallationError> {
+    run(Command::new(ZPOOL_BIN).args(["set", &format!("{}={}", name, value), pool_name]))
+        .map_err(InstallationError::ZfsSetFailed)
+}
+
+pub fn create_dataset(name: &str, opts: &[(&str, &str)]) -> Result<(), InstallationError> {
+    let mut zfs_cmd = Command::new(ZFS_BIN);
+    zfs_cmd.arg("create");
+    for (key, value) in opts {
+        zfs_cmd.args(["-o", format!("{}={}", key, value).as_str()]);
+    }
+    zfs_cmd.arg(name);
+    run(&mut zfs_cmd).map_err(InstallationError::DatasetCreateFailed)
+}
+
+/// Create a volume of `size`, e.g. `4G`
+pub fn create_volume(
+    name: &str,
+    size: &str,
+    opts: &[(&str, &str)],
+) -> Result<(), InstallationError> {
+    let mut zfs_cmd = Command::new(ZFS_BIN);
+    zfs_cmd.args(["create", "-V", size]);
+    for (key, value) in opts {
+        zfs_cmd.args(["-o", format!("{}={}", key, value).as_str()]);
+    }
+    zfs_cmd.arg(name);
+    run(&mut zfs_cmd).map_err(InstallationError::VolumeCreateFailed)
+}
+
+pub fn mount_dataset(name: &str) -> Result<(), InstallationError> {
+    run(Command::new(ZFS_BIN).args(["mount", name])).map_err(InstallationError::MountFailed)
+}
+
+pub fn export_pool(pool_name: &str) -> Result<(), InstallationError> {
+    run(Command::new(ZPOOL_BIN).args(["export", pool_name]))
+        .map_err(InstallationError::PoolExportFailed)
+}
+
+/// Device paths of the imported pools, as pool name and device
+pub fn imported_pool_devices() -> Result<Vec<(String, String)>, String> {
+    let out = run_output(Command::new(ZPOOL_BIN).args(["list", "-H", "-P", "-v", "-o", "name"]))?;
+    Ok(pool_devices(&out))
+}
+
+/// Name of the whole disk a device or partition path belongs to, as lsblk reports disks
+pub fn whole_disk(device: &str) -> String {
+    match run_output(Command::new(LSBLK_BIN).args(["-ndo", "PKNAME", device])) {
+        Ok(parent) if !parent.trim().is_empty() => parent.trim().to_string(),
+        // Whole disks have no parent, by-id and by-path names are symlinks to them
+        _ => std::fs::canonicalize(device)
+            .ok()
+            .and_then(|path| {
+                path.file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+            })
+            .unwrap_or_else(|| device.trim_start_matches("/dev/").to_string()),
+    }
+}
+
+/// Name and value of a property of every imported pool
+pub fn pool_property(property: &str) -> Result<Vec<(String, String)>, String> {
+    let out = run_output(Command::new(ZPOOL_BIN).args([
+        "list",
+        "-H",
+        "-o",
+        &format!("name,{}", property),
+    ]))?;
+    Ok(out
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect())
+}
+
+/// Datasets directly below `parent` as tab separated `name,mountpoint,mounted,used,creation`
+/// with exact numbers
+pub fn list_child_datasets(parent: &str) -> Result<String, String> {
+    run_output(Command::new(ZFS_BIN).args([
+        "list",
+        "-H",
+        "-p",
+        "-d",
+        "1",
+        "-o",
+        "name,mountpoint,mounted,used,creation",
+        parent,
+    ]))
+}
+
+/// Run a command, returning its stderr if it fails
+pub fn run(cmd: &mut Command) -> Result<(), String> {
+    run_output(cmd).map(|_| ())
+}
+
+/// Run a command and capture its stdout, returning its stderr if it fails
+pub fn run_output(cmd: &mut Command) -> Result<String, String> {
+    let out = cmd.output().map_err(|e| e.to_string())?;
+    if !out.status.success() {
+        return Err(String::from_utf8_lossy(&out.stderr).to_string());
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_partitions_like_the_kernel() {
+        assert_eq!(partition_path("sda", 3), "/dev/sda3");
+        assert_eq!(partition_path("/dev/nvme0n1", 3), "/dev/nvme0n1p3");
+        assert_eq!(
+            partition_path(
+                "/dev/disk/by-id/ata-WDC_WD40EFRX-68N32N0_WD-WCC7K1234567",
+                2
+            ),
+            "/dev/disk/by-id/ata-WDC_WD40EFRX-68N32N0_WD-WCC7K1234567-part2"
+        );
+    }
+}
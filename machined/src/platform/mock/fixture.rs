@@ -0,0 +1,377 @@
+use crate::diskselect::parse_size;
+use crate::error::FixtureError;
+use crate::machined::{
+    CpuTopology, DiskInfo, FirmwareInfo, FirmwareMode, InstallPhase, NetworkInterface,
+};
+use crate::sysinfo::smbios;
+use config::{Config, File, FileFormat};
+use serde::Deserialize;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Machine simulated when machined is not configured with a fixture
+const DEFAULT_FIXTURE: &str = include_str!("../../../testdata/mock/fixture.toml");
+
+/// SMBIOS table of fixtures that do not bring their own dump
+const DEFAULT_SMBIOS: &[u8] = include_bytes!("../../../testdata/smbios/server-3.3.bin");
+
+static FIXTURE: Mutex<Option<Arc<Fixture>>> = Mutex::new(None);
+
+/// Hardware the mock platform reports and installs to, and how its installs go
+#[derive(Debug, Clone)]
+pub struct Fixture {
+    pub disks: Vec<DiskInfo>,
+    pub links: Vec<NetworkInterface>,
+    /// Raw SMBIOS dump, entry point followed by the structure table
+    pub smbios: Vec<u8>,
+    pub cpu: CpuTopology,
+    pub memory_bytes: u64,
+    pub firmware: FirmwareInfo,
+    /// Sizes of the layers of every image installed
+    pub layers: Vec<u64>,
+    /// Pause after each install step, so clients can watch an install progress
+    pub step_delay: Duration,
+    pub failures: Vec<Failure>,
+}
+
+/// A step of the simulated install that fails with `message`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Failure {
+    pub phase: InstallPhase,
+    /// Step of the phase that fails, the first one when not set
+    pub step: Option<u32>,
+    pub message: String,
+    /// The step fails like a layer that does not match its digest after it was extracted
+    pub digest_mismatch: bool,
+}
+
+impl Failure {
+    pub fn matches(&self, phase: InstallPhase, step: u32) -> bool {
+        self.phase == phase && self.step.unwrap_or(1) == step
+    }
+}
+
+#[derive(Deserialize)]
+struct FixtureFile {
+    #[serde(default)]
+    disks: Vec<DiskFile>,
+    #[serde(default)]
+    links: Vec<LinkFile>,
+    /// Path of a raw SMBIOS dump, relative to the fixture
+    smbios: Option<String>,
+    #[serde(default)]
+    cpu: CpuFile,
+    #[serde(default = "default_memory")]
+    memory: String,
+    #[serde(default = "default_firmware")]
+    firmware: String,
+    #[serde(default)]
+    secure_boot: bool,
+    #[serde(default = "default_layers")]
+    layers: Vec<String>,
+    #[serde(default)]
+    step_delay_ms: u64,
+    #[serde(default)]
+    failures: Vec<FailureFile>,
+}
+
+#[derive(Deserialize)]
+struct DiskFile {
+    device: String,
+    #[serde(default)]
+    vendor: String,
+    #[serde(default)]
+    product: String,
+    #[serde(default)]
+    serial: String,
+    size: String,
+    #[serde(default)]
+    removable: bool,
+    #[serde(default)]
+    solid_state: bool,
+    /// Device paths, `/dev/dsk` and `/dev/rdsk` paths of the device when not set
+    paths: Option<Vec<String>>,
+    #[serde(default)]
+    fault_status: String,
+    #[serde(default)]
+    location_code: String,
+    #[serde(default)]
+    chassis_bay: String,
+}
+
+#[derive(Deserialize)]
+struct LinkFile {
+    name: String,
+    /// Datalink of the interface, the interface name when not set
+    link: Option<String>,
+    #[serde(default)]
+    mac_address: String,
+    #[serde(default = "default_link_class")]
+    class: String,
+    #[serde(default = "default_link_media")]
+    media: String,
+    #[serde(default = "default_link_state")]
+    state: String,
+    #[serde(default = "default_link_speed")]
+    speed: String,
+    #[serde(default = "default_link_mtu")]
+    mtu: String,
+    #[serde(default)]
+    over: bool,
+}
+
+#[derive(Deserialize, Default)]
+struct CpuFile {
+    #[serde(default)]
+    model: String,
+    #[serde(default)]
+    sockets: u32,
+    #[serde(default)]
+    cores: u32,
+    #[serde(default)]
+    threads: u32,
+}
+
+#[derive(Deserialize)]
+struct FailureFile {
+    /// Install phase like `pools` or `unpack`
+    phase: String,
+    step: Option<u32>,
+    message: String,
+    #[serde(default)]
+    digest_mismatch: bool,
+}
+
+fn default_memory() -> String {
+    "16G".to_string()
+}
+
+fn default_firmware() -> String {
+    "uefi".to_string()
+}
+
+fn default_layers() -> Vec<String> {
+    vec!["512M".to_string(), "32M".to_string()]
+}
+
+fn default_link_class() -> String {
+    "phys".to_string()
+}
+
+fn default_link_media() -> String {
+    "Ethernet".to_string()
+}
+
+fn default_link_state() -> String {
+    "up".to_string()
+}
+
+fn default_link_speed() -> String {
+    "1000".to_string()
+}
+
+fn default_link_mtu() -> String {
+    "1500".to_string()
+}
+
+/// The fixture the mock platform simulates, the built-in machine until one is loaded
+pub fn fixture() -> Arc<Fixture> {
+    FIXTURE
+        .lock()
+        .unwrap()
+        .get_or_insert_with(|| {
+            Arc::new(
+                Fixture::parse(DEFAULT_FIXTURE, FileFormat::Toml, Path::new("."))
+                    .expect("built-in hardware fixture is valid"),
+            )
+        })
+        .clone()
+}
+
+/// Simulate the machine described by the fixture at `path` from now on
+pub fn load_fixture(path: &str) -> Result<(), FixtureError> {
+    let fixture = Fixture::load(Path::new(path))?;
+    *FIXTURE.lock().unwrap() = Some(Arc::new(fixture));
+    Ok(())
+}
+
+impl Fixture {
+    /// Read a fixture in any format the configuration of machined can be written in
+    pub fn load(path: &Path) -> Result<Self, FixtureError> {
+        let file: FixtureFile = Config::builder()
+            .add_source(File::from(path))
+            .build()
+            .and_then(|cfg| cfg.try_deserialize())
+            .map_err(|e| FixtureError::Load(path.display().to_string(), e))?;
+        Self::from_file(file, path.parent().unwrap_or(Path::new(".")))
+    }
+
+    pub fn parse(content: &str, format: FileFormat, dir: &Path) -> Result<Self, FixtureError> {
+        let file: FixtureFile = Config::builder()
+            .add_source(File::from_str(content, format))
+            .build()
+            .and_then(|cfg| cfg.try_deserialize())
+            .map_err(|e| FixtureError::Load("<inline>".to_string(), e))?;
+        Self::from_file(file, dir)
+    }
+
+    fn from_file(file: FixtureFile, dir: &Path) -> Result<Self, FixtureError> {
+        let size = |size: &str| {
+            parse_size(size).ok_or_else(|| FixtureError::InvalidSize(size.to_string()))
+        };
+
+        let dump = match &file.smbios {
+            Some(path) => {
+                let path = dir.join(path);
+                std::fs::read(&path)
+                    .map_err(|e| FixtureError::ReadSmbios(path.display().to_string(), e))?
+            }
+            None => DEFAULT_SMBIOS.to_vec(),
+        };
+        // Fail now rather than on every GetSystemInfo call
+        smbios::parse_dump(&dump)
+            .map_err(|e| FixtureError::InvalidSmbios(file.smbios.clone().unwrap_or_default(), e))?;
+
+        let disks = file
+            .disks
+            .into_iter()
+            .map(|disk| {
+                Ok(DiskInfo {
+                    size_bytes: size(&disk.size)?,
+                    paths: disk.paths.unwrap_or_else(|| {
+                        vec![
+                            format!("/dev/dsk/{}", disk.device),
+                            format!("/dev/rdsk/{}", disk.device),
+                        ]
+                    }),
+                    device: disk.device,
+                    vendor: disk.vendor,
+                    product: disk.product,
+                    serial: disk.serial,
+                    removable: disk.removable,
+                    solid_state: disk.solid_state,
+                    fault_status: disk.fault_status,
+                    location_code: disk.location_code,
+                    chassis_bay: disk.chassis_bay,
+                })
+            })
+            .collect::<Result<Vec<_>, FixtureError>>()?;
+
+        let links = file
+            .links
+            .into_iter()
+            .map(|link| NetworkInterface {
+                link: link.link.unwrap_or_else(|| link.name.clone()),
+                name: link.name,
+                class: link.class,
+                media: link.media,
+                state: link.state,
+                speed: link.speed,
+                mac_address: link.mac_address,
+                over: link.over,
+                mtu: link.mtu,
+            })
+            .collect();
+
+        let mode = match file.firmware.to_lowercase().as_str() {
+            "uefi" => FirmwareMode::Uefi,
+            "bios" => FirmwareMode::Bios,
+            _ => return Err(FixtureError::UnknownFirmware(file.firmware)),
+        };
+
+        let failures = file
+            .failures
+            .into_iter()
+            .map(|failure| {
+                let phase = InstallPhase::from_str_name(&format!(
+                    "INSTALL_PHASE_{}",
+                    failure.phase.to_uppercase()
+                ))
+                .filter(|phase| *phase != InstallPhase::Unspecified)
+                .ok_or_else(|| FixtureError::UnknownPhase(failure.phase.clone()))?;
+                Ok(Failure {
+                    phase,
+                    step: failure.step,
+                    message: failure.message,
+                    digest_mismatch: failure.digest_mismatch,
+                })
+            })
+            .collect::<Result<Vec<_>, FixtureError>>()?;
+
+        Ok(Fixture {
+            disks,
+            links,
+            smbios: dump,
+            cpu: CpuTopology {
+                model: file.cpu.model,
+                sockets: file.cpu.sockets,
+                cores: file.cpu.cores,
+                threads: file.cpu.threads,
+            },
+            memory_bytes: size(&file.memory)?,
+            firmware: FirmwareInfo {
+                mode: mode.into(),
+                secure_boot: file.secure_boot,
+            },
+            layers: file
+                .layers
+                .iter()
+                .map(|layer| size(layer))
+                .collect::<Result<Vec<_>, FixtureError>>()?,
+            step_delay: Duration::from_millis(file.step_delay_ms),
+            failures,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in_fixture_describes_a_server() {
+        let fixture = fixture();
+        assert_eq!(fixture.disks.len(), 7);
+        let boot = &fixture.disks[0];
+        assert_eq!(boot.device, "c1t0d0");
+        assert_eq!(boot.paths, vec!["/dev/dsk/c1t0d0", "/dev/rdsk/c1t0d0"]);
+        assert!(boot.solid_state);
+        assert!(fixture.disks.iter().any(|disk| disk.removable));
+        assert_eq!(fixture.links[0].link, "igb0");
+        assert_eq!(fixture.links[1].state, "down");
+        assert_eq!(fixture.memory_bytes, 32 << 30);
+        assert_eq!(fixture.firmware.mode(), FirmwareMode::Uefi);
+        assert_eq!(fixture.layers, vec![512 << 20, 32 << 20]);
+        assert!(fixture.failures.is_empty());
+    }
+
+    #[test]
+    fn parses_failures_and_rejects_unknown_phases() {
+        let fixture = Fixture::parse(
+            r#"{
+                "firmware": "bios",
+                "links": [{ "name": "net0", "mac_address": "2:8:20:0:0:1" }],
+                "failures": [{ "phase": "unpack", "step": 2, "message": "disk full" }]
+            }"#,
+            FileFormat::Json,
+            Path::new("."),
+        )
+        .unwrap();
+        assert_eq!(fixture.firmware.mode(), FirmwareMode::Bios);
+        assert_eq!(fixture.links[0].link, "net0");
+        assert!(fixture.failures[0].matches(InstallPhase::Unpack, 2));
+        assert!(!fixture.failures[0].matches(InstallPhase::Unpack, 1));
+
+        let unknown = Fixture::parse(
+            "[[failures]]\nphase = \"partying\"\nmessage = \"no\"\n",
+            FileFormat::Toml,
+            Path::new("."),
+        );
+        assert!(matches!(unknown, Err(FixtureError::UnknownPhase(phase)) if phase == "partying"));
+        assert!(matches!(
+            Fixture::parse("memory = \"lots\"\n", FileFormat::Toml, Path::new(".")),
+            Err(FixtureError::InvalidSize(_))
+        ));
+    }
+}
@@ -1,88 +1,799 @@
 use crate::config::MachinedConfig;
-use crate::machined::InstallProgress;
-use crate::util::report_install_debug;
+use crate::diskprep::DiskInUse;
+use crate::diskselect::disk_name;
+use crate::error::InstallationError;
+use crate::machined::{BootEnvironment, DiskInfo, InstallPhase, InstallProgress, InstallReport};
+use crate::platform::hardware::{resolve_pool_disks, wipe_pool_disks};
+use crate::platform::PowerAction;
+use crate::report::REPORT_PATH;
+use crate::util::{
+    check_cancelled, report_install_bytes, report_install_debug, report_install_error,
+    report_install_info, report_install_step, report_install_warning, unverified_image,
+};
+use crate::validate::same_mac;
 use crate::Arc;
-use machineconfig::MachineConfig;
+use machineconfig::{Interface, MachineConfig, Pool, PostInstall, SysConfig, VolumeUsage};
+use sha2::{Digest, Sha256};
+use std::sync::Mutex;
 use tokio::sync::mpsc::error::SendError;
 use tokio::sync::mpsc::Sender;
+use tokio_util::sync::CancellationToken;
 use tonic::Status;
+use tracing::info;
 
+mod fixture;
+
+use fixture::Failure;
+pub use fixture::{fixture, load_fixture, Fixture};
+
+/// Boot environment name template used when the configuration has neither name nor template
+const DEFAULT_BE_TEMPLATE: &str = "mock-{date}";
+
+/// Pools and boot environments the mock installs leave behind, so the boot environment,
+/// export and power RPCs act on them like on a real machine
+struct MockMachine {
+    pools: Vec<Pool>,
+    boot_environments: Vec<BootEnvironment>,
+}
+
+static MACHINE: Mutex<MockMachine> = Mutex::new(MockMachine {
+    pools: Vec::new(),
+    boot_environments: Vec::new(),
+});
+
+type Progress = Sender<Result<InstallProgress, Status>>;
+type SendResult = Result<(), SendError<Result<InstallProgress, Status>>>;
+
+/// Simulate an install on the machine of the hardware fixture, reporting the progress the
+/// illumos backend reports and failing where the fixture injects failures
 pub async fn install_system(
     mc: &MachineConfig,
     _config: Arc<MachinedConfig>,
+    report: &mut InstallReport,
+    cancel: &CancellationToken,
     tx: Sender<Result<InstallProgress, Status>>,
-) -> Result<(), SendError<Result<InstallProgress, Status>>> {
-    tx.send(report_install_debug("Mocking Installation"))
-        .await?;
-    for pool in &mc.pools {
-        tx.send(report_install_debug(
-            format!(
-                "Would create pool {} with vdevs {} and options: {}",
-                pool.name,
-                pool.vdevs
-                    .iter()
-                    .map(|vdev| format!("\"{}: {}\"", vdev.kind, vdev.disks.join(",")))
-                    .collect::<Vec<_>>()
-                    .join(","),
-                pool.options
-                    .iter()
-                    .map(|o| format!("{}", o))
-                    .collect::<Vec<_>>()
-                    .join(","),
+) -> SendResult {
+    simulate_install(mc, &fixture(), report, cancel, &tx).await
+}
+
+async fn simulate_install(
+    mc: &MachineConfig,
+    fixture: &Fixture,
+    report: &mut InstallReport,
+    cancel: &CancellationToken,
+    tx: &Progress,
+) -> SendResult {
+    let mut sim = Simulation {
+        fixture,
+        tx,
+        cancel,
+        completed: Vec::new(),
+    };
+    let result = sim.run(mc, report).await;
+    if result.is_err() {
+        let outcome = if cancel.is_cancelled() {
+            "cancelled"
+        } else {
+            "failed"
+        };
+        if mc.skip_rollback && !unverified_image(&result) {
+            tx.send(report_install_info(
+                format!(
+                    "Installation {}, leaving pools and datasets in place",
+                    outcome
+                )
+                .as_str(),
+            ))
+            .await?;
+        } else {
+            if mc.skip_rollback {
+                tx.send(report_install_warning(
+                    "Ignoring skip-rollback, a layer did not match its digest after it was extracted",
+                ))
+                .await?;
+            }
+            tx.send(report_install_info(
+                format!("Installation {}, rolling back completed steps", outcome).as_str(),
+            ))
+            .await?;
+            sim.rollback().await?;
+        }
+    }
+    result
+}
+
+/// A completed step of the simulated install that a rollback undoes
+#[derive(Debug, Clone, PartialEq)]
+enum Completed {
+    Pool(String),
+    Dataset(String),
+    Mount(String),
+}
+
+struct Simulation<'a> {
+    fixture: &'a Fixture,
+    tx: &'a Progress,
+    cancel: &'a CancellationToken,
+    completed: Vec<Completed>,
+}
+
+impl Simulation<'_> {
+    /// Report the start of a step and fail it if the fixture injects a failure into it or the
+    /// install was cancelled
+    async fn step(&self, phase: InstallPhase, step: u32, total: u32, msg: &str) -> SendResult {
+        check_cancelled(self.cancel)?;
+        self.tx
+            .send(report_install_step(phase, step, total, msg))
+            .await?;
+        if !self.fixture.step_delay.is_zero() {
+            tokio::time::sleep(self.fixture.step_delay).await;
+        }
+        match self.injected(phase, step) {
+            Some(failure) if failure.digest_mismatch => {
+                self.tx
+                    .send(report_install_error(&InstallationError::Simulated(
+                        failure.message.clone(),
+                    )))
+                    .await?;
+                Err(SendError(Err(Status::data_loss(format!("{} failed", msg)))))
+            }
+            Some(failure) => {
+                self.fail(InstallationError::Simulated(failure.message.clone()))
+                    .await
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn injected(&self, phase: InstallPhase, step: u32) -> Option<&Failure> {
+        self.fixture
+            .failures
+            .iter()
+            .find(|failure| failure.matches(phase, step))
+    }
+
+    async fn fail(&self, e: InstallationError) -> SendResult {
+        self.tx.send(report_install_error(&e)).await?;
+        Err(SendError(Err(Status::internal("Internal error"))))
+    }
+
+    async fn debug(&self, msg: &str) -> SendResult {
+        self.tx.send(report_install_debug(msg)).await
+    }
+
+    async fn run(&mut self, mc: &MachineConfig, report: &mut InstallReport) -> SendResult {
+        self.debug("Starting installation").await?;
+
+        let pools = resolve_pool_disks(&mc.pools, self.tx).await?;
+        if let Some(wipe) = &mc.wipe_disks {
+            wipe_pool_disks(&pools, wipe, self.cancel, self.tx).await?;
+        }
+
+        let pool_count = pools.len() as u32;
+        for (idx, pool) in pools.iter().enumerate() {
+            self.step(
+                InstallPhase::Pools,
+                idx as u32 + 1,
+                pool_count,
+                format!("Setting up pool {}", &pool.name).as_str(),
             )
-            .as_str(),
-        ))
+            .await?;
+            info!(
+                "Would create pool {} with vdevs \"{}\"",
+                pool.name,
+                pool.vdev_args().join(" ")
+            );
+            self.completed.push(Completed::Pool(pool.name.clone()));
+            self.debug(format!("Pool {} created", &pool.name).as_str())
+                .await?;
+        }
+
+        let root_pool = mc.root_pool();
+        self.step(InstallPhase::Datasets, 1, 4, "Creating base root dataset")
+            .await?;
+        self.completed
+            .push(Completed::Dataset(format!("{}/ROOT", root_pool)));
+        self.debug("base root Dataset created").await?;
+
+        self.step(InstallPhase::Datasets, 2, 4, "Creating boot environment")
+            .await?;
+        let be_name = match mc.boot_environment(DEFAULT_BE_TEMPLATE, chrono::Utc::now()) {
+            Ok(be_name) => be_name,
+            Err(e) => {
+                return self
+                    .fail(InstallationError::InvalidBootEnvironmentName(e.to_string()))
+                    .await
+            }
+        };
+        report.boot_environment = be_name.clone();
+        let be_path = format!("{}/ROOT/{}", root_pool, be_name);
+        self.completed.push(Completed::Dataset(be_path.clone()));
+        self.debug(format!("boot environment {} created", be_path).as_str())
+            .await?;
+
+        self.step(
+            InstallPhase::Datasets,
+            3,
+            4,
+            "Mounting boot environment to /a",
+        )
         .await?;
-    }
+        self.completed.push(Completed::Mount(be_path.clone()));
+        self.debug("boot environment mounted to /a").await?;
 
-    tx.send(report_install_debug(
-        format!("Would extract image {} as root", &mc.image).as_str(),
-    ))
-    .await?;
+        self.step(
+            InstallPhase::Datasets,
+            4,
+            4,
+            "Creating datasets and volumes",
+        )
+        .await?;
+        for pool in &pools {
+            for (name, _) in pool.dataset_tree() {
+                self.completed.push(Completed::Dataset(name.clone()));
+                self.debug(format!("dataset {} created", name).as_str())
+                    .await?;
+            }
+            for volume in &pool.volumes {
+                let name = format!("{}/{}", pool.name, volume.name);
+                self.completed.push(Completed::Dataset(name.clone()));
+                self.debug(format!("volume {} of {} created", name, volume.size).as_str())
+                    .await?;
+            }
+        }
 
-    tx.send(report_install_debug(
-        format!("Would set Hostname to {}", &mc.sysconfig.hostname).as_str(),
-    ))
-    .await?;
+        self.unpack_image(mc, report).await?;
+        self.apply_sysconfig(&mc.sysconfig, &pools).await?;
+        self.apply_postinstall(&mc.postinstall).await?;
 
-    for ns in &mc.sysconfig.nameservers {
-        tx.send(report_install_debug(
-            format!("Would add nameserver {}", ns).as_str(),
-        ))
+        self.step(
+            InstallPhase::Bootloader,
+            1,
+            1,
+            "Activating boot environment and installing bootloader",
+        )
         .await?;
+        record_install(&pools, &be_name);
+        self.tx
+            .send(report_install_info("bootenvironment activated"))
+            .await?;
+
+        report.finished_at = chrono::Utc::now().timestamp();
+        info!("Would write install report to /a/{}", REPORT_PATH);
+        Ok(())
     }
 
-    for (idx, iface) in mc.sysconfig.interfaces.iter().enumerate() {
-        if let Some(selector) = &iface.selector {
-            //TODO: Add some search code in multiple platforms
-            tx.send(report_install_debug(
-                format!("Would work on interface {}", selector).as_str(),
-            ))
+    /// Resolve the image and unpack layers of the sizes the fixture gives.
+    ///
+    /// Nothing is downloaded, the digests are derived from the image reference so the same
+    /// image always reports the same manifest and layers.
+    async fn unpack_image(&self, mc: &MachineConfig, report: &mut InstallReport) -> SendResult {
+        mc.image_source().map_err(|e| {
+            SendError(Err(Status::internal(format!(
+                "Parsing image reference failed: {}",
+                e
+            ))))
+        })?;
+        self.step(InstallPhase::Fetch, 1, 1, "resolving image manifest")
             .await?;
-        } else {
-            tx.send(report_install_debug(
-                format!("Would operate on {} interface", idx).as_str(),
-            ))
+        self.debug("image manifest resolved").await?;
+        report.manifest_digest = simulated_digest(&mc.image, "manifest");
+        report.layer_digests = (0..self.fixture.layers.len())
+            .map(|idx| simulated_digest(&mc.image, &format!("layer{}", idx)))
+            .collect();
+
+        self.tx
+            .send(report_install_info("installing image to root dataset"))
+            .await?;
+        let total_steps = self.fixture.layers.len() as u32;
+        for (idx, size) in self.fixture.layers.iter().enumerate() {
+            let step = idx as u32 + 1;
+            self.step(
+                InstallPhase::Unpack,
+                step,
+                total_steps,
+                format!("unpacking layer {}", report.layer_digests[idx]).as_str(),
+            )
             .await?;
+            for quarter in 1..=4 {
+                self.tx
+                    .send(report_install_bytes(
+                        InstallPhase::Unpack,
+                        step,
+                        total_steps,
+                        size * quarter / 4,
+                        *size,
+                    ))
+                    .await?;
+            }
         }
+        Ok(())
+    }
 
-        if let Some(name) = &iface.name {
-            tx.send(report_install_debug(
-                format!("Would set interface name to {}", name).as_str(),
-            ))
+    async fn apply_sysconfig(&self, sc: &SysConfig, pools: &[Pool]) -> SendResult {
+        self.step(InstallPhase::Sysconfig, 1, 4, "Setting hostname")
             .await?;
+        if sc.hostname.is_empty() {
+            self.debug("no hostname configured").await?;
         }
-        for addr in &iface.addresses {
-            tx.send(report_install_debug(
-                format!(
-                    "Adding Address {} of type {} with address {:?}",
-                    addr.name, addr.kind, addr.address
-                )
-                .as_str(),
-            ))
-            .await?
+
+        self.step(InstallPhase::Sysconfig, 2, 4, "Configuring nameservers")
+            .await?;
+        if sc.nameservers.is_empty() {
+            self.debug("no nameservers configured").await?;
         }
+
+        self.step(
+            InstallPhase::Sysconfig,
+            3,
+            4,
+            "Configuring network interfaces",
+        )
+        .await?;
+        if sc.interfaces.is_empty() {
+            self.debug("no network interfaces configured").await?;
+        } else {
+            for iface in &sc.interfaces {
+                if let Err(e) = self.resolve_link(iface) {
+                    return self.fail(e).await;
+                }
+            }
+            self.debug("network interfaces will be configured on first boot")
+                .await?;
+        }
+
+        self.step(
+            InstallPhase::Sysconfig,
+            4,
+            4,
+            "Configuring swap and dump volumes",
+        )
+        .await?;
+        let volume_devices = |usage: VolumeUsage| {
+            pools
+                .iter()
+                .flat_map(|pool| {
+                    pool.volumes
+                        .iter()
+                        .filter(move |volume| volume.usage == Some(usage))
+                        .map(|volume| format!("/dev/zvol/dsk/{}/{}", pool.name, volume.name))
+                })
+                .collect::<Vec<_>>()
+        };
+        let swap = volume_devices(VolumeUsage::Swap);
+        if !swap.is_empty() {
+            self.debug(format!("swap on {}", swap.join(" ")).as_str())
+                .await?;
+        }
+        if let Some(dump) = volume_devices(VolumeUsage::Dump).first() {
+            self.debug(format!("crash dumps go to {}", dump).as_str())
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Find the link of the fixture an interface refers to, like the illumos backend
+    /// resolves `mac:` selectors against the links of the machine
+    fn resolve_link(&self, iface: &Interface) -> Result<String, InstallationError> {
+        match (&iface.selector, &iface.name) {
+            (Some(selector), _) => {
+                let mac = selector
+                    .strip_prefix("mac:")
+                    .ok_or_else(|| InstallationError::InvalidInterfaceSelector(selector.clone()))?;
+                self.fixture
+                    .links
+                    .iter()
+                    .find(|link| same_mac(&link.mac_address, mac))
+                    .map(|link| link.link.clone())
+                    .ok_or_else(|| InstallationError::InterfaceNotFound(selector.clone()))
+            }
+            (None, Some(name)) => Ok(name.clone()),
+            (None, None) => Err(InstallationError::InterfaceNotFound(String::from(
+                "interface without name or selector",
+            ))),
+        }
+    }
+
+    async fn apply_postinstall(&self, postinstall: &PostInstall) -> SendResult {
+        let mut parts: Vec<(&str, Vec<String>)> = Vec::new();
+        if !postinstall.files.is_empty() {
+            parts.push((
+                "Writing files",
+                postinstall
+                    .files
+                    .iter()
+                    .map(|file| format!("wrote {}", file.path))
+                    .collect(),
+            ));
+        }
+        if !postinstall.packages.is_empty() {
+            parts.push((
+                "Installing packages",
+                vec![format!("pkg install {}", postinstall.packages.join(" "))],
+            ));
+        }
+        if !postinstall.smf_manifests.is_empty() {
+            parts.push((
+                "Adding SMF manifests for first boot",
+                postinstall
+                    .smf_manifests
+                    .iter()
+                    .map(|manifest| {
+                        format!(
+                            "SMF manifest {} will be imported on first boot",
+                            manifest.file_name()
+                        )
+                    })
+                    .collect(),
+            ));
+        }
+        if !postinstall.scripts.is_empty() {
+            parts.push((
+                "Running postinstall scripts",
+                postinstall
+                    .scripts
+                    .iter()
+                    .map(|script| format!("running script {}", script.name))
+                    .collect(),
+            ));
+        }
+
+        let total_steps = parts.len() as u32;
+        for (idx, (description, details)) in parts.iter().enumerate() {
+            self.step(
+                InstallPhase::Postinstall,
+                idx as u32 + 1,
+                total_steps,
+                description,
+            )
+            .await?;
+            for detail in details {
+                self.debug(detail).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Undo the completed steps in reverse order like the illumos rollback: datasets in
+    /// pools created by this install go away with the pool, mounts are undone first.
+    /// Injected rollback failures are reported and the remaining steps still attempted.
+    async fn rollback(self) -> SendResult {
+        let pending = self
+            .completed
+            .iter()
+            .rev()
+            .filter(|step| match step {
+                Completed::Dataset(dataset) => !self.completed.contains(&Completed::Pool(
+                    dataset.split('/').next().unwrap_or_default().to_string(),
+                )),
+                _ => true,
+            })
+            .collect::<Vec<_>>();
+        let total = pending.len() as u32;
+        for (idx, step) in pending.iter().enumerate() {
+            let step_no = idx as u32 + 1;
+            let description = match step {
+                Completed::Pool(pool) => format!("Destroying pool {}", pool),
+                Completed::Dataset(dataset) => format!("Destroying dataset {}", dataset),
+                Completed::Mount(dataset) => format!("Unmounting {} from /a", dataset),
+            };
+            self.tx
+                .send(report_install_step(
+                    InstallPhase::Rollback,
+                    step_no,
+                    total,
+                    description.as_str(),
+                ))
+                .await?;
+            if let Some(failure) = self.injected(InstallPhase::Rollback, step_no) {
+                self.tx
+                    .send(report_install_error(&InstallationError::Simulated(
+                        failure.message.clone(),
+                    )))
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Digest of a simulated blob of an image, stable for an image reference
+fn simulated_digest(image: &str, blob: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(image.as_bytes());
+    hasher.update([0]);
+    hasher.update(blob.as_bytes());
+    format!("sha256:{:x}", hasher.finalize())
+}
+
+/// Remember the pools and the boot environment of an install, which is activated like
+/// `make_be_bootable` does
+fn record_install(pools: &[Pool], be_name: &str) {
+    let mut machine = MACHINE.lock().unwrap();
+    for pool in pools {
+        if !machine.pools.iter().any(|p| p.name == pool.name) {
+            machine.pools.push(pool.clone());
+        }
+    }
+    for be in machine.boot_environments.iter_mut() {
+        be.active_on_reboot = false;
+    }
+    machine.boot_environments.push(BootEnvironment {
+        name: be_name.to_string(),
+        active: false,
+        active_on_reboot: true,
+        mountpoint: "/a".to_string(),
+        space_bytes: 0,
+        created: chrono::Utc::now().timestamp(),
+    });
+}
+
+pub fn list_boot_environments() -> Result<Vec<BootEnvironment>, InstallationError> {
+    Ok(MACHINE.lock().unwrap().boot_environments.clone())
+}
+
+pub fn activate_boot_environment(name: &str) -> Result<BootEnvironment, InstallationError> {
+    let mut machine = MACHINE.lock().unwrap();
+    if !machine.boot_environments.iter().any(|be| be.name == name) {
+        return Err(InstallationError::BootEnvironmentNotFound(name.to_string()));
+    }
+    info!("Would activate boot environment {}", name);
+    for be in machine.boot_environments.iter_mut() {
+        be.active_on_reboot = be.name == name;
+    }
+    Ok(machine
+        .boot_environments
+        .iter()
+        .find(|be| be.name == name)
+        .cloned()
+        .unwrap_or_default())
+}
+
+pub fn export_pools() -> Result<Vec<String>, InstallationError> {
+    let mut machine = MACHINE.lock().unwrap();
+    let pools = std::mem::take(&mut machine.pools)
+        .into_iter()
+        .map(|pool| pool.name)
+        .collect::<Vec<_>>();
+    for pool in &pools {
+        info!("Would export pool {}", pool);
     }
+    for be in machine.boot_environments.iter_mut() {
+        be.mountpoint.clear();
+    }
+    Ok(pools)
+}
 
+/// Disks of the pools the simulated installs left imported, the mock installer runs from
+/// no disk of the fixture
+pub fn disks_in_use() -> Result<Vec<DiskInUse>, InstallationError> {
+    Ok(MACHINE
+        .lock()
+        .unwrap()
+        .pools
+        .iter()
+        .flat_map(|pool| {
+            pool.disks().map(|disk| DiskInUse {
+                device: disk_name(disk).to_string(),
+                used_by: format!("pool {}", pool.name),
+            })
+        })
+        .collect())
+}
+
+pub fn wipe_disk(disk: &DiskInfo, secure_erase: bool) -> Result<(), InstallationError> {
+    info!(
+        "Would clear the ZFS labels and partition tables of {}{}",
+        disk.device,
+        if secure_erase {
+            " and securely erase it"
+        } else {
+            ""
+        }
+    );
     Ok(())
 }
+
+/// Nothing goes down, the mock machine comes back up at once booted from the boot
+/// environment that was active on reboot
+pub fn power(action: PowerAction) -> Result<(), InstallationError> {
+    info!("Would {}", action);
+    let mut machine = MACHINE.lock().unwrap();
+    if action == PowerAction::Reboot {
+        for be in machine.boot_environments.iter_mut() {
+            be.active = be.active_on_reboot;
+            be.mountpoint = if be.active {
+                "/".to_string()
+            } else {
+                String::new()
+            };
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fixture::Failure;
+    use super::*;
+    use crate::machined::install_progress::Message;
+    use crate::machined::ProgressLevel;
+    use tokio::sync::mpsc;
+
+    const CONFIG: &str = r#"
+image = "oci://aopc.cloud/openindiana/hipster:2024.12"
+boot_environment_name = "mock-failure"
+
+[[pools]]
+name = "rpool"
+vdevs = [{ kind = "mirror", disks = ["c1t0d0", "c1t1d0"] }]
+volumes = [{ name = "swap", size = "2G", usage = "swap" }]
+
+[[sysconfig.interfaces]]
+name = "net0"
+selector = "mac:02:08:20:0a:0b:0c"
+addresses = [{ name = "v4", kind = "dhcp4" }]
+"#;
+
+    /// Run a simulated install and collect its progress as `level: message` lines, cancelling
+    /// it once it reports a step of `cancel_in`
+    async fn simulate(
+        mc: &MachineConfig,
+        fixture: &Fixture,
+        cancel_in: Option<InstallPhase>,
+    ) -> (bool, InstallReport, Vec<String>) {
+        // A single slot makes the install wait for every message to be looked at
+        let (tx, mut rx) = mpsc::channel(1);
+        let cancel = &CancellationToken::new();
+        let install = async move {
+            let mut report = InstallReport::default();
+            let succeeded = simulate_install(mc, fixture, &mut report, cancel, &tx)
+                .await
+                .is_ok();
+            (succeeded, report)
+        };
+        let progress = async {
+            let mut lines = Vec::new();
+            while let Some(Ok(progress)) = rx.recv().await {
+                if progress.step > 0 && Some(progress.phase()) == cancel_in {
+                    cancel.cancel();
+                }
+                let Some(Message::Info(msg) | Message::Error(msg)) = progress.message.clone()
+                else {
+                    continue;
+                };
+                match progress.level() {
+                    ProgressLevel::Debug => {}
+                    _ if progress.step > 0 => lines.push(format!("step: {}", msg)),
+                    level => lines.push(format!("{}: {}", level.as_str_name(), msg)),
+                }
+            }
+            lines
+        };
+        let ((succeeded, report), lines) = tokio::join!(install, progress);
+        (succeeded, report, lines)
+    }
+
+    #[tokio::test]
+    async fn injected_failure_rolls_back() {
+        let mc = machineconfig::parse_config("mock.toml", CONFIG).unwrap();
+        let mut fixture = fixture().as_ref().clone();
+        fixture.failures.push(Failure {
+            phase: InstallPhase::Unpack,
+            step: Some(2),
+            message: String::from("No space left on device"),
+            digest_mismatch: false,
+        });
+
+        let (succeeded, report, lines) = simulate(&mc, &fixture, None).await;
+        assert!(!succeeded);
+        assert_eq!(report.boot_environment, "mock-failure");
+        assert_eq!(report.layer_digests.len(), 2);
+        assert_eq!(report.finished_at, 0);
+        let failed = lines
+            .iter()
+            .position(|line| line.ends_with(": No space left on device"))
+            .unwrap();
+        assert!(lines[failed - 1].starts_with("step: unpacking layer sha256:"));
+        assert_eq!(
+            lines[failed + 1..],
+            [
+                "INFO: Installation failed, rolling back completed steps",
+                "step: Unmounting rpool/ROOT/mock-failure from /a",
+                "step: Destroying pool rpool",
+            ]
+        );
+
+        // The same configuration on a machine without the selected link fails in sysconfig
+        fixture.failures.clear();
+        fixture.links.clear();
+        let (succeeded, _, lines) = simulate(&mc, &fixture, None).await;
+        assert!(!succeeded);
+        assert!(lines.contains(&String::from(
+            "ERROR: no network link matches mac:02:08:20:0a:0b:0c"
+        )));
+    }
+
+    #[tokio::test]
+    async fn digest_mismatch_rolls_back_despite_skip_rollback() {
+        let mut mc = machineconfig::parse_config("mock.toml", CONFIG).unwrap();
+        mc.skip_rollback = true;
+        let mut fixture = fixture().as_ref().clone();
+        fixture.failures.push(Failure {
+            phase: InstallPhase::Unpack,
+            step: None,
+            message: String::from("layer does not match its digest"),
+            digest_mismatch: true,
+        });
+
+        let (succeeded, _, lines) = simulate(&mc, &fixture, None).await;
+        assert!(!succeeded);
+        let failed = lines
+            .iter()
+            .position(|line| line.ends_with(": layer does not match its digest"))
+            .unwrap();
+        assert_eq!(
+            lines[failed + 1..],
+            [
+                "WARNING: Ignoring skip-rollback, a layer did not match its digest after it was extracted",
+                "INFO: Installation failed, rolling back completed steps",
+                "step: Unmounting rpool/ROOT/mock-failure from /a",
+                "step: Destroying pool rpool",
+            ]
+        );
+
+        // Other failures leave the pools in place
+        fixture.failures[0].digest_mismatch = false;
+        let (succeeded, _, lines) = simulate(&mc, &fixture, None).await;
+        assert!(!succeeded);
+        assert_eq!(
+            lines.last().unwrap(),
+            "INFO: Installation failed, leaving pools and datasets in place"
+        );
+    }
+
+    #[tokio::test]
+    async fn cancelled_install_rolls_back() {
+        let mc = machineconfig::parse_config("mock.toml", CONFIG).unwrap();
+        let (succeeded, _, lines) = simulate(&mc, &fixture(), Some(InstallPhase::Unpack)).await;
+        assert!(!succeeded);
+        let unpack = lines
+            .iter()
+            .position(|line| line.starts_with("step: unpacking layer sha256:"))
+            .unwrap();
+        assert_eq!(
+            lines[unpack + 1..],
+            [
+                "INFO: Installation cancelled, rolling back completed steps",
+                "step: Unmounting rpool/ROOT/mock-failure from /a",
+                "step: Destroying pool rpool",
+            ]
+        );
+    }
+
+    #[test]
+    fn simulates_boot_environments() {
+        let rpool = Pool {
+            name: "rpool".to_string(),
+            ..Default::default()
+        };
+        record_install(std::slice::from_ref(&rpool), "mock-first");
+        record_install(&[rpool], "mock-second");
+        activate_boot_environment("mock-first").unwrap();
+        assert!(matches!(
+            activate_boot_environment("missing"),
+            Err(InstallationError::BootEnvironmentNotFound(_))
+        ));
+
+        assert_eq!(export_pools().unwrap(), vec!["rpool".to_string()]);
+        assert!(export_pools().unwrap().is_empty());
+
+        power(PowerAction::Reboot).unwrap();
+        let bes = list_boot_environments().unwrap();
+        let first = bes.iter().find(|be| be.name == "mock-first").unwrap();
+        let second = bes.iter().find(|be| be.name == "mock-second").unwrap();
+        assert!(first.active && first.active_on_reboot);
+        assert_eq!(first.mountpoint, "/");
+        assert!(!second.active && second.mountpoint.is_empty());
+    }
+}
@@ -1,15 +1,51 @@
-#[cfg(target_os = "illumos")]
+mod hardware;
+#[cfg(any(target_os = "illumos", target_os = "linux"))]
+#[cfg(not(feature = "mock"))]
+mod image;
+#[cfg(any(target_os = "illumos", target_os = "linux"))]
+#[cfg(not(feature = "mock"))]
+mod layer;
+#[cfg(any(target_os = "illumos", target_os = "linux"))]
+#[cfg(not(feature = "mock"))]
+mod layout;
+#[cfg(any(target_os = "illumos", target_os = "linux"))]
+#[cfg(not(feature = "mock"))]
+mod postinstall;
+
+#[cfg(all(target_os = "illumos", not(feature = "mock")))]
 mod illumos;
-#[cfg(not(target_os = "illumos"))]
+#[cfg(all(target_os = "linux", not(feature = "mock")))]
+mod linux;
+#[cfg(any(feature = "mock", not(any(target_os = "illumos", target_os = "linux"))))]
 mod mock;
 
-#[cfg(target_os = "illumos")]
+pub use hardware::discover_hardware;
+#[cfg(all(target_os = "illumos", not(feature = "mock")))]
 pub use illumos::*;
+#[cfg(all(target_os = "linux", not(feature = "mock")))]
+pub use linux::*;
 use miette::Diagnostic;
+use std::fmt::{Display, Formatter};
 use thiserror::Error;
 
-#[cfg(not(target_os = "illumos"))]
+#[cfg(any(feature = "mock", not(any(target_os = "illumos", target_os = "linux"))))]
 pub use mock::*;
 
 #[derive(Error, Debug, Diagnostic)]
 pub enum Error {}
+
+/// How the machine goes down
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PowerAction {
+    Reboot,
+    PowerOff,
+}
+
+impl Display for PowerAction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PowerAction::Reboot => write!(f, "reboot"),
+            PowerAction::PowerOff => write!(f, "power off"),
+        }
+    }
+}
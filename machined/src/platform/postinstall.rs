@@ -0,0 +1,405 @@
+use crate::error::InstallationError;
+use crate::machined::{InstallPhase, InstallProgress};
+use crate::platform::layer::follow_in_root;
+use crate::util::{
+    check_cancelled, report_install_debug, report_install_error, report_install_step,
+};
+use machineconfig::{PostInstall, PostInstallFile, Script, SmfManifest};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use tokio::sync::mpsc::error::SendError;
+use tokio::sync::mpsc::Sender;
+use tokio_util::sync::CancellationToken;
+use tonic::Status;
+
+/// Root of the installed system
+const TARGET_ROOT: &str = "/a";
+
+/// Directory `manifest-import` imports site specific manifests from on boot
+#[cfg(target_os = "illumos")]
+const SITE_MANIFEST_DIR: &str = "var/svc/manifest/site";
+
+#[cfg(target_os = "illumos")]
+const PKG_BIN: &str = "/usr/bin/pkg";
+
+/// Exit status of `pkg install` when all packages are installed already
+#[cfg(target_os = "illumos")]
+const PKG_EXIT_NOTHING_TO_DO: i32 = 4;
+
+const CHROOT_BIN: &str = "chroot";
+
+/// The parts of the postinstall section in the order they are applied
+enum Part<'a> {
+    Files(&'a [PostInstallFile]),
+    Packages(&'a [String]),
+    SmfManifests(&'a [SmfManifest]),
+    Scripts(&'a [Script]),
+}
+
+impl Part<'_> {
+    fn is_empty(&self) -> bool {
+        match self {
+            Part::Files(files) => files.is_empty(),
+            Part::Packages(packages) => packages.is_empty(),
+            Part::SmfManifests(manifests) => manifests.is_empty(),
+            Part::Scripts(scripts) => scripts.is_empty(),
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            Part::Files(_) => "Writing files",
+            Part::Packages(_) => "Installing packages",
+            Part::SmfManifests(_) => "Adding SMF manifests for first boot",
+            Part::Scripts(_) => "Running postinstall scripts",
+        }
+    }
+}
+
+/// Customise the system installed to /a: write files, install packages, add SMF manifests
+/// and run scripts, each configured part being one step of the postinstall phase
+pub async fn apply_postinstall(
+    postinstall: &PostInstall,
+    cancel: &CancellationToken,
+    tx: &Sender<Result<InstallProgress, Status>>,
+) -> Result<(), SendError<Result<InstallProgress, Status>>> {
+    let root = Path::new(TARGET_ROOT);
+    let parts = [
+        Part::Files(&postinstall.files),
+        Part::Packages(&postinstall.packages),
+        Part::SmfManifests(&postinstall.smf_manifests),
+        Part::Scripts(&postinstall.scripts),
+    ]
+    .into_iter()
+    .filter(|part| !part.is_empty())
+    .collect::<Vec<_>>();
+
+    let total_steps = parts.len() as u32;
+    for (idx, part) in parts.iter().enumerate() {
+        check_cancelled(cancel)?;
+        tx.send(report_install_step(
+            InstallPhase::Postinstall,
+            idx as u32 + 1,
+            total_steps,
+            part.description(),
+        ))
+        .await?;
+        let result = match part {
+            Part::Files(files) => write_files(root, files, tx).await,
+            Part::Packages(packages) => install_packages(root, packages, tx).await,
+            Part::SmfManifests(manifests) => add_smf_manifests(root, manifests, tx).await,
+            Part::Scripts(scripts) => run_scripts(root, scripts, tx).await,
+        };
+        if let Err(e) = result {
+            tx.send(report_install_error(&e)).await?;
+            return Err(SendError(Err(Status::internal("Internal error"))));
+        }
+    }
+    Ok(())
+}
+
+async fn write_files(
+    root: &Path,
+    files: &[PostInstallFile],
+    tx: &Sender<Result<InstallProgress, Status>>,
+) -> Result<(), InstallationError> {
+    for file in files {
+        write_file(root, file)?;
+        tx.send(report_install_debug(
+            format!("wrote {}", file.path).as_str(),
+        ))
+        .await
+        .map_err(|_e| InstallationError::SendFailed)?;
+    }
+    Ok(())
+}
+
+/// Write a file into the installed system, owners are looked up in its passwd and group files
+fn write_file(root: &Path, file: &PostInstallFile) -> Result<(), InstallationError> {
+    let path = target_path(root, &file.path)?;
+    let content = file.payload().read()?;
+    let mode = file.mode()?;
+    let uid = lookup_id(root, "etc/passwd", file.owner.as_deref())?;
+    let gid = lookup_id(root, "etc/group", file.group.as_deref())?;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, content)?;
+    std::os::unix::fs::chown(&path, Some(uid), Some(gid))?;
+    fs::set_permissions(&path, fs::Permissions::from_mode(mode))?;
+    Ok(())
+}
+
+/// Path below `root` of an absolute path in the installed system. Symlinks in the image are
+/// followed the way the installed system will, without leaving `root`.
+fn target_path(root: &Path, path: &str) -> Result<PathBuf, InstallationError> {
+    let relative = Path::new(path)
+        .strip_prefix("/")
+        .map_err(|_| InstallationError::InvalidPostinstallPath(path.to_string()))?;
+    if relative
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_)))
+        || relative.as_os_str().is_empty()
+    {
+        return Err(InstallationError::InvalidPostinstallPath(path.to_string()));
+    }
+    follow_in_root(root, relative)
+}
+
+/// Id of a user or group of the installed system, `root` if none is named. Numeric ids are
+/// taken as they are.
+fn lookup_id(root: &Path, database: &str, name: Option<&str>) -> Result<u32, InstallationError> {
+    let name = name.unwrap_or("root");
+    if let Ok(id) = name.parse() {
+        return Ok(id);
+    }
+    let entries = fs::read_to_string(follow_in_root(root, Path::new(database))?)?;
+    entries
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.len() > 2 && fields[0] == name)
+        .and_then(|fields| fields[2].parse().ok())
+        .ok_or_else(|| InstallationError::UnknownOwner(name.to_string()))
+}
+
+#[cfg(target_os = "illumos")]
+async fn install_packages(
+    root: &Path,
+    packages: &[String],
+    tx: &Sender<Result<InstallProgress, Status>>,
+) -> Result<(), InstallationError> {
+    tx.send(report_install_debug(
+        format!("pkg install {}", packages.join(" ")).as_str(),
+    ))
+    .await
+    .map_err(|_e| InstallationError::SendFailed)?;
+    let out = Command::new(PKG_BIN)
+        .arg("-R")
+        .arg(root)
+        .args(["install", "--accept"])
+        .args(packages)
+        .output()?;
+    if !out.status.success() && out.status.code() != Some(PKG_EXIT_NOTHING_TO_DO) {
+        return Err(InstallationError::PackageInstallFailed(
+            String::from_utf8_lossy(&out.stderr).to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "illumos"))]
+async fn install_packages(
+    _root: &Path,
+    _packages: &[String],
+    _tx: &Sender<Result<InstallProgress, Status>>,
+) -> Result<(), InstallationError> {
+    Err(InstallationError::PostinstallUnsupported(String::from(
+        "packages",
+    )))
+}
+
+/// Put SMF manifests where `manifest-import` imports them from when the installed system boots
+#[cfg(target_os = "illumos")]
+async fn add_smf_manifests(
+    root: &Path,
+    manifests: &[SmfManifest],
+    tx: &Sender<Result<InstallProgress, Status>>,
+) -> Result<(), InstallationError> {
+    let dir = follow_in_root(root, Path::new(SITE_MANIFEST_DIR))?;
+    fs::create_dir_all(&dir)?;
+    for manifest in manifests {
+        let file_name = manifest.file_name();
+        if file_name.contains('/') {
+            return Err(InstallationError::InvalidPostinstallPath(file_name));
+        }
+        let path = dir.join(&file_name);
+        fs::write(&path, manifest.payload().read()?)?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o444))?;
+        tx.send(report_install_debug(
+            format!("SMF manifest {} will be imported on first boot", file_name).as_str(),
+        ))
+        .await
+        .map_err(|_e| InstallationError::SendFailed)?;
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "illumos"))]
+async fn add_smf_manifests(
+    _root: &Path,
+    _manifests: &[SmfManifest],
+    _tx: &Sender<Result<InstallProgress, Status>>,
+) -> Result<(), InstallationError> {
+    Err(InstallationError::PostinstallUnsupported(String::from(
+        "SMF manifests",
+    )))
+}
+
+/// Run scripts chrooted into the installed system one after the other, their output is
+/// reported as debug messages
+async fn run_scripts(
+    root: &Path,
+    scripts: &[Script],
+    tx: &Sender<Result<InstallProgress, Status>>,
+) -> Result<(), InstallationError> {
+    for (idx, script) in scripts.iter().enumerate() {
+        // Scripts are named by their position, configured names need not be file names
+        let script_path = format!("/tmp/.postinstall-{}", idx);
+        let host_path = target_path(root, &script_path)?;
+        if let Some(parent) = host_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&host_path, script.payload().read()?)?;
+        fs::set_permissions(&host_path, fs::Permissions::from_mode(0o700))?;
+
+        tx.send(report_install_debug(
+            format!("running script {}", script.name).as_str(),
+        ))
+        .await
+        .map_err(|_e| InstallationError::SendFailed)?;
+        let out = Command::new(CHROOT_BIN)
+            .arg(root)
+            .arg(script.interpreter())
+            .arg(&script_path)
+            .output();
+        let _ = fs::remove_file(&host_path);
+        let out = out?;
+
+        for line in String::from_utf8_lossy(&out.stdout).lines() {
+            tx.send(report_install_debug(
+                format!("{}: {}", script.name, line).as_str(),
+            ))
+            .await
+            .map_err(|_e| InstallationError::SendFailed)?;
+        }
+        if !out.status.success() {
+            return Err(InstallationError::PostinstallScriptFailed(
+                script.name.clone(),
+                String::from_utf8_lossy(&out.stderr).trim().to_string(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestRoot {
+        path: PathBuf,
+    }
+
+    impl TestRoot {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "machined-postinstall-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(path.join("etc")).unwrap();
+            fs::write(
+                path.join("etc/passwd"),
+                "root:x:0:0:Super-User:/root:/usr/bin/bash\nwebservd:x:80:80:WebServer:/:\n",
+            )
+            .unwrap();
+            fs::write(path.join("etc/group"), "root::0:\nsys::3:root\n").unwrap();
+            TestRoot { path }
+        }
+    }
+
+    impl Drop for TestRoot {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+
+    #[test]
+    fn resolves_paths_and_owners_in_the_target() {
+        let root = TestRoot::new("owners");
+        assert_eq!(
+            target_path(&root.path, "/etc/motd").unwrap(),
+            root.path.join("etc/motd")
+        );
+        for path in ["etc/motd", "/", "/etc/../../motd"] {
+            assert!(matches!(
+                target_path(&root.path, path),
+                Err(InstallationError::InvalidPostinstallPath(_))
+            ));
+        }
+
+        assert_eq!(lookup_id(&root.path, "etc/passwd", None).unwrap(), 0);
+        assert_eq!(
+            lookup_id(&root.path, "etc/passwd", Some("webservd")).unwrap(),
+            80
+        );
+        assert_eq!(lookup_id(&root.path, "etc/group", Some("sys")).unwrap(), 3);
+        assert_eq!(
+            lookup_id(&root.path, "etc/group", Some("101")).unwrap(),
+            101
+        );
+        assert!(matches!(
+            lookup_id(&root.path, "etc/group", Some("staff")),
+            Err(InstallationError::UnknownOwner(_))
+        ));
+    }
+
+    #[test]
+    fn writes_files_with_their_mode() {
+        let root = TestRoot::new("files");
+        // Owned by whoever runs the tests so it works without privileges
+        let uid = unsafe { libc::getuid() }.to_string();
+        let gid = unsafe { libc::getgid() }.to_string();
+        let file = PostInstallFile {
+            path: String::from("/etc/ssh/sshd_config.d/10-site.conf"),
+            mode: Some(String::from("0600")),
+            owner: Some(uid),
+            group: Some(gid),
+            content: Some(String::from("PermitRootLogin no\n")),
+            ..Default::default()
+        };
+        write_file(&root.path, &file).unwrap();
+        let path = root.path.join("etc/ssh/sshd_config.d/10-site.conf");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "PermitRootLogin no\n");
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o7777,
+            0o600
+        );
+    }
+
+    #[test]
+    fn writes_files_through_symlinks_inside_the_target() {
+        let root = TestRoot::new("symlinks");
+        let host = TestRoot::new("symlinks-host");
+        std::os::unix::fs::symlink(host.path.join("etc"), root.path.join("etc/ssh")).unwrap();
+        std::os::unix::fs::symlink("/etc/motd.d/site", root.path.join("etc/motd")).unwrap();
+        let uid = unsafe { libc::getuid() }.to_string();
+        let gid = unsafe { libc::getgid() }.to_string();
+        for path in ["/etc/ssh/sshd_config", "/etc/motd"] {
+            let file = PostInstallFile {
+                path: String::from(path),
+                owner: Some(uid.clone()),
+                group: Some(gid.clone()),
+                content: Some(String::from("site\n")),
+                ..Default::default()
+            };
+            write_file(&root.path, &file).unwrap();
+        }
+
+        // The absolute symlink points into the target like it will on the installed system
+        assert!(!host.path.join("etc/sshd_config").exists());
+        let written = root.path.join(host.path.strip_prefix("/").unwrap());
+        assert_eq!(
+            fs::read_to_string(written.join("etc/sshd_config")).unwrap(),
+            "site\n"
+        );
+        assert_eq!(
+            fs::read_to_string(root.path.join("etc/motd.d/site")).unwrap(),
+            "site\n"
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use crate::error::InstallationError;
+use crate::machined::{FirmwareMode, InstallReport, InstallSession, SystemInfoRequest};
+use crate::sysinfo;
+use jwt_simple::reexports::serde_json;
+use machineconfig::{ConfigFormat, MachineConfig};
+use serde::Serializer;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// Where the install report is written inside the installed system
+pub const REPORT_PATH: &str = "var/log/machined/install-report.json";
+
+/// Start the report of the install running in `session`: who started it, with which
+/// configuration and on which hardware. The platform adds the image and the boot environment
+/// as the install gets to them.
+pub fn start_report(session: &InstallSession, operator: &str, mc: &MachineConfig) -> InstallReport {
+    let machineconfig = mc
+        .redacted()
+        .to_string_as(ConfigFormat::Json)
+        .unwrap_or_else(|e| {
+            warn!(
+                "Failed to record the machine configuration in the install report: {}",
+                e
+            );
+            String::new()
+        });
+    let hardware = sysinfo::get_system_info(&SystemInfoRequest::default())
+        .inspect_err(|status| {
+            warn!(
+                "Failed to record the hardware in the install report: {}",
+                status.message()
+            )
+        })
+        .ok();
+    InstallReport {
+        session_id: session.id.clone(),
+        session_name: session.name.clone(),
+        operator: operator.to_string(),
+        machined_version: env!("CARGO_PKG_VERSION").to_string(),
+        image: mc.image.clone(),
+        machineconfig,
+        hardware,
+        started_at: session.started_at,
+        ..Default::default()
+    }
+}
+
+/// Finish the report and write it as JSON into the system mounted at `root`
+#[cfg_attr(feature = "mock", allow(dead_code))]
+pub fn write_report(report: &mut InstallReport, root: &Path) -> Result<PathBuf, InstallationError> {
+    report.finished_at = chrono::Utc::now().timestamp();
+
+    let mut json = serde_json::to_value(&*report)?;
+    // Keep the configuration readable instead of a JSON document escaped into a string
+    if let Ok(mc) = serde_json::from_str::<serde_json::Value>(&report.machineconfig) {
+        json["machineconfig"] = mc;
+    }
+
+    let path = root.join(REPORT_PATH);
+    let write = |path: &Path| {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_vec_pretty(&json)?)
+    };
+    write(&path).map_err(|e| InstallationError::WriteReport(path.display().to_string(), e))?;
+    Ok(path)
+}
+
+/// Write the firmware mode of the hardware by its name, numbers that are no known mode as
+/// they are
+pub fn serialize_firmware_mode<S: Serializer>(
+    mode: &i32,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match FirmwareMode::try_from(*mode) {
+        Ok(mode) => serializer.serialize_str(mode.as_str_name()),
+        Err(_) => serializer.serialize_i32(*mode),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machined::{CpuTopology, FirmwareInfo, SystemInfoResponse};
+
+    #[test]
+    fn writes_the_report_with_a_readable_redacted_config() {
+        let mc: MachineConfig = machineconfig::parse_config(
+            "machine.toml",
+            r#"
+image = "oci://aopc.cloud/openindiana/hipster:2024.12"
+
+[[postinstall.files]]
+path = "/root/.ssh/authorized_keys"
+content = "ssh-ed25519 AAAA operator@example"
+"#,
+        )
+        .unwrap();
+        let session = InstallSession {
+            id: "5d0c8a3e-8e0b-4c0c-a1f6-0c6d0a1b2c3d".to_string(),
+            name: "rack1-node01".to_string(),
+            started_at: 1_733_047_200,
+            ..Default::default()
+        };
+        let mut report = start_report(&session, "alice", &mc);
+        report.manifest_digest = format!("sha256:{}", "ab".repeat(32));
+        report.hardware = Some(SystemInfoResponse {
+            cpu: Some(CpuTopology {
+                model: "Xeon".to_string(),
+                sockets: 1,
+                ..Default::default()
+            }),
+            memory_bytes: 32 << 30,
+            firmware: Some(FirmwareInfo {
+                mode: FirmwareMode::Uefi.into(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+
+        let root = std::env::temp_dir().join(format!("machined-report-{}", uuid::Uuid::new_v4()));
+        let path = write_report(&mut report, &root).unwrap();
+        assert_eq!(path, root.join("var/log/machined/install-report.json"));
+        assert!(report.finished_at >= report.started_at);
+
+        let written: serde_json::Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(written["operator"], "alice");
+        assert_eq!(written["session_name"], "rack1-node01");
+        assert_eq!(written["machined_version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(written["manifest_digest"], report.manifest_digest);
+        assert_eq!(written["hardware"]["memory_bytes"], 32u64 << 30);
+        assert_eq!(written["hardware"]["cpu"]["model"], "Xeon");
+        assert_eq!(
+            written["hardware"]["firmware"]["mode"],
+            "FIRMWARE_MODE_UEFI"
+        );
+        assert_eq!(
+            written["machineconfig"]["postinstall"]["files"][0]["content"],
+            "<redacted, 33 bytes>"
+        );
+        assert!(!fs::read_to_string(&path).unwrap().contains("AAAA"));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,571 @@
+use crate::config::MachinedConfig;
+use crate::machined::install_progress::Message;
+use crate::machined::{
+    InstallProgress, InstallReport, InstallSession, InstallSessionState, ProgressLevel,
+};
+use crate::platform;
+use crate::report::start_report;
+use crate::util::report_install_warning;
+use machineconfig::MachineConfig;
+use prost::Message as _;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::runtime::Handle;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, mpsc};
+use tokio::task;
+use tokio_util::sync::CancellationToken;
+use tonic::Status;
+use tracing::{info, warn};
+
+const SESSION_FILE_EXT: &str = "session";
+const EVENTS_FILE_EXT: &str = "events";
+const REPORT_FILE_EXT: &str = "report";
+const EVENT_BUFFER_SIZE: usize = 256;
+
+/// All install sessions known to machined.
+///
+/// Every install runs as a session which owns the install task and records its progress to
+/// disk. Clients only follow a session, so a client disconnecting no longer stops the install
+/// and can later reattach and replay what it missed.
+#[derive(Debug)]
+pub struct InstallSessions {
+    dir: PathBuf,
+    /// Number of finished sessions kept on disk
+    retention: usize,
+    sessions: Mutex<Vec<Arc<Session>>>,
+}
+
+impl InstallSessions {
+    /// Open the session directory and load the sessions recorded in it.
+    ///
+    /// Sessions that were still running when machined stopped are marked as interrupted. Only
+    /// the newest `retention` finished sessions are kept.
+    pub fn open<P: AsRef<Path>>(dir: P, retention: usize) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let mut sessions = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(SESSION_FILE_EXT) {
+                continue;
+            }
+            match Session::load(&dir, &path) {
+                Ok(session) => sessions.push(Arc::new(session)),
+                Err(e) => warn!("Failed to load install session {}: {}", path.display(), e),
+            }
+        }
+        sessions.sort_by_key(|session| session.info().started_at);
+        prune(&mut sessions, retention);
+
+        Ok(Self {
+            dir,
+            retention,
+            sessions: Mutex::new(sessions),
+        })
+    }
+
+    pub fn list(&self) -> Vec<InstallSession> {
+        let sessions = self.sessions.lock().unwrap();
+        sessions.iter().map(|session| session.info()).collect()
+    }
+
+    /// Find a session by its id or name.
+    pub fn get(&self, session: &str) -> Option<Arc<Session>> {
+        let sessions = self.sessions.lock().unwrap();
+        sessions
+            .iter()
+            .find(|s| s.info().id == session)
+            .or_else(|| sessions.iter().rev().find(|s| s.info().name == session))
+            .cloned()
+    }
+
+    /// Report of the last install that succeeded
+    pub fn last_report(&self) -> Option<InstallReport> {
+        let sessions = self.sessions.lock().unwrap();
+        sessions.iter().rev().find_map(|session| session.report())
+    }
+
+    /// The install session that is still running, if any
+    pub fn running(&self) -> Option<Arc<Session>> {
+        let sessions = self.sessions.lock().unwrap();
+        sessions.iter().find(|s| s.is_running()).cloned()
+    }
+
+    /// Start a new install session running the installation for `mc` on behalf of `operator`.
+    ///
+    /// Only one install can run at a time as all of them install into /a.
+    pub fn start(
+        &self,
+        name: String,
+        operator: &str,
+        mc: MachineConfig,
+        config: Arc<MachinedConfig>,
+    ) -> Result<Arc<Session>, Status> {
+        let mut sessions = self.sessions.lock().unwrap();
+        if let Some(running) = sessions.iter().find(|s| s.is_running()) {
+            return Err(Status::failed_precondition(format!(
+                "install session {} is still running",
+                running.info().name
+            )));
+        }
+
+        let now = chrono::Utc::now();
+        let name = if name.is_empty() {
+            format!("install-{}", now.format("%Y%m%d-%H%M%S"))
+        } else {
+            name
+        };
+        if sessions.iter().any(|s| s.info().name == name) {
+            return Err(Status::already_exists(format!(
+                "install session {} already exists",
+                name
+            )));
+        }
+
+        let info = InstallSession {
+            id: uuid::Uuid::new_v4().as_hyphenated().to_string(),
+            name,
+            state: InstallSessionState::Running.into(),
+            started_at: now.timestamp(),
+            finished_at: 0,
+            events: 0,
+        };
+        prune(&mut sessions, self.retention);
+        info!("Starting install session {} ({})", info.name, info.id);
+        let session = Arc::new(Session::new(&self.dir, info, Vec::new(), true));
+        session.persist_info();
+        session.run(mc, operator, config);
+        sessions.push(session.clone());
+        Ok(session)
+    }
+}
+
+#[derive(Debug)]
+pub struct Session {
+    log: Mutex<SessionLog>,
+    cancel: CancellationToken,
+}
+
+#[derive(Debug)]
+struct SessionLog {
+    info: InstallSession,
+    history: Vec<InstallProgress>,
+    events: Option<broadcast::Sender<InstallProgress>>,
+    /// What the install put on the machine, once it succeeded
+    report: Option<InstallReport>,
+    session_file: PathBuf,
+    events_file: PathBuf,
+    report_file: PathBuf,
+}
+
+impl Session {
+    fn new(dir: &Path, info: InstallSession, history: Vec<InstallProgress>, live: bool) -> Self {
+        let session_file = dir.join(format!("{}.{}", info.id, SESSION_FILE_EXT));
+        let events_file = dir.join(format!("{}.{}", info.id, EVENTS_FILE_EXT));
+        let report_file = dir.join(format!("{}.{}", info.id, REPORT_FILE_EXT));
+        let events = if live {
+            Some(broadcast::channel(EVENT_BUFFER_SIZE).0)
+        } else {
+            None
+        };
+        Self {
+            log: Mutex::new(SessionLog {
+                info,
+                history,
+                events,
+                report: None,
+                session_file,
+                events_file,
+                report_file,
+            }),
+            cancel: CancellationToken::new(),
+        }
+    }
+
+    fn load(dir: &Path, session_file: &Path) -> io::Result<Self> {
+        let mut info =
+            InstallSession::decode(fs::read(session_file)?.as_slice()).map_err(invalid_data)?;
+
+        let mut history = Vec::new();
+        let events_file = dir.join(format!("{}.{}", info.id, EVENTS_FILE_EXT));
+        if events_file.exists() {
+            let data = fs::read(&events_file)?;
+            let mut buf = data.as_slice();
+            while !buf.is_empty() {
+                history.push(
+                    InstallProgress::decode_length_delimited(&mut buf).map_err(invalid_data)?,
+                );
+            }
+        }
+        info.events = history.len() as u64;
+
+        let report_file = dir.join(format!("{}.{}", info.id, REPORT_FILE_EXT));
+        let report = if report_file.exists() {
+            Some(InstallReport::decode(fs::read(&report_file)?.as_slice()).map_err(invalid_data)?)
+        } else {
+            None
+        };
+
+        let interrupted = info.state() == InstallSessionState::Running;
+        if interrupted {
+            info.set_state(InstallSessionState::Interrupted);
+            info.finished_at = chrono::Utc::now().timestamp();
+        }
+        let session = Self::new(dir, info, history, false);
+        session.log.lock().unwrap().report = report;
+        if interrupted {
+            session.persist_info();
+        }
+        Ok(session)
+    }
+
+    pub fn info(&self) -> InstallSession {
+        self.log.lock().unwrap().info.clone()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.log.lock().unwrap().info.state() == InstallSessionState::Running
+    }
+
+    /// Report of the install, only sessions that succeeded have one
+    pub fn report(&self) -> Option<InstallReport> {
+        self.log.lock().unwrap().report.clone()
+    }
+
+    fn run(self: &Arc<Self>, mc: MachineConfig, operator: &str, config: Arc<MachinedConfig>) {
+        let (tx, mut rx) = mpsc::channel(100);
+        let info = self.info();
+        let operator = operator.to_string();
+        let cancel = self.cancel.clone();
+        // The install runs zpool, zfs, pkg and scripts synchronously. It gets a blocking thread
+        // of its own so the RPCs, cancelling it among them, are still served meanwhile.
+        let runtime = Handle::current();
+        let install = task::spawn_blocking(move || {
+            runtime.block_on(async move {
+                let mut report = start_report(&info, &operator, &mc);
+                platform::install_system(&mc, config, &mut report, &cancel, tx)
+                    .await
+                    .map(|()| report)
+            })
+        });
+
+        let session = self.clone();
+        tokio::spawn(async move {
+            // The channel closes once the install task has finished
+            while let Some(progress) = rx.recv().await {
+                session.record(progress);
+            }
+            let state = match install.await {
+                Ok(Ok(report)) => {
+                    session.keep_report(report);
+                    InstallSessionState::Succeeded
+                }
+                // The install has rolled back and reported the cancellation itself
+                Ok(Err(_)) if session.cancel.is_cancelled() => InstallSessionState::Cancelled,
+                Ok(Err(e)) => {
+                    if let Err(status) = e.0 {
+                        session.record(Err(status));
+                    }
+                    InstallSessionState::Failed
+                }
+                Err(e) => {
+                    session.record(Err(Status::internal(e.to_string())));
+                    InstallSessionState::Failed
+                }
+            };
+            session.finish(state);
+        });
+    }
+
+    fn record(&self, progress: Result<InstallProgress, Status>) {
+        let mut log = self.log.lock().unwrap();
+        let mut progress = progress.unwrap_or_else(|status| InstallProgress {
+            level: ProgressLevel::Error.into(),
+            message: Some(Message::Error(status.message().to_string())),
+            ..Default::default()
+        });
+        progress.session_id = log.info.id.clone();
+
+        if let Err(e) = append_event(&log.events_file, &progress) {
+            warn!("Failed to persist install progress: {}", e);
+        }
+        if let Some(events) = &log.events {
+            // Nobody following the session is fine, the history keeps the message
+            let _ = events.send(progress.clone());
+        }
+        log.info.events += 1;
+        log.history.push(progress);
+    }
+
+    fn finish(&self, state: InstallSessionState) {
+        {
+            let mut log = self.log.lock().unwrap();
+            info!(
+                "Install session {} finished: {}",
+                log.info.name,
+                state.as_str_name()
+            );
+            log.info.set_state(state);
+            log.info.finished_at = chrono::Utc::now().timestamp();
+            // Dropping the sender ends the streams of all attached clients
+            log.events = None;
+        }
+        self.persist_info();
+    }
+
+    fn keep_report(&self, report: InstallReport) {
+        let mut log = self.log.lock().unwrap();
+        if let Err(e) = fs::write(&log.report_file, report.encode_to_vec()) {
+            warn!(
+                "Failed to persist install report of {}: {}",
+                log.info.name, e
+            );
+        }
+        log.report = Some(report);
+    }
+
+    /// Cancel the install running in this session.
+    ///
+    /// The install stops before its next step and rolls back the steps it completed, the
+    /// session is cancelled once the rollback is done.
+    pub fn cancel(&self) -> Result<(), Status> {
+        if !self.is_running() {
+            return Err(Status::failed_precondition(
+                "install session is not running",
+            ));
+        }
+        self.cancel.cancel();
+        Ok(())
+    }
+
+    /// Follow the session: replays the recorded history and then streams new progress
+    /// messages until the install finishes.
+    ///
+    /// The install is not affected when the returned receiver is dropped.
+    pub fn follow(&self) -> mpsc::Receiver<Result<InstallProgress, Status>> {
+        let (history, events) = {
+            let log = self.log.lock().unwrap();
+            (
+                log.history.clone(),
+                log.events.as_ref().map(|events| events.subscribe()),
+            )
+        };
+
+        let (tx, rx) = mpsc::channel(100);
+        tokio::spawn(async move {
+            for progress in history {
+                if tx.send(Ok(progress)).await.is_err() {
+                    return;
+                }
+            }
+            let Some(mut events) = events else {
+                return;
+            };
+            loop {
+                let progress = match events.recv().await {
+                    Ok(progress) => Ok(progress),
+                    Err(RecvError::Lagged(skipped)) => report_install_warning(
+                        format!(
+                            "skipped {} progress messages, reattach to see the full history",
+                            skipped
+                        )
+                        .as_str(),
+                    ),
+                    Err(RecvError::Closed) => return,
+                };
+                if tx.send(progress).await.is_err() {
+                    return;
+                }
+            }
+        });
+        rx
+    }
+
+    /// Delete the files of a finished session
+    fn remove(&self) {
+        let log = self.log.lock().unwrap();
+        info!("Removing install session {}", log.info.name);
+        for file in [&log.session_file, &log.events_file, &log.report_file] {
+            match fs::remove_file(file) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                    warn!("Failed to remove {}: {}", file.display(), e)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn persist_info(&self) {
+        let log = self.log.lock().unwrap();
+        if let Err(e) = fs::write(&log.session_file, log.info.encode_to_vec()) {
+            warn!("Failed to persist install session {}: {}", log.info.name, e);
+        }
+    }
+}
+
+/// Remove the oldest finished sessions beyond `retention`. The session of the last install
+/// that succeeded is kept regardless, its report is still served.
+fn prune(sessions: &mut Vec<Arc<Session>>, retention: usize) {
+    let last_report = sessions.iter().rposition(|s| s.report().is_some());
+    let mut excess = sessions
+        .iter()
+        .filter(|s| !s.is_running())
+        .count()
+        .saturating_sub(retention);
+    let mut index = 0;
+    sessions.retain(|session| {
+        let expired = excess > 0 && !session.is_running() && Some(index) != last_report;
+        index += 1;
+        if expired {
+            excess -= 1;
+            session.remove();
+        }
+        !expired
+    });
+}
+
+fn invalid_data(e: prost::DecodeError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+fn append_event(path: &Path, progress: &InstallProgress) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(&progress.encode_length_delimited_to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("machined-sessions-{}", uuid::Uuid::new_v4()))
+    }
+
+    #[tokio::test]
+    async fn reloads_history_and_marks_running_sessions_interrupted() {
+        let dir = session_dir();
+        let info = InstallSession {
+            id: "0b6c3a1e-5f0d-4a43-9d59-4c1f2f6d1a10".to_string(),
+            name: "rack1-node01".to_string(),
+            state: InstallSessionState::Running.into(),
+            started_at: 1_700_000_000,
+            ..Default::default()
+        };
+        {
+            fs::create_dir_all(&dir).unwrap();
+            let session = Session::new(&dir, info.clone(), Vec::new(), true);
+            session.persist_info();
+            session.record(crate::util::report_install_info("creating pool rpool"));
+            session.record(Err(Status::internal("zpool create failed")));
+            session.keep_report(InstallReport {
+                session_id: info.id.clone(),
+                operator: "alice".to_string(),
+                ..Default::default()
+            });
+        }
+
+        let sessions = InstallSessions::open(&dir, 20).unwrap();
+        let listed = sessions.list();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].state(), InstallSessionState::Interrupted);
+        assert_eq!(listed[0].events, 2);
+        assert_ne!(listed[0].finished_at, 0);
+        assert_eq!(sessions.last_report().unwrap().operator, "alice");
+
+        let session = sessions.get("rack1-node01").unwrap();
+        assert!(!session.is_running());
+        assert!(session.cancel().is_err());
+
+        let mut replay = session.follow();
+        let first = replay.recv().await.unwrap().unwrap();
+        assert_eq!(first.session_id, info.id);
+        assert_eq!(
+            first.message,
+            Some(Message::Info("creating pool rpool".to_string()))
+        );
+        let second = replay.recv().await.unwrap().unwrap();
+        assert_eq!(second.level(), ProgressLevel::Error);
+        assert!(replay.recv().await.is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn removes_oldest_finished_sessions() {
+        let dir = session_dir();
+        fs::create_dir_all(&dir).unwrap();
+        let mut ids = Vec::new();
+        for n in 0..5 {
+            let info = InstallSession {
+                id: uuid::Uuid::new_v4().to_string(),
+                name: format!("install-{}", n),
+                state: InstallSessionState::Failed.into(),
+                started_at: 1_700_000_000 + n,
+                ..Default::default()
+            };
+            let session = Session::new(&dir, info.clone(), Vec::new(), false);
+            session.persist_info();
+            session.record(crate::util::report_install_info("creating pool rpool"));
+            if n == 1 {
+                session.keep_report(InstallReport {
+                    session_id: info.id.clone(),
+                    ..Default::default()
+                });
+            }
+            ids.push(info.id);
+        }
+
+        let sessions = InstallSessions::open(&dir, 2).unwrap();
+        let names = sessions
+            .list()
+            .into_iter()
+            .map(|session| session.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["install-1", "install-4"]);
+        assert_eq!(sessions.last_report().unwrap().session_id, ids[1]);
+        for id in [&ids[0], &ids[2], &ids[3]] {
+            for ext in [SESSION_FILE_EXT, EVENTS_FILE_EXT] {
+                assert!(!dir.join(format!("{}.{}", id, ext)).exists());
+            }
+        }
+        assert!(dir.join(format!("{}.{}", ids[4], EVENTS_FILE_EXT)).exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn attached_clients_get_history_then_live_progress() {
+        let dir = session_dir();
+        fs::create_dir_all(&dir).unwrap();
+        let info = InstallSession {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: "live".to_string(),
+            ..Default::default()
+        };
+        let session = Session::new(&dir, info, Vec::new(), true);
+        session.record(crate::util::report_install_info("before attach"));
+
+        let mut follower = session.follow();
+        session.record(crate::util::report_install_info("after attach"));
+        session.finish(InstallSessionState::Succeeded);
+
+        let mut messages = Vec::new();
+        while let Some(progress) = follower.recv().await {
+            messages.push(progress.unwrap().message);
+        }
+        assert_eq!(
+            messages,
+            vec![
+                Some(Message::Info("before attach".to_string())),
+                Some(Message::Info("after attach".to_string())),
+            ]
+        );
+        assert_eq!(session.info().state(), InstallSessionState::Succeeded);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,638 @@
+use super::{cpu_topology, parse_hex, smbios, LogicalCpu};
+use crate::machined::{
+    CpuTopology, DiskInfo, FirmwareInfo, FirmwareMode, NetworkInterface, PartitionInfo, PciDevice,
+    SmbiosInfo,
+};
+use std::collections::BTreeMap;
+use std::fs;
+use std::process::Command;
+use std::str::FromStr;
+use tonic::Status;
+use tracing::{debug, error};
+
+/// SMBIOS entry point followed by the structure table
+const SMBIOS_DEV: &str = "/dev/smbios";
+const PCIEADM_BIN: &str = "/usr/lib/pci/pcieadm";
+const KSTAT_BIN: &str = "/usr/bin/kstat";
+const PRTCONF_BIN: &str = "/usr/sbin/prtconf";
+
+/// Execute the diskinfo command and parse its output
+pub fn get_disk_info() -> Result<Vec<DiskInfo>, Status> {
+    debug!("Executing diskinfo command");
+
+    // Run basic diskinfo command to get general disk information
+    let basic_output = Command::new("/usr/bin/diskinfo").output().map_err(|e| {
+        error!("Failed to execute diskinfo command: {}", e);
+        Status::internal(format!("Failed to execute diskinfo command: {}", e))
+    })?;
+
+    if !basic_output.status.success() {
+        let error_msg = String::from_utf8_lossy(&basic_output.stderr);
+        error!("diskinfo command failed: {}", error_msg);
+        return Err(Status::internal(format!(
+            "diskinfo command failed: {}",
+            error_msg
+        )));
+    }
+
+    // Run diskinfo -P command to get chassis and bay information
+    debug!("Executing diskinfo -P command");
+    let location_output = Command::new("/usr/bin/diskinfo")
+        .arg("-P")
+        .output()
+        .map_err(|e| {
+            error!("Failed to execute diskinfo -P command: {}", e);
+            Status::internal(format!("Failed to execute diskinfo -P command: {}", e))
+        })?;
+
+    if !location_output.status.success() {
+        let error_msg = String::from_utf8_lossy(&location_output.stderr);
+        error!("diskinfo -P command failed: {}", error_msg);
+        return Err(Status::internal(format!(
+            "diskinfo -P command failed: {}",
+            error_msg
+        )));
+    }
+
+    // Parse both outputs and merge the results
+    let basic_output_str = String::from_utf8_lossy(&basic_output.stdout);
+    let location_output_str = String::from_utf8_lossy(&location_output.stdout);
+
+    let basic_disks = parse_diskinfo_basic(&basic_output_str)?;
+    let location_info = parse_diskinfo_location(&location_output_str)?;
+
+    // Merge the information
+    merge_disk_info(basic_disks, location_info)
+}
+
+/// Parse the output of the basic diskinfo command
+fn parse_diskinfo_basic(output: &str) -> Result<Vec<DiskInfo>, Status> {
+    let mut disks = Vec::new();
+    let mut lines = output.lines();
+
+    // Skip the header line
+    if let Some(_header) = lines.next() {
+        for line in lines {
+            // Skip empty lines
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 6 {
+                error!("Invalid diskinfo output line: {}", line);
+                continue;
+            }
+
+            let device = fields[1].to_string(); // Skip the TYPE field
+            let vendor = fields[2].to_string();
+            let product = fields[3].to_string();
+            let size_str = fields[4].to_string();
+            let removable_str = fields[5].to_string();
+            let solid_state_str = fields[6].to_string();
+
+            // Parse size (convert to bytes)
+            let size_bytes = parse_size(&size_str).unwrap_or(0);
+
+            // Parse removable flag
+            let removable = removable_str.eq_ignore_ascii_case("yes");
+
+            // Parse solid state flag
+            let solid_state = solid_state_str.eq_ignore_ascii_case("yes");
+
+            // Get all paths to this disk
+            let paths = get_disk_paths(&device).unwrap_or_else(|_| vec![]);
+
+            let disk_info = DiskInfo {
+                device,
+                vendor,
+                product,
+                serial: String::new(), // Will be filled in from location info
+                size_bytes,
+                removable,
+                solid_state,
+                paths,
+                fault_status: String::new(), // Will be filled in from location info
+                location_code: String::new(), // Will be filled in from location info
+                chassis_bay: String::new(),  // Will be filled in from location info
+            };
+
+            disks.push(disk_info);
+        }
+    }
+
+    Ok(disks)
+}
+
+/// Parse the output of the diskinfo -P command to get location information
+fn parse_diskinfo_location(
+    output: &str,
+) -> Result<std::collections::HashMap<String, (String, String, String, String)>, Status> {
+    let mut location_info = std::collections::HashMap::new();
+    let mut lines = output.lines();
+
+    // Skip the header line
+    if let Some(_header) = lines.next() {
+        for line in lines {
+            // Skip empty lines
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            // Based on the examples, diskinfo -P output has the following format:
+            // DISK                    VID      PID              SERIAL               FLT LOC LOCATION
+            // c1t0d0                  Virtio   Block Device     BHYVE-0A7E-C570-228F -   -   -
+            // or
+            // c1t0d0                  Virtio   Block Device     BHYVE-0A7E-C570-228F -   -   -1,-1
+
+            // Split by whitespace, but be careful with the SERIAL field which might contain spaces
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() < 4 {
+                error!("Invalid diskinfo -P output line: {}", line);
+                continue;
+            }
+
+            let device = parts[0].to_string();
+            let vendor = parts[1].to_string();
+
+            // The product might contain spaces, so we need to be careful
+            // We know that the serial number is the 4th field, so we'll work backwards
+            let mut product_parts = Vec::new();
+            let mut i = 2;
+            while i < parts.len() - 3 {
+                product_parts.push(parts[i]);
+                i += 1;
+            }
+            let product = product_parts.join(" ");
+
+            let serial = parts[i].to_string();
+            let fault_status = parts[i + 1].to_string();
+            let location_code = parts[i + 2].to_string();
+
+            // The LOCATION field might be missing or might be the last field
+            let chassis_bay = if i + 3 < parts.len() {
+                parts[i + 3].to_string()
+            } else {
+                "-".to_string()
+            };
+
+            debug!(
+                "Parsed location info for {}: serial={}, fault={}, loc={}, chassis_bay={}",
+                device, serial, fault_status, location_code, chassis_bay
+            );
+
+            // Store the location information for this device
+            location_info.insert(device, (serial, fault_status, location_code, chassis_bay));
+        }
+    }
+
+    Ok(location_info)
+}
+
+/// Merge the basic disk information with the location information
+fn merge_disk_info(
+    mut basic_disks: Vec<DiskInfo>,
+    location_info: std::collections::HashMap<String, (String, String, String, String)>,
+) -> Result<Vec<DiskInfo>, Status> {
+    for disk in &mut basic_disks {
+        if let Some((serial, fault_status, location_code, chassis_bay)) =
+            location_info.get(&disk.device)
+        {
+            disk.serial = serial.clone();
+            disk.fault_status = fault_status.clone();
+            disk.location_code = location_code.clone();
+            disk.chassis_bay = chassis_bay.clone();
+        }
+    }
+
+    Ok(basic_disks)
+}
+
+/// Parse a size string (e.g., "500.0G") to bytes
+fn parse_size(size_str: &str) -> Result<u64, Status> {
+    let mut size_chars = size_str.chars().peekable();
+    let mut size_num_str = String::new();
+    let mut unit_str = String::new();
+
+    // Extract the numeric part
+    while let Some(c) = size_chars.peek() {
+        if c.is_ascii_digit() || *c == '.' {
+            size_num_str.push(*c);
+            size_chars.next();
+        } else {
+            break;
+        }
+    }
+
+    // Extract the unit part
+    while let Some(c) = size_chars.next() {
+        unit_str.push(c);
+    }
+
+    // Parse the numeric part
+    let size_num = f64::from_str(&size_num_str).map_err(|e| {
+        error!("Failed to parse size number: {}", e);
+        Status::internal(format!("Failed to parse size number: {}", e))
+    })?;
+
+    // Convert to bytes based on the unit
+    let bytes = match unit_str.to_uppercase().as_str() {
+        "B" => size_num,
+        "K" => size_num * 1024.0,
+        "M" => size_num * 1024.0 * 1024.0,
+        "G" => size_num * 1024.0 * 1024.0 * 1024.0,
+        "T" => size_num * 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        "P" => size_num * 1024.0 * 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        _ => {
+            error!("Unknown size unit: {}", unit_str);
+            size_num
+        }
+    };
+
+    Ok(bytes as u64)
+}
+
+/// Get all paths to a disk
+fn get_disk_paths(device: &str) -> Result<Vec<String>, Status> {
+    debug!("Getting paths for disk {}", device);
+    let output = Command::new("/usr/bin/ls")
+        .args([
+            "-l",
+            &format!("/dev/dsk/{}", device),
+            "/dev/rdsk/{}",
+            device,
+        ])
+        .output()
+        .map_err(|e| {
+            error!("Failed to execute ls command: {}", e);
+            Status::internal(format!("Failed to execute ls command: {}", e))
+        })?;
+
+    if !output.status.success() {
+        let error_msg = String::from_utf8_lossy(&output.stderr);
+        error!("ls command failed: {}", error_msg);
+        return Err(Status::internal(format!(
+            "ls command failed: {}",
+            error_msg
+        )));
+    }
+
+    let output_str = String::from_utf8_lossy(&output.stdout);
+    let paths = output_str
+        .lines()
+        .map(|line| line.to_string())
+        .collect::<Vec<String>>();
+
+    Ok(paths)
+}
+
+/// Execute the dladm command and parse its output
+pub fn get_network_info() -> Result<Vec<NetworkInterface>, Status> {
+    debug!("Executing dladm show-phys command");
+    let output = Command::new("/usr/sbin/dladm")
+        .args([
+            "show-phys",
+            "-m",
+            "-o",
+            "link,class,media,state,speed,over,mtu",
+        ])
+        .output()
+        .map_err(|e| {
+            error!("Failed to execute dladm command: {}", e);
+            Status::internal(format!("Failed to execute dladm command: {}", e))
+        })?;
+
+    if !output.status.success() {
+        let error_msg = String::from_utf8_lossy(&output.stderr);
+        error!("dladm command failed: {}", error_msg);
+        return Err(Status::internal(format!(
+            "dladm command failed: {}",
+            error_msg
+        )));
+    }
+
+    let output_str = String::from_utf8_lossy(&output.stdout);
+    parse_dladm_output(&output_str)
+}
+
+/// Parse the output of the dladm command
+fn parse_dladm_output(output: &str) -> Result<Vec<NetworkInterface>, Status> {
+    let mut interfaces = Vec::new();
+    let mut lines = output.lines();
+
+    // Skip the header line
+    if let Some(_header) = lines.next() {
+        for line in lines {
+            // Skip empty lines
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 7 {
+                error!("Invalid dladm output line: {}", line);
+                continue;
+            }
+
+            let link = fields[0].to_string();
+            let class = fields[1].to_string();
+            let media = fields[2].to_string();
+            let state = fields[3].to_string();
+            let speed = fields[4].to_string();
+            let over_str = fields[5].to_string();
+            let mtu = fields[6].to_string();
+
+            // Parse over flag
+            let over = !over_str.eq_ignore_ascii_case("--");
+
+            // Get MAC address
+            let mac_address = get_mac_address(&link).unwrap_or_else(|_| "".to_string());
+
+            let interface = NetworkInterface {
+                name: link.clone(),
+                link,
+                class,
+                media,
+                state,
+                speed,
+                mac_address,
+                over,
+                mtu,
+            };
+
+            interfaces.push(interface);
+        }
+    }
+
+    Ok(interfaces)
+}
+
+/// Get the MAC address for a network interface
+fn get_mac_address(link: &str) -> Result<String, Status> {
+    debug!("Getting MAC address for interface {}", link);
+    let output = Command::new("/usr/sbin/dladm")
+        .args(["show-phys", "-m", link])
+        .output()
+        .map_err(|e| {
+            error!("Failed to execute dladm command: {}", e);
+            Status::internal(format!("Failed to execute dladm command: {}", e))
+        })?;
+
+    if !output.status.success() {
+        let error_msg = String::from_utf8_lossy(&output.stderr);
+        error!("dladm command failed: {}", error_msg);
+        return Err(Status::internal(format!(
+            "dladm command failed: {}",
+            error_msg
+        )));
+    }
+
+    let output_str = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = output_str.lines().collect();
+    if lines.len() < 2 {
+        return Ok("".to_string());
+    }
+
+    // The MAC address is in the second line, second column
+    let fields: Vec<&str> = lines[1].split_whitespace().collect();
+    if fields.len() < 2 {
+        return Ok("".to_string());
+    }
+
+    Ok(fields[1].to_string())
+}
+
+/// Read the SMBIOS table the kernel exports in /dev/smbios and decode it
+pub fn get_smbios_info() -> Result<SmbiosInfo, Status> {
+    debug!("Reading SMBIOS table from {}", SMBIOS_DEV);
+    let data = fs::read(SMBIOS_DEV).map_err(|e| {
+        error!("Failed to read {}: {}", SMBIOS_DEV, e);
+        Status::internal(format!("Failed to read {}: {}", SMBIOS_DEV, e))
+    })?;
+    smbios::parse_dump(&data).map_err(|e| {
+        error!("Failed to parse SMBIOS table: {}", e);
+        Status::internal(format!("Failed to parse SMBIOS table: {}", e))
+    })
+}
+
+/// Enumerate partitions/slices under /dev/dsk for each disk
+pub fn get_partitions_info(disks: &[DiskInfo]) -> Vec<PartitionInfo> {
+    let mut parts: Vec<PartitionInfo> = Vec::new();
+    let entries = match fs::read_dir("/dev/dsk") {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("Failed to read /dev/dsk: {}", e);
+            return parts;
+        }
+    };
+    let mut names: Vec<String> = Vec::new();
+    for ent in entries {
+        if let Ok(de) = ent {
+            if let Ok(name_os) = de.file_name().into_string() {
+                names.push(name_os);
+            }
+        }
+    }
+    for d in disks.iter() {
+        let prefix_s = format!("{}s", d.device);
+        let prefix_p = format!("{}p", d.device);
+        for n in names.iter() {
+            let is_slice =
+                n.starts_with(&prefix_s) && n[prefix_s.len()..].chars().all(|c| c.is_ascii_digit());
+            let is_part =
+                n.starts_with(&prefix_p) && n[prefix_p.len()..].chars().all(|c| c.is_ascii_digit());
+            if is_slice || is_part {
+                parts.push(PartitionInfo {
+                    device: n.clone(),
+                    size_bytes: 0,
+                    parent_device: d.device.clone(),
+                });
+            }
+        }
+    }
+    parts
+}
+
+/// Run a command and return its standard output
+fn command_output(cmd: &str, args: &[&str]) -> Result<String, Status> {
+    debug!("Executing {} {}", cmd, args.join(" "));
+    let output = Command::new(cmd).args(args).output().map_err(|e| {
+        error!("Failed to execute {}: {}", cmd, e);
+        Status::internal(format!("Failed to execute {}: {}", cmd, e))
+    })?;
+    if !output.status.success() {
+        let error_msg = String::from_utf8_lossy(&output.stderr);
+        error!("{} failed: {}", cmd, error_msg);
+        return Err(Status::internal(format!("{} failed: {}", cmd, error_msg)));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Enumerate the PCI devices with pcieadm
+pub fn get_pci_devices() -> Result<Vec<PciDevice>, Status> {
+    let output = command_output(
+        PCIEADM_BIN,
+        &[
+            "show-devs",
+            "-p",
+            "-o",
+            "bus,dev,func,vid,did,subvid,subsys,rev,driver",
+        ],
+    )?;
+    Ok(parse_pcieadm_output(&output))
+}
+
+/// Format a PCI address the way Linux does, `0000:3b:00.1`
+fn pci_address(segment: u32, bus: u32, device: u32, function: u32) -> String {
+    format!("{:04x}:{:02x}:{:02x}.{:x}", segment, bus, device, function)
+}
+
+/// Parse the parsable output of `pcieadm show-devs`, colons in values are escaped
+fn parse_pcieadm_output(output: &str) -> Vec<PciDevice> {
+    let mut devices = Vec::new();
+    for line in output.lines() {
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        let mut chars = line.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => field.extend(chars.next()),
+                ':' => fields.push(std::mem::take(&mut field)),
+                _ => field.push(c),
+            }
+        }
+        fields.push(field);
+        if fields.len() < 9 {
+            continue;
+        }
+        let hex = |idx: usize| parse_hex(&fields[idx]).unwrap_or_default();
+        devices.push(PciDevice {
+            address: pci_address(0, hex(0), hex(1), hex(2)),
+            vendor_id: hex(3),
+            device_id: hex(4),
+            subsystem_vendor_id: hex(5),
+            subsystem_device_id: hex(6),
+            // pcieadm does not show the class code
+            class_code: 0,
+            revision: hex(7),
+            driver: match fields[8].as_str() {
+                "--" => String::new(),
+                driver => driver.to_string(),
+            },
+        });
+    }
+    devices.sort_by(|a, b| a.address.cmp(&b.address));
+    devices
+}
+
+/// Count the sockets, cores and threads from the cpu_info kstats
+pub fn get_cpu_topology() -> Result<CpuTopology, Status> {
+    let output = command_output(
+        KSTAT_BIN,
+        &[
+            "-p",
+            "cpu_info:::brand",
+            "cpu_info:::chip_id",
+            "cpu_info:::core_id",
+        ],
+    )?;
+    Ok(cpu_topology(&parse_kstat_cpus(&output)))
+}
+
+/// Group `kstat -p` lines of a statistic and its value by CPU instance
+fn parse_kstat_cpus(output: &str) -> Vec<LogicalCpu> {
+    let mut cpus = BTreeMap::<u32, LogicalCpu>::new();
+    for line in output.lines() {
+        let Some((name, value)) = line.split_once('\t') else {
+            continue;
+        };
+        let parts = name.split(':').collect::<Vec<_>>();
+        let (Some(instance), Some(statistic)) = (parts.get(1), parts.get(3)) else {
+            continue;
+        };
+        let Ok(instance) = instance.parse::<u32>() else {
+            continue;
+        };
+        let cpu = cpus.entry(instance).or_insert_with(|| LogicalCpu {
+            model: String::new(),
+            socket: String::new(),
+            core: instance.to_string(),
+        });
+        let value = value.trim().to_string();
+        match *statistic {
+            "brand" => cpu.model = value,
+            "chip_id" => cpu.socket = value,
+            "core_id" => cpu.core = value,
+            _ => {}
+        }
+    }
+    cpus.into_values().collect()
+}
+
+/// Physical memory as reported by `prtconf -m` in megabytes
+pub fn get_memory_size() -> Result<u64, Status> {
+    let output = command_output(PRTCONF_BIN, &["-m"])?;
+    let mib = output
+        .trim()
+        .parse::<u64>()
+        .map_err(|e| Status::internal(format!("Failed to parse prtconf output: {}", e)))?;
+    Ok(mib << 20)
+}
+
+/// The root node carries the EFI system table address when booted by UEFI
+pub fn get_firmware_info() -> FirmwareInfo {
+    let uefi = command_output(PRTCONF_BIN, &["-v", "/devices"])
+        .map(|output| output.contains("name='efi-systab'"))
+        .unwrap_or(false);
+    FirmwareInfo {
+        mode: if uefi {
+            FirmwareMode::Uefi
+        } else {
+            FirmwareMode::Bios
+        }
+        .into(),
+        // illumos does not support secure boot
+        secure_boot: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_pcieadm_output() {
+        let output = "0:1f:3:8086:a348:1028:8ad:10:--\n\
+                      3b:0:1:8086:1572:8086:6:2:i40e\n";
+        let devices = parse_pcieadm_output(output);
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].address, "0000:00:1f.3");
+        assert_eq!(devices[0].driver, "");
+        assert_eq!(devices[1].address, "0000:3b:00.1");
+        assert_eq!(
+            (devices[1].vendor_id, devices[1].device_id),
+            (0x8086, 0x1572)
+        );
+        assert_eq!(devices[1].driver, "i40e");
+    }
+
+    #[test]
+    fn counts_kstat_cpus() {
+        let output = "cpu_info:0:cpu_info0:brand\tIntel(R) Xeon(R) Silver 4210R CPU @ 2.40GHz\n\
+                      cpu_info:0:cpu_info0:chip_id\t0\n\
+                      cpu_info:0:cpu_info0:core_id\t0\n\
+                      cpu_info:1:cpu_info1:chip_id\t0\n\
+                      cpu_info:1:cpu_info1:core_id\t0\n\
+                      cpu_info:2:cpu_info2:chip_id\t1\n\
+                      cpu_info:2:cpu_info2:core_id\t4\n";
+        let topology = cpu_topology(&parse_kstat_cpus(output));
+        assert_eq!(
+            topology.model,
+            "Intel(R) Xeon(R) Silver 4210R CPU @ 2.40GHz"
+        );
+        assert_eq!(
+            (topology.sockets, topology.cores, topology.threads),
+            (2, 2, 3)
+        );
+    }
+}
@@ -0,0 +1,427 @@
+use super::{cpu_topology, parse_hex, smbios, LogicalCpu};
+use crate::machined::{
+    BaseboardInfo, BiosInfo, ChassisInfo, CpuTopology, DiskInfo, FirmwareInfo, FirmwareMode,
+    NetworkInterface, PartitionInfo, PciDevice, SmbiosInfo, SystemBootInfo,
+    SystemInfo as SmbiosSystemInfo,
+};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tonic::Status;
+use tracing::{debug, error};
+
+const SYS_BLOCK: &str = "/sys/block";
+const SYS_CLASS_NET: &str = "/sys/class/net";
+const DMI_ID: &str = "/sys/firmware/dmi/id";
+const DMI_ENTRY_POINT: &str = "/sys/firmware/dmi/tables/smbios_entry_point";
+const DMI_TABLE: &str = "/sys/firmware/dmi/tables/DMI";
+const SYS_PCI_DEVICES: &str = "/sys/bus/pci/devices";
+const PROC_CPUINFO: &str = "/proc/cpuinfo";
+const PROC_MEMINFO: &str = "/proc/meminfo";
+const SYS_FIRMWARE_EFI: &str = "/sys/firmware/efi";
+const EFIVARS: &str = "/sys/firmware/efi/efivars";
+const SECURE_BOOT_VAR: &str = "SecureBoot-8be4df61-93ca-11d2-aa0d-00e098032b8c";
+const UDEV_DATA: &str = "/run/udev/data";
+const DISK_BY_ID: &str = "/dev/disk/by-id";
+
+/// Block device sizes in sysfs are always counted in 512 byte sectors
+const SECTOR_SIZE: u64 = 512;
+
+/// Read a sysfs attribute, treating missing or unreadable attributes as empty
+fn read_attr(path: impl AsRef<Path>) -> String {
+    fs::read_to_string(path)
+        .map(|value| value.trim().to_string())
+        .unwrap_or_default()
+}
+
+fn read_attr_u64(path: impl AsRef<Path>) -> u64 {
+    read_attr(path).parse().unwrap_or_default()
+}
+
+/// Parse the `E:` properties of a udev database entry
+fn parse_udev_data(data: &str) -> HashMap<String, String> {
+    data.lines()
+        .filter_map(|line| line.strip_prefix("E:"))
+        .filter_map(|property| property.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+/// Look up the udev properties of a block device by its major:minor number
+fn udev_properties(dev_dir: &Path) -> HashMap<String, String> {
+    let dev = read_attr(dev_dir.join("dev"));
+    match fs::read_to_string(Path::new(UDEV_DATA).join(format!("b{}", dev))) {
+        Ok(data) => parse_udev_data(&data),
+        Err(e) => {
+            debug!("No udev data for block device {}: {}", dev, e);
+            HashMap::new()
+        }
+    }
+}
+
+/// Enumerate the disks in /sys/block
+pub fn get_disk_info() -> Result<Vec<DiskInfo>, Status> {
+    debug!("Reading disks from {}", SYS_BLOCK);
+    let entries = fs::read_dir(SYS_BLOCK).map_err(|e| {
+        error!("Failed to read {}: {}", SYS_BLOCK, e);
+        Status::internal(format!("Failed to read {}: {}", SYS_BLOCK, e))
+    })?;
+
+    let mut disks = Vec::new();
+    for entry in entries.flatten() {
+        let dir = entry.path();
+        // Loop, ram, zram and device mapper devices have no backing device
+        if !dir.join("device").exists() {
+            continue;
+        }
+        let device = entry.file_name().to_string_lossy().to_string();
+        disks.push(read_disk(&device, &dir));
+    }
+    disks.sort_by(|a, b| a.device.cmp(&b.device));
+    Ok(disks)
+}
+
+fn read_disk(device: &str, dir: &Path) -> DiskInfo {
+    let udev = udev_properties(dir);
+    let udev_value = |key: &str| udev.get(key).cloned().unwrap_or_default();
+
+    let mut vendor = read_attr(dir.join("device/vendor"));
+    if vendor.is_empty() {
+        vendor = udev_value("ID_VENDOR");
+    }
+    let mut product = read_attr(dir.join("device/model"));
+    if product.is_empty() {
+        product = udev_value("ID_MODEL");
+    }
+    let mut serial = udev_value("ID_SERIAL_SHORT");
+    if serial.is_empty() {
+        serial = read_attr(dir.join("device/serial"));
+    }
+
+    DiskInfo {
+        device: device.to_string(),
+        vendor,
+        product,
+        serial,
+        size_bytes: read_attr_u64(dir.join("size")) * SECTOR_SIZE,
+        removable: read_attr(dir.join("removable")) == "1",
+        solid_state: read_attr(dir.join("queue/rotational")) == "0",
+        paths: get_disk_paths(device),
+        fault_status: String::new(),
+        location_code: udev_value("ID_PATH"),
+        chassis_bay: get_enclosure_slot(dir).unwrap_or_default(),
+    }
+}
+
+/// The device node of a disk and all its /dev/disk/by-id links
+fn get_disk_paths(device: &str) -> Vec<String> {
+    let dev_path = PathBuf::from("/dev").join(device);
+    let mut paths = vec![dev_path.to_string_lossy().to_string()];
+    if let Ok(entries) = fs::read_dir(DISK_BY_ID) {
+        let mut links = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|link| fs::canonicalize(link).is_ok_and(|target| target == dev_path))
+            .map(|link| link.to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        links.sort();
+        paths.extend(links);
+    }
+    paths
+}
+
+/// SES enclosures link the slot a disk sits in as `enclosure_device:<slot>`
+fn get_enclosure_slot(dir: &Path) -> Option<String> {
+    fs::read_dir(dir.join("device"))
+        .ok()?
+        .flatten()
+        .find_map(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .strip_prefix("enclosure_device:")
+                .map(|slot| slot.to_string())
+        })
+}
+
+/// Enumerate the network links in /sys/class/net
+pub fn get_network_info() -> Result<Vec<NetworkInterface>, Status> {
+    debug!("Reading network links from {}", SYS_CLASS_NET);
+    let entries = fs::read_dir(SYS_CLASS_NET).map_err(|e| {
+        error!("Failed to read {}: {}", SYS_CLASS_NET, e);
+        Status::internal(format!("Failed to read {}: {}", SYS_CLASS_NET, e))
+    })?;
+
+    let mut interfaces = Vec::new();
+    for entry in entries.flatten() {
+        let link = entry.file_name().to_string_lossy().to_string();
+        if link == "lo" {
+            continue;
+        }
+        let dir = entry.path();
+        let physical = dir.join("device").exists();
+        let media = match read_attr(dir.join("type")).as_str() {
+            "1" => "Ethernet",
+            "32" => "Infiniband",
+            _ => "unknown",
+        };
+        interfaces.push(NetworkInterface {
+            name: link.clone(),
+            link,
+            class: String::from(if physical { "phys" } else { "virtual" }),
+            media: media.to_string(),
+            state: read_attr(dir.join("operstate")),
+            // Reading the speed of a link that is down fails
+            speed: read_attr(dir.join("speed")),
+            mac_address: read_attr(dir.join("address")),
+            over: !physical,
+            mtu: read_attr(dir.join("mtu")),
+        });
+    }
+    interfaces.sort_by(|a, b| a.link.cmp(&b.link));
+    Ok(interfaces)
+}
+
+/// Decode the raw SMBIOS table the kernel exports in /sys/firmware/dmi/tables
+pub fn get_smbios_info() -> Result<SmbiosInfo, Status> {
+    debug!("Reading SMBIOS table from {}", DMI_TABLE);
+    let tables = fs::read(DMI_ENTRY_POINT).and_then(|eps| Ok((eps, fs::read(DMI_TABLE)?)));
+    match tables {
+        Ok((eps, table)) => match smbios::parse_tables(&eps, &table) {
+            Ok(info) => return Ok(info),
+            Err(e) => error!("Failed to parse SMBIOS table: {}", e),
+        },
+        // The tables are only readable by root
+        Err(e) => debug!("Failed to read SMBIOS table: {}", e),
+    }
+    get_dmi_id_info()
+}
+
+/// Read the SMBIOS strings the kernel exports in /sys/firmware/dmi/id
+fn get_dmi_id_info() -> Result<SmbiosInfo, Status> {
+    debug!("Reading DMI information from {}", DMI_ID);
+    let dmi = Path::new(DMI_ID);
+    if !dmi.exists() {
+        error!("{} does not exist", DMI_ID);
+        return Err(Status::internal(format!("{} does not exist", DMI_ID)));
+    }
+    let attr = |name: &str| read_attr(dmi.join(name));
+
+    Ok(SmbiosInfo {
+        bios: Some(BiosInfo {
+            vendor: attr("bios_vendor"),
+            version: attr("bios_version"),
+            release_date: attr("bios_date"),
+            version_number: attr("bios_release"),
+            ..Default::default()
+        }),
+        system: Some(SmbiosSystemInfo {
+            manufacturer: attr("sys_vendor"),
+            product: attr("product_name"),
+            version: attr("product_version"),
+            serial_number: attr("product_serial"),
+            uuid: attr("product_uuid"),
+            sku_number: attr("product_sku"),
+            family: attr("product_family"),
+            ..Default::default()
+        }),
+        baseboard: Some(BaseboardInfo {
+            manufacturer: attr("board_vendor"),
+            product: attr("board_name"),
+            version: attr("board_version"),
+            serial_number: attr("board_serial"),
+            asset_tag: attr("board_asset_tag"),
+            ..Default::default()
+        }),
+        chassis: Some(ChassisInfo {
+            manufacturer: attr("chassis_vendor"),
+            version: attr("chassis_version"),
+            serial_number: attr("chassis_serial"),
+            asset_tag: attr("chassis_asset_tag"),
+            chassis_type: attr("chassis_type").parse().unwrap_or_default(),
+            ..Default::default()
+        }),
+        processors: Vec::new(),
+        memory_arrays: Vec::new(),
+        memory_devices: Vec::new(),
+        memory_array_mapped_addresses: Vec::new(),
+        boot: Some(SystemBootInfo::default()),
+        caches: Vec::new(),
+        slots: Vec::new(),
+    })
+}
+
+/// Enumerate the partitions sysfs lists below each disk
+pub fn get_partitions_info(disks: &[DiskInfo]) -> Vec<PartitionInfo> {
+    let mut parts = Vec::new();
+    for disk in disks {
+        let Ok(entries) = fs::read_dir(Path::new(SYS_BLOCK).join(&disk.device)) else {
+            continue;
+        };
+        let mut disk_parts = entries
+            .flatten()
+            .filter(|entry| entry.path().join("partition").exists())
+            .map(|entry| PartitionInfo {
+                device: entry.file_name().to_string_lossy().to_string(),
+                size_bytes: read_attr_u64(entry.path().join("size")) * SECTOR_SIZE,
+                parent_device: disk.device.clone(),
+            })
+            .collect::<Vec<_>>();
+        disk_parts.sort_by(|a, b| a.device.cmp(&b.device));
+        parts.extend(disk_parts);
+    }
+    parts
+}
+
+/// Enumerate the PCI devices in /sys/bus/pci/devices
+pub fn get_pci_devices() -> Result<Vec<PciDevice>, Status> {
+    debug!("Reading PCI devices from {}", SYS_PCI_DEVICES);
+    let entries = fs::read_dir(SYS_PCI_DEVICES).map_err(|e| {
+        error!("Failed to read {}: {}", SYS_PCI_DEVICES, e);
+        Status::internal(format!("Failed to read {}: {}", SYS_PCI_DEVICES, e))
+    })?;
+
+    let mut devices = Vec::new();
+    for entry in entries.flatten() {
+        let dir = entry.path();
+        let id = |name: &str| parse_hex(&read_attr(dir.join(name))).unwrap_or_default();
+        let driver = fs::read_link(dir.join("driver"))
+            .ok()
+            .and_then(|link| {
+                link.file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+            })
+            .unwrap_or_default();
+        devices.push(PciDevice {
+            address: entry.file_name().to_string_lossy().to_string(),
+            vendor_id: id("vendor"),
+            device_id: id("device"),
+            subsystem_vendor_id: id("subsystem_vendor"),
+            subsystem_device_id: id("subsystem_device"),
+            class_code: id("class"),
+            revision: id("revision"),
+            driver,
+        });
+    }
+    devices.sort_by(|a, b| a.address.cmp(&b.address));
+    Ok(devices)
+}
+
+/// Count the sockets, cores and threads listed in /proc/cpuinfo
+pub fn get_cpu_topology() -> Result<CpuTopology, Status> {
+    let cpuinfo = fs::read_to_string(PROC_CPUINFO).map_err(|e| {
+        error!("Failed to read {}: {}", PROC_CPUINFO, e);
+        Status::internal(format!("Failed to read {}: {}", PROC_CPUINFO, e))
+    })?;
+    Ok(cpu_topology(&parse_cpuinfo(&cpuinfo)))
+}
+
+/// Split /proc/cpuinfo into its logical CPUs
+fn parse_cpuinfo(cpuinfo: &str) -> Vec<LogicalCpu> {
+    let mut cpus = Vec::new();
+    for block in cpuinfo.split("\n\n") {
+        let fields = block
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .map(|(key, value)| (key.trim(), value.trim()))
+            .collect::<HashMap<_, _>>();
+        let Some(processor) = fields.get("processor") else {
+            continue;
+        };
+        // Architectures without topology fields get a core per logical CPU
+        cpus.push(LogicalCpu {
+            model: fields
+                .get("model name")
+                .or_else(|| fields.get("cpu model"))
+                .unwrap_or(&"")
+                .to_string(),
+            socket: fields.get("physical id").unwrap_or(&"0").to_string(),
+            core: fields.get("core id").unwrap_or(processor).to_string(),
+        });
+    }
+    cpus
+}
+
+/// Total usable memory from /proc/meminfo
+pub fn get_memory_size() -> Result<u64, Status> {
+    let meminfo = fs::read_to_string(PROC_MEMINFO).map_err(|e| {
+        error!("Failed to read {}: {}", PROC_MEMINFO, e);
+        Status::internal(format!("Failed to read {}: {}", PROC_MEMINFO, e))
+    })?;
+    let kib = meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("MemTotal:"))
+        .and_then(|value| {
+            value
+                .trim()
+                .trim_end_matches("kB")
+                .trim()
+                .parse::<u64>()
+                .ok()
+        })
+        .ok_or_else(|| Status::internal(format!("No MemTotal in {}", PROC_MEMINFO)))?;
+    Ok(kib * 1024)
+}
+
+/// The kernel only exposes /sys/firmware/efi when booted by UEFI
+pub fn get_firmware_info() -> FirmwareInfo {
+    if !Path::new(SYS_FIRMWARE_EFI).exists() {
+        return FirmwareInfo {
+            mode: FirmwareMode::Bios.into(),
+            secure_boot: false,
+        };
+    }
+    // The variable data follows four bytes of attributes
+    let secure_boot = fs::read(Path::new(EFIVARS).join(SECURE_BOOT_VAR))
+        .map(|data| data.get(4) == Some(&1))
+        .unwrap_or(false);
+    FirmwareInfo {
+        mode: FirmwareMode::Uefi.into(),
+        secure_boot,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_udev_properties() {
+        let data = "S:disk/by-id/ata-Samsung_SSD_870_S6PNNX0R123456\n\
+                    I:1234567\n\
+                    E:ID_SERIAL_SHORT=S6PNNX0R123456\n\
+                    E:ID_PATH=pci-0000:00:17.0-ata-1\n\
+                    G:systemd\n";
+        let properties = parse_udev_data(data);
+        assert_eq!(properties.len(), 2);
+        assert_eq!(properties["ID_SERIAL_SHORT"], "S6PNNX0R123456");
+        assert_eq!(properties["ID_PATH"], "pci-0000:00:17.0-ata-1");
+    }
+
+    #[test]
+    fn counts_cpu_topology() {
+        let mut cpuinfo = String::new();
+        for (processor, socket, core) in [(0, 0, 0), (1, 0, 1), (2, 1, 0), (3, 1, 1), (4, 0, 0)] {
+            cpuinfo.push_str(&format!(
+                "processor\t: {}\nmodel name\t: AMD EPYC 7313P 16-Core Processor\n\
+                 physical id\t: {}\ncore id\t\t: {}\n\n",
+                processor, socket, core
+            ));
+        }
+        let topology = cpu_topology(&parse_cpuinfo(&cpuinfo));
+        assert_eq!(topology.model, "AMD EPYC 7313P 16-Core Processor");
+        assert_eq!(
+            (topology.sockets, topology.cores, topology.threads),
+            (2, 4, 5)
+        );
+
+        // aarch64 lists neither model names nor topology
+        let topology = cpu_topology(&parse_cpuinfo(
+            "processor\t: 0\nBogoMIPS\t: 50.00\n\nprocessor\t: 1\nBogoMIPS\t: 50.00\n",
+        ));
+        assert_eq!(
+            (topology.sockets, topology.cores, topology.threads),
+            (1, 2, 2)
+        );
+    }
+}
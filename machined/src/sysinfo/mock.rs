@@ -0,0 +1,46 @@
+use super::smbios;
+use crate::machined::{
+    CpuTopology, DiskInfo, FirmwareInfo, NetworkInterface, PartitionInfo, PciDevice, SmbiosInfo,
+};
+use crate::platform::fixture;
+use tonic::Status;
+use tracing::error;
+
+/// Disks of the simulated machine
+pub fn get_disk_info() -> Result<Vec<DiskInfo>, Status> {
+    Ok(fixture().disks.clone())
+}
+
+/// Network links of the simulated machine
+pub fn get_network_info() -> Result<Vec<NetworkInterface>, Status> {
+    Ok(fixture().links.clone())
+}
+
+/// Decode the SMBIOS dump of the fixture like the one read from /dev/smbios
+pub fn get_smbios_info() -> Result<SmbiosInfo, Status> {
+    smbios::parse_dump(&fixture().smbios).map_err(|e| {
+        error!("Failed to parse SMBIOS table: {}", e);
+        Status::internal(format!("Failed to parse SMBIOS table: {}", e))
+    })
+}
+
+/// The disks of the simulated machine are blank
+pub fn get_partitions_info(_disks: &[DiskInfo]) -> Vec<PartitionInfo> {
+    Vec::new()
+}
+
+pub fn get_pci_devices() -> Result<Vec<PciDevice>, Status> {
+    Ok(Vec::new())
+}
+
+pub fn get_cpu_topology() -> Result<CpuTopology, Status> {
+    Ok(fixture().cpu.clone())
+}
+
+pub fn get_memory_size() -> Result<u64, Status> {
+    Ok(fixture().memory_bytes)
+}
+
+pub fn get_firmware_info() -> FirmwareInfo {
+    fixture().firmware
+}
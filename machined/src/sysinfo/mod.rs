@@ -0,0 +1,154 @@
+#[cfg(all(not(target_os = "linux"), not(feature = "mock")))]
+mod illumos;
+#[cfg(all(target_os = "linux", not(feature = "mock")))]
+mod linux;
+#[cfg(feature = "mock")]
+mod mock;
+pub mod smbios;
+
+#[cfg(all(not(target_os = "linux"), not(feature = "mock")))]
+pub use illumos::*;
+#[cfg(all(target_os = "linux", not(feature = "mock")))]
+pub use linux::*;
+#[cfg(feature = "mock")]
+pub use mock::*;
+
+use crate::machined::{CpuTopology, SystemInfoRequest, SystemInfoResponse, SystemInfoSection};
+use std::collections::HashSet;
+use tonic::Status;
+use tracing::warn;
+
+/// Collect the sections of the system information asked for, all of them if none are
+pub fn get_system_info(request: &SystemInfoRequest) -> Result<SystemInfoResponse, Status> {
+    let wants = |section: SystemInfoSection| {
+        request.sections.is_empty() || request.sections.contains(&(section as i32))
+    };
+
+    let mut response = SystemInfoResponse::default();
+    if wants(SystemInfoSection::Disks) || wants(SystemInfoSection::Partitions) {
+        let disks = get_disk_info()?;
+        if wants(SystemInfoSection::Partitions) {
+            response.partitions = get_partitions_info(&disks);
+        }
+        if wants(SystemInfoSection::Disks) {
+            response.disks = disks;
+        }
+    }
+    if wants(SystemInfoSection::Network) {
+        response.network_interfaces = get_network_info()?;
+    }
+    if wants(SystemInfoSection::Smbios) {
+        response.smbios = Some(get_smbios_info()?);
+    }
+    if wants(SystemInfoSection::Pci) {
+        response.pci_devices = best_effort(request, SystemInfoSection::Pci, get_pci_devices())?;
+    }
+    if wants(SystemInfoSection::Cpu) {
+        response.cpu = best_effort(
+            request,
+            SystemInfoSection::Cpu,
+            get_cpu_topology().map(Some),
+        )?;
+    }
+    if wants(SystemInfoSection::Memory) {
+        response.memory_bytes = best_effort(request, SystemInfoSection::Memory, get_memory_size())?;
+    }
+    if wants(SystemInfoSection::Firmware) {
+        response.firmware = Some(get_firmware_info());
+    }
+    Ok(response)
+}
+
+/// Leave a section out when it cannot be collected, unless the request asked for it.
+///
+/// Disks, network and SMBIOS are what installs and claims depend on. The other sections come
+/// from tools like pcieadm or kstat that may fail on some machines, which should not take the
+/// whole response down.
+fn best_effort<T: Default>(
+    request: &SystemInfoRequest,
+    section: SystemInfoSection,
+    result: Result<T, Status>,
+) -> Result<T, Status> {
+    match result {
+        Err(status) if request.sections.is_empty() => {
+            warn!(
+                "Leaving out {}: {}",
+                section.as_str_name(),
+                status.message()
+            );
+            Ok(T::default())
+        }
+        result => result,
+    }
+}
+
+/// A logical CPU as reported by the operating system
+#[cfg_attr(feature = "mock", allow(dead_code))]
+struct LogicalCpu {
+    model: String,
+    socket: String,
+    core: String,
+}
+
+/// Count sockets, cores and threads of a list of logical CPUs
+#[cfg_attr(feature = "mock", allow(dead_code))]
+fn cpu_topology(cpus: &[LogicalCpu]) -> CpuTopology {
+    let sockets = cpus
+        .iter()
+        .map(|cpu| cpu.socket.as_str())
+        .collect::<HashSet<_>>();
+    let cores = cpus
+        .iter()
+        .map(|cpu| (cpu.socket.as_str(), cpu.core.as_str()))
+        .collect::<HashSet<_>>();
+    CpuTopology {
+        model: cpus
+            .iter()
+            .map(|cpu| cpu.model.clone())
+            .find(|model| !model.is_empty())
+            .unwrap_or_default(),
+        sockets: sockets.len() as u32,
+        cores: cores.len() as u32,
+        threads: cpus.len() as u32,
+    }
+}
+
+/// Parse a hexadecimal ID with or without `0x` prefix
+#[cfg_attr(feature = "mock", allow(dead_code))]
+fn parse_hex(value: &str) -> Option<u32> {
+    let value = value.trim();
+    let value = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .unwrap_or(value);
+    u32::from_str_radix(value, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn optional_sections_fail_only_when_asked_for() {
+        let all = SystemInfoRequest::default();
+        let failed = || Err::<u64, _>(Status::internal("kstat failed"));
+        assert_eq!(
+            best_effort(&all, SystemInfoSection::Memory, failed()).unwrap(),
+            0
+        );
+
+        let memory = SystemInfoRequest {
+            sections: vec![SystemInfoSection::Memory.into()],
+        };
+        assert_eq!(
+            best_effort(&memory, SystemInfoSection::Memory, failed())
+                .unwrap_err()
+                .message(),
+            "kstat failed"
+        );
+        assert_eq!(
+            best_effort(&memory, SystemInfoSection::Memory, Ok(1u64 << 30)).unwrap(),
+            1 << 30
+        );
+    }
+}
@@ -0,0 +1,771 @@
+//! Parser for the raw SMBIOS structure table.
+//!
+//! The table is read from sysfs on Linux, from `/dev/smbios` on illumos or from a
+//! `dmidecode --dump-bin` file and decoded without relying on any external tool.
+use crate::error::SmbiosError;
+use crate::machined::{
+    BaseboardInfo, BiosInfo, CacheInfo, ChassisInfo, MemoryArrayInfo, MemoryArrayMappedAddressInfo,
+    MemoryDeviceInfo, ProcessorInfo, SmbiosInfo, SystemBootInfo, SystemInfo as SmbiosSystemInfo,
+    SystemSlotInfo,
+};
+use uuid::Uuid;
+
+const TYPE_BIOS: u8 = 0;
+const TYPE_SYSTEM: u8 = 1;
+const TYPE_BASEBOARD: u8 = 2;
+const TYPE_CHASSIS: u8 = 3;
+const TYPE_PROCESSOR: u8 = 4;
+const TYPE_CACHE: u8 = 7;
+const TYPE_SLOT: u8 = 9;
+const TYPE_MEMARRAY: u8 = 16;
+const TYPE_MEMDEVICE: u8 = 17;
+const TYPE_MEMARRAYMAP: u8 = 19;
+const TYPE_BOOT: u8 = 32;
+const TYPE_END: u8 = 127;
+
+/// The fields of an SMBIOS 2.x or 3.x entry point needed to find the structure table
+#[derive(Debug, PartialEq)]
+pub struct EntryPoint {
+    pub major: u8,
+    pub minor: u8,
+    pub table_address: u64,
+    /// Exact length of the table for 2.x, the maximum length for 3.x
+    pub table_length: usize,
+}
+
+impl EntryPoint {
+    fn at_least(&self, major: u8, minor: u8) -> bool {
+        (self.major, self.minor) >= (major, minor)
+    }
+}
+
+/// A structure of the table with its formatted section and string set
+#[derive(Debug)]
+pub struct Structure<'a> {
+    pub kind: u8,
+    pub handle: u16,
+    formatted: &'a [u8],
+    strings: Vec<&'a [u8]>,
+}
+
+impl Structure<'_> {
+    fn byte(&self, offset: usize) -> Option<u8> {
+        self.formatted.get(offset).copied()
+    }
+
+    fn word(&self, offset: usize) -> Option<u16> {
+        let bytes = self.formatted.get(offset..offset + 2)?;
+        Some(u16::from_le_bytes(bytes.try_into().ok()?))
+    }
+
+    fn dword(&self, offset: usize) -> Option<u32> {
+        let bytes = self.formatted.get(offset..offset + 4)?;
+        Some(u32::from_le_bytes(bytes.try_into().ok()?))
+    }
+
+    fn qword(&self, offset: usize) -> Option<u64> {
+        let bytes = self.formatted.get(offset..offset + 8)?;
+        Some(u64::from_le_bytes(bytes.try_into().ok()?))
+    }
+
+    /// The string referenced by the index at `offset`, empty if there is none
+    fn string(&self, offset: usize) -> String {
+        match self.byte(offset) {
+            Some(index) if index > 0 => self
+                .strings
+                .get(index as usize - 1)
+                .map(|s| String::from_utf8_lossy(s).trim().to_string())
+                .unwrap_or_default(),
+            _ => String::new(),
+        }
+    }
+
+    /// A handle field, 0 when the field is absent or does not reference a structure
+    fn handle_ref(&self, offset: usize) -> u32 {
+        match self.word(offset) {
+            Some(0xFFFE) | Some(0xFFFF) | None => 0,
+            Some(handle) => handle as u32,
+        }
+    }
+}
+
+fn checksum_ok(data: &[u8]) -> bool {
+    data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) == 0
+}
+
+/// Parse a 64-bit (`_SM3_`), 32-bit (`_SM_`) or legacy (`_DMI_`) entry point
+pub fn parse_entry_point(data: &[u8]) -> Result<EntryPoint, SmbiosError> {
+    if data.starts_with(b"_SM3_") {
+        let len = *data.get(0x06).ok_or(SmbiosError::NoEntryPoint)? as usize;
+        let eps = data.get(..len.max(0x18)).ok_or(SmbiosError::NoEntryPoint)?;
+        if !checksum_ok(&eps[..len]) {
+            return Err(SmbiosError::BadChecksum);
+        }
+        Ok(EntryPoint {
+            major: eps[0x07],
+            minor: eps[0x08],
+            table_length: u32::from_le_bytes(eps[0x0C..0x10].try_into().unwrap()) as usize,
+            table_address: u64::from_le_bytes(eps[0x10..0x18].try_into().unwrap()),
+        })
+    } else if data.starts_with(b"_SM_") {
+        let len = *data.get(0x05).ok_or(SmbiosError::NoEntryPoint)? as usize;
+        let eps = data.get(..len.max(0x1F)).ok_or(SmbiosError::NoEntryPoint)?;
+        if !checksum_ok(&eps[..len]) || !checksum_ok(&eps[0x10..0x1F]) {
+            return Err(SmbiosError::BadChecksum);
+        }
+        let (major, minor) = match (eps[0x06], eps[0x07]) {
+            // Some firmware reports 2.33 for 2.3.3 and 2.51 for 2.5.1
+            (2, 33) => (2, 3),
+            (2, 51) => (2, 5),
+            version => version,
+        };
+        Ok(EntryPoint {
+            major,
+            minor,
+            table_length: u16::from_le_bytes(eps[0x16..0x18].try_into().unwrap()) as usize,
+            table_address: u32::from_le_bytes(eps[0x18..0x1C].try_into().unwrap()) as u64,
+        })
+    } else if data.starts_with(b"_DMI_") {
+        let eps = data.get(..0x0F).ok_or(SmbiosError::NoEntryPoint)?;
+        if !checksum_ok(eps) {
+            return Err(SmbiosError::BadChecksum);
+        }
+        Ok(EntryPoint {
+            major: eps[0x0E] >> 4,
+            minor: eps[0x0E] & 0x0F,
+            table_length: u16::from_le_bytes(eps[0x06..0x08].try_into().unwrap()) as usize,
+            table_address: u32::from_le_bytes(eps[0x08..0x0C].try_into().unwrap()) as u64,
+        })
+    } else {
+        Err(SmbiosError::NoEntryPoint)
+    }
+}
+
+/// Split a structure table into its structures, stopping at the end-of-table structure
+pub fn parse_structures(table: &[u8]) -> Result<Vec<Structure<'_>>, SmbiosError> {
+    let mut structures = Vec::new();
+    let mut offset = 0;
+    while offset + 4 <= table.len() {
+        let kind = table[offset];
+        let length = table[offset + 1] as usize;
+        let handle = u16::from_le_bytes([table[offset + 2], table[offset + 3]]);
+        if length < 4 || offset + length > table.len() {
+            return Err(SmbiosError::Truncated(offset));
+        }
+        let formatted = &table[offset..offset + length];
+
+        // The string set ends with two NUL bytes, a structure without strings has just those
+        let string_start = offset + length;
+        let string_len = table[string_start..]
+            .windows(2)
+            .position(|w| w == [0, 0])
+            .ok_or(SmbiosError::Truncated(offset))?;
+        let strings = table[string_start..string_start + string_len]
+            .split(|b| *b == 0)
+            .filter(|s| !s.is_empty())
+            .collect();
+
+        structures.push(Structure {
+            kind,
+            handle,
+            formatted,
+            strings,
+        });
+        offset = string_start + string_len + 2;
+        if kind == TYPE_END {
+            break;
+        }
+    }
+    Ok(structures)
+}
+
+/// Decode a structure table whose entry point was read separately, as sysfs exports them
+#[cfg_attr(any(not(target_os = "linux"), feature = "mock"), allow(dead_code))]
+pub fn parse_tables(entry_point: &[u8], table: &[u8]) -> Result<SmbiosInfo, SmbiosError> {
+    let eps = parse_entry_point(entry_point)?;
+    let table = &table[..eps.table_length.min(table.len())];
+    Ok(decode(&eps, &parse_structures(table)?))
+}
+
+/// Decode a file holding the entry point followed by the structure table.
+///
+/// The table is found at the table address of the entry point if that lies within the
+/// file, as in `dmidecode --dump-bin` files, otherwise at offset 0x20 where dumps that kept
+/// the physical address of the table place it.
+#[cfg_attr(target_os = "linux", allow(dead_code))]
+pub fn parse_dump(dump: &[u8]) -> Result<SmbiosInfo, SmbiosError> {
+    const DUMP_TABLE_OFFSET: u64 = 0x20;
+    let eps = parse_entry_point(dump)?;
+    let offset = if eps.table_address < dump.len() as u64 {
+        eps.table_address
+    } else if DUMP_TABLE_OFFSET < dump.len() as u64 {
+        DUMP_TABLE_OFFSET
+    } else {
+        return Err(SmbiosError::TableOutOfRange(eps.table_address));
+    } as usize;
+    let end = (offset + eps.table_length).min(dump.len());
+    Ok(decode(&eps, &parse_structures(&dump[offset..end])?))
+}
+
+/// Map the structures of a table onto the SMBIOS messages of the API
+pub fn decode(eps: &EntryPoint, structures: &[Structure]) -> SmbiosInfo {
+    let mut info = SmbiosInfo {
+        bios: Some(BiosInfo::default()),
+        system: Some(SmbiosSystemInfo::default()),
+        baseboard: Some(BaseboardInfo::default()),
+        chassis: Some(ChassisInfo::default()),
+        boot: Some(SystemBootInfo::default()),
+        ..Default::default()
+    };
+    for s in structures {
+        match s.kind {
+            TYPE_BIOS => info.bios = Some(decode_bios(s)),
+            TYPE_SYSTEM => info.system = Some(decode_system(eps, s)),
+            // Only the first baseboard is the main board
+            TYPE_BASEBOARD if info.baseboard == Some(BaseboardInfo::default()) => {
+                info.baseboard = Some(decode_baseboard(s))
+            }
+            TYPE_CHASSIS if info.chassis == Some(ChassisInfo::default()) => {
+                info.chassis = Some(decode_chassis(s))
+            }
+            TYPE_PROCESSOR => info.processors.push(decode_processor(s)),
+            TYPE_CACHE => info.caches.push(decode_cache(s)),
+            TYPE_SLOT => info.slots.push(decode_slot(s)),
+            TYPE_MEMARRAY => info.memory_arrays.push(decode_memory_array(s)),
+            TYPE_MEMDEVICE => info.memory_devices.push(decode_memory_device(s)),
+            TYPE_MEMARRAYMAP => info
+                .memory_array_mapped_addresses
+                .push(decode_memory_array_mapped_address(s)),
+            TYPE_BOOT => {
+                info.boot = Some(SystemBootInfo {
+                    status_code: s.byte(0x0A).unwrap_or_default() as u32,
+                })
+            }
+            _ => {}
+        }
+    }
+    info
+}
+
+/// BIOS information (type 0)
+fn decode_bios(s: &Structure) -> BiosInfo {
+    let segment = s.word(0x06).unwrap_or_default();
+    let rom_size = match s.byte(0x09) {
+        // Sizes of 16M and more are given in the extended ROM size
+        Some(0xFF) => match s.word(0x18) {
+            Some(ext) if ext >> 14 == 1 => (ext as u64 & 0x3FFF) << 30,
+            Some(ext) => (ext as u64 & 0x3FFF) << 20,
+            // Tables older than SMBIOS 3.1 have no extended size, dmidecode reports 16M
+            None => 16 << 20,
+        },
+        Some(size) => (size as u64 + 1) << 16,
+        None => 0,
+    };
+    let version_number = match (s.byte(0x14), s.byte(0x15)) {
+        (Some(major), Some(minor)) if major != 0xFF => format!("{}.{}", major, minor),
+        _ => String::new(),
+    };
+    BiosInfo {
+        vendor: s.string(0x04),
+        version: s.string(0x05),
+        release_date: s.string(0x08),
+        address_segment: format!("{:#x}", segment),
+        rom_size: rom_size.min(u32::MAX as u64) as u32,
+        image_size: (0x10000 - segment as u32) * 16,
+        characteristics: s.qword(0x0A).unwrap_or_default() as u32,
+        characteristics_ext1: s.byte(0x12).unwrap_or_default() as u32,
+        characteristics_ext2: s.byte(0x13).unwrap_or_default() as u32,
+        version_number,
+    }
+}
+
+/// System information (type 1)
+fn decode_system(eps: &EntryPoint, s: &Structure) -> SmbiosSystemInfo {
+    let uuid = s
+        .formatted
+        .get(0x08..0x18)
+        .and_then(|bytes| <[u8; 16]>::try_from(bytes).ok())
+        // All zeroes or all ones mean the UUID is not set
+        .filter(|bytes| bytes.iter().any(|b| *b != 0) && bytes.iter().any(|b| *b != 0xFF))
+        .map(|bytes| {
+            // Since 2.6 the first three fields are little endian
+            if eps.at_least(2, 6) {
+                Uuid::from_bytes_le(bytes)
+            } else {
+                Uuid::from_bytes(bytes)
+            }
+            .to_string()
+        })
+        .unwrap_or_default();
+    SmbiosSystemInfo {
+        manufacturer: s.string(0x04),
+        product: s.string(0x05),
+        version: s.string(0x06),
+        serial_number: s.string(0x07),
+        uuid,
+        wakeup_event: s.byte(0x18).unwrap_or_default() as u32,
+        sku_number: s.string(0x19),
+        family: s.string(0x1A),
+    }
+}
+
+/// Baseboard information (type 2)
+fn decode_baseboard(s: &Structure) -> BaseboardInfo {
+    BaseboardInfo {
+        manufacturer: s.string(0x04),
+        product: s.string(0x05),
+        version: s.string(0x06),
+        serial_number: s.string(0x07),
+        asset_tag: s.string(0x08),
+        flags: s.byte(0x09).unwrap_or_default() as u32,
+        location_tag: s.string(0x0A),
+        chassis: s.handle_ref(0x0B),
+        board_type: s.byte(0x0D).unwrap_or_default() as u32,
+    }
+}
+
+/// Chassis information (type 3)
+fn decode_chassis(s: &Structure) -> ChassisInfo {
+    let kind = s.byte(0x05).unwrap_or_default();
+    let element_records = s.byte(0x13).unwrap_or_default();
+    let element_len = s.byte(0x14).unwrap_or_default();
+    // The SKU follows the variable length contained elements
+    let sku_offset = 0x15 + element_records as usize * element_len as usize;
+    ChassisInfo {
+        manufacturer: s.string(0x04),
+        version: s.string(0x06),
+        serial_number: s.string(0x07),
+        asset_tag: s.string(0x08),
+        oem_data: s.dword(0x0D).unwrap_or_default(),
+        sku_number: s.string(sku_offset),
+        lock_present: kind & 0x80 != 0,
+        chassis_type: (kind & 0x7F) as u32,
+        boot_up_state: s.byte(0x09).unwrap_or_default() as u32,
+        power_supply_state: s.byte(0x0A).unwrap_or_default() as u32,
+        thermal_state: s.byte(0x0B).unwrap_or_default() as u32,
+        chassis_height: s.byte(0x11).unwrap_or_default() as u32,
+        power_cords: s.byte(0x12).unwrap_or_default() as u32,
+        element_records: element_records as u32,
+    }
+}
+
+/// A speed in MHz or MT/s, empty when unknown
+fn speed(value: Option<u32>, unit: &str) -> String {
+    match value {
+        Some(value) if value > 0 => format!("{} {}", value, unit),
+        _ => String::new(),
+    }
+}
+
+/// A count that is continued in a wider field once it reaches 0xFF
+fn count(s: &Structure, offset: usize, offset2: usize) -> u32 {
+    match s.byte(offset) {
+        Some(0xFF) => s.word(offset2).unwrap_or(0xFF) as u32,
+        Some(count) => count as u32,
+        None => 0,
+    }
+}
+
+/// Processor information (type 4)
+fn decode_processor(s: &Structure) -> ProcessorInfo {
+    let voltage = s.byte(0x11).unwrap_or_default();
+    let supported_voltages = if voltage & 0x80 != 0 {
+        format!("{:.1}V", (voltage & 0x7F) as f32 / 10.0)
+    } else {
+        [(0x01, "5.0V"), (0x02, "3.3V"), (0x04, "2.9V")]
+            .iter()
+            .filter(|(bit, _)| voltage & bit != 0)
+            .map(|(_, v)| *v)
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let status = s.byte(0x18).unwrap_or_default();
+    let family = match s.byte(0x06) {
+        Some(0xFE) => s.word(0x28).unwrap_or(0xFE) as u32,
+        Some(family) => family as u32,
+        None => 0,
+    };
+    ProcessorInfo {
+        manufacturer: s.string(0x07),
+        version: s.string(0x10),
+        serial_number: s.string(0x20),
+        asset_tag: s.string(0x21),
+        location_tag: s.string(0x04),
+        part_number: s.string(0x22),
+        family,
+        // The processor signature is the low half of the processor ID
+        cpuid: s.qword(0x08).unwrap_or_default() as u32,
+        r#type: s.byte(0x05).unwrap_or_default() as u32,
+        socket_upgrade: s.byte(0x19).unwrap_or_default() as u32,
+        socket_populated: status & 0x40 != 0,
+        processor_status: (status & 0x07) as u32,
+        supported_voltages,
+        core_count: count(s, 0x23, 0x2A),
+        cores_enabled: count(s, 0x24, 0x2C),
+        thread_count: count(s, 0x25, 0x2E),
+        processor_characteristics: s.word(0x26).unwrap_or_default() as u32,
+        external_clock: speed(s.word(0x12).map(u32::from), "MHz"),
+        maximum_speed: speed(s.word(0x14).map(u32::from), "MHz"),
+        current_speed: speed(s.word(0x16).map(u32::from), "MHz"),
+        l1_cache_handle: s.handle_ref(0x1A),
+        l2_cache_handle: s.handle_ref(0x1C),
+        l3_cache_handle: s.handle_ref(0x1E),
+        threads_enabled: s.word(0x30).unwrap_or_default() as u32,
+    }
+}
+
+/// A cache size from its 16-bit field or, if that is saturated, its 32-bit field
+fn cache_size(s: &Structure, offset: usize, offset2: usize) -> u64 {
+    let size = |value: u64, granularity_bit: u32| {
+        let units = value & ((1 << granularity_bit) - 1);
+        if value >> granularity_bit & 1 == 1 {
+            units * 64 * 1024
+        } else {
+            units * 1024
+        }
+    };
+    match (s.word(offset), s.dword(offset2)) {
+        (Some(0xFFFF), Some(size2)) => size(size2 as u64, 31),
+        (Some(size16), _) => size(size16 as u64, 15),
+        _ => 0,
+    }
+}
+
+/// Cache information (type 7)
+fn decode_cache(s: &Structure) -> CacheInfo {
+    let config = s.word(0x05).unwrap_or_default();
+    CacheInfo {
+        handle: s.handle as u32,
+        socket_designation: s.string(0x04),
+        level: (config & 0x07) as u32 + 1,
+        enabled: config & 0x80 != 0,
+        max_size: cache_size(s, 0x07, 0x13),
+        installed_size: cache_size(s, 0x09, 0x17),
+        error_correction: s.byte(0x10).unwrap_or_default() as u32,
+        cache_type: s.byte(0x11).unwrap_or_default() as u32,
+        associativity: s.byte(0x12).unwrap_or_default() as u32,
+    }
+}
+
+/// System slot information (type 9)
+fn decode_slot(s: &Structure) -> SystemSlotInfo {
+    let bus_address = match (s.word(0x0D), s.byte(0x0F), s.byte(0x10)) {
+        (Some(segment), Some(bus), Some(devfn))
+            if segment != 0xFFFF && bus != 0xFF && devfn != 0xFF =>
+        {
+            format!(
+                "{:04x}:{:02x}:{:02x}.{:x}",
+                segment,
+                bus,
+                devfn >> 3,
+                devfn & 0x07
+            )
+        }
+        _ => String::new(),
+    };
+    SystemSlotInfo {
+        designation: s.string(0x04),
+        slot_type: s.byte(0x05).unwrap_or_default() as u32,
+        data_bus_width: s.byte(0x06).unwrap_or_default() as u32,
+        current_usage: s.byte(0x07).unwrap_or_default() as u32,
+        length: s.byte(0x08).unwrap_or_default() as u32,
+        slot_id: s.word(0x09).unwrap_or_default() as u32,
+        characteristics1: s.byte(0x0B).unwrap_or_default() as u32,
+        characteristics2: s.byte(0x0C).unwrap_or_default() as u32,
+        bus_address,
+    }
+}
+
+/// Physical memory array (type 16)
+fn decode_memory_array(s: &Structure) -> MemoryArrayInfo {
+    let max_capacity = match s.dword(0x07) {
+        Some(0x8000_0000) => s.qword(0x0F).unwrap_or_default(),
+        Some(kib) => kib as u64 * 1024,
+        None => 0,
+    };
+    MemoryArrayInfo {
+        location: s.byte(0x04).unwrap_or_default() as u32,
+        r#use: s.byte(0x05).unwrap_or_default() as u32,
+        ecc: s.byte(0x06).unwrap_or_default() as u32,
+        slots: s.word(0x0D).unwrap_or_default() as u32,
+        max_capacity,
+    }
+}
+
+/// A voltage given in millivolts, empty when unknown
+fn voltage(millivolts: Option<u16>) -> String {
+    match millivolts {
+        Some(mv) if mv > 0 => format!("{:.2}V", mv as f32 / 1000.0),
+        _ => String::new(),
+    }
+}
+
+/// Memory device (type 17)
+fn decode_memory_device(s: &Structure) -> MemoryDeviceInfo {
+    let size = match s.word(0x0C) {
+        // Not installed or unknown
+        Some(0) | Some(0xFFFF) | None => 0,
+        // Devices of 32G and more are sized in MiB by the extended size
+        Some(0x7FFF) => (s.dword(0x1C).unwrap_or_default() as u64 & 0x7FFF_FFFF) << 20,
+        Some(size) if size & 0x8000 != 0 => (size as u64 & 0x7FFF) << 10,
+        Some(size) => (size as u64) << 20,
+    };
+    let width = |offset| match s.word(offset) {
+        Some(0xFFFF) | None => 0,
+        Some(width) => width as u32,
+    };
+    // Speeds above 65534 MT/s are given in the extended speed fields
+    let speed_at = |offset, offset2| match s.word(offset) {
+        Some(0xFFFF) => s.dword(offset2).map(|v| v & 0x7FFF_FFFF),
+        value => value.map(u32::from),
+    };
+    let device_locator = s.string(0x10);
+    MemoryDeviceInfo {
+        manufacturer: s.string(0x17),
+        serial_number: s.string(0x18),
+        asset_tag: s.string(0x19),
+        location_tag: device_locator.clone(),
+        part_number: s.string(0x1A),
+        array_handle: s.handle_ref(0x04),
+        error_handle: s.handle_ref(0x06),
+        total_width: width(0x08),
+        data_width: width(0x0A),
+        size,
+        form_factor: s.byte(0x0E).unwrap_or_default() as u32,
+        set: s.byte(0x0F).unwrap_or_default() as u32,
+        rank: (s.byte(0x1B).unwrap_or_default() & 0x0F) as u32,
+        memory_type: s.byte(0x12).unwrap_or_default() as u32,
+        flags: s.word(0x13).unwrap_or_default() as u32,
+        speed: speed(speed_at(0x15, 0x54), "MT/s"),
+        configured_speed: speed(speed_at(0x20, 0x58), "MT/s"),
+        device_locator,
+        bank_locator: s.string(0x11),
+        min_voltage: voltage(s.word(0x22)),
+        max_voltage: voltage(s.word(0x24)),
+        configured_voltage: voltage(s.word(0x26)),
+    }
+}
+
+/// Memory array mapped address (type 19)
+fn decode_memory_array_mapped_address(s: &Structure) -> MemoryArrayMappedAddressInfo {
+    let (start, end) = match (s.dword(0x04), s.dword(0x08)) {
+        // Ranges beyond 4T are given in bytes in the extended addresses
+        (Some(0xFFFF_FFFF), _) => (
+            s.qword(0x0F).unwrap_or_default(),
+            s.qword(0x17).unwrap_or_default(),
+        ),
+        (Some(start), Some(end)) => (start as u64 * 1024, end as u64 * 1024 + 1023),
+        _ => (0, 0),
+    };
+    MemoryArrayMappedAddressInfo {
+        array_handle: s.handle_ref(0x0C),
+        devices_per_row: s.byte(0x0E).unwrap_or_default() as u32,
+        physical_address: start,
+        size: if end > start { end - start + 1 } else { 0 },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QEMU: &[u8] = include_bytes!("../../testdata/smbios/qemu-q35-2.8.bin");
+    const SERVER: &[u8] = include_bytes!("../../testdata/smbios/server-3.3.bin");
+    const THINKPAD: &[u8] = include_bytes!("../../testdata/smbios/thinkpad-t430-2.7.bin");
+    const PRECISION_EPS: &[u8] =
+        include_bytes!("../../testdata/smbios/precision-3620-3.0/smbios_entry_point");
+    const PRECISION_DMI: &[u8] = include_bytes!("../../testdata/smbios/precision-3620-3.0/DMI");
+
+    const GIB: u64 = 1 << 30;
+
+    #[test]
+    fn parses_entry_points() {
+        let eps = parse_entry_point(QEMU).unwrap();
+        assert_eq!((eps.major, eps.minor), (2, 8));
+        assert_eq!(eps.table_address, 0x20);
+        assert_eq!(eps.table_length, QEMU.len() - 0x20);
+
+        let eps = parse_entry_point(SERVER).unwrap();
+        assert_eq!((eps.major, eps.minor), (3, 3));
+
+        let mut corrupt = SERVER.to_vec();
+        corrupt[0x0C] ^= 0xFF;
+        assert_eq!(parse_entry_point(&corrupt), Err(SmbiosError::BadChecksum));
+        assert_eq!(
+            parse_entry_point(b"not an entry point"),
+            Err(SmbiosError::NoEntryPoint)
+        );
+    }
+
+    #[test]
+    fn decodes_qemu_table() {
+        let info = parse_dump(QEMU).unwrap();
+        let bios = info.bios.unwrap();
+        assert_eq!(bios.vendor, "SeaBIOS");
+        assert_eq!(bios.release_date, "04/01/2014");
+        assert_eq!(bios.rom_size, 64 * 1024);
+        assert_eq!(bios.image_size, 96 * 1024);
+        let system = info.system.unwrap();
+        assert_eq!(system.manufacturer, "QEMU");
+        assert_eq!(system.product, "Standard PC (Q35 + ICH9, 2009)");
+        assert_eq!(system.uuid, "6b8c2f4e-1d3a-4b5c-9e7f-0a1b2c3d4e5f");
+        assert_eq!(info.processors.len(), 2);
+        assert_eq!(info.processors[1].location_tag, "CPU 1");
+        assert_eq!(info.processors[0].family, 0x6B);
+        assert_eq!(info.processors[0].cpuid, 0x00800F82);
+        assert_eq!(info.processors[0].l1_cache_handle, 0);
+        assert_eq!(info.memory_arrays[0].max_capacity, 8 * GIB);
+        assert_eq!(info.memory_devices[0].size, 8 * GIB);
+        assert_eq!(info.memory_devices[0].device_locator, "DIMM 0");
+        assert_eq!(info.memory_array_mapped_addresses[0].size, 8 * GIB);
+        assert!(info.caches.is_empty());
+    }
+
+    #[test]
+    fn decodes_server_table() {
+        let info = parse_dump(SERVER).unwrap();
+        let bios = info.bios.unwrap();
+        assert_eq!(bios.version_number, "2.5");
+        assert_eq!(bios.rom_size, 32 << 20);
+        let system = info.system.unwrap();
+        assert_eq!(system.uuid, "4c4c4544-0042-3510-8052-b4c04f4e4d32");
+        assert_eq!(system.family, "PowerEdge");
+        let chassis = info.chassis.unwrap();
+        assert!(chassis.lock_present);
+        assert_eq!(chassis.chassis_type, 0x17);
+        assert_eq!(chassis.sku_number, "SKU Number");
+        assert_eq!(info.baseboard.unwrap().product, "0Y2K8N");
+
+        let cpu = &info.processors[0];
+        assert_eq!(cpu.version, "Intel(R) Xeon(R) Gold 6342 CPU @ 2.80GHz");
+        assert_eq!(cpu.supported_voltages, "1.2V");
+        assert_eq!(cpu.maximum_speed, "3000 MHz");
+        assert_eq!((cpu.core_count, cpu.thread_count), (24, 48));
+        assert_eq!(cpu.l3_cache_handle, 0x0702);
+
+        let l3 = info
+            .caches
+            .iter()
+            .find(|cache| cache.handle == cpu.l3_cache_handle)
+            .unwrap();
+        assert_eq!(l3.level, 3);
+        assert!(l3.enabled);
+        assert_eq!(l3.installed_size, 36 << 20);
+
+        assert_eq!(info.slots.len(), 2);
+        assert_eq!(info.slots[0].designation, "PCIe Slot 1");
+        assert_eq!(info.slots[0].bus_address, "0000:31:00.0");
+        assert_eq!(info.slots[1].current_usage, 3);
+        assert_eq!(info.slots[1].bus_address, "0000:4b:02.1");
+
+        assert_eq!(info.memory_arrays[0].max_capacity, 4 << 40);
+        let dimm = &info.memory_devices[0];
+        assert_eq!(dimm.size, 32 * GIB);
+        assert_eq!(dimm.speed, "3200 MT/s");
+        assert_eq!(dimm.configured_voltage, "1.20V");
+        assert_eq!(dimm.rank, 2);
+        assert_eq!(dimm.part_number, "M393A4K40EB3-CWE");
+        assert_eq!(info.memory_devices[1].size, 0);
+        assert_eq!(
+            info.memory_array_mapped_addresses[0].physical_address,
+            4 * GIB
+        );
+        assert_eq!(info.memory_array_mapped_addresses[0].size, 32 * GIB);
+    }
+
+    #[test]
+    fn decodes_captured_thinkpad_dump() {
+        let eps = parse_entry_point(THINKPAD).unwrap();
+        assert_eq!((eps.major, eps.minor), (2, 7));
+        assert_eq!(eps.table_length, THINKPAD.len() - 0x20);
+
+        let info = parse_dump(THINKPAD).unwrap();
+        let bios = info.bios.unwrap();
+        assert_eq!(bios.vendor, "LENOVO");
+        assert_eq!(bios.version, "G1ET45WW (1.20 )");
+        assert_eq!(bios.rom_size, 12 << 20);
+        let system = info.system.unwrap();
+        assert_eq!(system.version, "ThinkPad T430");
+        assert_eq!(system.uuid, "b4c3d881-5192-11cb-aa19-e6932635bc8b");
+        assert_eq!(system.sku_number, "LENOVO_MT_2347");
+        assert_eq!(info.chassis.unwrap().chassis_type, 0x0A);
+
+        let cpu = &info.processors[0];
+        assert_eq!(cpu.version, "Intel(R) Core(TM) i5-3320M CPU @ 2.60GHz");
+        assert_eq!(cpu.cpuid, 0x000306A9);
+        assert_eq!((cpu.core_count, cpu.thread_count), (2, 4));
+        assert_eq!(cpu.current_speed, "2600 MHz");
+        let l3 = info
+            .caches
+            .iter()
+            .find(|cache| cache.handle == cpu.l3_cache_handle)
+            .unwrap();
+        assert_eq!(l3.installed_size, 3 << 20);
+
+        // SMBIOS 2.7 has no voltages for memory devices yet
+        assert_eq!(info.memory_devices.len(), 2);
+        let dimm = &info.memory_devices[1];
+        assert_eq!(dimm.device_locator, "ChannelB-DIMM0");
+        assert_eq!(dimm.bank_locator, "BANK 2");
+        assert_eq!(dimm.part_number, "HMT351S6CFR8C-PB");
+        assert_eq!(dimm.size, 4 * GIB);
+        assert_eq!(dimm.speed, "1600 MT/s");
+        assert_eq!(dimm.configured_voltage, "");
+        assert_eq!(info.memory_array_mapped_addresses[0].size, 8 * GIB);
+        assert_eq!(info.slots[0].designation, "ExpressCard Slot");
+    }
+
+    #[test]
+    fn decodes_captured_sysfs_tables() {
+        let info = parse_tables(PRECISION_EPS, PRECISION_DMI).unwrap();
+        let bios = info.bios.unwrap();
+        assert_eq!(bios.vendor, "Dell Inc.");
+        assert_eq!(bios.release_date, "01/24/2017");
+        // 0xFF without the extended ROM size of SMBIOS 3.1
+        assert_eq!(bios.rom_size, 16 << 20);
+        let system = info.system.unwrap();
+        assert_eq!(system.product, "Precision Tower 3620");
+        assert_eq!(system.uuid, "4c4c4544-004b-4e10-805a-b7c04f424832");
+        assert_eq!(
+            info.baseboard.unwrap().serial_number,
+            "/7KNZBH2/CN722006CF01LX/"
+        );
+
+        let cpu = &info.processors[0];
+        assert_eq!(cpu.version, "Intel(R) Xeon(R) CPU E3-1225 v5 @ 3.30GHz");
+        assert_eq!((cpu.core_count, cpu.thread_count), (4, 4));
+        assert_eq!(cpu.maximum_speed, "4400 MHz");
+
+        let populated = info
+            .memory_devices
+            .iter()
+            .filter(|dimm| dimm.size > 0)
+            .collect::<Vec<_>>();
+        assert_eq!(info.memory_devices.len(), 4);
+        assert_eq!(populated.len(), 2);
+        assert_eq!(populated[0].part_number, "HMA41GU6AFR8N-TF");
+        assert_eq!(populated[0].rank, 2);
+        assert_eq!(populated[0].configured_voltage, "1.20V");
+        assert_eq!(info.memory_arrays[0].max_capacity, 64 * GIB);
+
+        assert_eq!(info.slots.len(), 5);
+        assert_eq!(info.slots[0].bus_address, "0000:01:00.0");
+        // Empty slots have no bus address
+        assert_eq!(info.slots[1].bus_address, "");
+    }
+
+    #[test]
+    fn sysfs_tables_match_dump() {
+        let eps_len = SERVER[0x06] as usize;
+        let from_sysfs = parse_tables(&SERVER[..eps_len], &SERVER[0x20..]).unwrap();
+        assert_eq!(from_sysfs, parse_dump(SERVER).unwrap());
+    }
+
+    #[test]
+    fn rejects_truncated_tables() {
+        assert_eq!(
+            parse_tables(&QEMU[..0x1F], &QEMU[0x20..0x40]).err(),
+            Some(SmbiosError::Truncated(0))
+        );
+    }
+}
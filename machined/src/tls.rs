@@ -0,0 +1,128 @@
+use crate::config::TlsConfig;
+use miette::{miette, IntoDiagnostic, Result};
+use sha2::{Digest, Sha256};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use tonic::transport::{Certificate, Identity, ServerTlsConfig};
+use tracing::info;
+
+/// Name the self-signed certificate is issued for, clients pinning it connect with this name
+pub const TLS_SERVER_NAME: &str = "machined";
+const CERT_FILE: &str = "cert.pem";
+const KEY_FILE: &str = "key.pem";
+
+/// Certificate and key machined serves
+pub struct TlsIdentity {
+    pub cert_pem: String,
+    pub key_pem: String,
+    /// SHA-256 fingerprint of the certificate, for operators to check before trusting it
+    pub fingerprint: String,
+}
+
+/// Load the configured certificate or the self-signed one, generating it on first start
+pub fn load_identity(config: &TlsConfig) -> Result<TlsIdentity> {
+    let (cert_pem, key_pem) = match (&config.cert, &config.key) {
+        (Some(cert), Some(key)) => (
+            fs::read_to_string(cert).into_diagnostic()?,
+            fs::read_to_string(key).into_diagnostic()?,
+        ),
+        _ => self_signed_identity(Path::new(&config.dir))?,
+    };
+    let fingerprint = fingerprint(&cert_pem)?;
+    Ok(TlsIdentity {
+        cert_pem,
+        key_pem,
+        fingerprint,
+    })
+}
+
+pub fn server_tls_config(config: &TlsConfig, identity: &TlsIdentity) -> Result<ServerTlsConfig> {
+    let tls =
+        ServerTlsConfig::new().identity(Identity::from_pem(&identity.cert_pem, &identity.key_pem));
+    match &config.client_ca {
+        Some(client_ca) => {
+            let ca = fs::read_to_string(client_ca).into_diagnostic()?;
+            Ok(tls.client_ca_root(Certificate::from_pem(ca)))
+        }
+        None => Ok(tls),
+    }
+}
+
+/// SHA-256 fingerprint of the first certificate of a PEM file as colon separated hex
+pub fn fingerprint(cert_pem: &str) -> Result<String> {
+    let cert = rustls_pemfile::certs(&mut cert_pem.as_bytes())
+        .next()
+        .ok_or_else(|| miette!("no certificate found in PEM data"))?
+        .into_diagnostic()?;
+    Ok(Sha256::digest(cert.as_ref())
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(":"))
+}
+
+/// Read the self-signed certificate of an earlier start or generate a new one, so the
+/// fingerprint stays the same as long as `dir` is kept
+fn self_signed_identity(dir: &Path) -> Result<(String, String)> {
+    let cert_path = dir.join(CERT_FILE);
+    let key_path = dir.join(KEY_FILE);
+    if cert_path.is_file() && key_path.is_file() {
+        return Ok((
+            fs::read_to_string(&cert_path).into_diagnostic()?,
+            fs::read_to_string(&key_path).into_diagnostic()?,
+        ));
+    }
+
+    info!(
+        "generating self-signed TLS certificate in {}",
+        dir.display()
+    );
+    let certified = rcgen::generate_simple_self_signed(vec![
+        TLS_SERVER_NAME.to_string(),
+        "localhost".to_string(),
+    ])
+    .into_diagnostic()?;
+    let cert_pem = certified.cert.pem();
+    let key_pem = certified.key_pair.serialize_pem();
+    fs::create_dir_all(dir).into_diagnostic()?;
+    let mut key_file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&key_path)
+        .into_diagnostic()?;
+    key_file.write_all(key_pem.as_bytes()).into_diagnostic()?;
+    fs::write(&cert_path, &cert_pem).into_diagnostic()?;
+    Ok((cert_pem, key_pem))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn keeps_the_self_signed_certificate() {
+        let dir = std::env::temp_dir().join(format!("machined-tls-{}", uuid::Uuid::new_v4()));
+        let config = TlsConfig {
+            dir: dir.display().to_string(),
+            ..Default::default()
+        };
+        let first = load_identity(&config).unwrap();
+        let second = load_identity(&config).unwrap();
+        assert_eq!(first.fingerprint, second.fingerprint);
+        assert_eq!(first.fingerprint.len(), 32 * 3 - 1);
+        assert_eq!(
+            fs::metadata(dir.join(KEY_FILE))
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o777,
+            0o600
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -1,12 +1,15 @@
 use crate::machined::install_progress::Message;
-use crate::machined::{InstallProgress, ProgressLevel};
+use crate::machined::{InstallPhase, InstallProgress, ProgressLevel};
 use std::error::Error;
-use tonic::Status;
+use tokio::sync::mpsc::error::SendError;
+use tokio_util::sync::CancellationToken;
+use tonic::{Code, Status};
 
 pub fn report_install_info(msg: &str) -> Result<InstallProgress, Status> {
     Ok(InstallProgress {
         level: ProgressLevel::Info.into(),
         message: Some(Message::Info(msg.into())),
+        ..Default::default()
     })
 }
 
@@ -14,6 +17,7 @@ pub fn report_install_warning(msg: &str) -> Result<InstallProgress, Status> {
     Ok(InstallProgress {
         level: ProgressLevel::Warning.into(),
         message: Some(Message::Error(msg.into())),
+        ..Default::default()
     })
 }
 
@@ -21,6 +25,7 @@ pub fn report_install_error<E: Error>(err: E) -> Result<InstallProgress, Status>
     Ok(InstallProgress {
         level: ProgressLevel::Error.into(),
         message: Some(Message::Error(err.to_string())),
+        ..Default::default()
     })
 }
 
@@ -28,5 +33,64 @@ pub fn report_install_debug(msg: &str) -> Result<InstallProgress, Status> {
     Ok(InstallProgress {
         level: ProgressLevel::Debug.into(),
         message: Some(Message::Info(msg.into())),
+        ..Default::default()
     })
 }
+
+/// Report that the installation started working on item `step` of `total_steps` in `phase`
+pub fn report_install_step(
+    phase: InstallPhase,
+    step: u32,
+    total_steps: u32,
+    msg: &str,
+) -> Result<InstallProgress, Status> {
+    Ok(InstallProgress {
+        level: ProgressLevel::Info.into(),
+        message: Some(Message::Info(msg.into())),
+        phase: phase.into(),
+        step,
+        total_steps,
+        ..Default::default()
+    })
+}
+
+/// Report how many bytes of the current item in `phase` have been processed
+pub fn report_install_bytes(
+    phase: InstallPhase,
+    step: u32,
+    total_steps: u32,
+    bytes_done: u64,
+    bytes_total: u64,
+) -> Result<InstallProgress, Status> {
+    Ok(InstallProgress {
+        level: ProgressLevel::Debug.into(),
+        message: Some(Message::Info(format!(
+            "{} of {} bytes",
+            bytes_done, bytes_total
+        ))),
+        phase: phase.into(),
+        step,
+        total_steps,
+        bytes_done,
+        bytes_total,
+        ..Default::default()
+    })
+}
+
+/// Whether an install failed on a layer that did not match its digest. Its files are already in
+/// the boot environment, so the install is rolled back even when the configuration skips that.
+pub fn unverified_image(result: &Result<(), SendError<Result<InstallProgress, Status>>>) -> bool {
+    matches!(result, Err(SendError(Err(status))) if status.code() == Code::DataLoss)
+}
+
+/// Stop the install between two steps once it was cancelled. The error ends the install like a
+/// failed step, so the completed steps are rolled back.
+pub fn check_cancelled(
+    cancel: &CancellationToken,
+) -> Result<(), SendError<Result<InstallProgress, Status>>> {
+    if cancel.is_cancelled() {
+        Err(SendError(Err(Status::cancelled("installation cancelled"))))
+    } else {
+        Ok(())
+    }
+}
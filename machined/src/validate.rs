@@ -0,0 +1,506 @@
+use crate::diskselect::{disk_name, is_selector, parse_size, resolve_disk_selectors, DiskSelector};
+use crate::machined::{DiskInfo, NetworkInterface, ValidateConfigResponse, ValidationIssue};
+use machineconfig::{ImageSource, MachineConfig, Pool, PostInstall, VolumeUsage};
+use std::collections::HashSet;
+
+/// Smallest disk an installation will put a pool on
+pub const MIN_DISK_SIZE: u64 = 8 * 1024 * 1024 * 1024;
+
+/// Disks and network links discovered on the machine
+#[derive(Debug, Default, Clone)]
+pub struct Hardware {
+    pub disks: Vec<DiskInfo>,
+    pub links: Vec<NetworkInterface>,
+}
+
+/// Check a machine configuration before anything is written to disk.
+///
+/// Without `hardware` only the structure of the configuration is checked.
+pub fn validate_config(mc: &MachineConfig, hardware: Option<&Hardware>) -> ValidateConfigResponse {
+    let mut issues = Vec::new();
+
+    if mc.pools.is_empty() {
+        issues.push(issue("config", "no pool configured"));
+    }
+    if mc.image.is_empty() {
+        issues.push(issue("config", "no image configured"));
+    } else {
+        match mc.image_source() {
+            // An image layout on the installation medium can be checked on the machine only
+            Ok(ImageSource::Layout { path, .. }) if hardware.is_some() => {
+                if !path.join("index.json").is_file() {
+                    issues.push(issue(
+                        "image",
+                        &format!("{} is not an OCI image layout", path.display()),
+                    ));
+                }
+            }
+            Ok(_) => {}
+            Err(e) => issues.push(issue("image", &e.to_string())),
+        }
+    }
+    if !mc.pools.is_empty() && !mc.pools.iter().any(|pool| pool.name == mc.root_pool()) {
+        issues.push(issue(
+            "config",
+            &format!("root pool {} is not configured", mc.root_pool()),
+        ));
+    }
+    // The platform default template is known to be valid, only configured ones are checked
+    if let Err(e) = mc.boot_environment("be", chrono::Utc::now()) {
+        issues.push(issue("boot environment", &e.to_string()));
+    }
+
+    // Selectors can only be resolved against discovered hardware, without it
+    // they are only checked for syntax and the vdevs holding them are not counted.
+    let mut pools = mc.pools.clone();
+    if let Some(hardware) = hardware {
+        if let Err(e) = resolve_disk_selectors(&mut pools, &hardware.disks) {
+            issues.push(issue("disk selectors", &e.to_string()));
+        }
+    }
+
+    let mut used_disks = HashSet::new();
+    for pool in &pools {
+        let pool_subject = format!("pool {}", pool.name);
+        if pool.vdevs.is_empty() {
+            issues.push(issue(&pool_subject, "pool has no data vdevs"));
+        }
+        for (group, vdevs) in pool.vdev_groups() {
+            let group = group.map(|g| format!("{} ", g)).unwrap_or_default();
+            for vdev in vdevs {
+                let minimum = vdev.kind.minimum_disks();
+                let has_selectors = vdev.disks.iter().any(|disk| is_selector(disk));
+                if !has_selectors && vdev.disks.len() < minimum {
+                    issues.push(issue(
+                        &pool_subject,
+                        &format!(
+                            "{}{} vdev needs at least {} disks but has {}",
+                            group,
+                            vdev.kind.name(),
+                            minimum,
+                            vdev.disks.len()
+                        ),
+                    ));
+                }
+                if group == "log " && vdev.kind.is_raidz() {
+                    issues.push(issue(&pool_subject, "log vdevs can not be raidz"));
+                }
+            }
+        }
+        for disk in pool.disks() {
+            let subject = format!("{} disk {}", pool_subject, disk);
+            if is_selector(disk) {
+                if let Err(e) = disk.parse::<DiskSelector>() {
+                    issues.push(issue(&subject, &e.to_string()));
+                }
+                continue;
+            }
+            if !used_disks.insert(disk_name(disk)) {
+                issues.push(issue(&subject, "disk is used more than once"));
+            }
+            if let Some(hardware) = hardware {
+                check_disk(&subject, disk, &hardware.disks, &mut issues);
+            }
+        }
+        check_datasets(pool, &mut issues);
+    }
+
+    for iface in &mc.sysconfig.interfaces {
+        let Some(selector) = &iface.selector else {
+            continue;
+        };
+        let subject = format!(
+            "interface {}",
+            iface.name.as_deref().unwrap_or(selector.as_str())
+        );
+        let Some(mac) = selector.strip_prefix("mac:") else {
+            issues.push(issue(
+                &subject,
+                &format!(
+                    "selector {} is not supported, expected mac:<address>",
+                    selector
+                ),
+            ));
+            continue;
+        };
+        if let Some(hardware) = hardware {
+            if !hardware
+                .links
+                .iter()
+                .any(|link| same_mac(&link.mac_address, mac))
+            {
+                issues.push(issue(
+                    &subject,
+                    &format!("no network link has MAC address {}", mac),
+                ));
+            }
+        }
+    }
+
+    check_postinstall(&mc.postinstall, &mut issues);
+
+    ValidateConfigResponse {
+        valid: issues.is_empty(),
+        issues,
+        hardware_checked: hardware.is_some(),
+    }
+}
+
+fn check_postinstall(postinstall: &PostInstall, issues: &mut Vec<ValidationIssue>) {
+    for file in &postinstall.files {
+        let subject = format!("postinstall file {}", file.path);
+        if !file.path.starts_with('/') {
+            issues.push(issue(&subject, "path must be absolute"));
+        }
+        if let Err(e) = file.mode() {
+            issues.push(issue(&subject, &e.to_string()));
+        }
+        if !file.payload().is_valid() {
+            issues.push(issue(&subject, "either source or content must be given"));
+        }
+    }
+    for manifest in &postinstall.smf_manifests {
+        let subject = format!("postinstall SMF manifest {}", manifest.name);
+        if manifest.name.is_empty() || manifest.name.contains('/') {
+            issues.push(issue(&subject, "name must be a file name"));
+        }
+        if !manifest.payload().is_valid() {
+            issues.push(issue(&subject, "either source or content must be given"));
+        }
+    }
+    for script in &postinstall.scripts {
+        if !script.payload().is_valid() {
+            issues.push(issue(
+                &format!("postinstall script {}", script.name),
+                "either source or content must be given",
+            ));
+        }
+    }
+}
+
+fn check_disk(subject: &str, disk: &str, disks: &[DiskInfo], issues: &mut Vec<ValidationIssue>) {
+    let name = disk_name(disk);
+    let Some(info) = disks
+        .iter()
+        .find(|info| info.device == name || info.paths.iter().any(|path| path == disk))
+    else {
+        issues.push(issue(subject, "disk does not exist"));
+        return;
+    };
+    if info.removable {
+        issues.push(issue(subject, "disk is removable media"));
+    }
+    if info.size_bytes < MIN_DISK_SIZE {
+        issues.push(issue(
+            subject,
+            &format!(
+                "disk has {} bytes, at least {} are required",
+                info.size_bytes, MIN_DISK_SIZE
+            ),
+        ));
+    }
+}
+
+fn check_datasets(pool: &Pool, issues: &mut Vec<ValidationIssue>) {
+    let invalid_name = |name: &str| name.is_empty() || name.contains(['/', '@', '#']);
+    let mut names = HashSet::new();
+    for (name, dataset) in pool.dataset_tree() {
+        let subject = format!("dataset {}", name);
+        if invalid_name(&dataset.name) {
+            issues.push(issue(
+                &subject,
+                "dataset names can not be empty or contain / @ #",
+            ));
+        }
+        if !names.insert(name.clone()) {
+            issues.push(issue(&subject, "dataset is configured more than once"));
+        }
+    }
+    let mut dumps = 0;
+    for volume in &pool.volumes {
+        let name = format!("{}/{}", pool.name, volume.name);
+        let subject = format!("volume {}", name);
+        if invalid_name(&volume.name) {
+            issues.push(issue(
+                &subject,
+                "volume names can not be empty or contain / @ #",
+            ));
+        }
+        if !names.insert(name) {
+            issues.push(issue(&subject, "volume is configured more than once"));
+        }
+        if !matches!(parse_size(&volume.size), Some(size) if size > 0) {
+            issues.push(issue(
+                &subject,
+                &format!("invalid volume size \"{}\"", volume.size),
+            ));
+        }
+        if volume.usage == Some(VolumeUsage::Dump) {
+            dumps += 1;
+            if dumps == 2 {
+                issues.push(issue(&subject, "only one volume can be the dump device"));
+            }
+        }
+    }
+}
+
+/// dladm prints MAC addresses without leading zeros (`2:8:20:...`)
+pub fn same_mac(a: &str, b: &str) -> bool {
+    let octets = |mac: &str| {
+        mac.split(':')
+            .map(|octet| u8::from_str_radix(octet, 16).ok())
+            .collect::<Vec<_>>()
+    };
+    !a.is_empty() && octets(a) == octets(b)
+}
+
+fn issue(subject: &str, message: &str) -> ValidationIssue {
+    ValidationIssue {
+        subject: subject.to_string(),
+        message: message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use machineconfig::{Interface, PostInstallFile, Script, VDev, VDevType, Volume};
+
+    fn disk(device: &str, size_bytes: u64, removable: bool) -> DiskInfo {
+        DiskInfo {
+            device: device.to_string(),
+            size_bytes,
+            removable,
+            ..Default::default()
+        }
+    }
+
+    fn config(kind: VDevType, disks: &[&str]) -> MachineConfig {
+        MachineConfig {
+            pools: vec![Pool {
+                name: String::from("rpool"),
+                vdevs: vec![VDev {
+                    kind,
+                    disks: disks.iter().map(|d| d.to_string()).collect(),
+                }],
+                ..Default::default()
+            }],
+            image: String::from("oci://aopc.cloud/openindiana/hipster:2024.12"),
+            ..Default::default()
+        }
+    }
+
+    fn hardware() -> Hardware {
+        Hardware {
+            disks: vec![
+                disk("c5t0d0", 100 * MIN_DISK_SIZE, false),
+                disk("c6t0d0", 100 * MIN_DISK_SIZE, false),
+                disk("c7t0d0", MIN_DISK_SIZE / 2, false),
+                disk("c8t0d0", 100 * MIN_DISK_SIZE, true),
+            ],
+            links: vec![NetworkInterface {
+                link: String::from("e1000g0"),
+                mac_address: String::from("2:8:20:a:b:c"),
+                ..Default::default()
+            }],
+        }
+    }
+
+    #[test]
+    fn valid_config_passes() {
+        let report = validate_config(
+            &config(VDevType::Mirror, &["c5t0d0", "/dev/dsk/c6t0d0s0"]),
+            Some(&hardware()),
+        );
+        assert!(report.valid, "{:?}", report.issues);
+        assert!(report.hardware_checked);
+    }
+
+    #[test]
+    fn reports_missing_small_and_removable_disks() {
+        let report = validate_config(
+            &config(VDevType::Raidz1, &["c7t0d0", "c8t0d0", "c9t0d0"]),
+            Some(&hardware()),
+        );
+        assert!(!report.valid);
+        let messages = report
+            .issues
+            .iter()
+            .map(|i| format!("{}: {}", i.subject, i.message))
+            .collect::<Vec<_>>();
+        assert_eq!(messages.len(), 3, "{:?}", messages);
+        assert!(messages[0].starts_with("pool rpool disk c7t0d0: disk has"));
+        assert_eq!(
+            messages[1],
+            "pool rpool disk c8t0d0: disk is removable media"
+        );
+        assert_eq!(messages[2], "pool rpool disk c9t0d0: disk does not exist");
+    }
+
+    #[test]
+    fn checks_vdev_disk_counts_without_hardware() {
+        let report = validate_config(&config(VDevType::Raidz2, &["c5t0d0", "c5t0d0"]), None);
+        assert!(!report.hardware_checked);
+        assert_eq!(report.issues.len(), 2);
+        assert_eq!(
+            report.issues[0].message,
+            "raidz2 vdev needs at least 4 disks but has 2"
+        );
+        assert_eq!(report.issues[1].message, "disk is used more than once");
+    }
+
+    #[test]
+    fn checks_auxiliary_vdevs_and_volumes() {
+        let mut mc = config(VDevType::Mirror, &["c5t0d0", "c6t0d0"]);
+        let pool = &mut mc.pools[0];
+        pool.log = vec![VDev {
+            kind: VDevType::Raidz1,
+            disks: vec![String::from("c7t0d0")],
+        }];
+        pool.spare = vec![String::from("c5t0d0")];
+        pool.volumes = vec![
+            Volume {
+                name: String::from("swap"),
+                size: String::from("lots"),
+                usage: Some(VolumeUsage::Swap),
+                ..Default::default()
+            },
+            Volume {
+                name: String::from("dump"),
+                size: String::from("2G"),
+                usage: Some(VolumeUsage::Dump),
+                ..Default::default()
+            },
+        ];
+        let report = validate_config(&mc, None);
+        let messages = report
+            .issues
+            .iter()
+            .map(|i| format!("{}: {}", i.subject, i.message))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                "pool rpool: log raidz1 vdev needs at least 3 disks but has 1",
+                "pool rpool: log vdevs can not be raidz",
+                "pool rpool disk c5t0d0: disk is used more than once",
+                "volume rpool/swap: invalid volume size \"lots\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_root_pool_and_boot_environment() {
+        let mut mc = config(VDevType::Mirror, &["c5t0d0", "c6t0d0"]);
+        mc.root_pool = Some(String::from("system"));
+        mc.boot_environment_template = Some(String::from("mydistro-{version}"));
+        let messages = validate_config(&mc, None)
+            .issues
+            .iter()
+            .map(|i| format!("{}: {}", i.subject, i.message))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                "config: root pool system is not configured",
+                "boot environment: boot environment template placeholder {version} is unknown",
+            ]
+        );
+    }
+
+    #[test]
+    fn resolves_disk_selectors_before_checking() {
+        let report = validate_config(
+            &config(VDevType::Mirror, &["c5t0d0", "size:10G..,smallest:1"]),
+            Some(&hardware()),
+        );
+        assert!(report.valid, "{:?}", report.issues);
+
+        let report = validate_config(
+            &config(VDevType::Mirror, &["c5t0d0", "size:10G..,smallest:2"]),
+            Some(&hardware()),
+        );
+        assert_eq!(report.issues.len(), 1);
+        assert_eq!(report.issues[0].subject, "disk selectors");
+    }
+
+    #[test]
+    fn checks_interface_selectors() {
+        let mut mc = config(VDevType::Mirror, &["c5t0d0", "c6t0d0"]);
+        mc.sysconfig.interfaces = vec![
+            Interface {
+                name: Some(String::from("net0")),
+                selector: Some(String::from("mac:02:08:20:0a:0b:0c")),
+                addresses: vec![],
+            },
+            Interface {
+                name: Some(String::from("net1")),
+                selector: Some(String::from("mac:02:08:20:0a:0b:0d")),
+                addresses: vec![],
+            },
+            Interface {
+                name: Some(String::from("net2")),
+                selector: Some(String::from("pci:0000:00:1f.6")),
+                addresses: vec![],
+            },
+        ];
+        let report = validate_config(&mc, Some(&hardware()));
+        let subjects = report
+            .issues
+            .iter()
+            .map(|i| i.subject.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(subjects, vec!["interface net1", "interface net2"]);
+    }
+
+    #[test]
+    fn checks_image_sources() {
+        let mut mc = config(VDevType::Mirror, &["c5t0d0", "c6t0d0"]);
+        mc.image = String::from("oci-layout:images/hipster");
+        let report = validate_config(&mc, None);
+        assert_eq!(report.issues[0].subject, "image");
+
+        mc.image = String::from("oci-layout:/nonexistent/images/hipster:2024.12");
+        assert!(validate_config(&mc, None).valid);
+        let report = validate_config(&mc, Some(&hardware()));
+        assert_eq!(report.issues[0].subject, "image");
+    }
+
+    #[test]
+    fn checks_postinstall_payloads() {
+        let mut mc = config(VDevType::Mirror, &["c5t0d0", "c6t0d0"]);
+        mc.postinstall.files = vec![
+            PostInstallFile {
+                path: String::from("/etc/motd"),
+                content: Some(String::from("hello")),
+                ..Default::default()
+            },
+            PostInstallFile {
+                path: String::from("etc/issue"),
+                mode: Some(String::from("0999")),
+                ..Default::default()
+            },
+        ];
+        mc.postinstall.scripts = vec![Script {
+            name: String::from("setup"),
+            source: Some(String::from("/usb/setup.sh")),
+            content: Some(String::from("true")),
+            ..Default::default()
+        }];
+        let report = validate_config(&mc, None);
+        let subjects = report
+            .issues
+            .iter()
+            .map(|i| i.subject.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            subjects,
+            vec![
+                "postinstall file etc/issue",
+                "postinstall file etc/issue",
+                "postinstall file etc/issue",
+                "postinstall script setup",
+            ]
+        );
+    }
+}
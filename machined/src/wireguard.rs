@@ -0,0 +1,250 @@
+use crate::config::WireguardConfig;
+use crate::process::run_capture_stdout;
+use base64::Engine;
+use miette::{miette, IntoDiagnostic, Result};
+use std::fs::{self, OpenOptions};
+use std::io::ErrorKind;
+use std::io::Write;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// Keeps NAT mappings between the lab network and the command server open
+const PERSISTENT_KEEPALIVE_SECS: u32 = 25;
+
+/// Holds the configuration handed to `wg setconf` while the tunnel is set up
+const CONF_DIR: &str = "/var/tmp/machined/wireguard";
+
+/// Bring up a userspace WireGuard tunnel to the command server.
+///
+/// The tunnel is run by wireguard-go and configured with wg, so it works without kernel
+/// support for WireGuard.
+pub fn start_tunnel(config: &WireguardConfig) -> Result<()> {
+    check_config(config)?;
+
+    info!(
+        "starting WireGuard tunnel {} to {}",
+        config.interface, config.server
+    );
+    run_capture_stdout(&["wireguard-go", config.interface.as_str()], None)?;
+
+    // wg only reads configurations from files, keep the private key readable for root only
+    let conf_dir = private_dir(Path::new(CONF_DIR))?;
+    let conf_path = conf_dir.join(format!("{}.conf", config.interface));
+    match fs::remove_file(&conf_path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => return Err(e).into_diagnostic(),
+        _ => {}
+    }
+    let mut conf_file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&conf_path)
+        .into_diagnostic()?;
+    conf_file
+        .write_all(render_config(config).as_bytes())
+        .into_diagnostic()?;
+    let configured = run_capture_stdout(
+        &[
+            "wg",
+            "setconf",
+            config.interface.as_str(),
+            &conf_path.display().to_string(),
+        ],
+        None,
+    );
+    let _ = fs::remove_file(&conf_path);
+    configured?;
+
+    configure_address(config)?;
+    info!(
+        "WireGuard tunnel {} is up with address {}",
+        config.interface, config.address
+    );
+    Ok(())
+}
+
+/// Create `dir` readable for its owner only and refuse one that somebody else could have
+/// planted, so files inside it can't be swapped or redirected through symlinks.
+fn private_dir(dir: &Path) -> Result<PathBuf> {
+    if let Some(parent) = dir.parent() {
+        fs::create_dir_all(parent).into_diagnostic()?;
+    }
+    match fs::DirBuilder::new().mode(0o700).create(dir) {
+        Err(e) if e.kind() != ErrorKind::AlreadyExists => return Err(e).into_diagnostic(),
+        _ => {}
+    }
+    let meta = fs::symlink_metadata(dir).into_diagnostic()?;
+    let euid = unsafe { libc::geteuid() };
+    if !meta.is_dir() {
+        return Err(miette!("{} is not a directory", dir.display()));
+    }
+    if meta.uid() != euid {
+        return Err(miette!(
+            "{} is owned by uid {} instead of {}",
+            dir.display(),
+            meta.uid(),
+            euid
+        ));
+    }
+    if meta.permissions().mode() & 0o077 != 0 {
+        return Err(miette!(
+            "{} is accessible by other users (mode {:o})",
+            dir.display(),
+            meta.permissions().mode() & 0o777
+        ));
+    }
+    Ok(dir.to_path_buf())
+}
+
+/// Configuration of the tunnel in the format of `wg setconf`
+fn render_config(config: &WireguardConfig) -> String {
+    let mut conf = format!(
+        "[Interface]\nPrivateKey = {}\n\n[Peer]\nPublicKey = {}\nEndpoint = {}\nPersistentKeepalive = {}\n",
+        config.private_key.trim(),
+        config.server_public_key.trim(),
+        config.server,
+        PERSISTENT_KEEPALIVE_SECS
+    );
+    conf.push_str(&format!("AllowedIPs = {}\n", config.peers.join(", ")));
+    conf
+}
+
+/// Refuse configurations `wg` would accept but that leave the tunnel useless
+fn check_config(config: &WireguardConfig) -> Result<()> {
+    for (name, key) in [
+        ("private_key", &config.private_key),
+        ("server_public_key", &config.server_public_key),
+    ] {
+        check_key(key).map_err(|e| miette!("wireguard.{} is invalid: {}", name, e))?;
+    }
+    if !config.address.contains('/') {
+        return Err(miette!(
+            "wireguard.address {} needs a prefix length, e.g. 10.100.0.2/24",
+            config.address
+        ));
+    }
+    // Without AllowedIPs the peer accepts and routes no traffic at all
+    if config.peers.is_empty() {
+        return Err(miette!(
+            "wireguard.peers is empty, name the networks routed through the tunnel"
+        ));
+    }
+    Ok(())
+}
+
+/// WireGuard keys are 32 bytes encoded as base64
+fn check_key(key: &str) -> Result<()> {
+    let bytes = base64::prelude::BASE64_STANDARD
+        .decode(key.trim())
+        .into_diagnostic()?;
+    if bytes.len() != 32 {
+        return Err(miette!("expected 32 bytes, got {}", bytes.len()));
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "illumos")]
+fn configure_address(config: &WireguardConfig) -> Result<()> {
+    let address = config.address.split('/').next().unwrap_or_default();
+    run_capture_stdout(
+        &[
+            "ifconfig",
+            config.interface.as_str(),
+            "inet",
+            config.address.as_str(),
+            "up",
+        ],
+        None,
+    )?;
+    for peer in &config.peers {
+        run_capture_stdout(
+            &[
+                "route",
+                "-n",
+                "add",
+                "-net",
+                peer.as_str(),
+                address,
+                "-interface",
+            ],
+            None,
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "illumos"))]
+fn configure_address(config: &WireguardConfig) -> Result<()> {
+    let interface = config.interface.as_str();
+    run_capture_stdout(
+        &[
+            "ip",
+            "address",
+            "add",
+            config.address.as_str(),
+            "dev",
+            interface,
+        ],
+        None,
+    )?;
+    run_capture_stdout(&["ip", "link", "set", interface, "up"], None)?;
+    for peer in &config.peers {
+        run_capture_stdout(
+            &["ip", "route", "replace", peer.as_str(), "dev", interface],
+            None,
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_wg_config() {
+        let config = WireguardConfig {
+            server: "cmd.example.com:51820".to_string(),
+            private_key: "yAnz5TF+lXXJte14tji3zlMNq+hd2rYUIgJBgB3fBmk=".to_string(),
+            server_public_key: "xTIBA5rboUvnH4htodjb6e697QjLERt1NAB4mZqp8Dg=".to_string(),
+            peers: vec!["10.100.0.0/24".to_string(), "10.200.0.0/16".to_string()],
+            address: "10.100.0.2/24".to_string(),
+            interface: "wg0".to_string(),
+        };
+        let conf = render_config(&config);
+        assert!(conf.contains("PrivateKey = yAnz5TF+lXXJte14tji3zlMNq+hd2rYUIgJBgB3fBmk=\n"));
+        assert!(conf.contains("Endpoint = cmd.example.com:51820\n"));
+        assert!(conf.contains("AllowedIPs = 10.100.0.0/24, 10.200.0.0/16\n"));
+
+        assert!(check_key(&config.private_key).is_ok());
+        assert!(check_key("c2hvcnQ=").is_err());
+
+        assert!(check_config(&config).is_ok());
+        let no_peers = WireguardConfig {
+            peers: Vec::new(),
+            ..config
+        };
+        assert!(check_config(&no_peers).is_err());
+    }
+
+    #[test]
+    fn refuses_shared_conf_dirs() {
+        let base = std::env::temp_dir().join(format!("machined-wg-{}", uuid::Uuid::new_v4()));
+        let dir = base.join("wireguard");
+        assert_eq!(private_dir(&dir).unwrap(), dir);
+        assert_eq!(
+            fs::metadata(&dir).unwrap().permissions().mode() & 0o777,
+            0o700
+        );
+        assert!(private_dir(&dir).is_ok());
+
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(private_dir(&dir).is_err());
+
+        let link = base.join("link");
+        std::os::unix::fs::symlink(&dir, &link).unwrap();
+        assert!(private_dir(&link).is_err());
+        let _ = fs::remove_dir_all(&base);
+    }
+}
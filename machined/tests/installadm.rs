@@ -0,0 +1,288 @@
+//! Drive installadm against machined built with the mock platform over a real gRPC socket.
+//!
+//! installadm is built from the workspace on first use, or taken from `INSTALLADM_BIN`.
+#![cfg(feature = "mock")]
+
+use std::fs;
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::OnceLock;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+const CLAIM_PASSWORD: &str = "it-secret";
+
+const FIXTURE: &str = include_str!("../testdata/mock/fixture.toml");
+
+const MACHINE_CONFIG: &str = r#"
+image = "oci://aopc.cloud/openindiana/hipster:2024.12"
+boot_environment_name = "it-install"
+
+[[pools]]
+name = "rpool"
+vdevs = [{ kind = "mirror", disks = ["c1t0d0", "c1t1d0"] }]
+volumes = [{ name = "swap", size = "2G", usage = "swap" }]
+
+[sysconfig]
+hostname = "node01"
+
+[[sysconfig.interfaces]]
+name = "net0"
+selector = "mac:2:8:20:a:b:c"
+addresses = [{ name = "v4", kind = "dhcp4" }]
+
+[[postinstall.files]]
+path = "/root/.ssh/authorized_keys"
+mode = "0600"
+content = "ssh-ed25519 AAAA it-operator@example"
+"#;
+
+fn installadm_bin() -> &'static Path {
+    static BIN: OnceLock<PathBuf> = OnceLock::new();
+    BIN.get_or_init(|| {
+        if let Ok(bin) = std::env::var("INSTALLADM_BIN") {
+            return PathBuf::from(bin);
+        }
+        let target_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("installadm");
+        let status = Command::new(std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string()))
+            .arg("build")
+            .arg("--manifest-path")
+            .arg(Path::new(env!("CARGO_MANIFEST_DIR")).join("../installadm/Cargo.toml"))
+            .arg("--target-dir")
+            .arg(&target_dir)
+            .status()
+            .expect("cargo runs");
+        assert!(status.success(), "building installadm failed");
+        target_dir.join("debug").join("installadm")
+    })
+}
+
+/// A machined process serving the mock platform, with installadm claiming it from a
+/// configuration directory of its own
+struct Machined {
+    child: Child,
+    dir: PathBuf,
+    url: String,
+}
+
+impl Machined {
+    fn start(fixture: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("machined-it-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("fixture.toml"), fixture).unwrap();
+
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let config = dir.join("machined.toml");
+        fs::write(
+            &config,
+            format!(
+                r#"
+listen = "127.0.0.1:{port}"
+claim_password = "{CLAIM_PASSWORD}"
+session_dir = "{dir}/sessions"
+blob_cache_dir = "{dir}/blobs"
+mock_fixture = "{dir}/fixture.toml"
+
+[tls]
+enabled = false
+"#,
+                dir = dir.display()
+            ),
+        )
+        .unwrap();
+
+        let child = Command::new(env!("CARGO_BIN_EXE_machined"))
+            .env("MACHINED_CONFIG", &config)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("machined starts");
+        let machined = Machined {
+            child,
+            dir,
+            url: format!("http://127.0.0.1:{}", port),
+        };
+
+        let deadline = Instant::now() + Duration::from_secs(30);
+        while TcpStream::connect(("127.0.0.1", port)).is_err() {
+            assert!(
+                Instant::now() < deadline,
+                "machined did not start listening"
+            );
+            sleep(Duration::from_millis(100));
+        }
+        machined
+    }
+
+    /// Run installadm, returning whether it succeeded and what it printed
+    fn installadm(&self, args: &[&str]) -> (bool, String) {
+        let output = Command::new(installadm_bin())
+            .args(args)
+            .env("HOME", &self.dir)
+            .env("XDG_CONFIG_HOME", self.dir.join("config"))
+            .env("USER", "it-operator")
+            .current_dir(&self.dir)
+            .output()
+            .expect("installadm runs");
+        let text = format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        (output.status.success(), text)
+    }
+
+    fn claim(&self) {
+        let (ok, output) = self.installadm(&["claim", &self.url, CLAIM_PASSWORD, "mock"]);
+        assert!(ok, "claim failed: {}", output);
+        assert!(output.contains("Claimed mock as it-operator"), "{}", output);
+    }
+
+    fn write(&self, name: &str, content: &str) -> String {
+        let path = self.dir.join(name);
+        fs::write(&path, content).unwrap();
+        path.display().to_string()
+    }
+}
+
+impl Drop for Machined {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Position of the first line containing `needle`, failing with the whole output if none does
+fn line_of(output: &str, needle: &str) -> usize {
+    output
+        .lines()
+        .position(|line| line.contains(needle))
+        .unwrap_or_else(|| panic!("no line contains {:?} in:\n{}", needle, output))
+}
+
+#[test]
+fn claims_inspects_and_installs_the_fixture() {
+    let machined = Machined::start(FIXTURE);
+    machined.claim();
+
+    let (ok, output) = machined.installadm(&["system-info", "mock"]);
+    assert!(ok, "{}", output);
+    assert!(output.contains("Firmware: UEFI"), "{}", output);
+    assert!(
+        output
+            .contains("Intel(R) Xeon(R) Gold 6342 CPU @ 2.80GHz (1 sockets, 24 cores, 48 threads)"),
+        "{}",
+        output
+    );
+    line_of(&output, "SAMSUNG MZ7LH480");
+    line_of(&output, "ST4000NM0295");
+    line_of(&output, "2:8:20:a:b:c");
+
+    let config = machined.write("machine.toml", MACHINE_CONFIG);
+    let (ok, output) = machined.installadm(&["validate", "mock", "-c", &config]);
+    assert!(ok, "{}", output);
+
+    // The USB stick is too small and removable, c9t0d0 does not exist in the fixture
+    let invalid = machined.write(
+        "invalid.toml",
+        &MACHINE_CONFIG.replace(r#"["c1t0d0", "c1t1d0"]"#, r#"["c3t0d0", "c9t0d0"]"#),
+    );
+    let (ok, output) = machined.installadm(&["validate", "mock", "-c", &invalid]);
+    assert!(!ok, "{}", output);
+    line_of(&output, "c9t0d0");
+    let (ok, output) = machined.installadm(&["install", "mock", "-c", &invalid]);
+    assert!(!ok, "{}", output);
+    line_of(&output, "c3t0d0");
+
+    let (ok, output) = machined.installadm(&["install", "mock", "-c", &config, "-s", "first"]);
+    assert!(ok, "{}", output);
+    let steps = [
+        "[INSTALL_PHASE_POOLS 1/1] Setting up pool rpool",
+        "[INSTALL_PHASE_DATASETS 1/4] Creating base root dataset",
+        "[INSTALL_PHASE_DATASETS 2/4] Creating boot environment",
+        "[INSTALL_PHASE_DATASETS 3/4] Mounting boot environment to /a",
+        "[INSTALL_PHASE_DATASETS 4/4] Creating datasets and volumes",
+        "[INSTALL_PHASE_FETCH 1/1] resolving image manifest",
+        "[INSTALL_PHASE_UNPACK 1/2] unpacking layer sha256:",
+        "[INSTALL_PHASE_UNPACK 2/2] unpacking layer sha256:",
+        "[INSTALL_PHASE_SYSCONFIG 1/4] Setting hostname",
+        "[INSTALL_PHASE_SYSCONFIG 3/4] Configuring network interfaces",
+        "[INSTALL_PHASE_SYSCONFIG 4/4] Configuring swap and dump volumes",
+        "[INSTALL_PHASE_BOOTLOADER 1/1] Activating boot environment and installing bootloader",
+        "bootenvironment activated",
+    ];
+    let lines = steps
+        .iter()
+        .map(|step| line_of(&output, step))
+        .collect::<Vec<_>>();
+    assert!(lines.windows(2).all(|w| w[0] < w[1]), "{}", output);
+    assert!(!output.contains("ERROR"), "{}", output);
+
+    let (ok, output) = machined.installadm(&["sessions", "mock"]);
+    assert!(ok, "{}", output);
+    assert!(output.contains("first"), "{}", output);
+    assert!(output.contains("SUCCEEDED"), "{}", output);
+
+    let (ok, output) = machined.installadm(&["boot-environments", "mock"]);
+    assert!(ok, "{}", output);
+    line_of(&output, "it-install");
+
+    let (ok, output) = machined.installadm(&["install-report", "mock"]);
+    assert!(ok, "{}", output);
+    line_of(&output, "Session:          first");
+    line_of(&output, "Operator:         it-operator");
+    line_of(
+        &output,
+        "Image:            oci://aopc.cloud/openindiana/hipster:2024.12",
+    );
+    line_of(&output, "Manifest:         sha256:");
+    line_of(&output, "Boot environment: it-install");
+    line_of(&output, "c1t0d0 ATA SAMSUNG MZ7LH480");
+    line_of(&output, r#""hostname": "node01""#);
+    line_of(&output, "<redacted, 36 bytes>");
+    assert!(!output.contains("AAAA"), "{}", output);
+}
+
+#[test]
+fn injected_failure_is_reported_and_rolled_back() {
+    let machined = Machined::start(&format!(
+        "{}\n[[failures]]\nphase = \"unpack\"\nstep = 2\nmessage = \"No space left on device\"\n",
+        FIXTURE
+    ));
+    machined.claim();
+
+    let config = machined.write("machine.toml", MACHINE_CONFIG);
+    let (_, output) = machined.installadm(&["install", "mock", "-c", &config, "-s", "failing"]);
+    let unpack = line_of(
+        &output,
+        "[INSTALL_PHASE_UNPACK 2/2] unpacking layer sha256:",
+    );
+    let failure = line_of(&output, "ERROR: No space left on device");
+    let rollback = line_of(&output, "Installation failed, rolling back completed steps");
+    let unmount = line_of(
+        &output,
+        "[INSTALL_PHASE_ROLLBACK 1/2] Unmounting rpool/ROOT/it-install from /a",
+    );
+    let destroy = line_of(
+        &output,
+        "[INSTALL_PHASE_ROLLBACK 2/2] Destroying pool rpool",
+    );
+    assert!(unpack < failure && failure < rollback && rollback < unmount && unmount < destroy);
+    assert!(!output.contains("INSTALL_PHASE_SYSCONFIG"), "{}", output);
+
+    let (ok, output) = machined.installadm(&["sessions", "mock"]);
+    assert!(ok, "{}", output);
+    assert!(output.contains("FAILED"), "{}", output);
+    let (ok, output) = machined.installadm(&["boot-environments", "mock"]);
+    assert!(ok, "{}", output);
+    assert!(!output.contains("it-install"), "{}", output);
+    let (ok, output) = machined.installadm(&["install-report", "mock", "failing"]);
+    assert!(!ok, "{}", output);
+    line_of(&output, "has not succeeded");
+}
@@ -39,6 +39,23 @@ impl From<anyhow::Error> for ClientError {
     }
 }
 
+/// A blob being streamed from the registry.
+pub struct BlobStream {
+    response: reqwest::Response,
+}
+
+impl BlobStream {
+    /// Size of the blob in bytes, if the registry sent it.
+    pub fn content_length(&self) -> Option<u64> {
+        self.response.content_length()
+    }
+
+    /// Receive the next chunk of the blob, `None` once the blob is complete.
+    pub async fn chunk(&mut self) -> Result<Option<Bytes>> {
+        Ok(self.response.chunk().await?)
+    }
+}
+
 /// A client for interacting with an OCI registry.
 pub struct Client {
     registry_url: String,
@@ -473,6 +490,18 @@ impl ClientSession {
         Ok(response.bytes().await?)
     }
 
+    /// Fetch a blob with the given digest as a stream of chunks.
+    pub async fn fetch_blob_stream(&mut self, digest: &OciDigest) -> Result<BlobStream> {
+        let url = format!("{}/v2/{}/blobs/{}", self.registry_url, self.repository, digest);
+        let response = self.authenticated_get(&url).await?;
+
+        if response.status() != StatusCode::OK {
+            return Err(anyhow::anyhow!("Failed to fetch blob: {}", response.status()));
+        }
+
+        Ok(BlobStream { response })
+    }
+
     /// Fetch a blob as a specific type.
     pub async fn fetch_blob_as<T: DeserializeOwned>(&mut self, digest: &OciDigest) -> Result<Option<T>> {
         let url = format!("{}/v2/{}/blobs/{}", self.registry_url, self.repository, digest);
@@ -563,6 +592,18 @@ impl ClientSession {
         &mut self,
         reference: &str,
     ) -> Result<Option<ManifestVariant>> {
+        Ok(self
+            .query_manifest_with_digest(reference)
+            .await?
+            .map(|(manifest, _digest)| manifest))
+    }
+
+    /// Query a manifest with the given reference together with its digest, the SHA-256 of the
+    /// manifest as the registry serves it.
+    pub async fn query_manifest_with_digest(
+        &mut self,
+        reference: &str,
+    ) -> Result<Option<(ManifestVariant, OciDigest)>> {
         let url = format!("{}/v2/{}/manifests/{}", self.registry_url, self.repository, reference);
 
         // Create the request with the appropriate Accept headers for all manifest types
@@ -599,23 +640,29 @@ impl ClientSession {
             .headers()
             .get("Content-Type")
             .map(|h| h.to_str().unwrap_or(""))
-            .unwrap_or("");
+            .unwrap_or("")
+            .to_string();
+
+        // The digest is computed over the bytes the registry serves, re-encoding the parsed
+        // manifest would not reproduce them
+        let body = final_response.bytes().await?;
+        let digest = {
+            use sha2::{Digest, Sha256};
+            OciDigest::new("sha256".to_string(), hex::encode(Sha256::digest(&body)))
+        };
 
         // Parse the manifest based on the content type
-        if content_type.contains("application/vnd.oci.image.manifest.v1+json") {
-            let manifest: ImageManifest = final_response.json().await?;
-            Ok(Some(ManifestVariant::Manifest(manifest)))
+        let manifest = if content_type.contains("application/vnd.oci.image.manifest.v1+json") {
+            ManifestVariant::Manifest(serde_json::from_slice::<ImageManifest>(&body)?)
         } else if content_type.contains("application/vnd.oci.image.index.v1+json") {
-            let manifest_list: ImageManifestList = final_response.json().await?;
-            Ok(Some(ManifestVariant::List(manifest_list)))
+            ManifestVariant::List(serde_json::from_slice::<ImageManifestList>(&body)?)
         } else if content_type.contains("application/vnd.oci.artifact.manifest.v1+json") {
-            let artifact: ArtifactManifest = final_response.json().await?;
-            Ok(Some(ManifestVariant::Artifact(artifact)))
+            ManifestVariant::Artifact(serde_json::from_slice::<ArtifactManifest>(&body)?)
         } else {
             // Try to parse as a standard image manifest as a fallback
-            let manifest: ImageManifest = final_response.json().await?;
-            Ok(Some(ManifestVariant::Manifest(manifest)))
-        }
+            ManifestVariant::Manifest(serde_json::from_slice::<ImageManifest>(&body)?)
+        };
+        Ok(Some((manifest, digest)))
     }
 
     /// Query a manifest as a specific type.
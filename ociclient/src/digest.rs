@@ -59,8 +59,8 @@ impl FromStr for OciDigest {
         let algorithm = parts[0].to_string();
         let hex = parts[1].to_string();
 
-        // Validate algorithm (currently only sha256 is supported)
-        if algorithm != "sha256" {
+        // Validate algorithm (sha256 and sha512 are the registered OCI algorithms)
+        if algorithm != "sha256" && algorithm != "sha512" {
             return Err(DigestError::UnsupportedAlgorithm(algorithm));
         }
 
@@ -4,7 +4,7 @@ pub mod image_reference;
 pub mod models;
 
 // Re-export main client types for convenience
-pub use client::{Client, ClientSession};
+pub use client::{BlobStream, Client, ClientSession};
 pub use digest::OciDigest;
 pub use image_reference::ImageReference;
 pub use models::{AnyOciConfig, Descriptor, ImageManifest, ImageManifestList, ManifestVariant};
@@ -1,8 +1,10 @@
 use knus;
+use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
 
 // Define types for knus parsing
-#[derive(Debug, Default, knus::Decode)]
+#[derive(Debug, Clone, Default, PartialEq, knus::Decode, Serialize, Deserialize)]
+#[serde(default)]
 pub struct SysConfig {
     #[knus(child, unwrap(argument))]
     pub hostname: String,
@@ -14,19 +16,22 @@ pub struct SysConfig {
     pub interfaces: Vec<Interface>,
 }
 
-#[derive(Debug, Default, knus::Decode)]
+#[derive(Debug, Clone, Default, PartialEq, knus::Decode, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Interface {
     #[knus(argument)]
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
 
     #[knus(property)]
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub selector: Option<String>,
 
     #[knus(children(name = "address"))]
     pub addresses: Vec<AddressObject>,
 }
 
-#[derive(Debug, Default, knus::Decode)]
+#[derive(Debug, Clone, Default, PartialEq, knus::Decode, Serialize, Deserialize)]
 pub struct AddressObject {
     #[knus(property)]
     pub name: String,
@@ -35,10 +40,14 @@ pub struct AddressObject {
     pub kind: AddressKind,
 
     #[knus(argument)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub address: Option<String>,
 }
 
-#[derive(knus::DecodeScalar, Debug, Default, strum::Display)]
+#[derive(
+    knus::DecodeScalar, Debug, Clone, Default, PartialEq, strum::Display, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
 pub enum AddressKind {
     #[default]
     Dhcp4,